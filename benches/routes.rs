@@ -0,0 +1,55 @@
+//! Route lookup timings, run with `cargo bench --bench routes`. Plain
+//! `std::time` so stable Rust needs nothing extra.
+
+use simple_social::{
+    response::{Response, StatusCode},
+    server::{Method, RequestHandler, Server},
+};
+use std::{
+    hint::black_box,
+    time::{Duration, Instant},
+};
+
+const ITERATIONS: u32 = 200_000;
+
+fn server(routes: usize) -> Server {
+    let mut server = Server::new("127.0.0.1:0", 1);
+    for i in 0..routes {
+        server.get(&format!("/route/{i}"), |_| {
+            Ok(Response::new(StatusCode::Ok))
+        });
+    }
+    #[cfg(feature = "serde")]
+    server.resource(
+        "/posts",
+        simple_social::store::MemStore::<u64, String>::new(),
+    );
+    server
+}
+
+fn time(server: &Server, method: Method, path: &str) -> Duration {
+    let start = Instant::now();
+    for _ in 0..ITERATIONS {
+        black_box(server.match_route(method, black_box(path)));
+    }
+    start.elapsed() / ITERATIONS
+}
+
+fn main() {
+    for routes in [1, 10, 100, 1000] {
+        let server = server(routes);
+        let mut cases = vec![
+            ("first", Method::Get, String::from("/route/0")),
+            ("last", Method::Get, format!("/route/{}", routes - 1)),
+            ("miss", Method::Get, String::from("/nowhere")),
+            ("method miss", Method::Post, String::from("/route/0")),
+        ];
+        if cfg!(feature = "serde") {
+            cases.push(("item", Method::Get, String::from("/posts/7")));
+        }
+        for (name, method, path) in &cases {
+            let per = time(&server, *method, path);
+            println!("{routes:>5} routes  {name:<12} {:>6} ns", per.as_nanos());
+        }
+    }
+}
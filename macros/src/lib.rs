@@ -0,0 +1,141 @@
+//! Attribute routing for `simple_social`, re-exported from it under the
+//! `macros` feature:
+//!
+//! ```ignore
+//! #[get("/posts")]
+//! fn list_posts(req: &Request) -> HandlerResult { ... }
+//!
+//! server.mount("/", routes![list_posts, admin::create_post]);
+//! ```
+//!
+//! Each attribute leaves the function as written and adds a hidden
+//! `__route_<name>` function next to it that registers it on a `Router`
+//! through `RequestHandler`. `routes!` calls those for every path given.
+
+use proc_macro::TokenStream;
+use proc_macro2::Span;
+use quote::{format_ident, quote};
+use syn::{
+    parse::Parser, parse_macro_input, punctuated::Punctuated, Error, ItemFn, LitStr, Path, Token,
+};
+
+#[proc_macro_attribute]
+pub fn get(attr: TokenStream, item: TokenStream) -> TokenStream {
+    route("get", attr, item)
+}
+
+#[proc_macro_attribute]
+pub fn post(attr: TokenStream, item: TokenStream) -> TokenStream {
+    route("post", attr, item)
+}
+
+#[proc_macro_attribute]
+pub fn put(attr: TokenStream, item: TokenStream) -> TokenStream {
+    route("put", attr, item)
+}
+
+#[proc_macro_attribute]
+pub fn delete(attr: TokenStream, item: TokenStream) -> TokenStream {
+    route("delete", attr, item)
+}
+
+/// A `Router` with every listed handler registered, in order.
+#[proc_macro]
+pub fn routes(input: TokenStream) -> TokenStream {
+    let paths = match Punctuated::<Path, Token![,]>::parse_terminated.parse(input) {
+        Ok(paths) => paths,
+        Err(e) => return e.to_compile_error().into(),
+    };
+    let registrations = paths.into_iter().map(|mut path| {
+        let last = path.segments.last_mut().expect("paths have a segment");
+        last.ident = format_ident!("__route_{}", last.ident);
+        quote! { #path(&mut router); }
+    });
+    quote! {
+        {
+            let mut router = ::simple_social::server::Router::new();
+            #(#registrations)*
+            router
+        }
+    }
+    .into()
+}
+
+fn route(method: &str, attr: TokenStream, item: TokenStream) -> TokenStream {
+    let path = parse_macro_input!(attr as LitStr);
+    let handler = parse_macro_input!(item as ItemFn);
+    if let Err(message) = check_path(&path.value()) {
+        // Keep the function, so its uses don't pile more errors on this one.
+        let error = Error::new(path.span(), message).to_compile_error();
+        return quote! { #error #handler }.into();
+    }
+
+    let vis = &handler.vis;
+    let name = &handler.sig.ident;
+    let register = format_ident!("__route_{}", name);
+    let method = syn::Ident::new(method, Span::call_site());
+    quote! {
+        #handler
+
+        #[doc(hidden)]
+        #vis fn #register(router: &mut ::simple_social::server::Router) {
+            ::simple_social::server::RequestHandler::#method(router, #path, #name);
+        }
+    }
+    .into()
+}
+
+/// A path that can never match a request path is a mistake worth catching
+/// before it ships. `:name` takes one segment and a last `*name` the rest,
+/// where `name` is an identifier for `Request::param`.
+fn check_path(path: &str) -> Result<(), String> {
+    if !path.starts_with('/') {
+        return Err(format!("route path {path:?} must start with `/`"));
+    }
+    if path == "/" {
+        return Ok(());
+    }
+    let last = path[1..].split('/').count() - 1;
+    for (i, segment) in path[1..].split('/').enumerate() {
+        if segment.is_empty() {
+            return Err(format!(
+                "route path {path:?} has an empty segment; drop the doubled or trailing `/`"
+            ));
+        }
+        let placeholder = segment
+            .strip_prefix(':')
+            .or_else(|| segment.strip_prefix('*'));
+        if let Some(name) = placeholder {
+            if !is_ident(name) {
+                return Err(format!(
+                    "route path {path:?} has the parameter `{segment}`, but `{name}` \
+                     isn't an identifier"
+                ));
+            }
+            if segment.starts_with('*') && i != last {
+                return Err(format!(
+                    "route path {path:?} has `{segment}` before its last segment; \
+                     a wildcard takes the rest of the path"
+                ));
+            }
+            continue;
+        }
+        if let Some(c) = segment
+            .chars()
+            .find(|c| !(c.is_ascii_alphanumeric() || "-._~!$&'()*+,;=@%".contains(*c)))
+        {
+            return Err(format!(
+                "route path {path:?} contains {c:?}, which a request path can't"
+            ));
+        }
+    }
+    Ok(())
+}
+
+fn is_ident(name: &str) -> bool {
+    let mut chars = name.chars();
+    chars
+        .next()
+        .is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
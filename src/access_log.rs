@@ -0,0 +1,243 @@
+use crate::{date::DateTime, encoding::json_escape, request::Request, response::Response};
+use std::{
+    borrow::Cow,
+    io::Write,
+    sync::{Arc, Mutex},
+    time::{Duration, SystemTime},
+};
+
+/// Extra key/value pairs appended to each JSON access log line.
+pub type LogFields = Arc<dyn Fn(&Request, &Response) -> Vec<(String, String)> + Send + Sync>;
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum LogFormat {
+    /// `host ident user [time] "request" status bytes`
+    Common,
+    /// Common plus the quoted referer and user agent.
+    Combined,
+    /// One JSON object per line: ts, method, path, status, duration_ms,
+    /// bytes, remote_addr, request_id, user_agent and any extra fields.
+    Json,
+}
+
+const REDACTED: &str = "[REDACTED]";
+
+/// Header names and query keys whose values never reach a log line. Applied
+/// while formatting, so handlers still see the real request.
+#[derive(Clone, Debug)]
+pub(crate) struct Redaction {
+    headers: Vec<String>,
+    query_keys: Vec<String>,
+}
+
+impl Default for Redaction {
+    fn default() -> Self {
+        Redaction {
+            headers: [
+                "authorization",
+                "proxy-authorization",
+                "cookie",
+                "set-cookie",
+                "x-api-key",
+            ]
+            .map(String::from)
+            .to_vec(),
+            query_keys: ["token", "access_token"].map(String::from).to_vec(),
+        }
+    }
+}
+
+impl Redaction {
+    pub(crate) fn add_header(&mut self, name: &str) {
+        self.headers.push(name.to_ascii_lowercase());
+    }
+
+    pub(crate) fn add_query_key(&mut self, key: &str) {
+        self.query_keys.push(String::from(key));
+    }
+
+    /// Referer values also get their query string redacted.
+    pub(crate) fn header<'a>(&self, name: &str, value: &'a str) -> Cow<'a, str> {
+        if self.headers.iter().any(|h| h.eq_ignore_ascii_case(name)) {
+            Cow::Borrowed(REDACTED)
+        } else if name.eq_ignore_ascii_case("Referer") {
+            self.url(value)
+        } else {
+            Cow::Borrowed(value)
+        }
+    }
+
+    pub(crate) fn query<'a>(&self, query: &'a str) -> Cow<'a, str> {
+        let hidden = |pair: &str| {
+            let key = pair.split_once('=').map_or(pair, |(k, _)| k);
+            self.query_keys.iter().any(|k| k.eq_ignore_ascii_case(key))
+        };
+        if !query.split('&').any(hidden) {
+            return Cow::Borrowed(query);
+        }
+        let pairs = query
+            .split('&')
+            .map(|pair| match pair.split_once('=') {
+                Some((key, _)) if hidden(pair) => format!("{key}={REDACTED}"),
+                _ => String::from(pair),
+            })
+            .collect::<Vec<_>>();
+        Cow::Owned(pairs.join("&"))
+    }
+
+    /// The path and query as the client sent them, minus secrets.
+    pub(crate) fn target(&self, req: &Request) -> String {
+        match req.query() {
+            Some(query) => format!("{}?{}", req.path(), self.query(query)),
+            None => String::from(req.path()),
+        }
+    }
+
+    /// Redacts the query string of a URL-valued header such as `Referer`.
+    fn url<'a>(&self, url: &'a str) -> Cow<'a, str> {
+        match url.split_once('?') {
+            Some((base, query)) => match self.query(query) {
+                Cow::Borrowed(_) => Cow::Borrowed(url),
+                Cow::Owned(query) => Cow::Owned(format!("{base}?{query}")),
+            },
+            None => Cow::Borrowed(url),
+        }
+    }
+
+    /// The request head as received, for trace-level dumps.
+    pub(crate) fn dump(&self, req: &Request) -> String {
+        let mut out = format!("{} {} {}", req.method(), self.target(req), req.version());
+        for (name, value) in req.headers() {
+            out.push_str(&format!("\n{name}: {}", self.header(name, value)));
+        }
+        out
+    }
+}
+
+pub(crate) struct AccessLog {
+    writer: Mutex<Box<dyn Write + Send>>,
+    format: LogFormat,
+}
+
+impl AccessLog {
+    pub(crate) fn new(writer: impl Write + Send + 'static, format: LogFormat) -> AccessLog {
+        AccessLog {
+            writer: Mutex::new(Box::new(writer)),
+            format,
+        }
+    }
+
+    pub(crate) fn record(
+        &self,
+        req: &Request,
+        res: &Response,
+        elapsed: Duration,
+        fields: Option<&LogFields>,
+        redaction: &Redaction,
+    ) {
+        let now = DateTime::from_system_time(SystemTime::now());
+        let line = match self.format {
+            LogFormat::Common | LogFormat::Combined => self.line(req, res, &now, redaction),
+            LogFormat::Json => json_line(req, res, &now, elapsed, fields, redaction),
+        };
+        let mut writer = self.writer.lock().unwrap_or_else(|e| e.into_inner());
+        if let Err(e) = writer
+            .write_all(line.as_bytes())
+            .and_then(|_| writer.flush())
+        {
+            log::warn!("Error writing access log: {e}");
+        }
+    }
+
+    fn line(&self, req: &Request, res: &Response, now: &DateTime, redaction: &Redaction) -> String {
+        let host = req
+            .client_ip()
+            .map(|ip| ip.to_string())
+            .unwrap_or_else(|| String::from("-"));
+        let request = format!(
+            "{} {} {}",
+            req.method(),
+            redaction.target(req),
+            req.version()
+        );
+        let bytes = match res.body_len() {
+            0 => String::from("-"),
+            n => n.to_string(),
+        };
+
+        let mut line = format!(
+            "{host} - - [{}] \"{}\" {} {bytes}",
+            now.clf(),
+            escape(&request),
+            res.status().code(),
+        );
+        if self.format == LogFormat::Combined {
+            for name in ["Referer", "User-Agent"] {
+                match req.header(name) {
+                    Some(value) => {
+                        line.push_str(&format!(" \"{}\"", escape(&redaction.header(name, value))))
+                    }
+                    None => line.push_str(" \"-\""),
+                }
+            }
+        }
+        line.push('\n');
+        line
+    }
+}
+
+fn json_line(
+    req: &Request,
+    res: &Response,
+    now: &DateTime,
+    elapsed: Duration,
+    fields: Option<&LogFields>,
+    redaction: &Redaction,
+) -> String {
+    let string_or_null = |v: Option<String>| match v {
+        Some(v) => format!("\"{}\"", json_escape(&v)),
+        None => String::from("null"),
+    };
+
+    let mut line = format!(
+        "{{\"ts\":\"{}\",\"method\":\"{}\",\"path\":\"{}\",\"status\":{},\"duration_ms\":{:.3},\"bytes\":{},\"remote_addr\":{},\"request_id\":{},\"user_agent\":{}",
+        now.rfc3339(),
+        req.method(),
+        json_escape(req.path()),
+        res.status().code(),
+        elapsed.as_secs_f64() * 1000.0,
+        res.body_len(),
+        string_or_null(req.client_ip().map(|ip| ip.to_string())),
+        req.id(),
+        string_or_null(
+            req.header("User-Agent")
+                .map(|v| redaction.header("User-Agent", v).into_owned())
+        ),
+    );
+    if let Some(fields) = fields {
+        for (key, value) in fields(req, res) {
+            line.push_str(&format!(
+                ",\"{}\":\"{}\"",
+                json_escape(&key),
+                json_escape(&value)
+            ));
+        }
+    }
+    line.push_str("}\n");
+    line
+}
+
+/// Escapes quotes, backslashes and control bytes the way Apache does, so a
+/// crafted header can't break the line apart.
+fn escape(s: &str) -> String {
+    let mut out = String::with_capacity(s.len());
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            c if c.is_control() => out.push_str(&format!("\\x{:02x}", c as u32)),
+            c => out.push(c),
+        }
+    }
+    out
+}
@@ -0,0 +1,71 @@
+use crate::static_files::StaticDir;
+use std::{
+    collections::HashMap,
+    io,
+    sync::{Arc, RwLock},
+};
+
+/// Which fingerprinted name stands for which file in one static mount,
+/// both as paths relative to its root.
+#[derive(Debug, Default)]
+pub(crate) struct Fingerprints {
+    pub(crate) forward: HashMap<String, String>,
+    pub(crate) reverse: HashMap<String, String>,
+}
+
+/// The fingerprinted URLs of every static mount set up with
+/// `StaticDir::fingerprinted`. `Server::assets` hands out a clone to
+/// capture in handlers; clones share the table.
+#[derive(Clone, Default)]
+pub struct AssetManifest {
+    mounts: Arc<RwLock<Vec<(String, StaticDir)>>>,
+}
+
+impl AssetManifest {
+    pub(crate) fn add(&self, prefix: &str, dir: StaticDir) -> io::Result<()> {
+        dir.scan_fingerprints()?;
+        let mut mounts = self.mounts.write().unwrap_or_else(|e| e.into_inner());
+        mounts.push((String::from(prefix), dir));
+        Ok(())
+    }
+
+    /// The cache-busting URL for `path`, such as `/assets/app.3f9ab2c1.css`
+    /// for `/assets/app.css`. Paths outside a fingerprinted mount, and
+    /// files it didn't have at the last scan, come back unchanged.
+    pub fn url(&self, path: &str) -> String {
+        let (path, query) = match path.split_once('?') {
+            Some((path, query)) => (path, Some(query)),
+            None => (path, None),
+        };
+        let mounts = self.mounts.read().unwrap_or_else(|e| e.into_inner());
+        let found = mounts
+            .iter()
+            .filter_map(|(prefix, dir)| {
+                let rel = match prefix.as_str() {
+                    "/" => path.strip_prefix('/')?,
+                    prefix => path.strip_prefix(prefix)?.strip_prefix('/')?,
+                };
+                Some((prefix, dir.fingerprint_of(rel)?))
+            })
+            .max_by_key(|(prefix, _)| prefix.len());
+        let mut url = match found {
+            Some((prefix, name)) => format!("{}/{name}", prefix.trim_end_matches('/')),
+            None => String::from(path),
+        };
+        if let Some(query) = query {
+            url.push('?');
+            url.push_str(query);
+        }
+        url
+    }
+
+    /// Hashes every file again, after a deploy or an edit. Old
+    /// fingerprints stop resolving.
+    pub fn rescan(&self) -> io::Result<()> {
+        let mounts = self.mounts.read().unwrap_or_else(|e| e.into_inner());
+        for (_, dir) in mounts.iter() {
+            dir.scan_fingerprints()?;
+        }
+        Ok(())
+    }
+}
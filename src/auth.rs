@@ -0,0 +1,45 @@
+use crate::encoding::base64_decode;
+
+/// A parsed `Authorization` header. Schemes match case-insensitively.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub enum Authorization {
+    Basic {
+        user: String,
+        pass: String,
+    },
+    Bearer(String),
+    /// Any other scheme, with everything after it left as sent.
+    Other {
+        scheme: String,
+        params: String,
+    },
+}
+
+impl Authorization {
+    /// `None` for an empty header, a Bearer with no token, or Basic
+    /// credentials that aren't base64 of UTF-8 `user:pass`.
+    pub fn parse(value: &str) -> Option<Authorization> {
+        let value = value.trim();
+        let (scheme, params) = value.split_once(' ').unwrap_or((value, ""));
+        let params = params.trim();
+        if scheme.is_empty() {
+            return None;
+        }
+
+        if scheme.eq_ignore_ascii_case("Basic") {
+            let decoded = String::from_utf8(base64_decode(params)?).ok()?;
+            let (user, pass) = decoded.split_once(':')?;
+            Some(Authorization::Basic {
+                user: String::from(user),
+                pass: String::from(pass),
+            })
+        } else if scheme.eq_ignore_ascii_case("Bearer") {
+            (!params.is_empty()).then(|| Authorization::Bearer(String::from(params)))
+        } else {
+            Some(Authorization::Other {
+                scheme: String::from(scheme),
+                params: String::from(params),
+            })
+        }
+    }
+}
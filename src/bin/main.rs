@@ -1,59 +1,156 @@
-use simple_social::server::*;
-use std::{fs, io::Write, process, thread::sleep, time::Duration};
+#[cfg(feature = "signals")]
+use simple_social::signals::Signal;
+use simple_social::{
+    config::ServerBuilder,
+    error::ServerError,
+    handlers::serve_file,
+    server::*,
+    sitemap::{ChangeFreq, SitemapUrl},
+    static_files::static_dir,
+};
+use std::process;
 
-const POOL_SIZE: usize = 4;
+struct StderrLogger;
 
-fn main() {
-    let mut server = Server::new("127.0.0.1:3000", POOL_SIZE);
+impl log::Log for StderrLogger {
+    fn enabled(&self, metadata: &log::Metadata) -> bool {
+        metadata.level() <= log::Level::Info
+    }
 
-    let mut user_router = Router::new();
+    fn log(&self, record: &log::Record) {
+        if self.enabled(record.metadata()) {
+            eprintln!("{}", record.args());
+        }
+    }
 
-    user_router.get("/profile", |mut stream| {
-        let content = fs::read_to_string("static/user/index.html").unwrap();
+    fn flush(&self) {}
+}
 
-        let res = format!(
-            "{}\r\nContent=Length: {}\r\n\r\n{}",
-            STATUS_OK,
-            content.len(),
-            content
-        );
+fn main() {
+    log::set_logger(&StderrLogger).expect("no logger installed yet");
+    log::set_max_level(log::LevelFilter::Info);
 
-        stream.write(res.as_bytes()).unwrap();
-        stream.flush().unwrap();
-    });
+    let mut server =
+        match ServerBuilder::from_env().and_then(|b| b.banner(BannerMode::Clear).build()) {
+            Ok(server) => server,
+            Err(e) => {
+                eprintln!("Invalid configuration: {e}");
+                process::exit(1);
+            }
+        };
 
+    let mut user_router = Router::new();
+    user_router.get("/profile", serve_file("static/user/index.html"));
     server.mount("/user", user_router);
 
-    server.get("/", |mut stream| {
-        let content = fs::read_to_string("static/index.html").unwrap();
+    server.mount_static("/css", static_dir("static/css"));
+    server.favicon_none();
+    server.get("/", serve_file("static/index.html"));
+    server.get("/user", serve_file("static/user.html"));
+    server.robots(|r| r.sitemap("/sitemap.xml"));
+    server.sitemap("/sitemap.xml", || {
+        vec![
+            SitemapUrl::new("/").changefreq(ChangeFreq::Daily),
+            SitemapUrl::new("/user"),
+        ]
+    });
 
-        let res = format!(
-            "{}\r\nContent=Length: {}\r\n\r\n{}",
-            STATUS_OK,
-            content.len(),
-            content
-        );
+    #[cfg(feature = "serde")]
+    {
+        use simple_social::{
+            feed::{FeedBuilder, FeedItem},
+            resource::Resource,
+            response::Response,
+            store::MemStore,
+        };
 
-        stream.write(res.as_bytes()).unwrap();
-        stream.flush().unwrap();
-    });
+        let store = MemStore::<u64, serde_json::Value>::new();
+        let feed_store = store.clone();
+        let posts = Resource::new(store).before_create(|post| match post["title"].as_str() {
+            Some(title) if !title.trim().is_empty() => Ok(()),
+            _ => Err(String::from("a post needs a title")),
+        });
+        server.resource("/posts", posts);
+        server.get("/feed.xml", move |req| {
+            let site = format!(
+                "{}://{}",
+                req.scheme(),
+                req.header("Host").unwrap_or("localhost")
+            );
+            let mut entries = feed_store.entries();
+            entries.sort_by_key(|(id, _)| std::cmp::Reverse(*id));
+            let feed = entries.iter().take(20).fold(
+                FeedBuilder::new("simple_social", &site).description("Latest posts"),
+                |feed, (id, post)| {
+                    let title = post["title"].as_str().unwrap_or_default();
+                    let item = FeedItem::new(title, &format!("{site}/posts/{id}"));
+                    feed.item(match post["body"].as_str() {
+                        Some(body) => item.content(body),
+                        None => item,
+                    })
+                },
+            );
+            Ok(Response::rss(&feed.build()))
+        });
+    }
 
-    server.get("/user", |mut stream| {
-        sleep(Duration::from_secs(5));
-        let content = fs::read_to_string("static/user.html").unwrap();
-        let res = format!(
-            "{}\r\nContent=Length: {}\r\n\r\n{}",
-            STATUS_OK,
-            content.len(),
-            content
-        );
-
-        stream.write(res.as_bytes()).unwrap();
-        stream.flush().unwrap();
-    });
+    #[cfg(feature = "sqlite")]
+    {
+        use simple_social::{db::Db, response::*};
+
+        let db = match Db::open("social.db", 4).and_then(|db| db.migrate("migrations").map(|_| db))
+        {
+            Ok(db) => db,
+            Err(e) => {
+                eprintln!("Could not open the database: {e}");
+                process::exit(1);
+            }
+        };
+        let reader = db.clone();
+        server.get("/db/posts", move |_req| {
+            let posts = reader.with_conn(|conn| {
+                let mut stmt = conn.prepare("SELECT id, body FROM posts ORDER BY id")?;
+                let rows = stmt.query_map([], |row| {
+                    Ok(format!(
+                        "{}: {}\n",
+                        row.get::<_, i64>(0)?,
+                        row.get::<_, String>(1)?
+                    ))
+                })?;
+                rows.collect::<Result<String, _>>()
+            })?;
+            Ok(Response::send(StatusCode::Ok, posts))
+        });
+        server.post("/db/posts", move |req| {
+            let form = match req.form() {
+                Ok(form) => form,
+                Err(e) => return Ok(e.response()),
+            };
+            let Some(body) = form.get("body").filter(|b| !b.trim().is_empty()) else {
+                return Ok(Response::send(
+                    StatusCode::BadRequest,
+                    "a post needs a body",
+                ));
+            };
+            db.with_conn(|conn| conn.execute("INSERT INTO posts (body) VALUES (?1)", [body]))?;
+            Ok(Response::new(StatusCode::SeeOther).header("Location", "/db/posts"))
+        });
+    }
+
+    #[cfg(feature = "signals")]
+    if let Err(e) = server.graceful_on_signals(&[Signal::Int, Signal::Term]) {
+        eprintln!("Could not install signal handlers: {:?}", e);
+    }
 
-    if let Err(e) = server.run() {
-        eprintln!("Application error: {:?}", e);
-        process::exit(1);
+    match server.run() {
+        Ok(()) => {}
+        Err(ServerError::Bind { addr, source }) => {
+            eprintln!("Could not bind {addr} ({source}), is something already running on it?");
+            process::exit(1);
+        }
+        Err(e) => {
+            eprintln!("Application error: {:?}", e);
+            process::exit(1);
+        }
     }
 }
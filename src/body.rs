@@ -0,0 +1,300 @@
+use crate::{buffers, stream::Stream};
+use std::{
+    io::{self, ErrorKind, Read},
+    sync::MutexGuard,
+};
+
+const MAX_CHUNK_LINE: usize = 4096;
+
+/// Somewhere body bytes come from: whatever has been buffered already, then
+/// the connection.
+pub(crate) trait Source {
+    fn buffer(&mut self) -> &mut Vec<u8>;
+
+    /// Reads more from the connection into the buffer. Fails on EOF, since
+    /// a body that stops early is always an error.
+    fn fill(&mut self) -> io::Result<()>;
+
+    fn read_data(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if self.buffer().is_empty() {
+            self.fill()?;
+        }
+        let buffer = self.buffer();
+        let n = buf.len().min(buffer.len());
+        buf[..n].copy_from_slice(&buffer[..n]);
+        buffer.drain(..n);
+        Ok(n)
+    }
+
+    fn read_line(&mut self) -> io::Result<String> {
+        loop {
+            let buffer = self.buffer();
+            if let Some(pos) = buffer.windows(2).position(|w| w == b"\r\n") {
+                let line = buffer.drain(..pos + 2).take(pos).collect::<Vec<_>>();
+                return String::from_utf8(line).map_err(|_| invalid("chunk line is not UTF-8"));
+            }
+            if buffer.len() > MAX_CHUNK_LINE {
+                return Err(invalid("chunk line too long"));
+            }
+            self.fill()?;
+        }
+    }
+}
+
+/// A borrowed connection and its read buffer, for bodies read before the
+/// handler runs.
+pub(crate) struct Conn<'a, S: Read> {
+    pub(crate) stream: &'a mut S,
+    pub(crate) buffer: &'a mut Vec<u8>,
+}
+
+impl<S: Read> Source for Conn<'_, S> {
+    fn buffer(&mut self) -> &mut Vec<u8> {
+        self.buffer
+    }
+
+    fn fill(&mut self) -> io::Result<()> {
+        fill(self.stream, self.buffer)
+    }
+}
+
+struct OwnedConn {
+    stream: Box<dyn Stream>,
+    buffer: Vec<u8>,
+}
+
+impl Source for OwnedConn {
+    fn buffer(&mut self) -> &mut Vec<u8> {
+        &mut self.buffer
+    }
+
+    fn fill(&mut self) -> io::Result<()> {
+        fill(&mut self.stream, &mut self.buffer)
+    }
+}
+
+fn fill(stream: &mut impl Read, buffer: &mut Vec<u8>) -> io::Result<()> {
+    match buffers::read_more(stream, buffer)? {
+        0 => Err(ErrorKind::UnexpectedEof.into()),
+        _ => Ok(()),
+    }
+}
+
+fn invalid(why: &str) -> io::Error {
+    io::Error::new(ErrorKind::InvalidData, why)
+}
+
+/// How the end of a body is found.
+#[derive(Clone, Copy, Debug)]
+pub(crate) enum Framing {
+    /// `Content-Length`; holds the bytes still to come.
+    Length(u64),
+    Chunked(Chunked),
+}
+
+impl Framing {
+    fn done(&self) -> bool {
+        match self {
+            Framing::Length(left) => *left == 0,
+            Framing::Chunked(chunked) => chunked.done,
+        }
+    }
+
+    pub(crate) fn read(&mut self, src: &mut impl Source, buf: &mut [u8]) -> io::Result<usize> {
+        match self {
+            Framing::Length(left) => {
+                let want = buf.len().min((*left).try_into().unwrap_or(usize::MAX));
+                if want == 0 {
+                    return Ok(0);
+                }
+                let n = src.read_data(&mut buf[..want])?;
+                *left -= n as u64;
+                Ok(n)
+            }
+            Framing::Chunked(chunked) => chunked.read(src, buf),
+        }
+    }
+}
+
+/// Decoder state for a `Transfer-Encoding: chunked` body.
+#[derive(Clone, Copy, Debug, Default)]
+pub(crate) struct Chunked {
+    /// Bytes left in the current chunk.
+    left: u64,
+    /// The CRLF that ends a chunk's data is still to be read.
+    end_of_chunk: bool,
+    done: bool,
+}
+
+impl Chunked {
+    fn read(&mut self, src: &mut impl Source, buf: &mut [u8]) -> io::Result<usize> {
+        if self.done || buf.is_empty() {
+            return Ok(0);
+        }
+        if self.left == 0 {
+            if self.end_of_chunk {
+                if !src.read_line()?.is_empty() {
+                    return Err(invalid("chunk data longer than its size"));
+                }
+                self.end_of_chunk = false;
+            }
+            let line = src.read_line()?;
+            let size = line.split(';').next().unwrap_or_default().trim();
+            let size = u64::from_str_radix(size, 16)
+                .ok()
+                .filter(|_| !size.is_empty() && !size.starts_with('+'))
+                .ok_or_else(|| invalid("bad chunk size"))?;
+            if size == 0 {
+                // Trailers carry nothing we use; skip up to the blank line.
+                while !src.read_line()?.is_empty() {}
+                self.done = true;
+                return Ok(0);
+            }
+            self.left = size;
+        }
+
+        let want = buf.len().min(self.left.try_into().unwrap_or(usize::MAX));
+        let n = src.read_data(&mut buf[..want])?;
+        self.left -= n as u64;
+        if self.left == 0 {
+            self.end_of_chunk = true;
+        }
+        Ok(n)
+    }
+}
+
+/// Reads a whole body into memory; `None` once it grows past `max`.
+pub(crate) fn read_all(
+    framing: &mut Framing,
+    src: &mut impl Source,
+    max: usize,
+) -> io::Result<Option<Vec<u8>>> {
+    let mut body = Vec::new();
+    let mut chunk = [0; 8 * 1024];
+    loop {
+        let n = framing.read(src, &mut chunk)?;
+        if n == 0 {
+            return Ok(Some(body));
+        }
+        if body.len() + n > max {
+            return Ok(None);
+        }
+        body.extend_from_slice(&chunk[..n]);
+    }
+}
+
+/// The connection, lent to a handler so it can read a body the server left
+/// unbuffered.
+pub(crate) struct BodyReader {
+    conn: OwnedConn,
+    framing: Framing,
+    read: u64,
+}
+
+impl BodyReader {
+    /// `buffered` holds the bytes already read past the request head: the
+    /// start of the body and possibly a pipelined request after it.
+    pub(crate) fn new(stream: Box<dyn Stream>, buffered: Vec<u8>, framing: Framing) -> BodyReader {
+        BodyReader {
+            conn: OwnedConn {
+                stream,
+                buffer: buffered,
+            },
+            framing,
+            read: 0,
+        }
+    }
+
+    /// Reads away whatever the handler left, up to `limit` bytes, and hands
+    /// the connection back with its leftover buffer and the body bytes read
+    /// in total. The flag is false if the body could not be drained, so the
+    /// connection can't be reused.
+    pub(crate) fn finish(mut self, limit: u64) -> (Box<dyn Stream>, Vec<u8>, u64, bool) {
+        let drained =
+            io::copy(&mut (&mut self).take(limit), &mut io::sink()).is_ok() && self.framing.done();
+        (self.conn.stream, self.conn.buffer, self.read, drained)
+    }
+}
+
+impl Read for BodyReader {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let n = self.framing.read(&mut self.conn, buf)?;
+        self.read += n as u64;
+        Ok(n)
+    }
+}
+
+/// What `Request::body_reader` hands out.
+pub(crate) enum Body<'a> {
+    Buffered(&'a [u8]),
+    Streaming(MutexGuard<'a, BodyReader>),
+    #[cfg(feature = "compression")]
+    Decoded(Box<dyn Read + 'a>),
+}
+
+impl Read for Body<'_> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        match self {
+            Body::Buffered(bytes) => bytes.read(buf),
+            Body::Streaming(reader) => reader.read(buf),
+            #[cfg(feature = "compression")]
+            Body::Decoded(reader) => reader.read(buf),
+        }
+    }
+}
+
+/// A `Content-Encoding` the server can undo.
+#[cfg(feature = "compression")]
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub(crate) enum Coding {
+    Gzip,
+    Deflate,
+}
+
+/// The codings listed in a `Content-Encoding` header, in the order they were
+/// applied. `identity` is skipped; `None` if any other one is unknown.
+#[cfg(feature = "compression")]
+pub(crate) fn codings(header: &str) -> Option<Vec<Coding>> {
+    header
+        .split(',')
+        .map(str::trim)
+        .filter(|c| !c.is_empty() && !c.eq_ignore_ascii_case("identity"))
+        .map(|c| match c.to_ascii_lowercase().as_str() {
+            "gzip" | "x-gzip" => Some(Coding::Gzip),
+            "deflate" => Some(Coding::Deflate),
+            _ => None,
+        })
+        .collect()
+}
+
+/// Wraps `reader` so it yields the body with every coding undone, last
+/// applied first.
+#[cfg(feature = "compression")]
+pub(crate) fn decoder<'a>(codings: &[Coding], reader: Box<dyn Read + 'a>) -> Box<dyn Read + 'a> {
+    use flate2::read::{MultiGzDecoder, ZlibDecoder};
+
+    codings
+        .iter()
+        .rev()
+        .fold(reader, |reader, coding| -> Box<dyn Read + 'a> {
+            match coding {
+                Coding::Gzip => Box::new(MultiGzDecoder::new(reader)),
+                Coding::Deflate => Box::new(ZlibDecoder::new(reader)),
+            }
+        })
+}
+
+/// Decodes a buffered body. `Ok(None)` once the output would pass `max`,
+/// which is what stops a small compressed body expanding without bound.
+#[cfg(feature = "compression")]
+pub(crate) fn decode_all(
+    codings: &[Coding],
+    body: &[u8],
+    max: usize,
+) -> io::Result<Option<Vec<u8>>> {
+    let mut out = Vec::new();
+    decoder(codings, Box::new(body))
+        .take(max as u64 + 1)
+        .read_to_end(&mut out)?;
+    Ok((out.len() <= max).then_some(out))
+}
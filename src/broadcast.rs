@@ -0,0 +1,181 @@
+use std::{
+    collections::VecDeque,
+    sync::{
+        atomic::{AtomicBool, AtomicU64, Ordering},
+        mpsc::RecvTimeoutError,
+        Arc, Condvar, Mutex, MutexGuard, Weak,
+    },
+    time::{Duration, Instant},
+};
+
+/// Fans messages out to every live `Subscriber`, for pushing one event to
+/// all open SSE streams or WebSockets. Clones share the same subscribers,
+/// so capture one in each handler that sends or subscribes.
+///
+/// Each subscriber has its own bounded queue. `send` never waits: when a
+/// slow subscriber's queue is full its oldest message is dropped and
+/// counted, and everyone else carries on.
+pub struct Broadcaster<T> {
+    inner: Arc<Inner<T>>,
+}
+
+impl<T> Clone for Broadcaster<T> {
+    fn clone(&self) -> Self {
+        Broadcaster {
+            inner: Arc::clone(&self.inner),
+        }
+    }
+}
+
+struct Inner<T> {
+    subscribers: Mutex<Vec<Weak<Queue<T>>>>,
+    capacity: usize,
+}
+
+struct Queue<T> {
+    items: Mutex<VecDeque<T>>,
+    ready: Condvar,
+    dropped: AtomicU64,
+    /// Set once every `Broadcaster` is gone.
+    closed: AtomicBool,
+}
+
+impl<T> Queue<T> {
+    fn lock(&self) -> MutexGuard<'_, VecDeque<T>> {
+        self.items.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+impl<T> Drop for Inner<T> {
+    fn drop(&mut self) {
+        let subscribers = self
+            .subscribers
+            .get_mut()
+            .unwrap_or_else(|e| e.into_inner());
+        for queue in subscribers.iter().filter_map(Weak::upgrade) {
+            let _items = queue.lock();
+            queue.closed.store(true, Ordering::Relaxed);
+            queue.ready.notify_all();
+        }
+    }
+}
+
+impl<T: Clone + Send> Broadcaster<T> {
+    /// Each subscriber holds at most `capacity` undelivered messages.
+    pub fn new(capacity: usize) -> Broadcaster<T> {
+        Broadcaster {
+            inner: Arc::new(Inner {
+                subscribers: Mutex::new(Vec::new()),
+                capacity: capacity.max(1),
+            }),
+        }
+    }
+
+    pub fn subscribe(&self) -> Subscriber<T> {
+        let queue = Arc::new(Queue {
+            items: Mutex::new(VecDeque::new()),
+            ready: Condvar::new(),
+            dropped: AtomicU64::new(0),
+            closed: AtomicBool::new(false),
+        });
+        self.subscribers().push(Arc::downgrade(&queue));
+        Subscriber { queue }
+    }
+
+    /// Queues `message` for every subscriber and returns how many there
+    /// were. Subscribers that have been dropped are pruned here.
+    pub fn send(&self, message: T) -> usize {
+        let mut subscribers = self.subscribers();
+        let mut delivered = 0;
+        subscribers.retain(|queue| {
+            let Some(queue) = queue.upgrade() else {
+                return false;
+            };
+            let mut items = queue.lock();
+            if items.len() >= self.inner.capacity {
+                items.pop_front();
+                queue.dropped.fetch_add(1, Ordering::Relaxed);
+            }
+            items.push_back(message.clone());
+            queue.ready.notify_one();
+            delivered += 1;
+            true
+        });
+        delivered
+    }
+
+    /// Subscribers still alive, as of the last `send` or `subscribe`.
+    pub fn subscriber_count(&self) -> usize {
+        let mut subscribers = self.subscribers();
+        subscribers.retain(|queue| queue.strong_count() > 0);
+        subscribers.len()
+    }
+
+    fn subscribers(&self) -> MutexGuard<'_, Vec<Weak<Queue<T>>>> {
+        self.inner
+            .subscribers
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+/// One receiving end of a `Broadcaster`. Dropping it unsubscribes.
+pub struct Subscriber<T> {
+    queue: Arc<Queue<T>>,
+}
+
+impl<T> Subscriber<T> {
+    /// Waits for the next message. `None` once every `Broadcaster` is gone
+    /// and the queue is empty.
+    pub fn recv(&self) -> Option<T> {
+        let mut items = self.queue.lock();
+        loop {
+            if let Some(item) = items.pop_front() {
+                return Some(item);
+            }
+            if self.queue.closed.load(Ordering::Relaxed) {
+                return None;
+            }
+            items = self
+                .queue
+                .ready
+                .wait(items)
+                .unwrap_or_else(|e| e.into_inner());
+        }
+    }
+
+    /// Like `recv`, giving up after `timeout` so a handler loop can check
+    /// whether its client is still there.
+    pub fn recv_timeout(&self, timeout: Duration) -> Result<T, RecvTimeoutError> {
+        let deadline = Instant::now() + timeout;
+        let mut items = self.queue.lock();
+        loop {
+            if let Some(item) = items.pop_front() {
+                return Ok(item);
+            }
+            if self.queue.closed.load(Ordering::Relaxed) {
+                return Err(RecvTimeoutError::Disconnected);
+            }
+            let left = deadline.saturating_duration_since(Instant::now());
+            if left.is_zero() {
+                return Err(RecvTimeoutError::Timeout);
+            }
+            items = self
+                .queue
+                .ready
+                .wait_timeout(items, left)
+                .unwrap_or_else(|e| e.into_inner())
+                .0;
+        }
+    }
+
+    pub fn try_recv(&self) -> Option<T> {
+        self.queue.lock().pop_front()
+    }
+
+    /// Messages thrown away because this subscriber fell `capacity`
+    /// behind.
+    pub fn dropped(&self) -> u64 {
+        self.queue.dropped.load(Ordering::Relaxed)
+    }
+}
@@ -0,0 +1,237 @@
+use std::{
+    io::{self, ErrorKind, IoSlice, Read, Write},
+    ops::{Deref, DerefMut},
+    sync::Mutex,
+};
+
+/// How much one read from a connection asks for, and what a fresh buffer
+/// starts with. Most request heads arrive in one read.
+const READ_SIZE: usize = 8 * 1024;
+
+/// Bodies up to this size go out in the same write as their head: one
+/// vectored write where the writer has them, otherwise copied in behind
+/// the head. Larger ones are written on their own after it.
+const COALESCE_LIMIT: usize = 8 * 1024;
+
+/// Buffers that grew past this are freed rather than kept, so one large
+/// upload doesn't pin its memory for good.
+const MAX_CAPACITY: usize = 64 * 1024;
+
+/// Read and write buffers passed from one connection to the next, so a
+/// busy server isn't allocating them for every connection. It keeps at
+/// most `max_buffers` of at most `MAX_CAPACITY` bytes each.
+pub(crate) struct BufferPool {
+    free: Mutex<Vec<Vec<u8>>>,
+    max_buffers: usize,
+}
+
+impl BufferPool {
+    pub(crate) fn new(max_buffers: usize) -> BufferPool {
+        BufferPool {
+            free: Mutex::new(Vec::with_capacity(max_buffers)),
+            max_buffers,
+        }
+    }
+
+    /// An empty buffer, handed back to the pool when the checkout drops.
+    pub(crate) fn checkout(&self) -> Checkout<'_> {
+        Checkout {
+            pool: self,
+            buf: self.take(),
+        }
+    }
+
+    /// An empty buffer for a caller that can't hold a `Checkout`, such as
+    /// the poll loop; it should come back through `give_back`.
+    pub(crate) fn take(&self) -> Vec<u8> {
+        self.free
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .pop()
+            .unwrap_or_else(|| Vec::with_capacity(READ_SIZE))
+    }
+
+    pub(crate) fn give_back(&self, mut buf: Vec<u8>) {
+        // Taken buffers leave an empty Vec behind, which isn't worth keeping.
+        if buf.capacity() == 0 || buf.capacity() > MAX_CAPACITY {
+            return;
+        }
+        buf.clear();
+        let mut free = self.free.lock().unwrap_or_else(|e| e.into_inner());
+        if free.len() < self.max_buffers {
+            free.push(buf);
+        }
+    }
+}
+
+/// A buffer on loan from a `BufferPool`. `mem::take` it to hand it off
+/// for good, as a websocket takeover does.
+pub(crate) struct Checkout<'a> {
+    pool: &'a BufferPool,
+    buf: Vec<u8>,
+}
+
+impl Deref for Checkout<'_> {
+    type Target = Vec<u8>;
+
+    fn deref(&self) -> &Vec<u8> {
+        &self.buf
+    }
+}
+
+impl DerefMut for Checkout<'_> {
+    fn deref_mut(&mut self) -> &mut Vec<u8> {
+        &mut self.buf
+    }
+}
+
+impl Drop for Checkout<'_> {
+    fn drop(&mut self) {
+        self.pool.give_back(std::mem::take(&mut self.buf));
+    }
+}
+
+/// Reads whatever `stream` has, up to `READ_SIZE`, straight onto the end
+/// of `buffer`. Bytes past the current request stay there for the next
+/// one on the connection.
+pub(crate) fn read_more(stream: &mut impl Read, buffer: &mut Vec<u8>) -> io::Result<usize> {
+    let start = buffer.len();
+    buffer.resize(start + READ_SIZE, 0);
+    let read = stream.read(&mut buffer[start..]);
+    buffer.truncate(start + *read.as_ref().unwrap_or(&0));
+    read
+}
+
+/// Writes `head` then `body` and flushes. A body up to `COALESCE_LIMIT`
+/// goes out with the head in one write: with `vectored` a `write_vectored`
+/// call, unless the socket takes less than all of it, otherwise copied
+/// into `head`.
+pub(crate) fn write_joined(
+    w: &mut impl Write,
+    head: &mut Vec<u8>,
+    body: &[u8],
+    vectored: bool,
+) -> io::Result<()> {
+    if body.len() > COALESCE_LIMIT {
+        w.write_all(head)?;
+        w.write_all(body)?;
+    } else if vectored && !body.is_empty() {
+        write_all_vectored(w, &mut [IoSlice::new(head), IoSlice::new(body)])?;
+    } else {
+        head.extend_from_slice(body);
+        w.write_all(head)?;
+    }
+    w.flush()
+}
+
+fn write_all_vectored(w: &mut impl Write, mut bufs: &mut [IoSlice<'_>]) -> io::Result<()> {
+    while !bufs.is_empty() {
+        match w.write_vectored(bufs) {
+            Ok(0) => return Err(ErrorKind::WriteZero.into()),
+            Ok(n) => IoSlice::advance_slices(&mut bufs, n),
+            Err(e) if e.kind() == ErrorKind::Interrupted => {}
+            Err(e) => return Err(e),
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::response::{Response, StatusCode};
+
+    /// Records every call and takes at most `cap` bytes from each.
+    struct Recorder {
+        cap: usize,
+        out: Vec<u8>,
+        writes: usize,
+        vectored: usize,
+    }
+
+    impl Recorder {
+        fn new(cap: usize) -> Recorder {
+            Recorder {
+                cap,
+                out: Vec::new(),
+                writes: 0,
+                vectored: 0,
+            }
+        }
+    }
+
+    impl Write for Recorder {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.writes += 1;
+            let n = buf.len().min(self.cap);
+            self.out.extend_from_slice(&buf[..n]);
+            Ok(n)
+        }
+
+        fn write_vectored(&mut self, bufs: &[IoSlice<'_>]) -> io::Result<usize> {
+            self.vectored += 1;
+            let mut taken = 0;
+            for buf in bufs {
+                let n = buf.len().min(self.cap - taken);
+                self.out.extend_from_slice(&buf[..n]);
+                taken += n;
+            }
+            Ok(taken)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    fn joined(cap: usize, body: &[u8], vectored: bool) -> Recorder {
+        let mut w = Recorder::new(cap);
+        write_joined(&mut w, &mut b"HEAD\r\n\r\n".to_vec(), body, vectored).unwrap();
+        w
+    }
+
+    #[test]
+    fn a_small_response_is_one_vectored_write() {
+        let res = Response::new(StatusCode::Ok).body("hello, world!");
+        let mut w = Recorder::new(usize::MAX);
+        res.write_with(&mut w, &mut Vec::new(), true).unwrap();
+        assert_eq!((w.vectored, w.writes), (1, 0));
+        let out = String::from_utf8(w.out).unwrap();
+        assert!(out.starts_with("HTTP/1.1 200"), "{out}");
+        assert!(out.ends_with("\r\n\r\nhello, world!"), "{out}");
+    }
+
+    #[test]
+    fn without_vectored_writes_a_small_body_is_copied_behind_the_head() {
+        let w = joined(usize::MAX, b"small", false);
+        assert_eq!((w.vectored, w.writes), (0, 1));
+        assert_eq!(w.out, b"HEAD\r\n\r\nsmall");
+    }
+
+    #[test]
+    fn a_large_body_goes_out_after_the_head() {
+        let body = vec![b'x'; COALESCE_LIMIT + 1];
+        for vectored in [true, false] {
+            let w = joined(usize::MAX, &body, vectored);
+            assert_eq!((w.vectored, w.writes), (0, 2), "vectored: {vectored}");
+            assert_eq!(&w.out[..8], b"HEAD\r\n\r\n");
+            assert_eq!(&w.out[8..], &body[..]);
+        }
+    }
+
+    #[test]
+    fn short_writes_are_finished() {
+        let small = b"hello, world!".to_vec();
+        let large: Vec<u8> = (0..COALESCE_LIMIT * 2).map(|i| i as u8).collect();
+        for body in [&small, &large, &Vec::new()] {
+            let mut want = b"HEAD\r\n\r\n".to_vec();
+            want.extend_from_slice(body);
+            for cap in [7, 1] {
+                for vectored in [true, false] {
+                    let w = joined(cap, body, vectored);
+                    assert!(w.out == want, "{} bytes, cap {cap}", body.len());
+                }
+            }
+        }
+    }
+}
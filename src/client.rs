@@ -0,0 +1,414 @@
+//! A small blocking HTTP/1.1 client for calling out to webhooks, built on
+//! the same body framing the server reads requests with. One request per
+//! connection; `https://` needs the `tls` feature.
+
+use crate::{
+    body::{self, Chunked, Conn, Framing},
+    error::ClientError,
+    server::Method,
+    ThreadPool,
+};
+use log::{error, warn};
+use std::{
+    io::{Read, Write},
+    net::{TcpStream, ToSocketAddrs},
+    sync::Arc,
+    thread,
+    time::Duration,
+};
+
+/// Redirects followed before giving up.
+pub const MAX_REDIRECTS: usize = 5;
+
+const MAX_HEAD: usize = 64 * 1024;
+const MAX_BODY: usize = 16 * 1024 * 1024;
+
+#[derive(Clone, Debug)]
+pub struct ClientResponse {
+    pub status: u16,
+    pub headers: Vec<(String, String)>,
+    pub body: Vec<u8>,
+}
+
+impl ClientResponse {
+    /// The first value of a header, matched case-insensitively.
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|(k, _)| k.eq_ignore_ascii_case(name))
+            .map(|(_, v)| v.as_str())
+    }
+
+    /// The body as text, with invalid UTF-8 replaced.
+    pub fn text(&self) -> String {
+        String::from_utf8_lossy(&self.body).into_owned()
+    }
+
+    pub fn is_success(&self) -> bool {
+        (200..300).contains(&self.status)
+    }
+}
+
+/// POSTs `body` to `url` and reads the whole reply. Redirects are followed
+/// up to `MAX_REDIRECTS`: 307 and 308 repeat the POST, the others switch
+/// to a GET without a body, as browsers do. `timeout` applies to
+/// connecting and to each read and write, not to the exchange as a whole.
+pub fn http_post(
+    url: &str,
+    content_type: &str,
+    body: &[u8],
+    timeout: Duration,
+) -> Result<ClientResponse, ClientError> {
+    let mut url = Url::parse(url)?;
+    let mut method = Method::Post;
+    let mut body = Some((content_type, body));
+    for hop in 0..=MAX_REDIRECTS {
+        let res = send(&url, method, body, timeout)?;
+        let location = match res.status {
+            301 | 302 | 303 | 307 | 308 => res.header("Location"),
+            _ => None,
+        };
+        let Some(location) = location else {
+            return Ok(res);
+        };
+        if hop == MAX_REDIRECTS {
+            break;
+        }
+        url = url.join(location)?;
+        if !matches!(res.status, 307 | 308) {
+            method = Method::Get;
+            body = None;
+        }
+    }
+    Err(ClientError::TooManyRedirects(MAX_REDIRECTS))
+}
+
+struct Url {
+    secure: bool,
+    host: String,
+    port: u16,
+    /// Path and query, always starting with `/`.
+    target: String,
+}
+
+impl Url {
+    fn parse(url: &str) -> Result<Url, ClientError> {
+        let invalid = || ClientError::InvalidUrl(String::from(url));
+        let (scheme, rest) = url.split_once("://").ok_or_else(invalid)?;
+        let secure = match scheme.to_ascii_lowercase().as_str() {
+            "http" => false,
+            "https" => true,
+            _ => return Err(ClientError::UnsupportedScheme(String::from(scheme))),
+        };
+        let rest = rest.split('#').next().unwrap_or_default();
+        let split = rest.find(['/', '?']).unwrap_or(rest.len());
+        let (authority, target) = rest.split_at(split);
+        if authority.contains('@') {
+            return Err(invalid());
+        }
+        let (host, port) = match authority.strip_prefix('[') {
+            Some(v6) => {
+                let (host, port) = v6.split_once(']').ok_or_else(invalid)?;
+                (host, port.strip_prefix(':'))
+            }
+            None => match authority.rsplit_once(':') {
+                Some((host, port)) => (host, Some(port)),
+                None => (authority, None),
+            },
+        };
+        let port = match port {
+            Some(port) => port.parse().map_err(|_| invalid())?,
+            None if secure => 443,
+            None => 80,
+        };
+        if host.is_empty() || host.contains([' ', '\r', '\n']) || target.contains([' ', '\r', '\n'])
+        {
+            return Err(invalid());
+        }
+        let target = if target.starts_with('/') {
+            String::from(target)
+        } else {
+            format!("/{target}")
+        };
+        Ok(Url {
+            secure,
+            host: String::from(host),
+            port,
+            target,
+        })
+    }
+
+    /// Where a `Location` header points, relative to this URL.
+    fn join(&self, location: &str) -> Result<Url, ClientError> {
+        if location.contains("://") {
+            return Url::parse(location);
+        }
+        let scheme = if self.secure { "https" } else { "http" };
+        if location.starts_with("//") {
+            return Url::parse(&format!("{scheme}:{location}"));
+        }
+        let target = if location.starts_with('/') {
+            String::from(location)
+        } else {
+            let path = self.target.split('?').next().unwrap_or_default();
+            let dir = &path[..path.rfind('/').map_or(0, |i| i + 1)];
+            format!("{dir}{location}")
+        };
+        Url::parse(&format!("{scheme}://{}{target}", self.authority()))
+    }
+
+    /// `host:port` as the Host header wants it, leaving out a default port.
+    fn authority(&self) -> String {
+        let host = if self.host.contains(':') {
+            format!("[{}]", self.host)
+        } else {
+            self.host.clone()
+        };
+        match (self.secure, self.port) {
+            (false, 80) | (true, 443) => host,
+            (_, port) => format!("{host}:{port}"),
+        }
+    }
+}
+
+trait Connection: Read + Write {}
+
+impl<T: Read + Write> Connection for T {}
+
+fn connect(url: &Url, timeout: Duration) -> Result<Box<dyn Connection>, ClientError> {
+    let mut last_err = None;
+    let mut sock = None;
+    for addr in (url.host.as_str(), url.port).to_socket_addrs()? {
+        match TcpStream::connect_timeout(&addr, timeout) {
+            Ok(s) => {
+                sock = Some(s);
+                break;
+            }
+            Err(e) => last_err = Some(e),
+        }
+    }
+    let Some(sock) = sock else {
+        return Err(last_err
+            .unwrap_or_else(|| std::io::Error::other(format!("{} has no address", url.host)))
+            .into());
+    };
+    sock.set_read_timeout(Some(timeout))?;
+    sock.set_write_timeout(Some(timeout))?;
+    if !url.secure {
+        return Ok(Box::new(sock));
+    }
+    #[cfg(feature = "tls")]
+    return Ok(Box::new(crate::tls::connect(&url.host, sock)?));
+    #[cfg(not(feature = "tls"))]
+    Err(ClientError::UnsupportedScheme(String::from(
+        "https (build with the tls feature)",
+    )))
+}
+
+fn send(
+    url: &Url,
+    method: Method,
+    body: Option<(&str, &[u8])>,
+    timeout: Duration,
+) -> Result<ClientResponse, ClientError> {
+    let mut stream = connect(url, timeout)?;
+    let mut head = format!(
+        "{method} {} HTTP/1.1\r\nHost: {}\r\nUser-Agent: simple_social\r\nConnection: close\r\n",
+        url.target,
+        url.authority()
+    );
+    if let Some((content_type, body)) = body {
+        head.push_str(&format!(
+            "Content-Type: {content_type}\r\nContent-Length: {}\r\n",
+            body.len()
+        ));
+    }
+    head.push_str("\r\n");
+    stream.write_all(head.as_bytes())?;
+    if let Some((_, body)) = body {
+        stream.write_all(body)?;
+    }
+    stream.flush()?;
+    read_response(&mut stream)
+}
+
+fn read_response(stream: &mut impl Read) -> Result<ClientResponse, ClientError> {
+    let invalid = |why: &str| ClientError::InvalidResponse(String::from(why));
+    let mut buffer = Vec::new();
+    loop {
+        let end = loop {
+            if let Some(end) = buffer.windows(4).position(|w| w == b"\r\n\r\n") {
+                break end;
+            }
+            if buffer.len() > MAX_HEAD {
+                return Err(invalid("head too large"));
+            }
+            let mut chunk = [0; 8 * 1024];
+            match stream.read(&mut chunk)? {
+                0 => return Err(invalid("connection closed before the head ended")),
+                n => buffer.extend_from_slice(&chunk[..n]),
+            }
+        };
+        let head = String::from_utf8(buffer.drain(..end + 4).take(end).collect())
+            .map_err(|_| invalid("head is not UTF-8"))?;
+        let mut lines = head.split("\r\n");
+        let mut status_line = lines.next().unwrap_or_default().split(' ');
+        if !status_line.next().is_some_and(|v| v.starts_with("HTTP/1.")) {
+            return Err(invalid("not an HTTP/1.x status line"));
+        }
+        let status: u16 = status_line
+            .next()
+            .and_then(|code| code.parse().ok())
+            .filter(|code| (100..600).contains(code))
+            .ok_or_else(|| invalid("bad status code"))?;
+        // Interim responses come before the real one.
+        if (100..200).contains(&status) {
+            continue;
+        }
+        let headers: Vec<(String, String)> = lines
+            .filter_map(|line| line.split_once(':'))
+            .map(|(k, v)| (String::from(k.trim()), String::from(v.trim())))
+            .collect();
+        let header = |name: &str| {
+            headers
+                .iter()
+                .find(|(k, _)| k.eq_ignore_ascii_case(name))
+                .map(|(_, v)| v.as_str())
+        };
+
+        let framing = if matches!(status, 204 | 304) {
+            Some(Framing::Length(0))
+        } else if header("Transfer-Encoding").is_some_and(|te| te.contains("chunked")) {
+            Some(Framing::Chunked(Chunked::default()))
+        } else {
+            match header("Content-Length") {
+                Some(length) => Some(Framing::Length(
+                    length.parse().map_err(|_| invalid("bad Content-Length"))?,
+                )),
+                None => None,
+            }
+        };
+        let body = match framing {
+            Some(mut framing) => {
+                let mut src = Conn {
+                    stream,
+                    buffer: &mut buffer,
+                };
+                body::read_all(&mut framing, &mut src, MAX_BODY)?
+            }
+            // No framing: the body runs until the server closes.
+            None => {
+                stream
+                    .take((MAX_BODY + 1 - buffer.len().min(MAX_BODY)) as u64)
+                    .read_to_end(&mut buffer)?;
+                (buffer.len() <= MAX_BODY).then_some(buffer)
+            }
+        };
+        let body = body.ok_or_else(|| invalid("body larger than 16MB"))?;
+        return Ok(ClientResponse {
+            status,
+            headers,
+            body,
+        });
+    }
+}
+
+/// Delivers payloads to one URL in the background, retrying with backoff.
+/// Clones share a pool of two delivery threads. Dropping the last clone
+/// waits for deliveries already queued, retries included.
+#[derive(Clone)]
+pub struct Webhook {
+    delivery: Delivery,
+    pool: Arc<ThreadPool>,
+}
+
+#[derive(Clone)]
+struct Delivery {
+    url: String,
+    content_type: String,
+    timeout: Duration,
+    retries: u32,
+    backoff: Duration,
+}
+
+impl Webhook {
+    /// JSON payloads, a ten second timeout, and three retries starting at
+    /// one second apart.
+    pub fn new(url: &str) -> Webhook {
+        Webhook {
+            delivery: Delivery {
+                url: String::from(url),
+                content_type: String::from("application/json"),
+                timeout: Duration::from_secs(10),
+                retries: 3,
+                backoff: Duration::from_secs(1),
+            },
+            pool: Arc::new(ThreadPool::new(2)),
+        }
+    }
+
+    pub fn content_type(mut self, content_type: &str) -> Webhook {
+        self.delivery.content_type = String::from(content_type);
+        self
+    }
+
+    pub fn timeout(mut self, timeout: Duration) -> Webhook {
+        self.delivery.timeout = timeout;
+        self
+    }
+
+    /// Attempts after the first, for connection failures and 408, 429 and
+    /// 5xx replies. Other statuses are final.
+    pub fn retries(mut self, retries: u32) -> Webhook {
+        self.delivery.retries = retries;
+        self
+    }
+
+    /// The pause before the first retry, doubling after each one.
+    pub fn backoff(mut self, backoff: Duration) -> Webhook {
+        self.delivery.backoff = backoff;
+        self
+    }
+
+    /// Queues `body` for delivery and returns straight away. The outcome
+    /// is only logged.
+    pub fn send(&self, body: impl Into<Vec<u8>>) {
+        let (delivery, body) = (self.delivery.clone(), body.into());
+        self.pool.execute(move || delivery.deliver(&body));
+    }
+
+    #[cfg(feature = "serde")]
+    pub fn send_json(&self, value: &impl serde::Serialize) -> Result<(), serde_json::Error> {
+        self.send(serde_json::to_vec(value)?);
+        Ok(())
+    }
+}
+
+impl Delivery {
+    fn deliver(&self, body: &[u8]) {
+        let mut pause = self.backoff;
+        for attempt in 0..=self.retries {
+            if attempt > 0 {
+                thread::sleep(pause);
+                pause = pause.saturating_mul(2);
+            }
+            let why = match http_post(&self.url, &self.content_type, body, self.timeout) {
+                Ok(res) if res.is_success() => return,
+                Ok(res) if matches!(res.status, 408 | 429 | 500..=599) => {
+                    format!("status {}", res.status)
+                }
+                Ok(res) => {
+                    warn!("Webhook {} refused the payload: {}", self.url, res.status);
+                    return;
+                }
+                Err(e) => e.to_string(),
+            };
+            warn!("Webhook {} attempt {} failed: {why}", self.url, attempt + 1);
+        }
+        error!(
+            "Giving up on webhook {} after {} attempts",
+            self.url,
+            self.retries + 1
+        );
+    }
+}
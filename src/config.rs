@@ -0,0 +1,779 @@
+use crate::{
+    access_log::LogFormat,
+    error::ConfigError,
+    server::{BannerMode, ColorPolicy, Server, MAX_BODY_SIZE, MAX_HEAD_SIZE},
+    static_files::StaticDir,
+};
+use std::{
+    env,
+    fs::OpenOptions,
+    io,
+    net::ToSocketAddrs,
+    path::{Path, PathBuf},
+    str::FromStr,
+    thread,
+    time::Duration,
+};
+
+/// Server settings checked as a whole by `build`, so a bad combination fails
+/// at startup with a `ConfigError` rather than somewhere inside `run`.
+/// Routes and anything that takes a callback are still added on the built
+/// `Server`.
+///
+/// Defaults: `127.0.0.1:3000`, one worker per available core, 8 KB heads,
+/// 1 MB bodies, 30 s read and write timeouts, 5 s keep-alive, 100 requests
+/// per connection and a 30 s shutdown deadline.
+#[derive(Clone)]
+pub struct ServerBuilder {
+    addr: String,
+    pool_size: usize,
+    max_head_size: usize,
+    max_body_size: usize,
+    read_timeout: Duration,
+    write_timeout: Duration,
+    keep_alive_timeout: Duration,
+    shutdown_timeout: Duration,
+    max_requests: usize,
+    max_connections: Option<usize>,
+    nodelay: bool,
+    charset: Option<String>,
+    banner: BannerMode,
+    color: ColorPolicy,
+    log_format: Option<LogFormat>,
+    log_path: Option<PathBuf>,
+    static_dir: Option<PathBuf>,
+    also: Vec<String>,
+    mounts: Vec<(String, StaticDir)>,
+    tls: Option<(PathBuf, PathBuf)>,
+}
+
+impl Default for ServerBuilder {
+    fn default() -> Self {
+        ServerBuilder {
+            addr: String::from("127.0.0.1:3000"),
+            pool_size: thread::available_parallelism().map_or(4, |n| n.get()),
+            max_head_size: MAX_HEAD_SIZE,
+            max_body_size: MAX_BODY_SIZE,
+            read_timeout: Duration::from_secs(30),
+            write_timeout: Duration::from_secs(30),
+            keep_alive_timeout: Duration::from_secs(5),
+            shutdown_timeout: Duration::from_secs(30),
+            max_requests: 100,
+            max_connections: None,
+            nodelay: false,
+            charset: Some(String::from("utf-8")),
+            banner: BannerMode::Plain,
+            color: ColorPolicy::Auto,
+            log_format: None,
+            log_path: None,
+            static_dir: None,
+            also: Vec::new(),
+            mounts: Vec::new(),
+            tls: None,
+        }
+    }
+}
+
+impl ServerBuilder {
+    /// The defaults overridden by whichever of these are set:
+    ///
+    /// - `HOST`, default `127.0.0.1`, and `PORT`, default `3000`
+    /// - `POOL_SIZE`, default one per core
+    /// - `MAX_BODY_SIZE` in bytes, default 1 MB
+    /// - `READ_TIMEOUT_SECS`, default 30
+    /// - `LOG_FORMAT`: `common`, `combined` or `json` for an access log on
+    ///   stdout; none by default
+    /// - `STATIC_DIR`: files served under `/` for paths no route claims
+    ///
+    /// A variable that is set but doesn't parse is an error naming it. The
+    /// result is an ordinary builder, so code can override any of it.
+    pub fn from_env() -> Result<ServerBuilder, ConfigError> {
+        ServerBuilder::default().env_overrides()
+    }
+
+    /// Applies the `from_env` variables on top of this builder, so the
+    /// environment can override values from code or a config file. `HOST`
+    /// and `PORT` each replace their half of the current address.
+    pub fn env_overrides(mut self) -> Result<ServerBuilder, ConfigError> {
+        let (current_host, current_port) = split_addr(&self.addr);
+        let host = var("HOST").unwrap_or(current_host);
+        let port: u16 = match parse_var("PORT")? {
+            Some(port) => port,
+            None => current_port.unwrap_or(3000),
+        };
+        self.addr = if host.contains(':') && !host.starts_with('[') {
+            format!("[{host}]:{port}")
+        } else {
+            format!("{host}:{port}")
+        };
+        if let Some(size) = parse_var("POOL_SIZE")? {
+            self.pool_size = size;
+        }
+        if let Some(max) = parse_var("MAX_BODY_SIZE")? {
+            self.max_body_size = max;
+        }
+        if let Some(secs) = parse_var("READ_TIMEOUT_SECS")? {
+            self.read_timeout = Duration::from_secs(secs);
+        }
+        if let Some(format) = var("LOG_FORMAT") {
+            self.log_format = Some(log_format(&format).ok_or_else(|| ConfigError::Env {
+                var: "LOG_FORMAT",
+                message: format!("expected common, combined or json, got {format:?}"),
+            })?);
+        }
+        if let Some(dir) = var("STATIC_DIR") {
+            self.static_dir = Some(PathBuf::from(dir));
+        }
+        Ok(self)
+    }
+
+    pub fn addr(mut self, addr: &str) -> ServerBuilder {
+        self.addr = String::from(addr);
+        self
+    }
+
+    pub fn pool_size(mut self, size: usize) -> ServerBuilder {
+        self.pool_size = size;
+        self
+    }
+
+    pub fn max_head_size(mut self, max: usize) -> ServerBuilder {
+        self.max_head_size = max;
+        self
+    }
+
+    pub fn max_body_size(mut self, max: usize) -> ServerBuilder {
+        self.max_body_size = max;
+        self
+    }
+
+    pub fn read_timeout(mut self, timeout: Duration) -> ServerBuilder {
+        self.read_timeout = timeout;
+        self
+    }
+
+    pub fn write_timeout(mut self, timeout: Duration) -> ServerBuilder {
+        self.write_timeout = timeout;
+        self
+    }
+
+    pub fn keep_alive_timeout(mut self, timeout: Duration) -> ServerBuilder {
+        self.keep_alive_timeout = timeout;
+        self
+    }
+
+    pub fn shutdown_timeout(mut self, timeout: Duration) -> ServerBuilder {
+        self.shutdown_timeout = timeout;
+        self
+    }
+
+    pub fn max_requests_per_connection(mut self, max: usize) -> ServerBuilder {
+        self.max_requests = max;
+        self
+    }
+
+    pub fn max_connections(mut self, max: usize) -> ServerBuilder {
+        self.max_connections = Some(max);
+        self
+    }
+
+    pub fn tcp_nodelay(mut self, nodelay: bool) -> ServerBuilder {
+        self.nodelay = nodelay;
+        self
+    }
+
+    pub fn default_charset(mut self, charset: Option<&str>) -> ServerBuilder {
+        self.charset = charset.map(String::from);
+        self
+    }
+
+    pub fn banner(mut self, mode: BannerMode) -> ServerBuilder {
+        self.banner = mode;
+        self
+    }
+
+    pub fn color(mut self, policy: ColorPolicy) -> ServerBuilder {
+        self.color = policy;
+        self
+    }
+
+    /// Writes an access log line per request to stdout.
+    pub fn access_log(mut self, format: LogFormat) -> ServerBuilder {
+        self.log_format = Some(format);
+        self
+    }
+
+    /// Sends the access log to a file, appending, instead of stdout. Only
+    /// takes effect along with a format.
+    pub fn access_log_file(mut self, path: impl Into<PathBuf>) -> ServerBuilder {
+        self.log_path = Some(path.into());
+        self
+    }
+
+    /// Another address to listen on besides `addr`.
+    pub fn bind_also(mut self, addr: &str) -> ServerBuilder {
+        self.also.push(String::from(addr));
+        self
+    }
+
+    pub fn mount_static(mut self, prefix: &str, dir: StaticDir) -> ServerBuilder {
+        self.mounts.push((String::from(prefix), dir));
+        self
+    }
+
+    /// Serves TLS with the PEM certificate chain and key at these paths,
+    /// loaded by `build`. Needs the `tls` feature.
+    pub fn tls(mut self, cert: impl Into<PathBuf>, key: impl Into<PathBuf>) -> ServerBuilder {
+        self.tls = Some((cert.into(), key.into()));
+        self
+    }
+
+    /// Serves files from `dir` under `/` for paths no route claims.
+    pub fn static_dir(mut self, dir: impl Into<PathBuf>) -> ServerBuilder {
+        self.static_dir = Some(dir.into());
+        self
+    }
+
+    pub fn build(self) -> Result<Server, ConfigError> {
+        self.validate()?;
+        let mut server = Server::new(&self.addr, self.pool_size);
+        server
+            .max_head_size(self.max_head_size)
+            .max_body_size(self.max_body_size)
+            .read_timeout(self.read_timeout)
+            .write_timeout(self.write_timeout)
+            .keep_alive_timeout(self.keep_alive_timeout)
+            .shutdown_timeout(self.shutdown_timeout)
+            .max_requests_per_connection(self.max_requests)
+            .tcp_nodelay(self.nodelay)
+            .default_charset(self.charset.as_deref())
+            .banner(self.banner)
+            .color(self.color);
+        if let Some(max) = self.max_connections {
+            server.max_connections(max);
+        }
+        for addr in &self.also {
+            server.bind_also(addr);
+        }
+        match (self.log_format, &self.log_path) {
+            (Some(format), Some(path)) => {
+                let file = OpenOptions::new()
+                    .create(true)
+                    .append(true)
+                    .open(path)
+                    .map_err(|e| file_error(path, e))?;
+                server.access_log(file, format);
+            }
+            (Some(format), None) => {
+                server.access_log(io::stdout(), format);
+            }
+            (None, _) => {}
+        }
+        if let Some((cert, key)) = &self.tls {
+            #[cfg(feature = "tls")]
+            server
+                .with_tls(cert, key)
+                .map_err(|e| file_error(cert, e))?;
+            #[cfg(not(feature = "tls"))]
+            return Err(ConfigError::File {
+                path: cert.clone(),
+                message: format!("TLS needs the tls feature (key {})", key.display()),
+            });
+        }
+        for (prefix, dir) in self.mounts {
+            server.mount_static(&prefix, dir);
+        }
+        if let Some(dir) = &self.static_dir {
+            server.mount_static("/", StaticDir::new(dir));
+        }
+        Ok(server)
+    }
+
+    fn validate(&self) -> Result<(), ConfigError> {
+        for addr in std::iter::once(&self.addr).chain(&self.also) {
+            let invalid = |reason: String| ConfigError::InvalidAddress {
+                addr: addr.clone(),
+                reason,
+            };
+            match addr.to_socket_addrs().map(|mut addrs| addrs.next()) {
+                Ok(Some(_)) => {}
+                Ok(None) => return Err(invalid(String::from("resolves to no addresses"))),
+                Err(e) => return Err(invalid(e.to_string())),
+            }
+        }
+        if self.pool_size == 0 {
+            return Err(ConfigError::ZeroPoolSize);
+        }
+        for (name, value) in [
+            ("max_head_size", self.max_head_size),
+            ("max_body_size", self.max_body_size),
+            ("max_requests_per_connection", self.max_requests),
+            ("max_connections", self.max_connections.unwrap_or(1)),
+        ] {
+            if value == 0 {
+                return Err(ConfigError::ZeroLimit(name));
+            }
+        }
+        for (name, value) in [
+            ("read_timeout", self.read_timeout),
+            ("write_timeout", self.write_timeout),
+            ("keep_alive_timeout", self.keep_alive_timeout),
+        ] {
+            if value.is_zero() {
+                return Err(ConfigError::ZeroTimeout(name));
+            }
+        }
+        Ok(())
+    }
+}
+
+/// The settings of a TOML file, turned into a `ServerBuilder` that can be
+/// adjusted further before `build`. Every section and key is optional, and
+/// a key this doesn't know is an error rather than silently ignored:
+///
+/// ```toml
+/// [server]
+/// listen = ["127.0.0.1:8080", "[::1]:8080"]
+/// pool_size = 8
+/// max_head_size = 8192
+/// max_body_size = 1048576
+/// max_connections = 512
+/// max_requests_per_connection = 100
+/// read_timeout_secs = 30
+/// write_timeout_secs = 30
+/// keep_alive_timeout_secs = 5
+/// shutdown_timeout_secs = 30
+/// tcp_nodelay = true
+/// default_charset = "utf-8"
+///
+/// [log]
+/// format = "combined"   # common, combined or json
+/// path = "access.log"   # stdout if left out
+///
+/// [tls]
+/// cert = "cert.pem"
+/// key = "key.pem"
+///
+/// [[static]]
+/// prefix = "/assets"
+/// dir = "public"
+/// cache_control = "public, max-age=3600"
+/// ```
+///
+/// Relative paths are taken from the file's directory, and an empty
+/// `default_charset` turns the charset off. The environment
+/// variables `ServerBuilder::from_env` reads override the file.
+#[cfg(feature = "config")]
+#[derive(serde::Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ServerConfig {
+    #[serde(default)]
+    server: ServerSection,
+    log: Option<LogSection>,
+    tls: Option<TlsSection>,
+    #[serde(default, rename = "static")]
+    statics: Vec<StaticSection>,
+}
+
+#[cfg(feature = "config")]
+#[derive(serde::Deserialize, Default)]
+#[serde(deny_unknown_fields)]
+struct ServerSection {
+    listen: Option<Vec<String>>,
+    pool_size: Option<usize>,
+    max_head_size: Option<usize>,
+    max_body_size: Option<usize>,
+    max_connections: Option<usize>,
+    max_requests_per_connection: Option<usize>,
+    read_timeout_secs: Option<u64>,
+    write_timeout_secs: Option<u64>,
+    keep_alive_timeout_secs: Option<u64>,
+    shutdown_timeout_secs: Option<u64>,
+    tcp_nodelay: Option<bool>,
+    default_charset: Option<String>,
+}
+
+#[cfg(feature = "config")]
+#[derive(serde::Deserialize)]
+#[serde(deny_unknown_fields)]
+struct LogSection {
+    format: Option<String>,
+    path: Option<PathBuf>,
+}
+
+#[cfg(feature = "config")]
+#[derive(serde::Deserialize)]
+#[serde(deny_unknown_fields)]
+struct TlsSection {
+    cert: PathBuf,
+    key: PathBuf,
+}
+
+#[cfg(feature = "config")]
+#[derive(serde::Deserialize)]
+#[serde(deny_unknown_fields)]
+struct StaticSection {
+    prefix: String,
+    dir: PathBuf,
+    cache_control: Option<String>,
+}
+
+#[cfg(feature = "config")]
+impl ServerConfig {
+    pub fn from_file(path: impl AsRef<Path>) -> Result<ServerBuilder, ConfigError> {
+        let path = path.as_ref();
+        let text = std::fs::read_to_string(path).map_err(|e| file_error(path, e))?;
+        let base = path.parent().unwrap_or(Path::new(""));
+        ServerConfig::parse(&text)?
+            .into_builder(base)?
+            .env_overrides()
+    }
+
+    /// The same as `from_file` for a config already in memory. Relative
+    /// paths stay relative to the working directory.
+    pub fn from_toml(text: &str) -> Result<ServerBuilder, ConfigError> {
+        ServerConfig::parse(text)?
+            .into_builder(Path::new(""))?
+            .env_overrides()
+    }
+
+    fn parse(text: &str) -> Result<ServerConfig, ConfigError> {
+        serde_path_to_error::deserialize(toml::Deserializer::new(text)).map_err(|e| {
+            let key = e.path().to_string();
+            let inner = e.into_inner();
+            let mut message = String::from(inner.message());
+            if let Some(span) = inner.span() {
+                let line = text[..span.start].matches('\n').count() + 1;
+                message.push_str(&format!(" (line {line})"));
+            }
+            ConfigError::Invalid { key, message }
+        })
+    }
+
+    fn into_builder(self, base: &Path) -> Result<ServerBuilder, ConfigError> {
+        let invalid = |key: &str, message: &str| ConfigError::Invalid {
+            key: String::from(key),
+            message: String::from(message),
+        };
+        let mut builder = ServerBuilder::default();
+        let server = self.server;
+        if let Some(listen) = server.listen {
+            let mut addrs = listen.iter();
+            let first = addrs
+                .next()
+                .ok_or_else(|| invalid("server.listen", "needs at least one address"))?;
+            builder = builder.addr(first);
+            for addr in addrs {
+                builder = builder.bind_also(addr);
+            }
+        }
+        if let Some(size) = server.pool_size {
+            builder = builder.pool_size(size);
+        }
+        if let Some(max) = server.max_head_size {
+            builder = builder.max_head_size(max);
+        }
+        if let Some(max) = server.max_body_size {
+            builder = builder.max_body_size(max);
+        }
+        if let Some(max) = server.max_connections {
+            builder = builder.max_connections(max);
+        }
+        if let Some(max) = server.max_requests_per_connection {
+            builder = builder.max_requests_per_connection(max);
+        }
+        if let Some(secs) = server.read_timeout_secs {
+            builder = builder.read_timeout(Duration::from_secs(secs));
+        }
+        if let Some(secs) = server.write_timeout_secs {
+            builder = builder.write_timeout(Duration::from_secs(secs));
+        }
+        if let Some(secs) = server.keep_alive_timeout_secs {
+            builder = builder.keep_alive_timeout(Duration::from_secs(secs));
+        }
+        if let Some(secs) = server.shutdown_timeout_secs {
+            builder = builder.shutdown_timeout(Duration::from_secs(secs));
+        }
+        if let Some(nodelay) = server.tcp_nodelay {
+            builder = builder.tcp_nodelay(nodelay);
+        }
+        if let Some(charset) = server.default_charset {
+            builder = builder.default_charset(Some(charset.as_str()).filter(|c| !c.is_empty()));
+        }
+        if let Some(log) = self.log {
+            let format = match log.format.as_deref() {
+                Some(name) => log_format(name).ok_or_else(|| {
+                    invalid(
+                        "log.format",
+                        &format!("expected common, combined or json, got {name:?}"),
+                    )
+                })?,
+                None => LogFormat::Common,
+            };
+            builder = builder.access_log(format);
+            if let Some(path) = log.path {
+                builder = builder.access_log_file(base.join(path));
+            }
+        }
+        if let Some(tls) = self.tls {
+            builder = builder.tls(base.join(tls.cert), base.join(tls.key));
+        }
+        for (i, mount) in self.statics.into_iter().enumerate() {
+            if !mount.prefix.starts_with('/') {
+                return Err(invalid(&format!("static[{i}].prefix"), "must start with /"));
+            }
+            let mut dir = StaticDir::new(base.join(mount.dir));
+            if let Some(value) = &mount.cache_control {
+                dir = dir.cache_control(value);
+            }
+            builder = builder.mount_static(&mount.prefix, dir);
+        }
+        Ok(builder)
+    }
+}
+
+fn file_error(path: &Path, e: io::Error) -> ConfigError {
+    ConfigError::File {
+        path: path.to_path_buf(),
+        message: e.to_string(),
+    }
+}
+
+/// The host and port of `host:port` or `[v6]:port`. The host keeps its
+/// brackets off so it can be put back either way.
+fn split_addr(addr: &str) -> (String, Option<u16>) {
+    match addr.rsplit_once(':') {
+        Some((host, port)) if !host.is_empty() && port.parse::<u16>().is_ok() => {
+            let host = host.trim_start_matches('[').trim_end_matches(']');
+            (String::from(host), port.parse().ok())
+        }
+        _ => (String::from(addr), None),
+    }
+}
+
+fn log_format(name: &str) -> Option<LogFormat> {
+    match name.to_ascii_lowercase().as_str() {
+        "common" => Some(LogFormat::Common),
+        "combined" => Some(LogFormat::Combined),
+        "json" => Some(LogFormat::Json),
+        _ => None,
+    }
+}
+
+/// An environment variable that is set and valid Unicode; empty counts as
+/// unset.
+fn var(name: &str) -> Option<String> {
+    env::var(name).ok().filter(|v| !v.trim().is_empty())
+}
+
+fn parse_var<T: FromStr>(name: &'static str) -> Result<Option<T>, ConfigError>
+where
+    T::Err: std::fmt::Display,
+{
+    var(name)
+        .map(|v| {
+            v.trim().parse().map_err(|e: T::Err| ConfigError::Env {
+                var: name,
+                message: e.to_string(),
+            })
+        })
+        .transpose()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Mutex;
+
+    const VARS: [&str; 7] = [
+        "HOST",
+        "PORT",
+        "POOL_SIZE",
+        "MAX_BODY_SIZE",
+        "READ_TIMEOUT_SECS",
+        "LOG_FORMAT",
+        "STATIC_DIR",
+    ];
+
+    /// Runs `f` with exactly `vars` set out of the ones `from_env` reads.
+    /// Tests in one binary share the environment, so they take turns.
+    fn with_env<T>(vars: &[(&str, &str)], f: impl FnOnce() -> T) -> T {
+        static ENV: Mutex<()> = Mutex::new(());
+        let _guard = ENV.lock().unwrap_or_else(|e| e.into_inner());
+        for name in VARS {
+            env::remove_var(name);
+        }
+        for (name, value) in vars {
+            env::set_var(name, value);
+        }
+        let result = f();
+        for name in VARS {
+            env::remove_var(name);
+        }
+        result
+    }
+
+    #[test]
+    fn defaults() {
+        let builder = Server::builder();
+        assert_eq!(builder.addr, "127.0.0.1:3000");
+        let cores = thread::available_parallelism().map_or(4, |n| n.get());
+        assert_eq!(builder.pool_size, cores);
+        assert_eq!(builder.max_head_size, 8 * 1024);
+        assert_eq!(builder.max_body_size, 1024 * 1024);
+        assert_eq!(builder.read_timeout, Duration::from_secs(30));
+        assert_eq!(builder.write_timeout, Duration::from_secs(30));
+        assert_eq!(builder.keep_alive_timeout, Duration::from_secs(5));
+        assert_eq!(builder.shutdown_timeout, Duration::from_secs(30));
+        assert_eq!(builder.max_requests, 100);
+        assert_eq!(builder.max_connections, None);
+        assert_eq!(builder.charset.as_deref(), Some("utf-8"));
+        assert_eq!(builder.banner, BannerMode::Plain);
+        assert_eq!(builder.color, ColorPolicy::Auto);
+        assert!(builder.log_format.is_none() && builder.tls.is_none());
+        assert!(builder.validate().is_ok());
+    }
+
+    #[test]
+    fn nonsense_addresses_are_refused() {
+        for addr in ["localhost", "127.0.0.1:99999", "not an address:80", ""] {
+            let err = Server::builder().addr(addr).build().err().unwrap();
+            assert!(
+                matches!(&err, ConfigError::InvalidAddress { addr: a, .. } if a == addr),
+                "{addr:?}: {err}"
+            );
+        }
+        let err = Server::builder()
+            .bind_also("[::1]:http-alt")
+            .build()
+            .err()
+            .unwrap();
+        assert!(matches!(err, ConfigError::InvalidAddress { .. }), "{err}");
+    }
+
+    #[test]
+    fn a_zero_pool_is_refused() {
+        let err = Server::builder().pool_size(0).build().err().unwrap();
+        assert!(matches!(err, ConfigError::ZeroPoolSize));
+        assert_eq!(err.to_string(), "pool_size must be at least 1");
+    }
+
+    #[test]
+    fn zero_limits_and_timeouts_are_refused() {
+        let limit = |builder: ServerBuilder| match builder.build() {
+            Err(ConfigError::ZeroLimit(name)) => name,
+            other => panic!("{:?}", other.err()),
+        };
+        assert_eq!(limit(Server::builder().max_head_size(0)), "max_head_size");
+        assert_eq!(limit(Server::builder().max_body_size(0)), "max_body_size");
+        assert_eq!(
+            limit(Server::builder().max_requests_per_connection(0)),
+            "max_requests_per_connection"
+        );
+        assert_eq!(
+            limit(Server::builder().max_connections(0)),
+            "max_connections"
+        );
+
+        let err = Server::builder()
+            .write_timeout(Duration::ZERO)
+            .build()
+            .err()
+            .unwrap();
+        assert!(matches!(err, ConfigError::ZeroTimeout("write_timeout")));
+        assert_eq!(err.to_string(), "write_timeout must be greater than zero");
+    }
+
+    #[test]
+    fn port_alone_keeps_the_other_defaults() {
+        let builder = with_env(&[("PORT", "8080")], ServerBuilder::from_env).unwrap();
+        assert_eq!(builder.addr, "127.0.0.1:8080");
+        let defaults = ServerBuilder::default();
+        assert_eq!(builder.pool_size, defaults.pool_size);
+        assert_eq!(builder.max_body_size, defaults.max_body_size);
+        assert_eq!(builder.read_timeout, defaults.read_timeout);
+        assert!(builder.log_format.is_none() && builder.static_dir.is_none());
+
+        // Over a builder, PORT swaps only the port.
+        let builder = with_env(&[("PORT", "8080")], || {
+            Server::builder().addr("0.0.0.0:9000").env_overrides()
+        })
+        .unwrap();
+        assert_eq!(builder.addr, "0.0.0.0:8080");
+        let builder = with_env(&[("HOST", "::1")], ServerBuilder::from_env).unwrap();
+        assert_eq!(builder.addr, "[::1]:3000");
+    }
+
+    #[test]
+    fn every_variable_is_read() {
+        let builder = with_env(
+            &[
+                ("HOST", "0.0.0.0"),
+                ("PORT", "80"),
+                ("POOL_SIZE", "3"),
+                ("MAX_BODY_SIZE", " 2048 "),
+                ("READ_TIMEOUT_SECS", "7"),
+                ("LOG_FORMAT", "JSON"),
+                ("STATIC_DIR", "public"),
+            ],
+            ServerBuilder::from_env,
+        )
+        .unwrap();
+        assert_eq!(builder.addr, "0.0.0.0:80");
+        assert_eq!(builder.pool_size, 3);
+        assert_eq!(builder.max_body_size, 2048);
+        assert_eq!(builder.read_timeout, Duration::from_secs(7));
+        assert!(matches!(builder.log_format, Some(LogFormat::Json)));
+        assert_eq!(builder.static_dir, Some(PathBuf::from("public")));
+
+        // Empty counts as unset.
+        let builder = with_env(
+            &[("PORT", ""), ("POOL_SIZE", "  ")],
+            ServerBuilder::from_env,
+        );
+        assert_eq!(builder.unwrap().addr, "127.0.0.1:3000");
+    }
+
+    #[test]
+    fn malformed_values_name_their_variable() {
+        for (name, value, message) in [
+            ("PORT", "eighty", "PORT: invalid digit found in string"),
+            (
+                "PORT",
+                "70000",
+                "PORT: number too large to fit in target type",
+            ),
+            (
+                "POOL_SIZE",
+                "-1",
+                "POOL_SIZE: invalid digit found in string",
+            ),
+            (
+                "MAX_BODY_SIZE",
+                "1MB",
+                "MAX_BODY_SIZE: invalid digit found in string",
+            ),
+            (
+                "READ_TIMEOUT_SECS",
+                "1.5",
+                "READ_TIMEOUT_SECS: invalid digit found in string",
+            ),
+            (
+                "LOG_FORMAT",
+                "xml",
+                "LOG_FORMAT: expected common, combined or json, got \"xml\"",
+            ),
+        ] {
+            let err = with_env(&[(name, value)], ServerBuilder::from_env)
+                .err()
+                .unwrap();
+            assert!(matches!(&err, ConfigError::Env { var, .. } if *var == name));
+            assert_eq!(err.to_string(), message);
+        }
+        // A zero that parses still fails validation.
+        let err = with_env(&[("POOL_SIZE", "0")], Server::from_env)
+            .err()
+            .unwrap();
+        assert_eq!(err, ConfigError::ZeroPoolSize);
+    }
+}
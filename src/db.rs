@@ -0,0 +1,235 @@
+use crate::error::DbError;
+use log::{info, warn};
+use rusqlite::{Connection, ErrorCode, OpenFlags};
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, Condvar, Mutex, MutexGuard,
+    },
+    thread,
+    time::{Duration, Instant},
+};
+
+/// The `rusqlite` this was built against, so handlers can use its types
+/// without pinning a matching version themselves.
+pub use rusqlite;
+
+/// How many times `with_conn` reruns a closure that hit a locked database.
+const BUSY_RETRIES: u32 = 8;
+
+static NEXT_MEMORY_DB: AtomicU64 = AtomicU64::new(0);
+
+/// A small pool of SQLite connections, shared between handlers. Clones use
+/// the same pool, so open one at startup and capture a clone in each
+/// handler that needs it; a pool as large as the server's thread pool
+/// means no handler ever waits for a connection.
+#[derive(Clone)]
+pub struct Db {
+    pool: Arc<Pool>,
+    timeout: Duration,
+}
+
+struct Pool {
+    idle: Mutex<Vec<Connection>>,
+    returned: Condvar,
+    size: usize,
+}
+
+/// A connection out of the pool, put back when dropped, even when the
+/// closure using it panics.
+struct Lease<'a> {
+    pool: &'a Pool,
+    conn: Option<Connection>,
+}
+
+impl Drop for Lease<'_> {
+    fn drop(&mut self) {
+        if let Some(conn) = self.conn.take() {
+            self.pool.idle().push(conn);
+            self.pool.returned.notify_one();
+        }
+    }
+}
+
+impl Pool {
+    fn idle(&self) -> MutexGuard<'_, Vec<Connection>> {
+        self.idle.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+impl Db {
+    /// Opens `size` connections to the database file at `path`, creating
+    /// it if needed. The file is switched to WAL mode so readers don't
+    /// wait for a writer, and each connection waits up to five seconds on
+    /// a lock before giving up.
+    pub fn open(path: impl AsRef<Path>, size: usize) -> Result<Db, DbError> {
+        let path = path.as_ref();
+        let db = Db::with_connections(size, || {
+            let conn = Connection::open(path)?;
+            conn.busy_timeout(Duration::from_secs(5))?;
+            Ok(conn)
+        })?;
+        db.with_conn(|conn| conn.pragma_update(None, "journal_mode", "WAL"))?;
+        Ok(db)
+    }
+
+    /// A private in-memory database that all of the pool's connections
+    /// share, for tests and demos. It is gone once the last clone is
+    /// dropped.
+    pub fn open_in_memory(size: usize) -> Result<Db, DbError> {
+        let uri = format!(
+            "file:simple_social_{}_{}?mode=memory&cache=shared",
+            std::process::id(),
+            NEXT_MEMORY_DB.fetch_add(1, Ordering::Relaxed)
+        );
+        Db::with_connections(size, || {
+            Connection::open_with_flags(&uri, OpenFlags::default() | OpenFlags::SQLITE_OPEN_URI)
+        })
+    }
+
+    fn with_connections(
+        size: usize,
+        open: impl Fn() -> rusqlite::Result<Connection>,
+    ) -> Result<Db, DbError> {
+        let size = size.max(1);
+        let conns = (0..size).map(|_| open()).collect::<Result<Vec<_>, _>>()?;
+        Ok(Db {
+            pool: Arc::new(Pool {
+                idle: Mutex::new(conns),
+                returned: Condvar::new(),
+                size,
+            }),
+            timeout: Duration::from_secs(30),
+        })
+    }
+
+    /// How long `with_conn` waits for a free connection before failing
+    /// with `PoolTimeout`. Thirty seconds by default.
+    pub fn pool_timeout(mut self, timeout: Duration) -> Db {
+        self.timeout = timeout;
+        self
+    }
+
+    pub fn size(&self) -> usize {
+        self.pool.size
+    }
+
+    /// Runs `f` on a pooled connection, waiting for one to come free. When
+    /// `f` fails because the database is busy or locked it is run again,
+    /// a few times, with a growing pause in between, so it should do its
+    /// writes inside a transaction rather than leave half of them behind.
+    pub fn with_conn<T>(
+        &self,
+        mut f: impl FnMut(&mut Connection) -> rusqlite::Result<T>,
+    ) -> Result<T, DbError> {
+        let mut lease = self.lease()?;
+        let conn = lease.conn.as_mut().expect("a lease holds a connection");
+        let mut attempt = 0;
+        loop {
+            match f(conn) {
+                Err(e) if is_busy(&e) && attempt < BUSY_RETRIES => {
+                    attempt += 1;
+                    thread::sleep(Duration::from_millis(5 << attempt.min(5)));
+                }
+                res => return res.map_err(DbError::from),
+            }
+        }
+    }
+
+    /// Applies the numbered `.sql` files in `dir` that haven't been applied
+    /// yet, in order of the number their name starts with: `001_posts.sql`
+    /// before `002_likes.sql`. Each file runs in its own transaction and
+    /// is recorded in a `schema_migrations` table once it succeeds.
+    /// Returns how many were applied.
+    pub fn migrate(&self, dir: impl AsRef<Path>) -> Result<usize, DbError> {
+        let dir = dir.as_ref();
+        let io_error = |source| DbError::Io {
+            path: dir.to_path_buf(),
+            source,
+        };
+        let mut files: Vec<(u64, PathBuf)> = Vec::new();
+        for entry in fs::read_dir(dir).map_err(io_error)? {
+            let path = entry.map_err(io_error)?.path();
+            if path.extension().is_none_or(|ext| ext != "sql") {
+                continue;
+            }
+            let name = path.file_name().unwrap_or_default().to_string_lossy();
+            let digits = name.chars().take_while(char::is_ascii_digit).count();
+            match name[..digits].parse() {
+                Ok(number) => files.push((number, path)),
+                Err(_) => warn!("Skipping {}: no migration number", path.display()),
+            }
+        }
+        files.sort();
+
+        let mut lease = self.lease()?;
+        let conn = lease.conn.as_mut().expect("a lease holds a connection");
+        conn.execute_batch(
+            "CREATE TABLE IF NOT EXISTS schema_migrations (
+                name TEXT PRIMARY KEY,
+                applied_at TEXT NOT NULL DEFAULT CURRENT_TIMESTAMP
+            )",
+        )?;
+        let mut applied = 0;
+        for (_, path) in files {
+            let name = path.file_name().unwrap_or_default().to_string_lossy();
+            let done: bool = conn.query_row(
+                "SELECT EXISTS (SELECT 1 FROM schema_migrations WHERE name = ?1)",
+                [&name],
+                |row| row.get(0),
+            )?;
+            if done {
+                continue;
+            }
+            let sql = fs::read_to_string(&path).map_err(|source| DbError::Io {
+                path: path.clone(),
+                source,
+            })?;
+            let run = |conn: &mut Connection| {
+                let tx = conn.transaction()?;
+                tx.execute_batch(&sql)?;
+                tx.execute("INSERT INTO schema_migrations (name) VALUES (?1)", [&name])?;
+                tx.commit()
+            };
+            run(conn).map_err(|source| DbError::Migration {
+                path: path.clone(),
+                source,
+            })?;
+            info!("Applied migration {name}");
+            applied += 1;
+        }
+        Ok(applied)
+    }
+
+    fn lease(&self) -> Result<Lease<'_>, DbError> {
+        let deadline = Instant::now() + self.timeout;
+        let mut idle = self.pool.idle();
+        loop {
+            if let Some(conn) = idle.pop() {
+                return Ok(Lease {
+                    pool: &self.pool,
+                    conn: Some(conn),
+                });
+            }
+            let left = deadline.saturating_duration_since(Instant::now());
+            if left.is_zero() {
+                return Err(DbError::PoolTimeout);
+            }
+            idle = self
+                .pool
+                .returned
+                .wait_timeout(idle, left)
+                .unwrap_or_else(|e| e.into_inner())
+                .0;
+        }
+    }
+}
+
+fn is_busy(e: &rusqlite::Error) -> bool {
+    matches!(
+        e.sqlite_error_code(),
+        Some(ErrorCode::DatabaseBusy | ErrorCode::DatabaseLocked)
+    )
+}
@@ -0,0 +1,61 @@
+use crate::{
+    encoding::{fnv1a, percent_decode},
+    mime,
+    request::Request,
+    response::{Response, ResponseBody, StatusCode},
+};
+use std::{collections::HashMap, path::Path};
+
+pub type Asset = (&'static str, &'static [u8], &'static str);
+
+struct EmbeddedFile {
+    body: &'static [u8],
+    content_type: &'static str,
+    etag: String,
+}
+
+pub struct EmbeddedAssets {
+    files: HashMap<String, EmbeddedFile>,
+}
+
+impl EmbeddedAssets {
+    pub fn new(assets: &'static [Asset]) -> EmbeddedAssets {
+        let files = assets
+            .iter()
+            .map(|&(path, body, content_type)| {
+                let content_type = if content_type.is_empty() {
+                    mime::from_path(Path::new(path))
+                } else {
+                    content_type
+                };
+                let file = EmbeddedFile {
+                    body,
+                    content_type,
+                    etag: format!("\"{:016x}\"", fnv1a(body)),
+                };
+                (String::from(path.trim_start_matches('/')), file)
+            })
+            .collect();
+
+        EmbeddedAssets { files }
+    }
+
+    pub(crate) fn serve(&self, req: &Request, rel: &str) -> Option<Response> {
+        let mut path = percent_decode(rel)?.trim_start_matches('/').to_owned();
+        if path.is_empty() || path.ends_with('/') {
+            path.push_str("index.html");
+        }
+        let file = self.files.get(&path)?;
+
+        if req.if_none_match(&file.etag) {
+            return Some(Response::new(StatusCode::NotModified).header("ETag", &file.etag));
+        }
+
+        Some(
+            Response::new(StatusCode::Ok)
+                .header("Content-Type", file.content_type)
+                .header("ETag", &file.etag)
+                .body(ResponseBody::Static(file.body)),
+        )
+    }
+}
@@ -0,0 +1,405 @@
+use crate::{
+    encoding::json_escape,
+    response::{Response, StatusCode},
+};
+use std::{error::Error, fmt, io, path::PathBuf, str::Utf8Error};
+
+#[derive(Debug)]
+pub enum ServerError {
+    /// An address could not be bound, most often because something else is
+    /// already listening on it.
+    Bind {
+        addr: String,
+        source: io::Error,
+    },
+    Io(io::Error),
+    InvalidRoute(String),
+    /// The shutdown deadline passed with connections still being served.
+    Shutdown,
+}
+
+impl fmt::Display for ServerError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ServerError::Bind { addr, source } => write!(f, "failed to bind {addr}: {source}"),
+            ServerError::Io(e) => write!(f, "{e}"),
+            ServerError::InvalidRoute(path) => write!(f, "invalid route path: {path:?}"),
+            ServerError::Shutdown => write!(f, "shutdown timed out with connections still open"),
+        }
+    }
+}
+
+impl Error for ServerError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            ServerError::Bind { source, .. } => Some(source),
+            ServerError::Io(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for ServerError {
+    fn from(e: io::Error) -> Self {
+        ServerError::Io(e)
+    }
+}
+
+/// A server configuration that can't work, caught before anything binds.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ConfigError {
+    /// The address isn't `host:port`, or the host doesn't resolve.
+    InvalidAddress {
+        addr: String,
+        reason: String,
+    },
+    ZeroPoolSize,
+    /// A size limit that would refuse every request, such as a zero
+    /// `max_head_size`.
+    ZeroLimit(&'static str),
+    ZeroTimeout(&'static str),
+    /// An environment variable that is set but doesn't parse.
+    Env {
+        var: &'static str,
+        message: String,
+    },
+    /// A file the configuration names, or the config file itself, could
+    /// not be read or used.
+    File {
+        path: PathBuf,
+        message: String,
+    },
+    /// A config file entry with the wrong type, an unknown key or a value
+    /// that makes no sense. `key` is the dotted path to it.
+    Invalid {
+        key: String,
+        message: String,
+    },
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ConfigError::InvalidAddress { addr, reason } => {
+                write!(f, "invalid address {addr:?}: {reason}")
+            }
+            ConfigError::ZeroPoolSize => write!(f, "pool_size must be at least 1"),
+            ConfigError::ZeroLimit(name) => write!(f, "{name} must be greater than zero"),
+            ConfigError::ZeroTimeout(name) => write!(f, "{name} must be greater than zero"),
+            ConfigError::Env { var, message } => write!(f, "{var}: {message}"),
+            ConfigError::File { path, message } => write!(f, "{}: {message}", path.display()),
+            ConfigError::Invalid { key, message } => write!(f, "{key}: {message}"),
+        }
+    }
+}
+
+impl Error for ConfigError {}
+
+/// Why `RequestBuilder::build` refused to make a request.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum BuildError {
+    /// The path is empty or does not start with `/`.
+    InvalidPath(String),
+    InvalidHeaderName(String),
+    /// The value holds a CR, LF or NUL, which would split the head.
+    InvalidHeaderValue(String),
+    InvalidVersion(String),
+    /// A body sent with `Transfer-Encoding: chunked` that isn't validly
+    /// chunked.
+    InvalidBody,
+}
+
+impl fmt::Display for BuildError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            BuildError::InvalidPath(path) => write!(f, "invalid request path: {path:?}"),
+            BuildError::InvalidHeaderName(name) => write!(f, "invalid header name: {name:?}"),
+            BuildError::InvalidHeaderValue(name) => {
+                write!(f, "invalid value for header {name:?}")
+            }
+            BuildError::InvalidVersion(version) => write!(f, "unsupported version: {version:?}"),
+            BuildError::InvalidBody => f.write_str("body is not validly chunked"),
+        }
+    }
+}
+
+impl Error for BuildError {}
+
+/// Why a template could not be loaded or rendered.
+#[derive(Debug)]
+pub enum TemplateError {
+    Io {
+        path: PathBuf,
+        source: io::Error,
+    },
+    /// An unclosed or malformed tag, with the line it starts on.
+    Syntax {
+        line: usize,
+        message: String,
+    },
+    /// `render` was given no value for this variable.
+    MissingVariable(String),
+}
+
+impl fmt::Display for TemplateError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TemplateError::Io { path, source } => write!(f, "{}: {source}", path.display()),
+            TemplateError::Syntax { line, message } => write!(f, "line {line}: {message}"),
+            TemplateError::MissingVariable(name) => write!(f, "no value for `{name}`"),
+        }
+    }
+}
+
+impl Error for TemplateError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            TemplateError::Io { source, .. } => Some(source),
+            _ => None,
+        }
+    }
+}
+
+/// A failure from `db::Db`. As a handler error it becomes a 500 like any
+/// other.
+#[cfg(feature = "sqlite")]
+#[derive(Debug)]
+pub enum DbError {
+    Sqlite(rusqlite::Error),
+    /// No pooled connection came free within the pool's timeout.
+    PoolTimeout,
+    Io {
+        path: PathBuf,
+        source: io::Error,
+    },
+    /// A migration file failed; it and any after it were not applied.
+    Migration {
+        path: PathBuf,
+        source: rusqlite::Error,
+    },
+}
+
+#[cfg(feature = "sqlite")]
+impl fmt::Display for DbError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DbError::Sqlite(e) => write!(f, "database error: {e}"),
+            DbError::PoolTimeout => write!(f, "timed out waiting for a database connection"),
+            DbError::Io { path, source } => write!(f, "{}: {source}", path.display()),
+            DbError::Migration { path, source } => {
+                write!(f, "migration {} failed: {source}", path.display())
+            }
+        }
+    }
+}
+
+#[cfg(feature = "sqlite")]
+impl Error for DbError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            DbError::Sqlite(e) | DbError::Migration { source: e, .. } => Some(e),
+            DbError::Io { source, .. } => Some(source),
+            DbError::PoolTimeout => None,
+        }
+    }
+}
+
+#[cfg(feature = "sqlite")]
+impl From<rusqlite::Error> for DbError {
+    fn from(e: rusqlite::Error) -> Self {
+        DbError::Sqlite(e)
+    }
+}
+
+/// Why `client::http_post` got no response.
+#[derive(Debug)]
+pub enum ClientError {
+    InvalidUrl(String),
+    /// Only `http://`, and `https://` with the `tls` feature.
+    UnsupportedScheme(String),
+    Io(io::Error),
+    /// The reply was not HTTP/1.x the client could read.
+    InvalidResponse(String),
+    /// Still being redirected after this many hops.
+    TooManyRedirects(usize),
+}
+
+impl fmt::Display for ClientError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ClientError::InvalidUrl(url) => write!(f, "invalid URL: {url}"),
+            ClientError::UnsupportedScheme(scheme) => write!(f, "unsupported scheme: {scheme}"),
+            ClientError::Io(e) => write!(f, "{e}"),
+            ClientError::InvalidResponse(why) => write!(f, "invalid response: {why}"),
+            ClientError::TooManyRedirects(hops) => write!(f, "gave up after {hops} redirects"),
+        }
+    }
+}
+
+impl Error for ClientError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            ClientError::Io(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for ClientError {
+    fn from(e: io::Error) -> Self {
+        ClientError::Io(e)
+    }
+}
+
+/// Why a request body could not be turned into what the handler asked for.
+#[derive(Debug, Clone)]
+pub enum BodyError {
+    /// The Content-Type is missing or not one this parser accepts.
+    UnsupportedMediaType(Option<String>),
+    InvalidUtf8(Utf8Error),
+    /// The body, or one part of it, is over the limit it was parsed with.
+    TooLarge,
+    /// The body does not follow its Content-Type's syntax.
+    Malformed(String),
+    /// The query string is badly encoded or does not match the target type.
+    Query(String),
+    /// The body is not valid JSON or does not match the target type.
+    Json {
+        message: String,
+        line: usize,
+        column: usize,
+    },
+}
+
+impl BodyError {
+    pub fn status(&self) -> StatusCode {
+        match self {
+            BodyError::UnsupportedMediaType(_) => StatusCode::UnsupportedMediaType,
+            BodyError::TooLarge => StatusCode::PayloadTooLarge,
+            _ => StatusCode::BadRequest,
+        }
+    }
+
+    /// A ready-made error response: the matching status and a body like
+    /// `{"error":"missing field `title` at line 1 column 57"}`.
+    pub fn response(&self) -> Response {
+        Response::new(self.status())
+            .header("Content-Type", "application/json")
+            .body(format!(
+                r#"{{"error":"{}"}}"#,
+                json_escape(&self.to_string())
+            ))
+    }
+}
+
+impl fmt::Display for BodyError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            BodyError::UnsupportedMediaType(Some(ct)) => {
+                write!(f, "unsupported content type: {ct}")
+            }
+            BodyError::UnsupportedMediaType(None) => write!(f, "missing content type"),
+            BodyError::InvalidUtf8(e) => write!(f, "body is not valid UTF-8: {e}"),
+            BodyError::TooLarge => write!(f, "body is too large"),
+            BodyError::Malformed(why) => write!(f, "malformed body: {why}"),
+            BodyError::Query(why) => write!(f, "invalid query string: {why}"),
+            BodyError::Json {
+                message,
+                line,
+                column,
+            } => write!(f, "{message} at line {line} column {column}"),
+        }
+    }
+}
+
+impl Error for BodyError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            BodyError::InvalidUtf8(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(feature = "serde")]
+impl From<serde_json::Error> for BodyError {
+    fn from(e: serde_json::Error) -> Self {
+        // serde_json appends the position itself; keep it out of `message`
+        // so Display doesn't repeat it.
+        let full = e.to_string();
+        let suffix = format!(" at line {} column {}", e.line(), e.column());
+        let message = full.strip_suffix(&suffix).unwrap_or(&full).to_string();
+        BodyError::Json {
+            message,
+            line: e.line(),
+            column: e.column(),
+        }
+    }
+}
+
+/// Why `UploadPolicy::validate` turned a file down.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum UploadError {
+    TooLarge {
+        size: usize,
+        max: usize,
+    },
+    /// The declared Content-Type, or the sniffed one when none was given,
+    /// is not on the allowed list.
+    TypeNotAllowed(Option<String>),
+    /// The contents are not what the Content-Type or file name claim.
+    ContentMismatch {
+        claimed: String,
+        sniffed: Option<&'static str>,
+    },
+    ExtensionNotAllowed(Option<String>),
+}
+
+impl UploadError {
+    pub fn status(&self) -> StatusCode {
+        match self {
+            UploadError::TooLarge { .. } => StatusCode::PayloadTooLarge,
+            UploadError::TypeNotAllowed(_) | UploadError::ContentMismatch { .. } => {
+                StatusCode::UnsupportedMediaType
+            }
+            UploadError::ExtensionNotAllowed(_) => StatusCode::BadRequest,
+        }
+    }
+
+    /// The matching status with a `{"error":"..."}` body, as
+    /// `BodyError::response` gives.
+    pub fn response(&self) -> Response {
+        Response::new(self.status())
+            .header("Content-Type", "application/json")
+            .body(format!(
+                r#"{{"error":"{}"}}"#,
+                json_escape(&self.to_string())
+            ))
+    }
+}
+
+impl fmt::Display for UploadError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            UploadError::TooLarge { size, max } => {
+                write!(f, "file is {size} bytes; the limit is {max}")
+            }
+            UploadError::TypeNotAllowed(Some(ct)) => write!(f, "file type not allowed: {ct}"),
+            UploadError::TypeNotAllowed(None) => write!(f, "file type not recognized"),
+            UploadError::ContentMismatch {
+                claimed,
+                sniffed: Some(sniffed),
+            } => write!(f, "file claims to be {claimed} but is {sniffed}"),
+            UploadError::ContentMismatch {
+                claimed,
+                sniffed: None,
+            } => write!(f, "file claims to be {claimed} but is not"),
+            UploadError::ExtensionNotAllowed(Some(ext)) => {
+                write!(f, "file extension not allowed: .{ext}")
+            }
+            UploadError::ExtensionNotAllowed(None) => write!(f, "file has no extension"),
+        }
+    }
+}
+
+impl Error for UploadError {}
@@ -0,0 +1,214 @@
+use crate::{date::DateTime, encoding::html_escape};
+use std::time::SystemTime;
+
+/// A syndication feed, rendered as RSS 2.0 with `to_rss` or Atom 1.0 with
+/// `to_atom`; `Response::rss` and `Response::atom` serve it. Start one
+/// with `FeedBuilder`.
+#[derive(Clone, Debug)]
+pub struct Feed {
+    title: String,
+    link: String,
+    description: Option<String>,
+    author: Option<String>,
+    updated: Option<SystemTime>,
+    items: Vec<FeedItem>,
+}
+
+pub struct FeedBuilder {
+    feed: Feed,
+}
+
+/// One entry. Its id defaults to its link, which suits posts whose URL
+/// never changes.
+#[derive(Clone, Debug)]
+pub struct FeedItem {
+    title: String,
+    link: String,
+    id: Option<String>,
+    published: Option<SystemTime>,
+    content: Option<String>,
+}
+
+impl FeedBuilder {
+    /// `link` is the site the feed belongs to, and doubles as the Atom
+    /// feed id.
+    pub fn new(title: &str, link: &str) -> FeedBuilder {
+        FeedBuilder {
+            feed: Feed {
+                title: String::from(title),
+                link: String::from(link),
+                description: None,
+                author: None,
+                updated: None,
+                items: Vec::new(),
+            },
+        }
+    }
+
+    /// The RSS channel description and Atom subtitle. RSS requires one, so
+    /// the title stands in when there is none.
+    pub fn description(mut self, description: &str) -> FeedBuilder {
+        self.feed.description = Some(String::from(description));
+        self
+    }
+
+    /// Atom needs an author; the title is used when none is set.
+    pub fn author(mut self, name: &str) -> FeedBuilder {
+        self.feed.author = Some(String::from(name));
+        self
+    }
+
+    /// When the feed last changed. Defaults to the newest item's
+    /// `published`, or the time it is rendered if no item has one.
+    pub fn updated(mut self, updated: SystemTime) -> FeedBuilder {
+        self.feed.updated = Some(updated);
+        self
+    }
+
+    /// Items appear in the order they are added, so add the newest first.
+    pub fn item(mut self, item: FeedItem) -> FeedBuilder {
+        self.feed.items.push(item);
+        self
+    }
+
+    pub fn build(self) -> Feed {
+        self.feed
+    }
+}
+
+impl FeedItem {
+    pub fn new(title: &str, link: &str) -> FeedItem {
+        FeedItem {
+            title: String::from(title),
+            link: String::from(link),
+            id: None,
+            published: None,
+            content: None,
+        }
+    }
+
+    /// A permanent identifier when the link may change, such as
+    /// `tag:example.com,2024:post-7`.
+    pub fn id(mut self, id: &str) -> FeedItem {
+        self.id = Some(String::from(id));
+        self
+    }
+
+    pub fn published(mut self, published: SystemTime) -> FeedItem {
+        self.published = Some(published);
+        self
+    }
+
+    /// The body as HTML, escaped into the feed for readers to render.
+    pub fn content(mut self, html: &str) -> FeedItem {
+        self.content = Some(String::from(html));
+        self
+    }
+}
+
+impl Feed {
+    pub fn to_rss(&self) -> String {
+        let mut out = String::from(r#"<?xml version="1.0" encoding="utf-8"?>"#);
+        out.push_str("\n<rss version=\"2.0\"><channel>");
+        element(&mut out, "title", &self.title);
+        element(&mut out, "link", &self.link);
+        element(
+            &mut out,
+            "description",
+            self.description.as_deref().unwrap_or(&self.title),
+        );
+        element(&mut out, "lastBuildDate", &self.updated().http());
+        for item in self.items.iter() {
+            out.push_str("<item>");
+            element(&mut out, "title", &item.title);
+            element(&mut out, "link", &item.link);
+            match &item.id {
+                Some(id) if *id != item.link => {
+                    out.push_str(&format!(
+                        "<guid isPermaLink=\"false\">{}</guid>",
+                        xml_escape(id)
+                    ));
+                }
+                _ => element(&mut out, "guid", &item.link),
+            }
+            if let Some(published) = item.published {
+                element(
+                    &mut out,
+                    "pubDate",
+                    &DateTime::from_system_time(published).http(),
+                );
+            }
+            if let Some(content) = &item.content {
+                element(&mut out, "description", content);
+            }
+            out.push_str("</item>");
+        }
+        out.push_str("</channel></rss>\n");
+        out
+    }
+
+    pub fn to_atom(&self) -> String {
+        let updated = self.updated();
+        let mut out = String::from(r#"<?xml version="1.0" encoding="utf-8"?>"#);
+        out.push_str("\n<feed xmlns=\"http://www.w3.org/2005/Atom\">");
+        element(&mut out, "title", &self.title);
+        if let Some(description) = &self.description {
+            element(&mut out, "subtitle", description);
+        }
+        out.push_str(&format!("<link href=\"{}\"/>", xml_escape(&self.link)));
+        element(&mut out, "id", &self.link);
+        element(&mut out, "updated", &updated.rfc3339());
+        out.push_str("<author>");
+        element(
+            &mut out,
+            "name",
+            self.author.as_deref().unwrap_or(&self.title),
+        );
+        out.push_str("</author>");
+        for item in self.items.iter() {
+            out.push_str("<entry>");
+            element(&mut out, "title", &item.title);
+            out.push_str(&format!("<link href=\"{}\"/>", xml_escape(&item.link)));
+            element(&mut out, "id", item.id.as_deref().unwrap_or(&item.link));
+            let entry_updated = item.published.map(DateTime::from_system_time);
+            element(
+                &mut out,
+                "updated",
+                &entry_updated.as_ref().unwrap_or(&updated).rfc3339(),
+            );
+            if let Some(published) = &entry_updated {
+                element(&mut out, "published", &published.rfc3339());
+            }
+            if let Some(content) = &item.content {
+                out.push_str(&format!(
+                    "<content type=\"html\">{}</content>",
+                    xml_escape(content)
+                ));
+            }
+            out.push_str("</entry>");
+        }
+        out.push_str("</feed>\n");
+        out
+    }
+
+    fn updated(&self) -> DateTime {
+        let updated = self
+            .updated
+            .or_else(|| self.items.iter().filter_map(|i| i.published).max())
+            .unwrap_or_else(SystemTime::now);
+        DateTime::from_system_time(updated)
+    }
+}
+
+fn element(out: &mut String, name: &str, text: &str) {
+    out.push_str(&format!("<{name}>{}</{name}>", xml_escape(text)));
+}
+
+/// Escapes markup and drops the control characters XML 1.0 can't carry at
+/// all, even escaped.
+fn xml_escape(text: &str) -> String {
+    let allowed = |c: &char| {
+        matches!(c, '\t' | '\n' | '\r') || (*c >= ' ' && !matches!(c, '\u{FFFE}' | '\u{FFFF}'))
+    };
+    html_escape(&text.chars().filter(allowed).collect::<String>())
+}
@@ -0,0 +1,163 @@
+use crate::static_files::weak_etag;
+use std::{
+    collections::HashMap,
+    fs, io,
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc, Mutex,
+    },
+    time::{Duration, Instant, SystemTime},
+};
+
+pub(crate) struct CachedFile {
+    pub(crate) body: Arc<[u8]>,
+    pub(crate) etag: String,
+    pub(crate) modified: SystemTime,
+    len: u64,
+}
+
+struct Entry {
+    file: Arc<CachedFile>,
+    checked: Instant,
+    used: u64,
+}
+
+#[derive(Default)]
+struct Inner {
+    entries: HashMap<PathBuf, Entry>,
+    size: usize,
+    tick: u64,
+}
+
+pub struct FileCache {
+    max_bytes: usize,
+    max_file_size: usize,
+    ttl: Option<Duration>,
+    inner: Mutex<Inner>,
+    hits: AtomicUsize,
+}
+
+impl FileCache {
+    pub fn new(max_bytes: usize, max_file_size: usize) -> FileCache {
+        FileCache {
+            max_bytes,
+            max_file_size: max_file_size.min(max_bytes),
+            ttl: None,
+            inner: Mutex::new(Inner::default()),
+            hits: AtomicUsize::new(0),
+        }
+    }
+
+    pub fn ttl(mut self, ttl: Duration) -> FileCache {
+        self.ttl = Some(ttl);
+        self
+    }
+
+    pub fn hits(&self) -> usize {
+        self.hits.load(Ordering::Relaxed)
+    }
+
+    pub fn size(&self) -> usize {
+        self.inner.lock().unwrap().size
+    }
+
+    /// The file at `path`, from memory when it hasn't changed. `None` for
+    /// files over the per-file cap, which are left for the caller to read
+    /// from disk as it would without a cache.
+    pub(crate) fn load(&self, path: &Path) -> io::Result<Option<Arc<CachedFile>>> {
+        let path = path.canonicalize()?;
+
+        if let Some(file) = self.lookup(&path)? {
+            self.hits.fetch_add(1, Ordering::Relaxed);
+            return Ok(Some(file));
+        }
+
+        let meta = fs::metadata(&path)?;
+        if meta.len() > self.max_file_size as u64 {
+            return Ok(None);
+        }
+        let modified = meta.modified()?;
+        let file = Arc::new(CachedFile {
+            body: fs::read(&path)?.into(),
+            etag: weak_etag(meta.len(), modified),
+            modified,
+            len: meta.len(),
+        });
+
+        if file.body.len() <= self.max_file_size {
+            self.insert(path, Arc::clone(&file));
+        }
+        Ok(Some(file))
+    }
+
+    /// The cached entry for `path` if it is still current. The lock is let
+    /// go while the file is stat'ed, so workers don't queue behind the disk.
+    fn lookup(&self, path: &Path) -> io::Result<Option<Arc<CachedFile>>> {
+        let (file, checked) = {
+            let inner = self.inner.lock().unwrap();
+            match inner.entries.get(path) {
+                Some(entry) => (Arc::clone(&entry.file), entry.checked),
+                None => return Ok(None),
+            }
+        };
+
+        let fresh = match self.ttl {
+            Some(ttl) if checked.elapsed() < ttl => true,
+            _ => {
+                let meta = fs::metadata(path)?;
+                meta.modified()? == file.modified && meta.len() == file.len
+            }
+        };
+
+        let mut inner = self.inner.lock().unwrap();
+        inner.tick += 1;
+        let tick = inner.tick;
+        // Another worker may have replaced the entry in the meantime; that
+        // one is left alone.
+        let current = inner
+            .entries
+            .get_mut(path)
+            .filter(|entry| Arc::ptr_eq(&entry.file, &file));
+        match current {
+            Some(entry) if fresh => {
+                entry.checked = Instant::now();
+                entry.used = tick;
+            }
+            Some(_) => {
+                if let Some(entry) = inner.entries.remove(path) {
+                    inner.size -= entry.file.body.len();
+                }
+            }
+            None => {}
+        }
+        Ok(fresh.then_some(file))
+    }
+
+    fn insert(&self, path: PathBuf, file: Arc<CachedFile>) {
+        let mut inner = self.inner.lock().unwrap();
+        inner.tick += 1;
+
+        let entry = Entry {
+            file,
+            checked: Instant::now(),
+            used: inner.tick,
+        };
+        inner.size += entry.file.body.len();
+        if let Some(old) = inner.entries.insert(path, entry) {
+            inner.size -= old.file.body.len();
+        }
+
+        while inner.size > self.max_bytes {
+            let oldest = inner
+                .entries
+                .iter()
+                .min_by_key(|(_, e)| e.used)
+                .map(|(p, _)| p.clone());
+            match oldest.and_then(|p| inner.entries.remove(&p)) {
+                Some(entry) => inner.size -= entry.file.body.len(),
+                None => break,
+            }
+        }
+    }
+}
@@ -0,0 +1,143 @@
+//! One-shot messages carried across a redirect in a signed cookie. A
+//! handler adds them with `Response::flash`, and the next request from the
+//! same browser sees them in `Request::flashes`.
+
+use crate::encoding::{constant_time_eq, hmac_sha1, percent_decode, percent_encode};
+use log::warn;
+use std::{
+    collections::hash_map::RandomState,
+    fmt::Display,
+    hash::{BuildHasher, Hasher},
+};
+
+pub(crate) const COOKIE: &str = "flash";
+
+/// Room left for the payload once the signature and cookie attributes are
+/// in, keeping the whole header under the 4096 bytes browsers promise.
+const MAX_PAYLOAD: usize = 3800;
+
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub enum FlashLevel {
+    Info,
+    Success,
+    Warning,
+    Error,
+}
+
+impl FlashLevel {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            FlashLevel::Info => "info",
+            FlashLevel::Success => "success",
+            FlashLevel::Warning => "warning",
+            FlashLevel::Error => "error",
+        }
+    }
+
+    fn parse(s: &str) -> Option<FlashLevel> {
+        match s {
+            "info" => Some(FlashLevel::Info),
+            "success" => Some(FlashLevel::Success),
+            "warning" => Some(FlashLevel::Warning),
+            "error" => Some(FlashLevel::Error),
+            _ => None,
+        }
+    }
+}
+
+impl Display for FlashLevel {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct Flash {
+    pub level: FlashLevel,
+    pub message: String,
+}
+
+/// A signing key for servers that aren't given one. It lives as long as
+/// the process, so flashes pending across a restart are dropped, which is
+/// harmless for one-shot messages.
+pub(crate) fn random_key() -> Vec<u8> {
+    let state = RandomState::new();
+    (0..4u64)
+        .flat_map(|i| {
+            let mut hasher = state.build_hasher();
+            hasher.write_u64(i);
+            hasher.finish().to_le_bytes()
+        })
+        .collect()
+}
+
+/// The signed cookie value for `flashes`, in order. When they don't fit,
+/// the one that overflows is cut short at a character boundary and any
+/// after it are dropped.
+pub(crate) fn encode(flashes: &[Flash], key: &[u8]) -> String {
+    let mut payload = String::new();
+    for (i, flash) in flashes.iter().enumerate() {
+        let sep = if payload.is_empty() { "" } else { "&" };
+        let start = format!("{sep}{}:", flash.level);
+        let room = MAX_PAYLOAD.saturating_sub(payload.len() + start.len());
+        let message = percent_encode(&flash.message);
+        if message.len() <= room {
+            payload.push_str(&start);
+            payload.push_str(&message);
+            continue;
+        }
+        let mut cut = String::new();
+        for c in flash.message.chars() {
+            let c = percent_encode(c.encode_utf8(&mut [0; 4]));
+            if cut.len() + c.len() > room {
+                break;
+            }
+            cut.push_str(&c);
+        }
+        if !cut.is_empty() {
+            payload.push_str(&start);
+            payload.push_str(&cut);
+        }
+        warn!(
+            "Flash messages exceed {MAX_PAYLOAD} bytes; truncated, {} dropped",
+            flashes.len() - i - 1
+        );
+        break;
+    }
+    format!("{}.{payload}", hex(&hmac_sha1(key, payload.as_bytes())))
+}
+
+/// The flashes in a cookie value, or none if its signature doesn't match.
+pub(crate) fn decode(value: &str, key: &[u8]) -> Vec<Flash> {
+    let Some((mac, payload)) = value.split_once('.') else {
+        return Vec::new();
+    };
+    let expected = hex(&hmac_sha1(key, payload.as_bytes()));
+    if !constant_time_eq(mac.as_bytes(), expected.as_bytes()) {
+        return Vec::new();
+    }
+    payload
+        .split('&')
+        .filter_map(|entry| {
+            let (level, message) = entry.split_once(':')?;
+            Some(Flash {
+                level: FlashLevel::parse(level)?,
+                message: percent_decode(message)?,
+            })
+        })
+        .collect()
+}
+
+/// A `Set-Cookie` value storing `value`, or deleting the cookie when it is
+/// `None`.
+pub(crate) fn set_cookie(value: Option<&str>, secure: bool) -> String {
+    let secure = if secure { "; Secure" } else { "" };
+    match value {
+        Some(value) => format!("{COOKIE}={value}; Path=/; HttpOnly; SameSite=Lax{secure}"),
+        None => format!("{COOKIE}=; Path=/; Max-Age=0; HttpOnly; SameSite=Lax{secure}"),
+    }
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{b:02x}")).collect()
+}
@@ -0,0 +1,178 @@
+use crate::{encoding::percent_decode, error::BodyError, mime, request::Request};
+
+/// A file part of a `multipart/form-data` body.
+#[derive(Clone, Debug)]
+pub struct UploadedFile {
+    pub field_name: String,
+    /// The name the client gave, unsanitized; never use it as a path as-is.
+    /// `safe_file_name` gives one that is.
+    pub file_name: Option<String>,
+    pub content_type: Option<String>,
+    pub data: Vec<u8>,
+}
+
+/// How much a multipart body may carry. Anything over either limit fails
+/// with `BodyError::TooLarge`.
+#[derive(Clone, Copy, Debug)]
+pub struct FormLimits {
+    pub max_file_size: usize,
+    pub max_total_size: usize,
+}
+
+impl Default for FormLimits {
+    fn default() -> Self {
+        FormLimits {
+            max_file_size: 512 * 1024,
+            max_total_size: 1024 * 1024,
+        }
+    }
+}
+
+/// The fields of an urlencoded or multipart form, in the order they came.
+#[derive(Clone, Debug, Default)]
+pub struct Form {
+    fields: Vec<(String, String)>,
+    files: Vec<UploadedFile>,
+}
+
+impl Form {
+    /// The first value of a text field.
+    pub fn get(&self, name: &str) -> Option<&str> {
+        self.fields
+            .iter()
+            .find(|(k, _)| k == name)
+            .map(|(_, v)| v.as_str())
+    }
+
+    pub fn get_all<'a>(&'a self, name: &'a str) -> impl Iterator<Item = &'a str> + 'a {
+        self.fields
+            .iter()
+            .filter(move |(k, _)| k == name)
+            .map(|(_, v)| v.as_str())
+    }
+
+    pub fn fields(&self) -> &[(String, String)] {
+        &self.fields
+    }
+
+    pub fn file(&self, name: &str) -> Option<&UploadedFile> {
+        self.files.iter().find(|f| f.field_name == name)
+    }
+
+    pub fn files(&self) -> &[UploadedFile] {
+        &self.files
+    }
+
+    pub(crate) fn parse(req: &Request, limits: &FormLimits) -> Result<Form, BodyError> {
+        let content_type = req.content_type();
+        match content_type.as_ref() {
+            Some(ct) if ct.is("application/x-www-form-urlencoded") => urlencoded(req.body()),
+            Some(ct) if ct.is("multipart/form-data") => {
+                let boundary = ct
+                    .boundary()
+                    .ok_or_else(|| BodyError::Malformed(String::from("missing boundary")))?;
+                multipart(req.body(), boundary, limits)
+            }
+            _ => Err(BodyError::UnsupportedMediaType(
+                req.header("Content-Type").map(String::from),
+            )),
+        }
+    }
+}
+
+fn urlencoded(body: &[u8]) -> Result<Form, BodyError> {
+    let text = std::str::from_utf8(body).map_err(BodyError::InvalidUtf8)?;
+    let decode = |s: &str| {
+        percent_decode(&s.replace('+', " "))
+            .ok_or_else(|| BodyError::Malformed(format!("bad percent-encoding in {s:?}")))
+    };
+    let mut form = Form::default();
+    for pair in text.split('&').filter(|p| !p.is_empty()) {
+        let (key, value) = pair.split_once('=').unwrap_or((pair, ""));
+        form.fields.push((decode(key)?, decode(value)?));
+    }
+    Ok(form)
+}
+
+fn multipart(body: &[u8], boundary: &str, limits: &FormLimits) -> Result<Form, BodyError> {
+    let malformed = |why: &str| BodyError::Malformed(String::from(why));
+    let delimiter = format!("--{boundary}");
+    let next_part = format!("\r\n--{boundary}");
+
+    let mut pos = find(body, delimiter.as_bytes())
+        .ok_or_else(|| malformed("no opening boundary"))?
+        + delimiter.len();
+    let mut form = Form::default();
+    let mut total = 0;
+    loop {
+        let rest = &body[pos..];
+        if rest.starts_with(b"--") {
+            return Ok(form);
+        }
+        let rest = rest
+            .strip_prefix(b"\r\n")
+            .ok_or_else(|| malformed("boundary not followed by CRLF"))?;
+        let head_end =
+            find(rest, b"\r\n\r\n").ok_or_else(|| malformed("truncated part headers"))?;
+        let head = std::str::from_utf8(&rest[..head_end])
+            .map_err(|_| malformed("part headers are not UTF-8"))?;
+        let data_start = head_end + 4;
+        let data_len = find(&rest[data_start..], next_part.as_bytes())
+            .ok_or_else(|| malformed("truncated part"))?;
+        let data = &rest[data_start..data_start + data_len];
+
+        total += data.len();
+        if total > limits.max_total_size {
+            return Err(BodyError::TooLarge);
+        }
+
+        let mut disposition = None;
+        let mut part_type = None;
+        for line in head.split("\r\n") {
+            let Some((name, value)) = line.split_once(':') else {
+                return Err(malformed("bad part header"));
+            };
+            if name.eq_ignore_ascii_case("Content-Disposition") {
+                disposition = Some(value.trim());
+            } else if name.eq_ignore_ascii_case("Content-Type") {
+                part_type = Some(value.trim());
+            }
+        }
+        let disposition =
+            disposition.ok_or_else(|| malformed("part without Content-Disposition"))?;
+        let field_name =
+            param(disposition, "name").ok_or_else(|| malformed("part without a name"))?;
+
+        match param(disposition, "filename") {
+            Some(file_name) => {
+                if data.len() > limits.max_file_size {
+                    return Err(BodyError::TooLarge);
+                }
+                form.files.push(UploadedFile {
+                    field_name,
+                    file_name: Some(file_name).filter(|n| !n.is_empty()),
+                    content_type: part_type.map(String::from),
+                    data: data.to_vec(),
+                });
+            }
+            None => {
+                let value = std::str::from_utf8(data).map_err(BodyError::InvalidUtf8)?;
+                form.fields.push((field_name, String::from(value)));
+            }
+        }
+
+        // Skip the part's data and the CRLF plus delimiter that ended it.
+        pos = body.len() - rest.len() + data_start + data_len + next_part.len();
+    }
+}
+
+fn param(header: &str, key: &str) -> Option<String> {
+    mime::params_of(header)
+        .into_iter()
+        .find(|(k, _)| k.eq_ignore_ascii_case(key))
+        .map(|(_, v)| v)
+}
+
+fn find(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+    haystack.windows(needle.len()).position(|w| w == needle)
+}
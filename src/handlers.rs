@@ -0,0 +1,38 @@
+use crate::{
+    mime,
+    request::Request,
+    response::{Response, ResponseBody, StatusCode},
+    server::HandlerFunc,
+};
+use std::{
+    fs, io,
+    path::{Path, PathBuf},
+};
+
+/// A handler that answers with the file at `path`, read on every request so
+/// edits show up without a restart. The Content-Type comes from the
+/// extension. A missing file is a 404 rather than a handler error.
+pub fn serve_file(path: impl AsRef<Path>) -> impl HandlerFunc {
+    let path: PathBuf = path.as_ref().to_path_buf();
+    let content_type = mime::from_path(&path);
+    move |_req: &Request| match fs::read(&path) {
+        Ok(body) => Ok(Response::new(StatusCode::Ok)
+            .header("Content-Type", content_type)
+            .body(body)),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(Response::new(StatusCode::NotFound)
+            .header("Content-Type", "text/html")
+            .body(ResponseBody::Static(include_bytes!("../static/404.html")))),
+        Err(e) => Err(format!("reading {}: {e}", path.display()).into()),
+    }
+}
+
+/// A handler that always answers with `body`, never touching the
+/// filesystem.
+pub fn serve_static_str(content_type: &str, body: &'static str) -> impl HandlerFunc {
+    let content_type = String::from(content_type);
+    move |_req: &Request| {
+        Ok(Response::new(StatusCode::Ok)
+            .header("Content-Type", &content_type)
+            .body(ResponseBody::Static(body.as_bytes())))
+    }
+}
@@ -0,0 +1,39 @@
+use crate::{
+    encoding::json_escape,
+    response::{Response, StatusCode},
+};
+
+/// What a health probe reports. `Degraded` still answers 200 so a load
+/// balancer keeps the instance, but the message shows up in the body.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub enum HealthStatus {
+    Healthy,
+    Degraded(String),
+    Unhealthy(String),
+}
+
+impl HealthStatus {
+    pub(crate) fn response(&self) -> Response {
+        let (status, body) = match self {
+            HealthStatus::Healthy => (StatusCode::Ok, String::from(r#"{"status":"healthy"}"#)),
+            HealthStatus::Degraded(message) => (
+                StatusCode::Ok,
+                format!(
+                    r#"{{"status":"degraded","message":"{}"}}"#,
+                    json_escape(message)
+                ),
+            ),
+            HealthStatus::Unhealthy(message) => (
+                StatusCode::ServiceUnavailable,
+                format!(
+                    r#"{{"status":"unhealthy","message":"{}"}}"#,
+                    json_escape(message)
+                ),
+            ),
+        };
+        Response::new(status)
+            .header("Content-Type", "application/json")
+            .header("Cache-Control", "no-store")
+            .body(body)
+    }
+}
@@ -0,0 +1,82 @@
+use crate::request::Request;
+use std::path::{Path, PathBuf};
+
+/// The languages a site is translated into, from `Server::i18n`.
+#[derive(Clone, Debug)]
+pub(crate) struct I18n {
+    default: String,
+    available: Vec<String>,
+}
+
+impl I18n {
+    /// `default` is added to `available` if it isn't there already.
+    pub(crate) fn new(default: &str, available: &[&str]) -> I18n {
+        let mut available: Vec<String> = available.iter().map(|l| String::from(*l)).collect();
+        if !available.iter().any(|l| l.eq_ignore_ascii_case(default)) {
+            available.insert(0, String::from(default));
+        }
+        I18n {
+            default: String::from(default),
+            available,
+        }
+    }
+
+    pub(crate) fn default_lang(&self) -> &str {
+        &self.default
+    }
+
+    /// A `?lang=` naming an available language wins, then Accept-Language,
+    /// then the default.
+    pub(crate) fn resolve(&self, req: &Request) -> String {
+        let by_query = req
+            .query_params()
+            .into_iter()
+            .find(|(k, _)| k == "lang")
+            .and_then(|(_, v)| {
+                let found = self.available.iter().find(|l| l.eq_ignore_ascii_case(&v));
+                found.cloned()
+            });
+        if let Some(lang) = by_query {
+            return lang;
+        }
+        let available: Vec<&str> = self.available.iter().map(String::as_str).collect();
+        let preferred = req.preferred_language(&available);
+        String::from(preferred.unwrap_or(&self.default))
+    }
+
+    fn is_variant(&self, path: &Path) -> bool {
+        let stem = path
+            .file_stem()
+            .and_then(|s| s.to_str())
+            .unwrap_or_default();
+        stem.rsplit_once('.')
+            .is_some_and(|(_, lang)| self.available.iter().any(|l| l.eq_ignore_ascii_case(lang)))
+    }
+}
+
+/// The file to send for `path` in the request's language, and the language
+/// it is in: `index.fr.html` for `index.html` when it exists, otherwise
+/// `path` itself, taken to be in the default language. `None` without
+/// `Server::i18n`, or when `path` already names a variant.
+pub(crate) fn localize(req: &Request, path: &Path) -> Option<(PathBuf, String)> {
+    let (i18n, lang) = (req.i18n.as_ref()?, req.lang()?);
+    if i18n.is_variant(path) {
+        return None;
+    }
+    if lang != i18n.default_lang() {
+        if let Some(variant) = variant(path, lang).filter(|v| v.is_file()) {
+            return Some((variant, String::from(lang)));
+        }
+    }
+    Some((path.to_path_buf(), String::from(i18n.default_lang())))
+}
+
+/// `dir/name.lang.ext` for `dir/name.ext`.
+fn variant(path: &Path, lang: &str) -> Option<PathBuf> {
+    let stem = path.file_stem()?.to_str()?;
+    let name = match path.extension().and_then(|e| e.to_str()) {
+        Some(ext) => format!("{stem}.{lang}.{ext}"),
+        None => format!("{stem}.{lang}"),
+    };
+    Some(path.with_file_name(name))
+}
@@ -1,9 +1,70 @@
+use log::debug;
 use std::{
-    sync::{mpsc, Arc, Mutex},
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        mpsc, Arc, Mutex,
+    },
     thread::{self, JoinHandle},
+    time::{Duration, Instant},
 };
 
+pub mod access_log;
+pub mod assets;
+pub mod auth;
+mod body;
+pub mod broadcast;
+mod buffers;
+pub mod client;
+pub mod config;
+mod date;
+#[cfg(feature = "sqlite")]
+pub mod db;
+#[cfg(feature = "embed")]
+pub mod embedded;
+mod encoding;
+pub mod error;
+pub mod feed;
+pub mod file_cache;
+pub mod flash;
+pub mod form;
+pub mod handlers;
+pub mod health;
+mod i18n;
+pub mod metrics;
+pub mod mime;
+pub mod openapi;
+pub mod pagination;
+#[cfg(feature = "poll")]
+mod poll;
+mod proxy;
+mod query;
+pub mod request;
+#[cfg(feature = "serde")]
+pub mod resource;
+pub mod response;
+pub mod robots;
+mod schedule;
 pub mod server;
+pub mod shutdown;
+#[cfg(feature = "signals")]
+pub mod signals;
+pub mod sitemap;
+pub mod sse;
+pub mod static_files;
+pub mod store;
+mod stream;
+pub mod templates;
+pub mod testing;
+pub mod throttle;
+#[cfg(feature = "tls")]
+mod tls;
+pub mod upload;
+pub mod websocket;
+
+/// Attribute routing: `#[get("/path")]` and friends on handler functions,
+/// collected into a `Router` with `routes![...]`.
+#[cfg(feature = "macros")]
+pub use simple_social_macros::{delete, get, post, put, routes};
 
 enum Message {
     NewJob(Job),
@@ -13,10 +74,17 @@ enum Message {
 pub struct ThreadPool {
     workers: Vec<Worker>,
     sender: mpsc::Sender<Message>,
+    queued: Arc<AtomicUsize>,
 }
 
 impl ThreadPool {
     pub fn new(size: usize) -> ThreadPool {
+        ThreadPool::with_queue(size, Arc::default())
+    }
+
+    /// Like `new`, but counts waiting jobs in `queued` so the caller can
+    /// watch the backlog without holding the pool.
+    pub(crate) fn with_queue(size: usize, queued: Arc<AtomicUsize>) -> ThreadPool {
         let (s, r) = mpsc::channel();
 
         let mut workers = Vec::with_capacity(size);
@@ -24,10 +92,19 @@ impl ThreadPool {
         let receiver = Arc::new(Mutex::new(r));
 
         for id in 0..size {
-            workers.push(Worker::new(id, Arc::clone(&receiver)));
+            workers.push(Worker::new(id, Arc::clone(&receiver), Arc::clone(&queued)));
         }
 
-        ThreadPool { workers, sender: s }
+        ThreadPool {
+            workers,
+            sender: s,
+            queued,
+        }
+    }
+
+    /// Jobs waiting for a free worker.
+    pub fn queued(&self) -> usize {
+        self.queued.load(Ordering::Relaxed)
     }
 
     pub fn execute<F>(&self, f: F)
@@ -35,22 +112,44 @@ impl ThreadPool {
         F: FnOnce() + Send + 'static,
     {
         let job = Message::NewJob(Box::new(f));
+        self.queued.fetch_add(1, Ordering::Relaxed);
         self.sender.send(job).unwrap();
     }
+
+    /// Lets the workers finish every job already queued, waiting at most
+    /// `timeout`. Returns false if some were still busy when time ran out;
+    /// those threads are left to finish on their own.
+    pub fn join_timeout(mut self, timeout: Duration) -> bool {
+        for _ in &self.workers {
+            self.sender.send(Message::Terminate).unwrap();
+        }
+
+        let deadline = Instant::now() + timeout;
+        let mut workers = std::mem::take(&mut self.workers);
+        while Instant::now() < deadline {
+            workers.retain(|w| w.thread.as_ref().is_some_and(|t| !t.is_finished()));
+            if workers.is_empty() {
+                return true;
+            }
+            thread::sleep(Duration::from_millis(10));
+        }
+        workers.retain(|w| w.thread.as_ref().is_some_and(|t| !t.is_finished()));
+        workers.is_empty()
+    }
 }
 
 impl Drop for ThreadPool {
     fn drop(&mut self) {
-        println!("Sending terminete message to all workers");
+        debug!("Sending terminate message to all workers");
 
         for _ in &self.workers {
             self.sender.send(Message::Terminate).unwrap();
         }
 
-        println!("Shutting down all workers");
+        debug!("Shutting down all workers");
 
         for worker in &mut self.workers {
-            println!("Shutting worker {}", worker.id);
+            debug!("Shutting worker {}", worker.id);
 
             if let Some(thread) = worker.thread.take() {
                 thread.join().unwrap();
@@ -67,14 +166,25 @@ struct Worker {
 }
 
 impl Worker {
-    fn new(id: usize, receiver: Arc<Mutex<mpsc::Receiver<Message>>>) -> Worker {
-        let thread = thread::spawn(move || loop {
-            let message = receiver.lock().unwrap().recv().unwrap();
-            match message {
-                Message::NewJob(job) => job(),
-                Message::Terminate => {
-                    println!("Terminating thread {id}");
-                    break;
+    fn new(
+        id: usize,
+        receiver: Arc<Mutex<mpsc::Receiver<Message>>>,
+        queued: Arc<AtomicUsize>,
+    ) -> Worker {
+        let thread = thread::spawn(move || {
+            #[cfg(feature = "tracing")]
+            let _span = tracing::debug_span!("worker", id).entered();
+            loop {
+                let message = receiver.lock().unwrap().recv().unwrap();
+                match message {
+                    Message::NewJob(job) => {
+                        queued.fetch_sub(1, Ordering::Relaxed);
+                        job()
+                    }
+                    Message::Terminate => {
+                        debug!("Terminating thread {id}");
+                        break;
+                    }
                 }
             }
         });
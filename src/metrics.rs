@@ -0,0 +1,585 @@
+use crate::{request::Request, server::Method};
+use std::{
+    sync::{
+        atomic::{AtomicU64, AtomicUsize, Ordering},
+        Arc, Mutex,
+    },
+    time::{Duration, Instant},
+};
+
+const METHODS: [Method; 6] = [
+    Method::Get,
+    Method::Post,
+    Method::Put,
+    Method::Delete,
+    Method::Head,
+    Method::Patch,
+];
+
+/// Upper bounds of the latency histogram buckets; anything slower lands in
+/// a final unbounded bucket.
+const LATENCY_BUCKETS_MS: [u64; 11] = [1, 5, 10, 25, 50, 100, 250, 500, 1000, 2500, 5000];
+
+/// Called with the phase breakdown of every request, see `Server::on_timing`.
+pub(crate) type TimingHook = Arc<dyn Fn(&Request, &PhaseTimings) + Send + Sync>;
+
+/// The route requests that matched nothing are counted under.
+pub const NOT_FOUND_ROUTE: &str = "__not_found__";
+
+#[derive(Default)]
+struct Histogram {
+    buckets: [AtomicU64; LATENCY_BUCKETS_MS.len() + 1],
+    micros: AtomicU64,
+}
+
+impl Histogram {
+    fn record(&self, elapsed: Duration) {
+        let ms = elapsed.as_millis() as u64;
+        let bucket = LATENCY_BUCKETS_MS
+            .iter()
+            .position(|bound| ms < *bound)
+            .unwrap_or(LATENCY_BUCKETS_MS.len());
+        self.buckets[bucket].fetch_add(1, Ordering::Relaxed);
+        self.micros
+            .fetch_add(elapsed.as_micros() as u64, Ordering::Relaxed);
+    }
+
+    fn snapshot(&self) -> (Vec<(Duration, u64)>, Duration) {
+        let buckets = LATENCY_BUCKETS_MS
+            .iter()
+            .map(|ms| Duration::from_millis(*ms))
+            .chain([Duration::MAX])
+            .zip(self.buckets.iter().map(|c| c.load(Ordering::Relaxed)))
+            .collect();
+        let total = Duration::from_micros(self.micros.load(Ordering::Relaxed));
+        (buckets, total)
+    }
+}
+
+/// Counters for one registered route, shared by every handler clone.
+#[derive(Default)]
+pub(crate) struct RouteCounters {
+    hits: AtomicU64,
+    errors: AtomicU64,
+    latency: Histogram,
+}
+
+impl RouteCounters {
+    pub(crate) fn record(&self, status: u16, elapsed: Duration) {
+        self.hits.fetch_add(1, Ordering::Relaxed);
+        if status >= 500 {
+            self.errors.fetch_add(1, Ordering::Relaxed);
+        }
+        self.latency.record(elapsed);
+    }
+
+    fn snapshot(&self, method: Method, route: &str) -> RouteStats {
+        let (latency, latency_total) = self.latency.snapshot();
+        RouteStats {
+            method,
+            route: String::from(route),
+            hits: self.hits.load(Ordering::Relaxed),
+            errors: self.errors.load(Ordering::Relaxed),
+            latency,
+            latency_total,
+        }
+    }
+}
+
+/// Counters shared by every worker. Everything on the request path is a
+/// relaxed atomic so recording a request never takes a lock; the route
+/// table is only locked when routes are registered or read.
+pub(crate) struct Metrics {
+    methods: [AtomicU64; METHODS.len()],
+    statuses: [AtomicU64; 500],
+    latency: Histogram,
+    queue_wait: Histogram,
+    bytes_in: AtomicU64,
+    bytes_out: AtomicU64,
+    routes: Mutex<Vec<(Method, String, Arc<RouteCounters>)>>,
+    not_found: [RouteCounters; METHODS.len()],
+    pub(crate) active: AtomicUsize,
+    pub(crate) idle: AtomicUsize,
+    pub(crate) queued: Arc<AtomicUsize>,
+    pub(crate) job_runs: AtomicU64,
+    pub(crate) job_overruns: AtomicU64,
+    pub(crate) shed: AtomicU64,
+}
+
+impl Default for Metrics {
+    fn default() -> Self {
+        Metrics {
+            methods: Default::default(),
+            statuses: std::array::from_fn(|_| AtomicU64::new(0)),
+            latency: Histogram::default(),
+            queue_wait: Histogram::default(),
+            bytes_in: AtomicU64::new(0),
+            bytes_out: AtomicU64::new(0),
+            routes: Mutex::default(),
+            not_found: Default::default(),
+            active: AtomicUsize::new(0),
+            idle: AtomicUsize::new(0),
+            queued: Arc::default(),
+            job_runs: AtomicU64::new(0),
+            job_overruns: AtomicU64::new(0),
+            shed: AtomicU64::new(0),
+        }
+    }
+}
+
+impl Metrics {
+    pub(crate) fn record(
+        &self,
+        method: Method,
+        status: u16,
+        elapsed: Duration,
+        bytes_in: usize,
+        bytes_out: usize,
+    ) {
+        if let Some(i) = METHODS.iter().position(|m| *m == method) {
+            self.methods[i].fetch_add(1, Ordering::Relaxed);
+        }
+        if let Some(counter) = status
+            .checked_sub(100)
+            .and_then(|i| self.statuses.get(i as usize))
+        {
+            counter.fetch_add(1, Ordering::Relaxed);
+        }
+        self.latency.record(elapsed);
+        self.bytes_in.fetch_add(bytes_in as u64, Ordering::Relaxed);
+        self.bytes_out
+            .fetch_add(bytes_out as u64, Ordering::Relaxed);
+    }
+
+    /// How long an accepted connection sat in the pool queue before a
+    /// worker picked it up.
+    pub(crate) fn record_queue_wait(&self, waited: Duration) {
+        self.queue_wait.record(waited);
+    }
+
+    /// The counters for a registered route pattern. Registering the same
+    /// method and pattern twice hands back the same counters.
+    pub(crate) fn route(&self, method: Method, route: &str) -> Arc<RouteCounters> {
+        let mut routes = self.routes.lock().unwrap();
+        if let Some((_, _, counters)) = routes.iter().find(|(m, r, _)| *m == method && r == route) {
+            return Arc::clone(counters);
+        }
+        let counters = Arc::new(RouteCounters::default());
+        routes.push((method, String::from(route), Arc::clone(&counters)));
+        counters
+    }
+
+    pub(crate) fn not_found(&self, method: Method) -> &RouteCounters {
+        let i = METHODS.iter().position(|m| *m == method).unwrap_or(0);
+        &self.not_found[i]
+    }
+
+    pub(crate) fn route_stats(&self) -> Vec<RouteStats> {
+        let mut stats = self
+            .routes
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|(method, route, counters)| counters.snapshot(*method, route))
+            .collect::<Vec<_>>();
+        stats.extend(
+            METHODS
+                .iter()
+                .zip(self.not_found.iter())
+                .map(|(method, counters)| counters.snapshot(*method, NOT_FOUND_ROUTE))
+                .filter(|s| s.hits > 0),
+        );
+        stats
+    }
+
+    pub(crate) fn snapshot(&self) -> MetricsSnapshot {
+        let load = |c: &AtomicU64| c.load(Ordering::Relaxed);
+
+        let by_method = METHODS
+            .iter()
+            .zip(self.methods.iter())
+            .map(|(m, c)| (*m, load(c)))
+            .collect::<Vec<_>>();
+        let by_status = self
+            .statuses
+            .iter()
+            .enumerate()
+            .map(|(i, c)| (i as u16 + 100, load(c)))
+            .filter(|(_, n)| *n > 0)
+            .collect();
+        let (latency, latency_total) = self.latency.snapshot();
+        let (queue_wait, queue_wait_total) = self.queue_wait.snapshot();
+
+        MetricsSnapshot {
+            requests: by_method.iter().map(|(_, n)| n).sum(),
+            by_method,
+            by_status,
+            latency,
+            latency_total,
+            queue_wait,
+            queue_wait_total,
+            bytes_in: load(&self.bytes_in),
+            bytes_out: load(&self.bytes_out),
+            active_connections: self.active.load(Ordering::Relaxed),
+            idle_connections: self.idle.load(Ordering::Relaxed),
+            queue_depth: self.queued.load(Ordering::Relaxed),
+            job_runs: load(&self.job_runs),
+            job_overruns: load(&self.job_overruns),
+            shed: load(&self.shed),
+            routes: self.route_stats(),
+        }
+    }
+}
+
+/// The smallest bucket bound that at least `p` (0.0 to 1.0) of the
+/// requests finished under.
+fn percentile(latency: &[(Duration, u64)], p: f64) -> Duration {
+    let total: u64 = latency.iter().map(|(_, n)| n).sum();
+    let wanted = (total as f64 * p.clamp(0.0, 1.0)).ceil() as u64;
+    let mut seen = 0;
+    for (bound, n) in latency {
+        seen += n;
+        if seen >= wanted.max(1) {
+            return *bound;
+        }
+    }
+    Duration::ZERO
+}
+
+/// A point-in-time copy of the server's counters.
+#[derive(Clone, Debug, Default)]
+pub struct MetricsSnapshot {
+    pub requests: u64,
+    pub by_method: Vec<(Method, u64)>,
+    /// Only statuses that have been sent at least once.
+    pub by_status: Vec<(u16, u64)>,
+    /// `(upper bound, count)` per bucket; the last bound is `Duration::MAX`.
+    pub latency: Vec<(Duration, u64)>,
+    /// Time spent on all requests together, for working out the mean.
+    pub latency_total: Duration,
+    /// Time connections spent waiting for a free worker, bucketed like
+    /// `latency`.
+    pub queue_wait: Vec<(Duration, u64)>,
+    pub queue_wait_total: Duration,
+    pub bytes_in: u64,
+    pub bytes_out: u64,
+    pub active_connections: usize,
+    /// Of `active_connections`, those between requests waiting for the
+    /// next one.
+    pub idle_connections: usize,
+    pub queue_depth: usize,
+    /// Runs of `Server::schedule` jobs started.
+    pub job_runs: u64,
+    /// Ticks skipped because the job's previous run hadn't finished.
+    pub job_overruns: u64,
+    /// Requests answered 503 by `Server::shed_above`.
+    pub shed: u64,
+    pub routes: Vec<RouteStats>,
+}
+
+/// Counters for one `(method, route pattern)` pair. HEAD requests count
+/// towards the GET route that served them.
+#[derive(Clone, Debug)]
+pub struct RouteStats {
+    pub method: Method,
+    /// The pattern as registered, or `NOT_FOUND_ROUTE`.
+    pub route: String,
+    pub hits: u64,
+    /// Responses with a 5xx status.
+    pub errors: u64,
+    pub latency: Vec<(Duration, u64)>,
+    pub latency_total: Duration,
+}
+
+impl RouteStats {
+    /// Approximate latency percentile, rounded up to a bucket bound.
+    pub fn percentile(&self, p: f64) -> Duration {
+        percentile(&self.latency, p)
+    }
+}
+
+impl MetricsSnapshot {
+    /// Approximate latency percentile over all requests, rounded up to a
+    /// bucket bound.
+    pub fn percentile(&self, p: f64) -> Duration {
+        percentile(&self.latency, p)
+    }
+
+    /// Renders the counters in the Prometheus text exposition format, with
+    /// every metric name prefixed `simple_social_`.
+    pub fn to_prometheus(&self) -> String {
+        let mut out = String::new();
+
+        family(
+            &mut out,
+            "requests_total",
+            "counter",
+            "Requests served, by method.",
+        );
+        for (method, n) in self.by_method.iter() {
+            out.push_str(&format!(
+                "simple_social_requests_total{{method=\"{}\"}} {n}\n",
+                label(&method.to_string())
+            ));
+        }
+
+        family(
+            &mut out,
+            "responses_total",
+            "counter",
+            "Responses sent, by status code.",
+        );
+        for (status, n) in self.by_status.iter() {
+            out.push_str(&format!(
+                "simple_social_responses_total{{status=\"{status}\"}} {n}\n"
+            ));
+        }
+
+        family(
+            &mut out,
+            "request_duration_seconds",
+            "histogram",
+            "Time from reading a request to writing its response.",
+        );
+        histogram(
+            &mut out,
+            "request_duration_seconds",
+            "",
+            &self.latency,
+            self.latency_total,
+        );
+
+        family(
+            &mut out,
+            "queue_wait_seconds",
+            "histogram",
+            "Time connections waited for a free worker.",
+        );
+        histogram(
+            &mut out,
+            "queue_wait_seconds",
+            "",
+            &self.queue_wait,
+            self.queue_wait_total,
+        );
+
+        let scalars = [
+            (
+                "received_bytes_total",
+                "counter",
+                "Request body bytes read.",
+                self.bytes_in,
+            ),
+            (
+                "sent_bytes_total",
+                "counter",
+                "Response body bytes written.",
+                self.bytes_out,
+            ),
+            (
+                "active_connections",
+                "gauge",
+                "Connections currently open.",
+                self.active_connections as u64,
+            ),
+            (
+                "idle_connections",
+                "gauge",
+                "Keep-alive connections waiting for their next request.",
+                self.idle_connections as u64,
+            ),
+            (
+                "queued_connections",
+                "gauge",
+                "Connections waiting for a free worker.",
+                self.queue_depth as u64,
+            ),
+            (
+                "job_runs_total",
+                "counter",
+                "Scheduled job runs started.",
+                self.job_runs,
+            ),
+            (
+                "job_overruns_total",
+                "counter",
+                "Scheduled job ticks skipped because the last run was still going.",
+                self.job_overruns,
+            ),
+            (
+                "shed_total",
+                "counter",
+                "Requests turned away with a 503 while shedding load.",
+                self.shed,
+            ),
+        ];
+        for (name, kind, help, value) in scalars {
+            family(&mut out, name, kind, help);
+            out.push_str(&format!("simple_social_{name} {value}\n"));
+        }
+
+        let route_labels = |r: &RouteStats| {
+            format!(
+                "method=\"{}\",route=\"{}\"",
+                label(&r.method.to_string()),
+                label(&r.route)
+            )
+        };
+        family(
+            &mut out,
+            "route_requests_total",
+            "counter",
+            "Requests served, by route.",
+        );
+        for r in self.routes.iter() {
+            out.push_str(&format!(
+                "simple_social_route_requests_total{{{}}} {}\n",
+                route_labels(r),
+                r.hits
+            ));
+        }
+        family(
+            &mut out,
+            "route_errors_total",
+            "counter",
+            "Responses with a 5xx status, by route.",
+        );
+        for r in self.routes.iter() {
+            out.push_str(&format!(
+                "simple_social_route_errors_total{{{}}} {}\n",
+                route_labels(r),
+                r.errors
+            ));
+        }
+        family(
+            &mut out,
+            "route_duration_seconds",
+            "histogram",
+            "Request latency, by route.",
+        );
+        for r in self.routes.iter() {
+            histogram(
+                &mut out,
+                "route_duration_seconds",
+                &route_labels(r),
+                &r.latency,
+                r.latency_total,
+            );
+        }
+        out
+    }
+}
+
+/// Where one request's time went. Sums to roughly the time from the first
+/// byte arriving to the last byte written, plus the queue wait.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct PhaseTimings {
+    /// Waiting in the pool for a worker. Only the first request on a
+    /// connection waits; later ones report zero.
+    pub queue: Duration,
+    /// Reading and parsing the request, from its first byte.
+    pub parse: Duration,
+    /// Finding the route.
+    pub route: Duration,
+    /// Running the handler or serving the file.
+    pub handle: Duration,
+    /// Writing the response.
+    pub write: Duration,
+}
+
+impl PhaseTimings {
+    pub fn total(&self) -> Duration {
+        self.queue + self.parse + self.route + self.handle + self.write
+    }
+}
+
+/// Laps `Instant`s between phases, or does nothing at all when nobody asked
+/// for timings.
+pub(crate) struct PhaseClock {
+    enabled: bool,
+    last: Option<Instant>,
+    pub(crate) timings: PhaseTimings,
+}
+
+impl PhaseClock {
+    pub(crate) fn new(enabled: bool) -> PhaseClock {
+        PhaseClock {
+            enabled,
+            last: None,
+            timings: PhaseTimings::default(),
+        }
+    }
+
+    /// Starts timing, unless already started.
+    pub(crate) fn start(&mut self) {
+        if self.enabled && self.last.is_none() {
+            self.last = Some(Instant::now());
+        }
+    }
+
+    /// Time since the previous lap or `start`.
+    pub(crate) fn lap(&mut self) -> Duration {
+        match self.last {
+            Some(last) => {
+                let now = Instant::now();
+                self.last = Some(now);
+                now - last
+            }
+            None => Duration::ZERO,
+        }
+    }
+
+    /// Hands back this request's timings and gets ready for the next one.
+    pub(crate) fn finish(&mut self) -> PhaseTimings {
+        self.last = None;
+        std::mem::take(&mut self.timings)
+    }
+}
+
+fn family(out: &mut String, name: &str, kind: &str, help: &str) {
+    out.push_str(&format!("# HELP simple_social_{name} {help}\n"));
+    out.push_str(&format!("# TYPE simple_social_{name} {kind}\n"));
+}
+
+/// Writes the cumulative `_bucket` series plus `_sum` and `_count`.
+/// `labels` is either empty or `key="value",...` without braces.
+fn histogram(
+    out: &mut String,
+    name: &str,
+    labels: &str,
+    latency: &[(Duration, u64)],
+    total: Duration,
+) {
+    let sep = if labels.is_empty() { "" } else { "," };
+    let mut seen = 0;
+    for (bound, n) in latency.iter() {
+        seen += n;
+        let le = if *bound == Duration::MAX {
+            String::from("+Inf")
+        } else {
+            bound.as_secs_f64().to_string()
+        };
+        out.push_str(&format!(
+            "simple_social_{name}_bucket{{{labels}{sep}le=\"{le}\"}} {seen}\n"
+        ));
+    }
+    let braced = if labels.is_empty() {
+        String::new()
+    } else {
+        format!("{{{labels}}}")
+    };
+    out.push_str(&format!(
+        "simple_social_{name}_sum{braced} {}\n",
+        total.as_secs_f64()
+    ));
+    out.push_str(&format!("simple_social_{name}_count{braced} {seen}\n"));
+}
+
+/// Escapes a label value: backslash, double quote and newline are the only
+/// characters the format cares about.
+fn label(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
@@ -0,0 +1,221 @@
+use std::{collections::HashMap, fmt::Display, path::Path};
+
+/// A parsed Content-Type: `type/subtype` plus its parameters. Type, subtype
+/// and parameter names are lowercased; parameter values keep their case.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct MediaType {
+    pub type_: String,
+    pub subtype: String,
+    pub params: HashMap<String, String>,
+}
+
+impl MediaType {
+    /// `None` unless the value has both a type and a subtype. Parameters
+    /// without a `=` are skipped; when a name repeats the first one wins.
+    pub fn parse(value: &str) -> Option<MediaType> {
+        let essence = value.split(';').next().unwrap_or_default();
+        let (type_, subtype) = essence.trim().split_once('/')?;
+        let (type_, subtype) = (type_.trim(), subtype.trim());
+        if type_.is_empty() || subtype.is_empty() {
+            return None;
+        }
+        let mut params = HashMap::new();
+        for (name, value) in params_of(value) {
+            params.entry(name).or_insert(value);
+        }
+        Some(MediaType {
+            type_: type_.to_ascii_lowercase(),
+            subtype: subtype.to_ascii_lowercase(),
+            params,
+        })
+    }
+
+    /// Whether this is `essence`, ignoring case and parameters.
+    pub fn is(&self, essence: &str) -> bool {
+        essence.split_once('/').is_some_and(|(t, s)| {
+            self.type_.eq_ignore_ascii_case(t.trim()) && self.subtype.eq_ignore_ascii_case(s.trim())
+        })
+    }
+
+    pub fn is_type(&self, type_: &str) -> bool {
+        self.type_.eq_ignore_ascii_case(type_)
+    }
+
+    /// The structured syntax suffix, `json` for `application/ld+json`.
+    pub fn suffix(&self) -> Option<&str> {
+        self.subtype.rsplit_once('+').map(|(_, s)| s)
+    }
+
+    pub fn param(&self, name: &str) -> Option<&str> {
+        self.params
+            .get(&name.to_ascii_lowercase())
+            .map(String::as_str)
+    }
+
+    pub fn charset(&self) -> Option<&str> {
+        self.param("charset")
+    }
+
+    pub fn boundary(&self) -> Option<&str> {
+        self.param("boundary").filter(|b| !b.is_empty())
+    }
+
+    pub fn essence(&self) -> String {
+        format!("{}/{}", self.type_, self.subtype)
+    }
+}
+
+impl Display for MediaType {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}/{}", self.type_, self.subtype)?;
+        let mut params: Vec<_> = self.params.iter().collect();
+        params.sort();
+        for (name, value) in params {
+            if value.is_empty() || value.contains([';', '"', '\\', ' ', ',', '=']) {
+                let escaped = value.replace('\\', "\\\\").replace('"', "\\\"");
+                write!(f, "; {name}=\"{escaped}\"")?;
+            } else {
+                write!(f, "; {name}={value}")?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// The `key=value` or `key="value"` parameters after the first `;` of a
+/// header like Content-Type or Content-Disposition. Quoted values may hold
+/// `;` and backslash escapes. Names are lowercased.
+pub(crate) fn params_of(header: &str) -> Vec<(String, String)> {
+    let mut params = Vec::new();
+    let Some((_, mut rest)) = header.split_once(';') else {
+        return params;
+    };
+    loop {
+        rest = rest.trim_start_matches([' ', '\t', ';']);
+        if rest.is_empty() {
+            return params;
+        }
+        let end = rest.find([';', '=']).unwrap_or(rest.len());
+        let name = rest[..end].trim().to_ascii_lowercase();
+        if !rest[end..].starts_with('=') {
+            rest = &rest[end..];
+            continue;
+        }
+        rest = rest[end + 1..].trim_start();
+        let value = if let Some(quoted) = rest.strip_prefix('"') {
+            let mut value = String::new();
+            let mut chars = quoted.char_indices();
+            let mut consumed = quoted.len();
+            while let Some((i, c)) = chars.next() {
+                match c {
+                    '\\' => value.extend(chars.next().map(|(_, c)| c)),
+                    '"' => {
+                        consumed = i + 1;
+                        break;
+                    }
+                    c => value.push(c),
+                }
+            }
+            rest = &quoted[consumed..];
+            let end = rest.find(';').unwrap_or(rest.len());
+            rest = &rest[end..];
+            value
+        } else {
+            let end = rest.find(';').unwrap_or(rest.len());
+            let value = rest[..end].trim().to_string();
+            rest = &rest[end..];
+            value
+        };
+        if !name.is_empty() {
+            params.push((name, value));
+        }
+    }
+}
+
+pub fn from_path(path: &Path) -> &'static str {
+    let ext = path
+        .extension()
+        .and_then(|e| e.to_str())
+        .map(|e| e.to_ascii_lowercase());
+
+    match ext.as_deref() {
+        Some("html") | Some("htm") => "text/html",
+        Some("css") => "text/css",
+        Some("js") | Some("mjs") => "application/javascript",
+        Some("json") => "application/json",
+        Some("txt") => "text/plain",
+        Some("xml") => "application/xml",
+        Some("svg") => "image/svg+xml",
+        Some("png") => "image/png",
+        Some("jpg") | Some("jpeg") => "image/jpeg",
+        Some("gif") => "image/gif",
+        Some("webp") => "image/webp",
+        Some("ico") => "image/x-icon",
+        Some("woff") => "font/woff",
+        Some("woff2") => "font/woff2",
+        Some("mp4") => "video/mp4",
+        Some("webm") => "video/webm",
+        Some("mp3") => "audio/mpeg",
+        Some("ogg") => "audio/ogg",
+        Some("wasm") => "application/wasm",
+        Some("pdf") => "application/pdf",
+        _ => "application/octet-stream",
+    }
+}
+
+/// The `type/subtype` part of a Content-Type, lowercased and without
+/// parameters.
+pub fn essence(content_type: &str) -> String {
+    content_type
+        .split(';')
+        .next()
+        .unwrap_or_default()
+        .trim()
+        .to_ascii_lowercase()
+}
+
+pub fn is_text(content_type: &str) -> bool {
+    let essence = content_type.split(';').next().unwrap_or_default().trim();
+    essence.starts_with("text/")
+        || essence.ends_with("+xml")
+        || matches!(
+            essence,
+            "application/json" | "application/javascript" | "application/xml"
+        )
+}
+
+pub fn with_charset(content_type: &str, charset: Option<&str>) -> String {
+    match charset {
+        Some(charset) if is_text(content_type) && !content_type.contains("charset=") => {
+            format!("{}; charset={}", content_type, charset)
+        }
+        _ => String::from(content_type),
+    }
+}
+
+/// The image type `data` starts with, going by its magic bytes: PNG, JPEG,
+/// GIF or WebP.
+pub fn sniff(data: &[u8]) -> Option<&'static str> {
+    if data.starts_with(b"\x89PNG\r\n\x1a\n") {
+        Some("image/png")
+    } else if data.starts_with(b"\xff\xd8\xff") {
+        Some("image/jpeg")
+    } else if data.starts_with(b"GIF87a") || data.starts_with(b"GIF89a") {
+        Some("image/gif")
+    } else if data.len() >= 12 && data.starts_with(b"RIFF") && &data[8..12] == b"WEBP" {
+        Some("image/webp")
+    } else {
+        None
+    }
+}
+
+/// The usual file extension for a type `sniff` recognizes.
+pub fn extension_for(content_type: &str) -> Option<&'static str> {
+    match essence(content_type).as_str() {
+        "image/png" => Some("png"),
+        "image/jpeg" => Some("jpg"),
+        "image/gif" => Some("gif"),
+        "image/webp" => Some("webp"),
+        _ => None,
+    }
+}
@@ -0,0 +1,125 @@
+use crate::{encoding::json_escape, server::RouteInfo};
+
+/// What `describe` attaches to a route for the OpenAPI document: a summary
+/// and the content types it takes and returns. Body schemas aren't known,
+/// so content entries are left generic.
+#[derive(Clone, Default, PartialEq, Eq, Debug)]
+pub struct RouteDoc {
+    summary: Option<String>,
+    request_type: Option<String>,
+    response_type: Option<String>,
+}
+
+impl RouteDoc {
+    pub fn new(summary: &str) -> RouteDoc {
+        RouteDoc {
+            summary: Some(String::from(summary)),
+            ..RouteDoc::default()
+        }
+    }
+
+    /// The Content-Type of the request body the route expects.
+    pub fn request(mut self, content_type: &str) -> RouteDoc {
+        self.request_type = Some(String::from(content_type));
+        self
+    }
+
+    /// The Content-Type the route answers with.
+    pub fn response(mut self, content_type: &str) -> RouteDoc {
+        self.response_type = Some(String::from(content_type));
+        self
+    }
+}
+
+/// An OpenAPI 3.0 document for every non-static route. `:name` and
+/// `*name` segments become `{name}` with a required string path parameter.
+pub(crate) fn document(title: &str, version: &str, routes: &[RouteInfo]) -> String {
+    let mut paths: Vec<(String, Vec<String>)> = Vec::new();
+    for route in routes.iter().filter(|r| !r.is_static) {
+        let (path, params) = template(&route.pattern);
+        let operation = operation(route, &params);
+        match paths.iter_mut().find(|(p, _)| *p == path) {
+            Some((_, operations)) => operations.push(operation),
+            None => paths.push((path, vec![operation])),
+        }
+    }
+    let paths = paths
+        .iter()
+        .map(|(path, operations)| format!("\"{}\":{{{}}}", json_escape(path), operations.join(",")))
+        .collect::<Vec<_>>();
+    format!(
+        r#"{{"openapi":"3.0.3","info":{{"title":"{}","version":"{}"}},"paths":{{{}}}}}"#,
+        json_escape(title),
+        json_escape(version),
+        paths.join(",")
+    )
+}
+
+/// `/posts/:id` as `/posts/{id}`, and the parameter names in order.
+fn template(pattern: &str) -> (String, Vec<&str>) {
+    let mut params = Vec::new();
+    let segments = pattern
+        .split('/')
+        .map(|segment| match segment.strip_prefix([':', '*']) {
+            Some(name) if !name.is_empty() => {
+                params.push(name);
+                format!("{{{name}}}")
+            }
+            _ => String::from(segment),
+        })
+        .collect::<Vec<_>>();
+    (segments.join("/"), params)
+}
+
+fn operation(route: &RouteInfo, params: &[&str]) -> String {
+    let method = route.method.to_string().to_ascii_lowercase();
+    let doc = route.doc.clone().unwrap_or_default();
+    let content = |content_type: &Option<String>| match content_type {
+        Some(ct) => format!(r#","content":{{"{}":{{}}}}"#, json_escape(ct)),
+        None => String::new(),
+    };
+
+    let mut fields = vec![format!(
+        r#""operationId":"{}""#,
+        json_escape(&operation_id(&method, &route.pattern))
+    )];
+    if let Some(summary) = &doc.summary {
+        fields.push(format!(r#""summary":"{}""#, json_escape(summary)));
+    }
+    if !params.is_empty() {
+        let params = params
+            .iter()
+            .map(|name| {
+                format!(
+                    r#"{{"name":"{}","in":"path","required":true,"schema":{{"type":"string"}}}}"#,
+                    json_escape(name)
+                )
+            })
+            .collect::<Vec<_>>();
+        fields.push(format!(r#""parameters":[{}]"#, params.join(",")));
+    }
+    if doc.request_type.is_some() {
+        fields.push(format!(
+            r#""requestBody":{{"required":true{}}}"#,
+            content(&doc.request_type)
+        ));
+    }
+    fields.push(format!(
+        r#""responses":{{"200":{{"description":"OK"{}}},"default":{{"description":"Error"}}}}"#,
+        content(&doc.response_type)
+    ));
+    format!("\"{method}\":{{{}}}", fields.join(","))
+}
+
+/// `get_user_profile` for `GET /user/profile`, `get_root` for `GET /`.
+fn operation_id(method: &str, pattern: &str) -> String {
+    let words = pattern
+        .split(|c: char| !c.is_ascii_alphanumeric())
+        .filter(|w| !w.is_empty())
+        .collect::<Vec<_>>();
+    if words.is_empty() {
+        format!("{method}_root")
+    } else {
+        format!("{method}_{}", words.join("_"))
+    }
+}
@@ -0,0 +1,115 @@
+use crate::{error::BodyError, query, request::Request, response::Response};
+
+/// What `Pagination::from_request` falls back to and clamps against.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct PageDefaults {
+    /// Used when the request has no `per_page`.
+    pub per_page: u64,
+    /// Larger `per_page` values are cut down to this.
+    pub max_per_page: u64,
+}
+
+impl Default for PageDefaults {
+    fn default() -> Self {
+        PageDefaults {
+            per_page: 20,
+            max_per_page: 100,
+        }
+    }
+}
+
+/// The `page` and `per_page` of a list request, counting pages from 1.
+/// Pages past the end are allowed and simply empty, so a client that
+/// overshoots still gets `Link` headers back to the real pages.
+#[derive(Clone, Debug)]
+pub struct Pagination {
+    page: u64,
+    per_page: u64,
+    path: String,
+    /// The rest of the query string, as sent, to carry into links.
+    others: Vec<String>,
+}
+
+impl Pagination {
+    /// Reads `page` and `per_page` from the query string. Zero is taken as
+    /// 1 and `per_page` is clamped to `max_per_page`; anything that isn't
+    /// a whole number is an error, whose `response` is a 400.
+    pub fn from_request(req: &Request, defaults: PageDefaults) -> Result<Pagination, BodyError> {
+        let raw = req.query().unwrap_or_default();
+        let params = query::parse(raw)
+            .ok_or_else(|| BodyError::Query(String::from("bad percent-encoding")))?;
+        let number = |name: &str| -> Result<Option<u64>, BodyError> {
+            let Some((_, values)) = params.iter().find(|(k, _)| k == name) else {
+                return Ok(None);
+            };
+            let value = values.last().cloned().flatten().unwrap_or_default();
+            value.trim().parse().map(Some).map_err(|_| {
+                BodyError::Query(format!("{name} must be a whole number, not {value:?}"))
+            })
+        };
+        let page = number("page")?.unwrap_or(1).max(1);
+        let per_page = number("per_page")?
+            .unwrap_or(defaults.per_page)
+            .clamp(1, defaults.max_per_page.max(1));
+        let others = raw
+            .split('&')
+            .filter(|pair| {
+                let key = pair.split_once('=').map_or(*pair, |(k, _)| k);
+                !pair.is_empty() && !matches!(key, "page" | "per_page")
+            })
+            .map(String::from)
+            .collect();
+        Ok(Pagination {
+            page,
+            per_page,
+            path: String::from(req.path()),
+            others,
+        })
+    }
+
+    pub fn page(&self) -> u64 {
+        self.page
+    }
+
+    pub fn per_page(&self) -> u64 {
+        self.per_page
+    }
+
+    /// How many items come before this page.
+    pub fn offset(&self) -> u64 {
+        (self.page - 1).saturating_mul(self.per_page)
+    }
+
+    pub fn limit(&self) -> u64 {
+        self.per_page
+    }
+
+    /// Adds `X-Total-Count` and a `Link` header with `first`, `last`, and
+    /// `prev` and `next` where they exist, for a list of `total` items.
+    /// The links keep the request's path and other query parameters.
+    pub fn apply(&self, total: u64, response: Response) -> Response {
+        let last = total.div_ceil(self.per_page).max(1);
+        let mut links = vec![(1, "first")];
+        if self.page > 1 {
+            links.push((self.page.min(last + 1) - 1, "prev"));
+        }
+        if self.page < last {
+            links.push((self.page + 1, "next"));
+        }
+        links.push((last, "last"));
+        let links = links
+            .into_iter()
+            .map(|(page, rel)| format!("<{}>; rel=\"{rel}\"", self.url(page)))
+            .collect::<Vec<_>>();
+        response
+            .header("X-Total-Count", &total.to_string())
+            .header("Link", &links.join(", "))
+    }
+
+    fn url(&self, page: u64) -> String {
+        let mut query = self.others.clone();
+        query.push(format!("page={page}"));
+        query.push(format!("per_page={}", self.per_page));
+        format!("{}?{}", self.path, query.join("&"))
+    }
+}
@@ -0,0 +1,327 @@
+//! The accept loop under the `poll` feature. One thread accepts every
+//! listener's connections and waits on all of them until a whole request
+//! head has arrived, so a slow or idle client costs a socket rather than a
+//! worker. Workers get a connection with its head already read, and hand
+//! it back here once a keep-alive response is written and nothing else is
+//! buffered.
+//!
+//! TLS connections skip the loop: their handshake only runs blocking, so
+//! they go straight to a worker, as without the feature.
+
+use crate::{
+    buffers,
+    server::{AcceptError, ConnectionGuard, Context, Progress, MIN_ACCEPT_BACKOFF},
+    shutdown::Shutdown,
+    stream::{Accepted, Listener, PollSocket},
+    ThreadPool,
+};
+use log::warn;
+use polling::{Events, Poller};
+use std::{
+    collections::{BTreeSet, HashMap},
+    io::{self, ErrorKind},
+    mem,
+    sync::{Arc, Mutex},
+    thread,
+    time::Instant,
+};
+
+type Returned = (ConnectionGuard, PollSocket, usize);
+
+/// The poller, and the keep-alive connections workers have handed back
+/// since the loop last looked.
+pub(crate) struct Parking {
+    poller: Poller,
+    /// `None` once the loop has stopped, so late ones are closed instead.
+    returned: Mutex<Option<Vec<Returned>>>,
+}
+
+impl Parking {
+    pub(crate) fn new() -> io::Result<Parking> {
+        Ok(Parking {
+            poller: Poller::new()?,
+            returned: Mutex::new(Some(Vec::new())),
+        })
+    }
+
+    /// Gives a connection back to the loop to wait for its next request.
+    /// `served` is how many it has had.
+    pub(crate) fn hand_back(&self, guard: ConnectionGuard, socket: PollSocket, served: usize) {
+        let mut returned = self.returned.lock().unwrap_or_else(|e| e.into_inner());
+        if let Some(list) = returned.as_mut() {
+            list.push((guard, socket, served));
+            drop(returned);
+            let _ = self.poller.notify();
+        }
+    }
+
+    fn take(&self) -> Vec<Returned> {
+        let mut returned = self.returned.lock().unwrap_or_else(|e| e.into_inner());
+        returned.as_mut().map(mem::take).unwrap_or_default()
+    }
+
+    fn close(&self) {
+        self.returned
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .take();
+    }
+}
+
+/// A connection the loop is reading a request head from.
+struct Waiting {
+    guard: ConnectionGuard,
+    socket: PollSocket,
+    /// From the server's `BufferPool` once the first bytes arrive.
+    buffer: Vec<u8>,
+    served: usize,
+    deadline: Instant,
+    /// Between requests and nothing read yet, so counted in
+    /// `MetricsSnapshot::idle_connections`.
+    idle: bool,
+}
+
+struct Connections<'a> {
+    poller: &'a Poller,
+    waiting: HashMap<usize, Waiting>,
+    deadlines: BTreeSet<(Instant, usize)>,
+    next_key: usize,
+}
+
+/// Runs until `shutdown` stops it. Listeners are watched under their
+/// index in `listeners`, connections under keys after those.
+pub(crate) fn run(
+    listeners: &[(Listener, Arc<Context>)],
+    parking: &Parking,
+    pool: &ThreadPool,
+    rejects: &ThreadPool,
+    shutdown: &Shutdown,
+) -> io::Result<()> {
+    let mut conns = Connections {
+        poller: &parking.poller,
+        waiting: HashMap::new(),
+        deadlines: BTreeSet::new(),
+        next_key: listeners.len(),
+    };
+    let res = serve(&mut conns, listeners, parking, pool, rejects, shutdown);
+
+    parking.close();
+    drop(parking.take());
+    let keys: Vec<usize> = conns.waiting.keys().copied().collect();
+    for key in keys {
+        conns.close(key);
+    }
+    for (listener, _) in listeners {
+        let _ = listener.unwatch(&parking.poller);
+        // Shared with the server's own handle on the socket.
+        let _ = listener.set_nonblocking(false);
+    }
+    res
+}
+
+fn serve(
+    conns: &mut Connections<'_>,
+    listeners: &[(Listener, Arc<Context>)],
+    parking: &Parking,
+    pool: &ThreadPool,
+    rejects: &ThreadPool,
+    shutdown: &Shutdown,
+) -> io::Result<()> {
+    for (key, (listener, _)) in listeners.iter().enumerate() {
+        listener.set_nonblocking(true)?;
+        listener.watch(conns.poller, key)?;
+    }
+
+    let mut events = Events::new();
+    while !shutdown.is_stopping() {
+        events.clear();
+        let timeout = conns
+            .deadlines
+            .first()
+            .map(|(at, _)| at.saturating_duration_since(Instant::now()));
+        match conns.poller.wait(&mut events, timeout) {
+            Ok(_) => {}
+            Err(e) if e.kind() == ErrorKind::Interrupted => continue,
+            Err(e) => return Err(e),
+        }
+        if shutdown.is_stopping() {
+            break;
+        }
+
+        for event in events.iter() {
+            match listeners.get(event.key) {
+                Some((listener, ctx)) => {
+                    conns.accept(listener, ctx, pool, rejects)?;
+                    listener.rewatch(conns.poller, event.key)?;
+                }
+                None => conns.read(event.key, pool, rejects),
+            }
+        }
+        for (guard, socket, served) in parking.take() {
+            conns.park(guard, socket, served);
+        }
+        conns.expire(Instant::now());
+    }
+    Ok(())
+}
+
+impl Connections<'_> {
+    /// Takes every connection waiting on `listener`.
+    fn accept(
+        &mut self,
+        listener: &Listener,
+        ctx: &Arc<Context>,
+        pool: &ThreadPool,
+        rejects: &ThreadPool,
+    ) -> io::Result<()> {
+        loop {
+            let accepted = match listener.accept_polled() {
+                Ok(accepted) => accepted,
+                Err(e) if e.kind() == ErrorKind::WouldBlock => return Ok(()),
+                Err(e) => match AcceptError::classify(&e) {
+                    AcceptError::Transient => continue,
+                    AcceptError::Exhausted => {
+                        // Every connection waits on this thread, so back
+                        // off no longer than the shortest accept backoff.
+                        let backoff = MIN_ACCEPT_BACKOFF;
+                        warn!("Error accepting connection, retrying in {backoff:?}: {e}");
+                        thread::sleep(backoff);
+                        return Ok(());
+                    }
+                    AcceptError::Fatal => return Err(e),
+                },
+            };
+
+            match accepted {
+                Accepted::Socket(socket) if ctx.over_limit() => {
+                    if let Ok(stream) = socket.into_stream() {
+                        ctx.turn_away(stream, rejects);
+                    }
+                }
+                Accepted::Stream(stream) if ctx.over_limit() => ctx.turn_away(stream, rejects),
+                Accepted::Socket(socket) => {
+                    self.park(ConnectionGuard::new(Arc::clone(ctx)), socket, 0);
+                }
+                Accepted::Stream(stream) => {
+                    let guard = ConnectionGuard::new(Arc::clone(ctx));
+                    ctx.hand_off(guard, stream, Progress::default(), pool, rejects);
+                }
+            }
+        }
+    }
+
+    /// Starts waiting for a connection's next request head.
+    fn park(&mut self, guard: ConnectionGuard, socket: PollSocket, served: usize) {
+        let key = self.next_key;
+        self.next_key += 1;
+        let watched = socket
+            .set_nonblocking(true)
+            .and_then(|()| socket.watch(self.poller, key));
+        if watched.is_err() {
+            return;
+        }
+        let idle = served > 0;
+        if idle {
+            guard.ctx().count_idle(true);
+        }
+        let deadline = Instant::now() + guard.ctx().head_timeout(idle);
+        self.deadlines.insert((deadline, key));
+        self.waiting.insert(
+            key,
+            Waiting {
+                guard,
+                socket,
+                buffer: Vec::new(),
+                served,
+                deadline,
+                idle,
+            },
+        );
+    }
+
+    /// Reads what has arrived, and queues the connection for a worker once
+    /// its head is all there.
+    fn read(&mut self, key: usize, pool: &ThreadPool, rejects: &ThreadPool) {
+        let Some(waiting) = self.waiting.get_mut(&key) else {
+            return;
+        };
+        // Only now, so idle connections don't each sit on a buffer.
+        if waiting.buffer.capacity() == 0 {
+            waiting.buffer = waiting.guard.ctx().buffers().take();
+        }
+        match buffers::read_more(&mut waiting.socket, &mut waiting.buffer) {
+            Ok(0) => {
+                self.close(key);
+                return;
+            }
+            Ok(_) => {}
+            Err(e) if matches!(e.kind(), ErrorKind::WouldBlock | ErrorKind::Interrupted) => {}
+            Err(_) => {
+                self.close(key);
+                return;
+            }
+        }
+
+        let ctx = Arc::clone(waiting.guard.ctx());
+        if ctx.head_ready(&waiting.buffer) {
+            let Some(waiting) = self.remove(key) else {
+                return;
+            };
+            let progress = Progress {
+                buffer: waiting.buffer,
+                served: waiting.served,
+            };
+            if let Ok(stream) = waiting.socket.into_stream() {
+                ctx.hand_off(waiting.guard, stream, progress, pool, rejects);
+            }
+            return;
+        }
+
+        if waiting.idle && !waiting.buffer.is_empty() {
+            // A request has started: from here it gets the read timeout.
+            waiting.idle = false;
+            ctx.count_idle(false);
+            self.deadlines.remove(&(waiting.deadline, key));
+            waiting.deadline = Instant::now() + ctx.head_timeout(false);
+            self.deadlines.insert((waiting.deadline, key));
+        }
+        if waiting.socket.rewatch(self.poller, key).is_err() {
+            self.close(key);
+        }
+    }
+
+    /// Closes connections past their deadline, with a 408 for any that
+    /// got part of the way through a head.
+    fn expire(&mut self, now: Instant) {
+        while let Some(&(at, key)) = self.deadlines.first() {
+            if at > now {
+                break;
+            }
+            self.deadlines.pop_first();
+            if let Some(mut waiting) = self.remove(key) {
+                if !waiting.buffer.is_empty() {
+                    waiting.guard.ctx().timed_out(&mut waiting.socket);
+                }
+                waiting.guard.ctx().buffers().give_back(waiting.buffer);
+            }
+        }
+    }
+
+    /// Stops watching a connection and closes it, keeping its buffer.
+    fn close(&mut self, key: usize) {
+        if let Some(waiting) = self.remove(key) {
+            waiting.guard.ctx().buffers().give_back(waiting.buffer);
+        }
+    }
+
+    /// Stops watching a connection; dropping what comes back closes it.
+    fn remove(&mut self, key: usize) -> Option<Waiting> {
+        let waiting = self.waiting.remove(&key)?;
+        self.deadlines.remove(&(waiting.deadline, key));
+        let _ = waiting.socket.unwatch(self.poller);
+        if waiting.idle {
+            waiting.guard.ctx().count_idle(false);
+        }
+        Some(waiting)
+    }
+}
@@ -0,0 +1,265 @@
+use crate::request::Request;
+use std::{
+    io,
+    net::{IpAddr, SocketAddr},
+};
+
+#[derive(Clone)]
+struct Cidr {
+    addr: IpAddr,
+    prefix: u32,
+}
+
+impl Cidr {
+    fn parse(s: &str) -> Option<Cidr> {
+        let (addr, prefix) = match s.split_once('/') {
+            Some((addr, prefix)) => (addr.parse::<IpAddr>().ok()?, Some(prefix.parse().ok()?)),
+            None => (s.parse::<IpAddr>().ok()?, None),
+        };
+        let bits = if addr.is_ipv4() { 32 } else { 128 };
+        let prefix = prefix.unwrap_or(bits);
+        (prefix <= bits).then_some(Cidr { addr, prefix })
+    }
+
+    fn contains(&self, ip: IpAddr) -> bool {
+        let (net, ip, bits) = match (self.addr, ip.to_canonical()) {
+            (IpAddr::V4(net), IpAddr::V4(ip)) => {
+                (u32::from(net) as u128, u32::from(ip) as u128, 32)
+            }
+            (IpAddr::V6(net), IpAddr::V6(ip)) => (u128::from(net), u128::from(ip), 128),
+            _ => return false,
+        };
+        let shift = bits - self.prefix;
+        shift >= bits || (net >> shift) == (ip >> shift)
+    }
+}
+
+/// Peers whose forwarding headers are believed. `"unix"` in the list trusts
+/// whatever is on the other end of a unix socket.
+#[derive(Clone, Default)]
+pub(crate) struct TrustedProxies {
+    nets: Vec<Cidr>,
+    unix: bool,
+}
+
+impl TrustedProxies {
+    pub(crate) fn parse(list: &[&str]) -> io::Result<TrustedProxies> {
+        let mut proxies = TrustedProxies::default();
+        for entry in list {
+            if *entry == "unix" {
+                proxies.unix = true;
+                continue;
+            }
+            let net = Cidr::parse(entry).ok_or_else(|| {
+                io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    format!("invalid proxy address: {entry}"),
+                )
+            })?;
+            proxies.nets.push(net);
+        }
+        Ok(proxies)
+    }
+
+    fn trusts(&self, peer: Option<IpAddr>) -> bool {
+        match peer {
+            Some(ip) => self.nets.iter().any(|net| net.contains(ip)),
+            None => self.unix,
+        }
+    }
+
+    /// Walks the forwarding chain from the nearest hop outwards and stops at
+    /// the first address that isn't a trusted proxy.
+    pub(crate) fn client_ip(&self, req: &Request) -> Option<IpAddr> {
+        let peer = req.remote_addr().map(|a| a.ip());
+        if !self.trusts(peer) {
+            return peer;
+        }
+
+        let hops = match req.header("Forwarded") {
+            Some(v) => forwarded_for(v),
+            // Each proxy may add its own line rather than append to one.
+            None => req
+                .headers_all("X-Forwarded-For")
+                .flat_map(|v| v.split(','))
+                .map(|h| parse_node(h.trim()))
+                .collect(),
+        };
+
+        let mut client = peer;
+        for hop in hops.into_iter().rev() {
+            match hop {
+                Some(ip) => {
+                    client = Some(ip);
+                    if !self.trusts(client) {
+                        break;
+                    }
+                }
+                None => break,
+            }
+        }
+        client
+    }
+
+    pub(crate) fn forwarded_proto<'a>(&self, req: &'a Request) -> Option<&'a str> {
+        if !self.trusts(req.remote_addr().map(|a| a.ip())) {
+            return None;
+        }
+        match req.header("Forwarded") {
+            Some(v) => v
+                .rsplit(',')
+                .next()
+                .and_then(|element| forwarded_param(element, "proto")),
+            None => req
+                .header("X-Forwarded-Proto")
+                .and_then(|v| v.rsplit(',').next())
+                .map(str::trim),
+        }
+    }
+}
+
+fn forwarded_for(value: &str) -> Vec<Option<IpAddr>> {
+    value
+        .split(',')
+        .map(|element| forwarded_param(element, "for").and_then(parse_node))
+        .collect()
+}
+
+fn forwarded_param<'a>(element: &'a str, name: &str) -> Option<&'a str> {
+    element.split(';').find_map(|pair| {
+        let (k, v) = pair.split_once('=')?;
+        k.trim()
+            .eq_ignore_ascii_case(name)
+            .then(|| v.trim().trim_matches('"'))
+    })
+}
+
+fn parse_node(node: &str) -> Option<IpAddr> {
+    node.parse::<IpAddr>()
+        .ok()
+        .or_else(|| node.parse::<SocketAddr>().ok().map(|a| a.ip()))
+        .or_else(|| {
+            node.strip_prefix('[')
+                .and_then(|n| n.strip_suffix(']'))
+                .and_then(|n| n.parse().ok())
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::request::RequestBuilder;
+
+    fn from(peer: &str) -> RequestBuilder {
+        Request::builder().remote_addr(format!("{peer}:4000").parse().unwrap())
+    }
+
+    fn ip(s: &str) -> Option<IpAddr> {
+        Some(s.parse().unwrap())
+    }
+
+    fn proxies() -> TrustedProxies {
+        TrustedProxies::parse(&["10.0.0.0/8", "192.168.1.1"]).unwrap()
+    }
+
+    #[test]
+    fn untrusted_peers_cannot_spoof() {
+        let req = from("203.0.113.9")
+            .header("X-Forwarded-For", "1.2.3.4")
+            .header("Forwarded", "for=5.6.7.8;proto=https")
+            .header("X-Forwarded-Proto", "https")
+            .build()
+            .unwrap();
+        assert_eq!(proxies().client_ip(&req), ip("203.0.113.9"));
+        assert_eq!(proxies().forwarded_proto(&req), None);
+    }
+
+    #[test]
+    fn a_trusted_peer_names_the_client() {
+        let req = from("10.1.2.3")
+            .header("X-Forwarded-For", "198.51.100.7")
+            .header("X-Forwarded-Proto", "https")
+            .build()
+            .unwrap();
+        assert_eq!(proxies().client_ip(&req), ip("198.51.100.7"));
+        assert_eq!(proxies().forwarded_proto(&req), Some("https"));
+    }
+
+    #[test]
+    fn multi_hop_chains_stop_at_the_first_untrusted_hop() {
+        let req = from("10.0.0.1")
+            .header(
+                "X-Forwarded-For",
+                "6.6.6.6, 198.51.100.7, 192.168.1.1, 10.9.9.9",
+            )
+            .build()
+            .unwrap();
+        assert_eq!(proxies().client_ip(&req), ip("198.51.100.7"));
+
+        let all_trusted = from("10.0.0.1")
+            .header("X-Forwarded-For", "10.0.0.5, 192.168.1.1")
+            .build()
+            .unwrap();
+        assert_eq!(proxies().client_ip(&all_trusted), ip("10.0.0.5"));
+    }
+
+    #[test]
+    fn forwarded_for_is_read_across_header_lines() {
+        let req = from("10.0.0.1")
+            .header("X-Forwarded-For", "198.51.100.7")
+            .header("x-forwarded-for", "10.2.2.2")
+            .build()
+            .unwrap();
+        assert_eq!(proxies().client_ip(&req), ip("198.51.100.7"));
+    }
+
+    #[test]
+    fn unreadable_hops_end_the_walk() {
+        let req = from("10.0.0.1")
+            .header("X-Forwarded-For", "198.51.100.7, unknown")
+            .build()
+            .unwrap();
+        assert_eq!(proxies().client_ip(&req), ip("10.0.0.1"));
+    }
+
+    #[test]
+    fn forwarded_wins_over_x_forwarded_for() {
+        let req = from("10.0.0.1")
+            .header("X-Forwarded-For", "6.6.6.6")
+            .header(
+                "Forwarded",
+                "for=192.0.2.60;proto=http, for=\"[2001:db8::1]:4711\";proto=https",
+            )
+            .build()
+            .unwrap();
+        assert_eq!(proxies().client_ip(&req), ip("2001:db8::1"));
+        assert_eq!(proxies().forwarded_proto(&req), Some("https"));
+    }
+
+    #[test]
+    fn mapped_addresses_match_ipv4_ranges() {
+        let req = from("[::ffff:10.0.0.1]")
+            .header("X-Forwarded-For", "198.51.100.7")
+            .build()
+            .unwrap();
+        assert_eq!(proxies().client_ip(&req), ip("198.51.100.7"));
+    }
+
+    #[test]
+    fn unix_sockets_are_trusted_only_when_listed() {
+        let req = Request::builder()
+            .header("X-Forwarded-For", "198.51.100.7")
+            .build()
+            .unwrap();
+        assert_eq!(proxies().client_ip(&req), None);
+        let unix = TrustedProxies::parse(&["unix"]).unwrap();
+        assert_eq!(unix.client_ip(&req), ip("198.51.100.7"));
+    }
+
+    #[test]
+    fn bad_entries_are_refused() {
+        for entry in ["10.0.0.0/33", "not-an-ip", "::/129"] {
+            assert!(TrustedProxies::parse(&[entry]).is_err(), "{entry}");
+        }
+    }
+}
@@ -0,0 +1,336 @@
+use crate::encoding::percent_decode;
+
+/// A decoded query string with repeated keys grouped, in first-seen order.
+/// `tags[]` is stored as `tags`, and a bare `flag` has a `None` value.
+pub(crate) type Multimap = Vec<(String, Vec<Option<String>>)>;
+
+/// `None` if any key or value has bad percent-encoding.
+pub(crate) fn parse(query: &str) -> Option<Multimap> {
+    let decode = |s: &str| percent_decode(&s.replace('+', " "));
+    let mut map: Multimap = Vec::new();
+    for pair in query.split('&').filter(|p| !p.is_empty()) {
+        let (key, value) = match pair.split_once('=') {
+            Some((k, v)) => (decode(k)?, Some(decode(v)?)),
+            None => (decode(pair)?, None),
+        };
+        let key = key.strip_suffix("[]").map(String::from).unwrap_or(key);
+        match map.iter_mut().find(|(k, _)| *k == key) {
+            Some((_, values)) => values.push(value),
+            None => map.push((key, vec![value])),
+        }
+    }
+    Some(map)
+}
+
+#[cfg(feature = "serde")]
+pub(crate) use de::from_multimap;
+
+#[cfg(feature = "serde")]
+mod de {
+    use super::Multimap;
+    use serde::de::{
+        self, value::Error, DeserializeOwned, DeserializeSeed, Deserializer, Error as _,
+        IntoDeserializer, MapAccess, SeqAccess, Visitor,
+    };
+    use std::vec;
+
+    /// Deserializes `T` from a parsed query. Without `strict`, keys the
+    /// target struct has no field for are ignored; with it they are errors.
+    pub(crate) fn from_multimap<T: DeserializeOwned>(
+        map: Multimap,
+        strict: bool,
+    ) -> Result<T, Error> {
+        T::deserialize(Query { map, strict })
+    }
+
+    struct Query {
+        map: Multimap,
+        strict: bool,
+    }
+
+    impl<'de> Deserializer<'de> for Query {
+        type Error = Error;
+
+        fn deserialize_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+            visitor.visit_map(Entries {
+                entries: self.map.into_iter(),
+                value: None,
+            })
+        }
+
+        fn deserialize_struct<V: Visitor<'de>>(
+            self,
+            _name: &'static str,
+            fields: &'static [&'static str],
+            visitor: V,
+        ) -> Result<V::Value, Error> {
+            if self.strict {
+                if let Some((key, _)) = self.map.iter().find(|(k, _)| !fields.contains(&k.as_str()))
+                {
+                    return Err(Error::custom(format!("unknown parameter `{key}`")));
+                }
+            }
+            self.deserialize_any(visitor)
+        }
+
+        serde::forward_to_deserialize_any! {
+            bool i8 i16 i32 i64 i128 u8 u16 u32 u64 u128 f32 f64 char str string
+            bytes byte_buf option unit unit_struct newtype_struct seq tuple
+            tuple_struct map enum identifier ignored_any
+        }
+    }
+
+    struct Entries {
+        entries: vec::IntoIter<(String, Vec<Option<String>>)>,
+        value: Option<Values>,
+    }
+
+    impl<'de> MapAccess<'de> for Entries {
+        type Error = Error;
+
+        fn next_key_seed<K: DeserializeSeed<'de>>(
+            &mut self,
+            seed: K,
+        ) -> Result<Option<K::Value>, Error> {
+            let Some((key, values)) = self.entries.next() else {
+                return Ok(None);
+            };
+            self.value = Some(Values {
+                key: key.clone(),
+                values,
+            });
+            seed.deserialize(key.into_deserializer()).map(Some)
+        }
+
+        fn next_value_seed<V: DeserializeSeed<'de>>(&mut self, seed: V) -> Result<V::Value, Error> {
+            let value = self
+                .value
+                .take()
+                .ok_or_else(|| Error::custom("value requested before key"))?;
+            seed.deserialize(value)
+        }
+    }
+
+    /// Every value given for one key. Sequences take them all; anything
+    /// else takes the last one.
+    struct Values {
+        key: String,
+        values: Vec<Option<String>>,
+    }
+
+    impl Values {
+        fn single(mut self) -> Value {
+            Value {
+                key: self.key,
+                value: self.values.pop().flatten(),
+            }
+        }
+    }
+
+    macro_rules! to_single {
+        ($($method:ident)*) => {
+            $(
+                fn $method<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+                    self.single().$method(visitor)
+                }
+            )*
+        };
+    }
+
+    impl<'de> Deserializer<'de> for Values {
+        type Error = Error;
+
+        fn deserialize_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+            if self.values.len() > 1 {
+                self.deserialize_seq(visitor)
+            } else {
+                self.single().deserialize_any(visitor)
+            }
+        }
+
+        fn deserialize_seq<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+            let key = self.key;
+            visitor.visit_seq(Items {
+                items: self.values.into_iter(),
+                key,
+            })
+        }
+
+        fn deserialize_option<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+            visitor.visit_some(self)
+        }
+
+        fn deserialize_newtype_struct<V: Visitor<'de>>(
+            self,
+            _name: &'static str,
+            visitor: V,
+        ) -> Result<V::Value, Error> {
+            visitor.visit_newtype_struct(self)
+        }
+
+        fn deserialize_tuple<V: Visitor<'de>>(
+            self,
+            _len: usize,
+            visitor: V,
+        ) -> Result<V::Value, Error> {
+            self.deserialize_seq(visitor)
+        }
+
+        to_single! {
+            deserialize_bool deserialize_i8 deserialize_i16 deserialize_i32
+            deserialize_i64 deserialize_i128 deserialize_u8 deserialize_u16
+            deserialize_u32 deserialize_u64 deserialize_u128 deserialize_f32
+            deserialize_f64 deserialize_char deserialize_str deserialize_string
+        }
+
+        fn deserialize_enum<V: Visitor<'de>>(
+            self,
+            name: &'static str,
+            variants: &'static [&'static str],
+            visitor: V,
+        ) -> Result<V::Value, Error> {
+            self.single().deserialize_enum(name, variants, visitor)
+        }
+
+        fn deserialize_ignored_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+            visitor.visit_unit()
+        }
+
+        serde::forward_to_deserialize_any! {
+            bytes byte_buf unit unit_struct tuple_struct map struct identifier
+        }
+    }
+
+    struct Items {
+        items: vec::IntoIter<Option<String>>,
+        key: String,
+    }
+
+    impl<'de> SeqAccess<'de> for Items {
+        type Error = Error;
+
+        fn next_element_seed<T: DeserializeSeed<'de>>(
+            &mut self,
+            seed: T,
+        ) -> Result<Option<T::Value>, Error> {
+            match self.items.next() {
+                Some(value) => seed
+                    .deserialize(Value {
+                        key: self.key.clone(),
+                        value,
+                    })
+                    .map(Some),
+                None => Ok(None),
+            }
+        }
+
+        fn size_hint(&self) -> Option<usize> {
+            Some(self.items.len())
+        }
+    }
+
+    /// One value, parsed into whatever scalar the target field wants.
+    struct Value {
+        key: String,
+        value: Option<String>,
+    }
+
+    impl Value {
+        fn parse<T: std::str::FromStr>(self, what: &str) -> Result<T, Error> {
+            let value = self.value.unwrap_or_default();
+            value
+                .parse()
+                .map_err(|_| Error::custom(format!("`{}`: {value:?} is not {what}", self.key)))
+        }
+    }
+
+    macro_rules! parse_as {
+        ($($method:ident $visit:ident $what:literal,)*) => {
+            $(
+                fn $method<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+                    visitor.$visit(self.parse($what)?)
+                }
+            )*
+        };
+    }
+
+    impl<'de> Deserializer<'de> for Value {
+        type Error = Error;
+
+        fn deserialize_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+            visitor.visit_string(self.value.unwrap_or_default())
+        }
+
+        /// A bare `?flag` or an empty `flag=` is true.
+        fn deserialize_bool<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+            let value = match self
+                .value
+                .as_deref()
+                .map(str::to_ascii_lowercase)
+                .as_deref()
+            {
+                None | Some("" | "true" | "1" | "on" | "yes") => true,
+                Some("false" | "0" | "off" | "no") => false,
+                Some(other) => {
+                    return Err(Error::custom(format!(
+                        "`{}`: {other:?} is not a boolean",
+                        self.key
+                    )))
+                }
+            };
+            visitor.visit_bool(value)
+        }
+
+        parse_as! {
+            deserialize_i8 visit_i8 "an integer",
+            deserialize_i16 visit_i16 "an integer",
+            deserialize_i32 visit_i32 "an integer",
+            deserialize_i64 visit_i64 "an integer",
+            deserialize_i128 visit_i128 "an integer",
+            deserialize_u8 visit_u8 "an unsigned integer",
+            deserialize_u16 visit_u16 "an unsigned integer",
+            deserialize_u32 visit_u32 "an unsigned integer",
+            deserialize_u64 visit_u64 "an unsigned integer",
+            deserialize_u128 visit_u128 "an unsigned integer",
+            deserialize_f32 visit_f32 "a number",
+            deserialize_f64 visit_f64 "a number",
+            deserialize_char visit_char "a single character",
+        }
+
+        fn deserialize_option<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+            visitor.visit_some(self)
+        }
+
+        fn deserialize_seq<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+            Values {
+                key: self.key,
+                values: vec![self.value],
+            }
+            .deserialize_seq(visitor)
+        }
+
+        fn deserialize_newtype_struct<V: Visitor<'de>>(
+            self,
+            _name: &'static str,
+            visitor: V,
+        ) -> Result<V::Value, Error> {
+            visitor.visit_newtype_struct(self)
+        }
+
+        fn deserialize_enum<V: Visitor<'de>>(
+            self,
+            _name: &'static str,
+            _variants: &'static [&'static str],
+            visitor: V,
+        ) -> Result<V::Value, Error> {
+            let value: de::value::StringDeserializer<Error> =
+                self.value.unwrap_or_default().into_deserializer();
+            visitor.visit_enum(value)
+        }
+
+        serde::forward_to_deserialize_any! {
+            str string bytes byte_buf unit unit_struct tuple tuple_struct map
+            struct identifier ignored_any
+        }
+    }
+}
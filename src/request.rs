@@ -0,0 +1,851 @@
+use crate::{
+    auth::Authorization,
+    body::{self, Body, BodyReader, Chunked, Conn, Framing},
+    buffers,
+    encoding::percent_decode,
+    error::{BodyError, BuildError},
+    flash::Flash,
+    form::{Form, FormLimits},
+    i18n::I18n,
+    metrics::PhaseClock,
+    mime::MediaType,
+    query,
+    response::StatusCode,
+    server::Method,
+};
+#[cfg(feature = "serde")]
+use std::any::{Any, TypeId};
+use std::{
+    collections::HashMap,
+    io::{self, ErrorKind, Read, Write},
+    net::{IpAddr, SocketAddr},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex, OnceLock,
+    },
+};
+
+pub(crate) enum ReadError {
+    Closed,
+    Io(io::Error),
+    Status(StatusCode),
+}
+
+impl From<io::Error> for ReadError {
+    fn from(e: io::Error) -> Self {
+        ReadError::Io(e)
+    }
+}
+
+pub struct Request {
+    method: Method,
+    path: String,
+    query: Option<String>,
+    version: String,
+    headers: Headers,
+    body: Vec<u8>,
+    pub(crate) remote_addr: Option<SocketAddr>,
+    pub(crate) client_ip: Option<IpAddr>,
+    pub(crate) scheme: &'static str,
+    pub(crate) id: u64,
+    /// How the body left on the connection ends, for requests read with
+    /// `stream_body`.
+    pub(crate) framing: Framing,
+    pub(crate) streaming: Option<Mutex<BodyReader>>,
+    /// What the matched route's `:name` and `*name` segments captured.
+    pub(crate) params: Vec<(String, String)>,
+    cookies: OnceLock<HashMap<String, String>>,
+    /// Each `json` parse so far, by target type.
+    #[cfg(feature = "serde")]
+    json: Mutex<HashMap<TypeId, Box<dyn Any + Send>>>,
+    /// From a valid flash cookie; filled in by the server.
+    pub(crate) flashes: Vec<Flash>,
+    /// Whether a handler asked for `flashes`, which uses them up.
+    pub(crate) flashes_read: AtomicBool,
+    /// Resolved by the server when `Server::i18n` is set.
+    pub(crate) lang: Option<String>,
+    pub(crate) i18n: Option<Arc<I18n>>,
+    /// Content codings still to undo on a streamed body.
+    #[cfg(feature = "compression")]
+    codings: Vec<body::Coding>,
+}
+
+impl Request {
+    /// Starts a request without a socket, for driving handlers directly.
+    pub fn builder() -> RequestBuilder {
+        RequestBuilder::default()
+    }
+
+    pub fn parse(buffer: &[u8]) -> Option<Request> {
+        let end = buffer
+            .windows(4)
+            .position(|w| w == b"\r\n\r\n")
+            .unwrap_or(buffer.len());
+        let head = std::str::from_utf8(&buffer[..end]).ok()?;
+        let mut lines = head.split("\r\n");
+
+        let mut parts = lines.next()?.split(' ');
+        let method = Method::parse(parts.next()?)?;
+        let target = parts.next()?;
+        let version = String::from(parts.next()?);
+        if parts.next().is_some() || !target.starts_with('/') {
+            return None;
+        }
+
+        let (path, query) = match target.split_once('?') {
+            Some((path, query)) => (String::from(path), Some(String::from(query))),
+            None => (String::from(target), None),
+        };
+
+        let mut headers = Headers::default();
+        for line in lines {
+            // No colon, or space before it, is a malformed field line
+            // (RFC 9112 §5), not one to skip.
+            let (k, v) = line.split_once(':')?;
+            if k.is_empty() || k.contains(|c: char| c.is_ascii_whitespace()) {
+                return None;
+            }
+            headers.push(String::from(k), String::from(v.trim()));
+        }
+
+        Some(Request {
+            method,
+            path,
+            query,
+            version,
+            headers,
+            body: Vec::new(),
+            remote_addr: None,
+            client_ip: None,
+            scheme: "http",
+            id: 0,
+            framing: Framing::Length(0),
+            streaming: None,
+            params: Vec::new(),
+            cookies: OnceLock::new(),
+            #[cfg(feature = "serde")]
+            json: Mutex::default(),
+            flashes: Vec::new(),
+            flashes_read: AtomicBool::new(false),
+            lang: None,
+            i18n: None,
+            #[cfg(feature = "compression")]
+            codings: Vec::new(),
+        })
+    }
+
+    pub(crate) fn read_from(
+        stream: &mut (impl Read + Write),
+        buffer: &mut Vec<u8>,
+        max_head: usize,
+        max_body: usize,
+        expect_continue: impl FnOnce(&Request) -> Result<(), StatusCode>,
+        stream_body: impl FnOnce(&Request) -> bool,
+        clock: &mut PhaseClock,
+    ) -> Result<Request, ReadError> {
+        let head_end = loop {
+            if !buffer.is_empty() {
+                clock.start();
+            }
+            if let Some(pos) = buffer.windows(4).position(|w| w == b"\r\n\r\n") {
+                if pos + 4 > max_head {
+                    return Err(ReadError::Status(StatusCode::RequestHeaderFieldsTooLarge));
+                }
+                break pos + 4;
+            }
+            if buffer.len() > max_head {
+                return Err(ReadError::Status(StatusCode::RequestHeaderFieldsTooLarge));
+            }
+            let n = read_chunk(stream, buffer)?;
+            if n == 0 && buffer.is_empty() {
+                return Err(ReadError::Closed);
+            }
+            if n == 0 {
+                return Err(ReadError::Status(StatusCode::BadRequest));
+            }
+        };
+
+        let mut req =
+            Request::parse(&buffer[..head_end]).ok_or(ReadError::Status(StatusCode::BadRequest))?;
+        if !matches!(req.version(), "HTTP/1.0" | "HTTP/1.1") {
+            return Err(ReadError::Status(StatusCode::HttpVersionNotSupported));
+        }
+
+        let (chunked, length) = req.body_framing().map_err(ReadError::Status)?;
+        let streamed = stream_body(&req);
+        if length > max_body && !streamed {
+            return Err(ReadError::Status(StatusCode::PayloadTooLarge));
+        }
+        #[cfg(feature = "compression")]
+        if let Some(encoding) = req.header("Content-Encoding") {
+            req.codings = body::codings(encoding)
+                .ok_or(ReadError::Status(StatusCode::UnsupportedMediaType))?;
+        }
+
+        let arrived = if chunked {
+            buffer.len() > head_end
+        } else {
+            buffer.len() >= head_end + length
+        };
+        if req.expects_continue() && !arrived {
+            expect_continue(&req).map_err(ReadError::Status)?;
+            stream.write_all(b"HTTP/1.1 100 Continue\r\n\r\n")?;
+            stream.flush()?;
+        }
+
+        let mut framing = if chunked {
+            Framing::Chunked(Chunked::default())
+        } else {
+            Framing::Length(length as u64)
+        };
+        if streamed {
+            buffer.drain(..head_end);
+            req.framing = framing;
+            #[cfg(feature = "compression")]
+            if !req.codings.is_empty() {
+                req.remove_headers(&["Content-Encoding", "Content-Length"]);
+            }
+            return Ok(req);
+        }
+        if chunked {
+            buffer.drain(..head_end);
+            let mut conn = Conn { stream, buffer };
+            req.body = body::read_all(&mut framing, &mut conn, max_body)
+                .map_err(body_error)?
+                .ok_or(ReadError::Status(StatusCode::PayloadTooLarge))?;
+            #[cfg(feature = "compression")]
+            req.decode_body(max_body)?;
+            return Ok(req);
+        }
+
+        while buffer.len() < head_end + length {
+            if read_chunk(stream, buffer)? == 0 {
+                return Err(ReadError::Status(StatusCode::BadRequest));
+            }
+        }
+
+        let consumed = head_end + length;
+        req.body = buffer[head_end..consumed].to_vec();
+        buffer.drain(..consumed);
+        #[cfg(feature = "compression")]
+        req.decode_body(max_body)?;
+
+        Ok(req)
+    }
+
+    /// Whether the body is chunked, and its length if not. Both framings
+    /// at once is how requests get smuggled past proxies, so that, or
+    /// Content-Length values that disagree, is refused rather than
+    /// guessed at (RFC 9112 §6.3).
+    fn body_framing(&self) -> Result<(bool, usize), StatusCode> {
+        let mut codings = self
+            .headers_all("Transfer-Encoding")
+            .flat_map(|v| v.split(','))
+            .map(str::trim)
+            .peekable();
+        if codings.peek().is_some() {
+            if self.header("Content-Length").is_some() {
+                return Err(StatusCode::BadRequest);
+            }
+            let codings: Vec<&str> = codings.collect();
+            let (last, rest) = codings.split_last().unwrap_or((&"", &[]));
+            // Without chunked last there's no telling where the body ends.
+            if !last.eq_ignore_ascii_case("chunked") {
+                return Err(StatusCode::BadRequest);
+            }
+            if !rest.is_empty() {
+                return Err(
+                    match rest.iter().any(|c| c.eq_ignore_ascii_case("chunked")) {
+                        true => StatusCode::BadRequest,
+                        false => StatusCode::NotImplemented,
+                    },
+                );
+            }
+            return Ok((true, 0));
+        }
+
+        let mut length = None;
+        for value in self
+            .headers_all("Content-Length")
+            .flat_map(|v| v.split(','))
+            .map(str::trim)
+        {
+            if value.is_empty() || !value.bytes().all(|b| b.is_ascii_digit()) {
+                return Err(StatusCode::BadRequest);
+            }
+            let value = value.parse().map_err(|_| StatusCode::BadRequest)?;
+            if length.is_some_and(|l| l != value) {
+                return Err(StatusCode::BadRequest);
+            }
+            length = Some(value);
+        }
+        Ok((false, length.unwrap_or(0)))
+    }
+
+    /// Swaps a compressed body for its decoded form and drops the headers
+    /// that described the compressed bytes, so handlers only ever see the
+    /// plain body.
+    #[cfg(feature = "compression")]
+    fn decode_body(&mut self, max_body: usize) -> Result<(), ReadError> {
+        if self.codings.is_empty() {
+            return Ok(());
+        }
+        self.body = body::decode_all(&self.codings, &self.body, max_body)
+            .map_err(|_| ReadError::Status(StatusCode::BadRequest))?
+            .ok_or(ReadError::Status(StatusCode::PayloadTooLarge))?;
+        self.codings.clear();
+        self.remove_headers(&["Content-Encoding", "Content-Length"]);
+        self.headers
+            .push(String::from("Content-Length"), self.body.len().to_string());
+        Ok(())
+    }
+
+    #[cfg(feature = "compression")]
+    fn remove_headers(&mut self, names: &[&str]) {
+        self.headers.remove(names);
+    }
+
+    pub fn method(&self) -> Method {
+        self.method
+    }
+
+    pub fn path(&self) -> &str {
+        &self.path
+    }
+
+    /// The part of the path a `:name` or `*name` route segment matched,
+    /// set once the request is dispatched.
+    pub fn param(&self, name: &str) -> Option<&str> {
+        self.params
+            .iter()
+            .find_map(|(n, value)| (n == name).then_some(value.as_str()))
+    }
+
+    pub fn params(&self) -> &[(String, String)] {
+        &self.params
+    }
+
+    pub fn query(&self) -> Option<&str> {
+        self.query.as_deref()
+    }
+
+    /// The decoded query parameters in order, repeats included. `tags[]`
+    /// is reported as `tags` and a bare `flag` has an empty value. Empty if
+    /// the query is missing or badly encoded.
+    pub fn query_params(&self) -> Vec<(String, String)> {
+        let map = self.query().and_then(query::parse).unwrap_or_default();
+        map.into_iter()
+            .flat_map(|(k, values)| {
+                values
+                    .into_iter()
+                    .map(move |v| (k.clone(), v.unwrap_or_default()))
+            })
+            .collect()
+    }
+
+    /// Deserializes the query string into `T`. Repeated keys and `key[]`
+    /// fill `Vec` fields, a bare `?flag` is `true`, and keys `T` has no
+    /// field for are ignored.
+    #[cfg(feature = "serde")]
+    pub fn query_as<T: serde::de::DeserializeOwned>(&self) -> Result<T, BodyError> {
+        self.query_into(false)
+    }
+
+    /// Like `query_as`, but a key `T` has no field for is an error.
+    #[cfg(feature = "serde")]
+    pub fn query_as_strict<T: serde::de::DeserializeOwned>(&self) -> Result<T, BodyError> {
+        self.query_into(true)
+    }
+
+    #[cfg(feature = "serde")]
+    fn query_into<T: serde::de::DeserializeOwned>(&self, strict: bool) -> Result<T, BodyError> {
+        let map = query::parse(self.query().unwrap_or_default())
+            .ok_or_else(|| BodyError::Query(String::from("bad percent-encoding")))?;
+        query::from_multimap(map, strict).map_err(|e| BodyError::Query(e.to_string()))
+    }
+
+    pub fn version(&self) -> &str {
+        &self.version
+    }
+
+    /// The first value of a header. Names match ASCII case-insensitively,
+    /// so `content-type` finds `Content-Type`.
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers.all(name).next()
+    }
+
+    /// Every value of a header that may repeat, such as `Cookie`, in the
+    /// order received. Names match case-insensitively.
+    pub fn headers_all<'a>(&'a self, name: &'a str) -> impl Iterator<Item = &'a str> + 'a {
+        self.headers.all(name)
+    }
+
+    /// All headers as received, with their original casing and order.
+    pub fn headers(&self) -> impl Iterator<Item = (&str, &str)> {
+        self.headers
+            .entries
+            .iter()
+            .map(|(k, v)| (k.as_str(), v.as_str()))
+    }
+
+    /// Cookies from every `Cookie` header, parsed on first use. Values are
+    /// left as sent apart from surrounding quotes; when a name repeats the
+    /// first one wins, and segments without a name are skipped.
+    pub fn cookies(&self) -> &HashMap<String, String> {
+        self.cookies.get_or_init(|| {
+            let mut cookies = HashMap::new();
+            for pair in self.headers_all("Cookie").flat_map(|h| h.split(';')) {
+                let Some((name, value)) = pair.split_once('=') else {
+                    continue;
+                };
+                let name = name.trim();
+                if name.is_empty() {
+                    continue;
+                }
+                let value = value.trim();
+                let value = value
+                    .strip_prefix('"')
+                    .and_then(|v| v.strip_suffix('"'))
+                    .unwrap_or(value);
+                cookies
+                    .entry(String::from(name))
+                    .or_insert_with(|| String::from(value));
+            }
+            cookies
+        })
+    }
+
+    pub fn cookie(&self, name: &str) -> Option<&str> {
+        self.cookies().get(name).map(String::as_str)
+    }
+
+    /// A cookie value with percent-escapes decoded, for cookies known to be
+    /// written that way. `None` if it is missing or badly encoded.
+    pub fn cookie_decoded(&self, name: &str) -> Option<String> {
+        percent_decode(self.cookie(name)?)
+    }
+
+    /// Messages left by the previous response with `Response::flash`, in
+    /// the order they were added. Reading them uses them up: the server
+    /// clears the cookie in this response. Until some handler reads them
+    /// they stay put, so a stylesheet or favicon request in between
+    /// doesn't lose them.
+    pub fn flashes(&self) -> Vec<Flash> {
+        self.flashes_read.store(true, Ordering::Relaxed);
+        self.flashes.clone()
+    }
+
+    /// `Accept-Language` as (tag, q) pairs, highest q first and in header
+    /// order among equals. Entries with an unparseable q are dropped.
+    pub fn accept_language(&self) -> Vec<(String, f32)> {
+        let mut ranges: Vec<(String, f32)> = self
+            .headers_all("Accept-Language")
+            .flat_map(|h| h.split(','))
+            .filter_map(|entry| {
+                let mut parts = entry.split(';');
+                let tag = parts.next()?.trim();
+                if tag.is_empty() {
+                    return None;
+                }
+                let mut q = 1.0;
+                for param in parts {
+                    if let Some((k, v)) = param.split_once('=') {
+                        if k.trim().eq_ignore_ascii_case("q") {
+                            q = v
+                                .trim()
+                                .parse::<f32>()
+                                .ok()
+                                .filter(|q| (0.0..=1.0).contains(q))?;
+                        }
+                    }
+                }
+                Some((tag.to_ascii_lowercase(), q))
+            })
+            .collect();
+        ranges.sort_by(|a, b| b.1.total_cmp(&a.1));
+        ranges
+    }
+
+    /// The entry of `available` the client likes best. A tag matches
+    /// exactly, by prefix (`fr` covers `fr-CA`), by primary subtag (`fr-CA`
+    /// falls back to `fr`) or through `*`, and the most specific match sets
+    /// its q, so `fr;q=0` rules French out even next to `*`. Ties go to the
+    /// earlier entry of `available`. `None` if nothing is acceptable.
+    pub fn preferred_language<'a>(&self, available: &[&'a str]) -> Option<&'a str> {
+        let ranges = self.accept_language();
+        let primary = |tag: &str| tag.split('-').next().unwrap_or_default().to_string();
+        let mut best: Option<(&str, f32)> = None;
+        for &lang in available {
+            let lang_lower = lang.to_ascii_lowercase();
+            let q = ranges
+                .iter()
+                .filter_map(|(range, q)| {
+                    let rank = if *range == lang_lower {
+                        4
+                    } else if lang_lower.starts_with(&format!("{range}-")) {
+                        3
+                    } else if range != "*" && primary(range) == primary(&lang_lower) {
+                        2
+                    } else if range == "*" {
+                        1
+                    } else {
+                        return None;
+                    };
+                    Some((rank, *q))
+                })
+                .max_by(|a, b| a.0.cmp(&b.0).then(a.1.total_cmp(&b.1)))
+                .map(|(_, q)| q);
+            match (q, best) {
+                (Some(q), Some((_, best_q))) if q <= best_q => {}
+                (Some(q), _) if q > 0.0 => best = Some((lang, q)),
+                _ => {}
+            }
+        }
+        best.map(|(lang, _)| lang)
+    }
+
+    /// The language this request is served in, picked once by
+    /// `Server::i18n` from `?lang=`, Accept-Language and the default.
+    /// `None` when the server has no `i18n` set up.
+    pub fn lang(&self) -> Option<&str> {
+        self.lang.as_deref()
+    }
+
+    /// The parsed Content-Type, or `None` if it is missing or has no
+    /// subtype.
+    pub fn content_type(&self) -> Option<MediaType> {
+        MediaType::parse(self.header("Content-Type")?)
+    }
+
+    pub fn authorization(&self) -> Option<Authorization> {
+        Authorization::parse(self.header("Authorization")?)
+    }
+
+    /// The buffered body. Empty on routes registered with
+    /// `Server::stream_body`; use `body_reader` there.
+    pub fn body(&self) -> &[u8] {
+        &self.body
+    }
+
+    /// Same as `body`, named to sit next to `body_text`.
+    pub fn body_bytes(&self) -> &[u8] {
+        &self.body
+    }
+
+    /// The buffered body as text, borrowed rather than copied. A charset
+    /// parameter other than UTF-8 (or its ASCII subset) is refused as an
+    /// unsupported media type; an empty body is `Ok("")`.
+    pub fn body_text(&self) -> Result<&str, BodyError> {
+        let charset = self
+            .content_type()
+            .and_then(|ct| ct.charset().map(String::from));
+        if let Some(charset) = charset {
+            if !["utf-8", "utf8", "us-ascii"]
+                .iter()
+                .any(|c| charset.eq_ignore_ascii_case(c))
+            {
+                return Err(BodyError::UnsupportedMediaType(
+                    self.header("Content-Type").map(String::from),
+                ));
+            }
+        }
+        std::str::from_utf8(&self.body).map_err(BodyError::InvalidUtf8)
+    }
+
+    /// Reads the body incrementally. On a `Server::stream_body` route this
+    /// pulls straight from the connection, so a handler can `io::copy` a
+    /// large upload to disk; elsewhere it reads the buffered body.
+    pub fn body_reader(&self) -> impl Read + '_ {
+        match &self.streaming {
+            Some(reader) => {
+                let reader = Body::Streaming(reader.lock().unwrap_or_else(|e| e.into_inner()));
+                #[cfg(feature = "compression")]
+                if !self.codings.is_empty() {
+                    return Body::Decoded(body::decoder(&self.codings, Box::new(reader)));
+                }
+                reader
+            }
+            None => Body::Buffered(&self.body),
+        }
+    }
+
+    /// Parses an `application/x-www-form-urlencoded` or
+    /// `multipart/form-data` body with the default `FormLimits`.
+    pub fn form(&self) -> Result<Form, BodyError> {
+        self.form_with(&FormLimits::default())
+    }
+
+    pub fn form_with(&self, limits: &FormLimits) -> Result<Form, BodyError> {
+        Form::parse(self, limits)
+    }
+
+    /// Parses a JSON body into `T`. The Content-Type must be
+    /// `application/json` or a `+json` type. The result is kept, so calling
+    /// this again for the same `T` returns a copy rather than parsing twice;
+    /// another type gets a parse of its own.
+    #[cfg(feature = "serde")]
+    pub fn json<T>(&self) -> Result<T, BodyError>
+    where
+        T: serde::de::DeserializeOwned + Clone + Send + 'static,
+    {
+        let mut parsed = self.json.lock().unwrap_or_else(|e| e.into_inner());
+        parsed
+            .entry(TypeId::of::<T>())
+            .or_insert_with(|| Box::new(self.parse_json::<T>()))
+            .downcast_ref::<Result<T, BodyError>>()
+            .expect("keyed by its own type")
+            .clone()
+    }
+
+    #[cfg(feature = "serde")]
+    fn parse_json<T: serde::de::DeserializeOwned>(&self) -> Result<T, BodyError> {
+        let json = self.content_type().is_some_and(|ct| {
+            ct.is("application/json") || (ct.is_type("application") && ct.suffix() == Some("json"))
+        });
+        if !json {
+            return Err(BodyError::UnsupportedMediaType(
+                self.header("Content-Type").map(String::from),
+            ));
+        }
+        Ok(serde_json::from_str(self.body_text()?)?)
+    }
+
+    /// The peer the request came in from, or `None` on a unix socket.
+    pub fn remote_addr(&self) -> Option<SocketAddr> {
+        self.remote_addr
+    }
+
+    /// The originating client, read from forwarding headers when the peer is
+    /// a trusted proxy and from the socket otherwise.
+    pub fn client_ip(&self) -> Option<IpAddr> {
+        self.client_ip.or(self.remote_addr.map(|a| a.ip()))
+    }
+
+    /// A number unique to this request within the process, for correlating
+    /// log lines.
+    pub fn id(&self) -> u64 {
+        self.id
+    }
+
+    /// `"https"` when the request came over TLS or a trusted proxy says so.
+    pub fn scheme(&self) -> &str {
+        self.scheme
+    }
+
+    fn expects_continue(&self) -> bool {
+        self.version == "HTTP/1.1"
+            && self
+                .header("Expect")
+                .is_some_and(|v| v.eq_ignore_ascii_case("100-continue"))
+    }
+
+    pub fn keep_alive(&self) -> bool {
+        let connection = self.header("Connection").unwrap_or_default();
+        let has = |token: &str| {
+            connection
+                .split(',')
+                .any(|t| t.trim().eq_ignore_ascii_case(token))
+        };
+        match self.version.as_str() {
+            "HTTP/1.0" => has("keep-alive"),
+            _ => !has("close"),
+        }
+    }
+
+    pub(crate) fn if_none_match(&self, etag: &str) -> bool {
+        let etag = etag.trim_start_matches("W/");
+        self.header("If-None-Match").is_some_and(|v| {
+            v.split(',')
+                .map(|t| t.trim())
+                .any(|t| t == "*" || t.trim_start_matches("W/") == etag)
+        })
+    }
+}
+
+/// Builds a `Request` the way the parser would have produced it from the
+/// same bytes on the wire. A target given to `path` may carry a query.
+#[derive(Clone, Debug)]
+pub struct RequestBuilder {
+    method: Method,
+    target: String,
+    version: String,
+    headers: Vec<(String, String)>,
+    body: Vec<u8>,
+    remote_addr: Option<SocketAddr>,
+    scheme: &'static str,
+}
+
+impl Default for RequestBuilder {
+    fn default() -> Self {
+        RequestBuilder {
+            method: Method::Get,
+            target: String::from("/"),
+            version: String::from("HTTP/1.1"),
+            headers: Vec::new(),
+            body: Vec::new(),
+            remote_addr: None,
+            scheme: "http",
+        }
+    }
+}
+
+impl RequestBuilder {
+    pub fn method(mut self, method: Method) -> RequestBuilder {
+        self.method = method;
+        self
+    }
+
+    pub fn path(mut self, target: &str) -> RequestBuilder {
+        self.target = String::from(target);
+        self
+    }
+
+    pub fn version(mut self, version: &str) -> RequestBuilder {
+        self.version = String::from(version);
+        self
+    }
+
+    pub fn header(mut self, name: &str, value: &str) -> RequestBuilder {
+        self.headers.push((String::from(name), String::from(value)));
+        self
+    }
+
+    /// Also sets Content-Length unless a framing header was given. Under
+    /// `Transfer-Encoding: chunked` the body is decoded as the parser would,
+    /// so it should be given chunked; a `Content-Encoding` is left as sent.
+    pub fn body(mut self, body: impl Into<Vec<u8>>) -> RequestBuilder {
+        self.body = body.into();
+        self
+    }
+
+    pub fn remote_addr(mut self, addr: SocketAddr) -> RequestBuilder {
+        self.remote_addr = Some(addr);
+        self
+    }
+
+    /// Marks the request as having come in over TLS.
+    pub fn https(mut self) -> RequestBuilder {
+        self.scheme = "https";
+        self
+    }
+
+    pub fn build(self) -> Result<Request, BuildError> {
+        if !self.target.starts_with('/') || self.target.contains([' ', '\r', '\n']) {
+            return Err(BuildError::InvalidPath(self.target));
+        }
+        if !matches!(self.version.as_str(), "HTTP/1.0" | "HTTP/1.1") {
+            return Err(BuildError::InvalidVersion(self.version));
+        }
+        let token = |c: char| c.is_ascii_alphanumeric() || "!#$%&'*+-.^_`|~".contains(c);
+        for (name, value) in &self.headers {
+            if name.is_empty() || !name.chars().all(token) {
+                return Err(BuildError::InvalidHeaderName(name.clone()));
+            }
+            if value.contains(['\r', '\n', '\0']) {
+                return Err(BuildError::InvalidHeaderValue(name.clone()));
+            }
+        }
+
+        let mut head = format!("{} {} {}\r\n", self.method, self.target, self.version);
+        for (name, value) in &self.headers {
+            head.push_str(&format!("{name}: {value}\r\n"));
+        }
+        let framed = self.headers.iter().any(|(k, _)| {
+            k.eq_ignore_ascii_case("Content-Length") || k.eq_ignore_ascii_case("Transfer-Encoding")
+        });
+        if !self.body.is_empty() && !framed {
+            head.push_str(&format!("Content-Length: {}\r\n", self.body.len()));
+        }
+        head.push_str("\r\n");
+
+        let mut req =
+            Request::parse(head.as_bytes()).ok_or(BuildError::InvalidPath(self.target))?;
+        req.body = self.body;
+        if let Ok((true, _)) = req.body_framing() {
+            let mut conn = Conn {
+                stream: &mut io::empty(),
+                buffer: &mut req.body,
+            };
+            req.body = body::read_all(
+                &mut Framing::Chunked(Chunked::default()),
+                &mut conn,
+                usize::MAX,
+            )
+            .ok()
+            .flatten()
+            .ok_or(BuildError::InvalidBody)?;
+        }
+        req.remote_addr = self.remote_addr;
+        req.scheme = self.scheme;
+        Ok(req)
+    }
+}
+
+/// Headers in the order received and with their original casing, indexed
+/// by lowercased name so a lookup is one hash rather than a scan.
+#[derive(Default)]
+struct Headers {
+    entries: Vec<(String, String)>,
+    index: HashMap<String, Vec<usize>>,
+}
+
+impl Headers {
+    fn push(&mut self, name: String, value: String) {
+        let key = name.to_ascii_lowercase();
+        self.index.entry(key).or_default().push(self.entries.len());
+        self.entries.push((name, value));
+    }
+
+    fn all<'a>(&'a self, name: &str) -> impl Iterator<Item = &'a str> + 'a {
+        let at = match lowercase(name, &mut [0; 64]) {
+            Some(key) => self.index.get(key),
+            None => self.index.get(&name.to_ascii_lowercase()),
+        };
+        at.map_or(&[][..], Vec::as_slice)
+            .iter()
+            .map(|&i| self.entries[i].1.as_str())
+    }
+
+    #[cfg(feature = "compression")]
+    fn remove(&mut self, names: &[&str]) {
+        let entries = std::mem::take(&mut self.entries);
+        self.index.clear();
+        for (k, v) in entries {
+            if !names.iter().any(|n| k.eq_ignore_ascii_case(n)) {
+                self.push(k, v);
+            }
+        }
+    }
+}
+
+/// `name` lowercased into `buf`, or `None` if it doesn't fit, so the usual
+/// short names are looked up without allocating.
+fn lowercase<'a>(name: &str, buf: &'a mut [u8]) -> Option<&'a str> {
+    let buf = buf.get_mut(..name.len())?;
+    buf.copy_from_slice(name.as_bytes());
+    buf.make_ascii_lowercase();
+    std::str::from_utf8(buf).ok()
+}
+
+fn read_chunk(stream: &mut impl Read, buffer: &mut Vec<u8>) -> Result<usize, ReadError> {
+    let waiting = !buffer.is_empty();
+    match buffers::read_more(stream, buffer) {
+        Ok(n) => Ok(n),
+        Err(e) if is_timeout(&e) && waiting => Err(ReadError::Status(StatusCode::RequestTimeout)),
+        Err(e) => Err(ReadError::Io(e)),
+    }
+}
+
+fn body_error(e: io::Error) -> ReadError {
+    match e.kind() {
+        _ if is_timeout(&e) => ReadError::Status(StatusCode::RequestTimeout),
+        ErrorKind::InvalidData | ErrorKind::UnexpectedEof => {
+            ReadError::Status(StatusCode::BadRequest)
+        }
+        _ => ReadError::Io(e),
+    }
+}
+
+pub(crate) fn is_timeout(e: &io::Error) -> bool {
+    matches!(e.kind(), ErrorKind::WouldBlock | ErrorKind::TimedOut)
+}
@@ -0,0 +1,152 @@
+use crate::{
+    encoding::json_escape,
+    request::Request,
+    response::{Response, StatusCode},
+    server::HandlerResult,
+    store::MemStore,
+};
+use serde::{de::DeserializeOwned, Serialize};
+use serde_json::Value;
+use std::sync::Arc;
+
+type Hook<T> = Arc<dyn Fn(&T) -> Result<(), String> + Send + Sync>;
+
+/// A JSON collection over a `MemStore`, registered with `Server::resource`.
+/// Items are keyed by the store's `next_id` and come back as their JSON
+/// object with an `"id"` field added; a value that isn't an object is
+/// wrapped as `{"id": .., "value": ..}`.
+pub struct Resource<T> {
+    store: MemStore<u64, T>,
+    before_create: Option<Hook<T>>,
+    before_update: Option<Hook<T>>,
+}
+
+impl<T> From<MemStore<u64, T>> for Resource<T> {
+    fn from(store: MemStore<u64, T>) -> Self {
+        Resource {
+            store,
+            before_create: None,
+            before_update: None,
+        }
+    }
+}
+
+impl<T> Resource<T>
+where
+    T: Serialize + DeserializeOwned + Clone + Send + Sync + 'static,
+{
+    pub fn new(store: MemStore<u64, T>) -> Resource<T> {
+        Resource::from(store)
+    }
+
+    /// Checks a POST body before it's stored. An `Err` is sent back as a
+    /// 400 with the message as `{"error": ..}`.
+    pub fn before_create(
+        mut self,
+        hook: impl Fn(&T) -> Result<(), String> + Send + Sync + 'static,
+    ) -> Resource<T> {
+        self.before_create = Some(Arc::new(hook));
+        self
+    }
+
+    /// Like `before_create`, for the body of a PUT.
+    pub fn before_update(
+        mut self,
+        hook: impl Fn(&T) -> Result<(), String> + Send + Sync + 'static,
+    ) -> Resource<T> {
+        self.before_update = Some(Arc::new(hook));
+        self
+    }
+
+    /// `GET /items`: every item, oldest first.
+    pub(crate) fn list(&self, _req: &Request) -> HandlerResult {
+        let mut entries = self.store.entries();
+        entries.sort_by_key(|(id, _)| *id);
+        let items = entries
+            .iter()
+            .map(|(id, value)| with_id(*id, value))
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(Response::new(StatusCode::Ok).json(&items)?)
+    }
+
+    /// `GET /items/:id`.
+    pub(crate) fn show(&self, req: &Request) -> HandlerResult {
+        match item_id(req).and_then(|id| Some((id, self.store.get(&id)?))) {
+            Some((id, value)) => Ok(Response::new(StatusCode::Ok).json(&with_id(id, &value)?)?),
+            None => Ok(not_found()),
+        }
+    }
+
+    /// `POST /items`: a 201 with the new item and its URL in Location.
+    pub(crate) fn create(&self, req: &Request) -> HandlerResult {
+        let value = match self.parse(req, self.before_create.as_ref()) {
+            Ok(value) => value,
+            Err(res) => return Ok(res),
+        };
+        let id = self.store.next_id();
+        let body = with_id(id, &value)?;
+        self.store.insert(id, value);
+        let location = format!("{}/{id}", req.path().trim_end_matches('/'));
+        Ok(Response::new(StatusCode::Created)
+            .header("Location", &location)
+            .json(&body)?)
+    }
+
+    /// `PUT /items/:id`: replaces an existing item. Unknown ids are a 404
+    /// rather than a create, since ids come from the store.
+    pub(crate) fn replace(&self, req: &Request) -> HandlerResult {
+        let Some(id) = item_id(req).filter(|id| self.store.get(id).is_some()) else {
+            return Ok(not_found());
+        };
+        let value = match self.parse(req, self.before_update.as_ref()) {
+            Ok(value) => value,
+            Err(res) => return Ok(res),
+        };
+        let body = with_id(id, &value)?;
+        if !self.store.update(&id, |old| *old = value) {
+            return Ok(not_found());
+        }
+        Ok(Response::new(StatusCode::Ok).json(&body)?)
+    }
+
+    /// `DELETE /items/:id`: a 204, or a 404 if it was already gone.
+    pub(crate) fn destroy(&self, req: &Request) -> HandlerResult {
+        match item_id(req).and_then(|id| self.store.remove(&id)) {
+            Some(_) => Ok(Response::status_only(StatusCode::NoContent)),
+            None => Ok(not_found()),
+        }
+    }
+
+    fn parse(&self, req: &Request, hook: Option<&Hook<T>>) -> Result<T, Response> {
+        let value = req.json::<T>().map_err(|e| e.response())?;
+        if let Some(hook) = hook {
+            hook(&value).map_err(|message| error(StatusCode::BadRequest, &message))?;
+        }
+        Ok(value)
+    }
+}
+
+/// The `:id` segment of an item path, `7` in `/posts/7`.
+fn item_id(req: &Request) -> Option<u64> {
+    req.param("id")?.parse().ok()
+}
+
+fn with_id(id: u64, value: &impl Serialize) -> Result<Value, serde_json::Error> {
+    match serde_json::to_value(value)? {
+        Value::Object(mut fields) => {
+            fields.insert(String::from("id"), Value::from(id));
+            Ok(Value::Object(fields))
+        }
+        value => Ok(serde_json::json!({ "id": id, "value": value })),
+    }
+}
+
+fn not_found() -> Response {
+    error(StatusCode::NotFound, "no such item")
+}
+
+fn error(status: StatusCode, message: &str) -> Response {
+    Response::new(status)
+        .header("Content-Type", "application/json")
+        .body(format!(r#"{{"error":"{}"}}"#, json_escape(message)))
+}
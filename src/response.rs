@@ -0,0 +1,525 @@
+use crate::{
+    buffers,
+    error::TemplateError,
+    feed::Feed,
+    flash::{Flash, FlashLevel},
+    i18n, mime,
+    request::Request,
+    templates::Template,
+};
+use std::{
+    cell::RefCell,
+    collections::HashMap,
+    fmt::Display,
+    io::{self, ErrorKind, Read, Write},
+    ops::Deref,
+    path::Path,
+    sync::Arc,
+};
+
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub enum StatusCode {
+    SwitchingProtocols,
+    Ok,
+    Created,
+    NoContent,
+    PartialContent,
+    MovedPermanently,
+    SeeOther,
+    NotModified,
+    BadRequest,
+    Unauthorized,
+    NotFound,
+    MethodNotAllowed,
+    RequestTimeout,
+    PayloadTooLarge,
+    UnsupportedMediaType,
+    RangeNotSatisfiable,
+    UpgradeRequired,
+    TooManyRequests,
+    RequestHeaderFieldsTooLarge,
+    InternalServerError,
+    NotImplemented,
+    ServiceUnavailable,
+    HttpVersionNotSupported,
+}
+
+impl StatusCode {
+    pub fn code(&self) -> u16 {
+        match self {
+            StatusCode::SwitchingProtocols => 101,
+            StatusCode::Ok => 200,
+            StatusCode::Created => 201,
+            StatusCode::NoContent => 204,
+            StatusCode::PartialContent => 206,
+            StatusCode::MovedPermanently => 301,
+            StatusCode::SeeOther => 303,
+            StatusCode::NotModified => 304,
+            StatusCode::BadRequest => 400,
+            StatusCode::Unauthorized => 401,
+            StatusCode::NotFound => 404,
+            StatusCode::MethodNotAllowed => 405,
+            StatusCode::RequestTimeout => 408,
+            StatusCode::PayloadTooLarge => 413,
+            StatusCode::UnsupportedMediaType => 415,
+            StatusCode::RangeNotSatisfiable => 416,
+            StatusCode::UpgradeRequired => 426,
+            StatusCode::TooManyRequests => 429,
+            StatusCode::RequestHeaderFieldsTooLarge => 431,
+            StatusCode::InternalServerError => 500,
+            StatusCode::NotImplemented => 501,
+            StatusCode::ServiceUnavailable => 503,
+            StatusCode::HttpVersionNotSupported => 505,
+        }
+    }
+
+    pub fn reason(&self) -> &'static str {
+        match self {
+            StatusCode::SwitchingProtocols => "Switching Protocols",
+            StatusCode::Ok => "OK",
+            StatusCode::Created => "Created",
+            StatusCode::NoContent => "No Content",
+            StatusCode::PartialContent => "Partial Content",
+            StatusCode::MovedPermanently => "Moved Permanently",
+            StatusCode::SeeOther => "See Other",
+            StatusCode::NotModified => "Not Modified",
+            StatusCode::BadRequest => "Bad Request",
+            StatusCode::Unauthorized => "Unauthorized",
+            StatusCode::NotFound => "Not Found",
+            StatusCode::MethodNotAllowed => "Method Not Allowed",
+            StatusCode::RequestTimeout => "Request Timeout",
+            StatusCode::PayloadTooLarge => "Payload Too Large",
+            StatusCode::UnsupportedMediaType => "Unsupported Media Type",
+            StatusCode::RangeNotSatisfiable => "Range Not Satisfiable",
+            StatusCode::UpgradeRequired => "Upgrade Required",
+            StatusCode::TooManyRequests => "Too Many Requests",
+            StatusCode::RequestHeaderFieldsTooLarge => "Request Header Fields Too Large",
+            StatusCode::InternalServerError => "Internal Server Error",
+            StatusCode::NotImplemented => "Not Implemented",
+            StatusCode::ServiceUnavailable => "Service Unavailable",
+            StatusCode::HttpVersionNotSupported => "HTTP Version Not Supported",
+        }
+    }
+}
+
+impl StatusCode {
+    /// False for the statuses whose responses never have a body or a
+    /// Content-Length.
+    pub fn allows_body(&self) -> bool {
+        !matches!(
+            self,
+            StatusCode::SwitchingProtocols | StatusCode::NoContent | StatusCode::NotModified
+        )
+    }
+}
+
+impl Display for StatusCode {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} {}", self.code(), self.reason())
+    }
+}
+
+/// Something `Response::send` can use as a body, along with the
+/// Content-Type it implies. `None` if the body couldn't be produced.
+pub trait IntoBody {
+    fn into_body(self) -> Option<(&'static str, Vec<u8>)>;
+}
+
+impl IntoBody for &str {
+    fn into_body(self) -> Option<(&'static str, Vec<u8>)> {
+        Some(("text/plain; charset=utf-8", self.as_bytes().to_vec()))
+    }
+}
+
+impl IntoBody for String {
+    fn into_body(self) -> Option<(&'static str, Vec<u8>)> {
+        Some(("text/plain; charset=utf-8", self.into_bytes()))
+    }
+}
+
+impl IntoBody for Vec<u8> {
+    fn into_body(self) -> Option<(&'static str, Vec<u8>)> {
+        Some(("application/octet-stream", self))
+    }
+}
+
+impl IntoBody for &[u8] {
+    fn into_body(self) -> Option<(&'static str, Vec<u8>)> {
+        Some(("application/octet-stream", self.to_vec()))
+    }
+}
+
+/// Sends its value serialized as JSON. A value that fails to serialize
+/// turns the response into an empty 500.
+#[cfg(feature = "serde")]
+pub struct Json<T>(pub T);
+
+#[cfg(feature = "serde")]
+impl<T: serde::Serialize> IntoBody for Json<T> {
+    fn into_body(self) -> Option<(&'static str, Vec<u8>)> {
+        match serde_json::to_vec(&self.0) {
+            Ok(body) => Some(("application/json", body)),
+            Err(e) => {
+                log::error!("Failed to serialize response body: {e}");
+                None
+            }
+        }
+    }
+}
+
+/// What `Response::body` takes. Bytes from a cache or built into the
+/// binary go in as `Shared` or `Static`, so every request sends the same
+/// copy instead of its own.
+#[derive(Clone, Debug)]
+pub enum ResponseBody {
+    Owned(Vec<u8>),
+    Static(&'static [u8]),
+    Shared(Arc<[u8]>),
+}
+
+impl Default for ResponseBody {
+    fn default() -> Self {
+        ResponseBody::Owned(Vec::new())
+    }
+}
+
+impl Deref for ResponseBody {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        match self {
+            ResponseBody::Owned(bytes) => bytes,
+            ResponseBody::Static(bytes) => bytes,
+            ResponseBody::Shared(bytes) => bytes,
+        }
+    }
+}
+
+impl From<Vec<u8>> for ResponseBody {
+    fn from(bytes: Vec<u8>) -> Self {
+        ResponseBody::Owned(bytes)
+    }
+}
+
+impl From<String> for ResponseBody {
+    fn from(text: String) -> Self {
+        ResponseBody::Owned(text.into_bytes())
+    }
+}
+
+impl From<&str> for ResponseBody {
+    fn from(text: &str) -> Self {
+        ResponseBody::Owned(text.as_bytes().to_vec())
+    }
+}
+
+impl From<&[u8]> for ResponseBody {
+    fn from(bytes: &[u8]) -> Self {
+        ResponseBody::Owned(bytes.to_vec())
+    }
+}
+
+impl<const N: usize> From<&[u8; N]> for ResponseBody {
+    fn from(bytes: &[u8; N]) -> Self {
+        ResponseBody::Owned(bytes.to_vec())
+    }
+}
+
+impl From<Arc<[u8]>> for ResponseBody {
+    fn from(bytes: Arc<[u8]>) -> Self {
+        ResponseBody::Shared(bytes)
+    }
+}
+
+pub struct Response {
+    version: &'static str,
+    status: StatusCode,
+    headers: Vec<(String, String)>,
+    body: ResponseBody,
+    /// Set by `body_stream`, in place of `body`. Boxed twice to keep
+    /// `Response` small for the common case without one.
+    stream: Option<Box<RefCell<Box<dyn Read + Send>>>>,
+    length: Option<u64>,
+    /// Turned into the flash cookie once the handler returns.
+    flashes: Vec<Flash>,
+}
+
+impl Response {
+    pub fn new(status: StatusCode) -> Response {
+        Response {
+            version: "HTTP/1.1",
+            status,
+            headers: Vec::new(),
+            body: ResponseBody::default(),
+            stream: None,
+            length: None,
+            flashes: Vec::new(),
+        }
+    }
+
+    /// A response with `body` and the Content-Type its type implies. Statuses
+    /// that can't carry a body drop it.
+    pub fn send(status: StatusCode, body: impl IntoBody) -> Response {
+        let Some((content_type, body)) = body.into_body() else {
+            return Response::new(StatusCode::InternalServerError);
+        };
+        if !status.allows_body() {
+            return Response::new(status);
+        }
+        Response::new(status)
+            .header("Content-Type", content_type)
+            .body(body)
+    }
+
+    /// A 200 HTML page from `template` filled in with `vars`.
+    pub fn render(
+        template: &Template,
+        vars: &HashMap<&str, String>,
+    ) -> Result<Response, TemplateError> {
+        Ok(Response::new(StatusCode::Ok)
+            .header("Content-Type", "text/html")
+            .body(template.render(vars)?))
+    }
+
+    /// Like `render`, but reads the template from `path` on each request,
+    /// preferring the variant for `Request::lang` such as `page.fr.html`
+    /// when `Server::i18n` is set, and says which language it sent.
+    pub fn render_localized(
+        req: &Request,
+        path: impl AsRef<Path>,
+        vars: &HashMap<&str, String>,
+    ) -> Result<Response, TemplateError> {
+        let path = path.as_ref();
+        let Some((path, lang)) = i18n::localize(req, path) else {
+            return Response::render(&Template::from_file(path)?, vars);
+        };
+        Ok(Response::render(&Template::from_file(path)?, vars)?
+            .header("Content-Language", &lang)
+            .header("Vary", "Accept-Language"))
+    }
+
+    /// A 200 with `feed` as RSS 2.0.
+    pub fn rss(feed: &Feed) -> Response {
+        Response::new(StatusCode::Ok)
+            .header("Content-Type", "application/rss+xml")
+            .body(feed.to_rss())
+    }
+
+    /// A 200 with `feed` as Atom 1.0.
+    pub fn atom(feed: &Feed) -> Response {
+        Response::new(StatusCode::Ok)
+            .header("Content-Type", "application/atom+xml")
+            .body(feed.to_atom())
+    }
+
+    /// A response with no body, for statuses like `NoContent`.
+    pub fn status_only(status: StatusCode) -> Response {
+        Response::new(status)
+    }
+
+    pub fn header(mut self, name: &str, value: &str) -> Response {
+        self.headers.push((String::from(name), String::from(value)));
+        self
+    }
+
+    pub fn body(mut self, body: impl Into<ResponseBody>) -> Response {
+        self.body = body.into();
+        self.stream = None;
+        self
+    }
+
+    /// Sends the next `length` bytes of `reader` as the body, copied to the
+    /// connection a chunk at a time rather than held in memory. Seek a file
+    /// to where the body starts before handing it over.
+    pub fn body_stream(mut self, reader: impl Read + Send + 'static, length: u64) -> Response {
+        self.body = ResponseBody::default();
+        self.stream = Some(Box::new(RefCell::new(Box::new(reader))));
+        self.length = Some(length);
+        self
+    }
+
+    /// Serializes `value` as the body and sets the JSON Content-Type.
+    #[cfg(feature = "serde")]
+    pub fn json(self, value: &impl serde::Serialize) -> Result<Response, serde_json::Error> {
+        let body = serde_json::to_vec(value)?;
+        Ok(self.header("Content-Type", "application/json").body(body))
+    }
+
+    /// Leaves a message for the next request from this browser, which
+    /// reads it with `Request::flashes`; meant for the page a POST
+    /// redirects to. Calls add up in order. The messages travel in a
+    /// signed cookie, so keep them short: past about 3.8KB the rest are
+    /// cut off.
+    pub fn flash(mut self, level: FlashLevel, message: &str) -> Response {
+        self.flashes.push(Flash {
+            level,
+            message: String::from(message),
+        });
+        self
+    }
+
+    pub(crate) fn take_flashes(&mut self) -> Vec<Flash> {
+        std::mem::take(&mut self.flashes)
+    }
+
+    pub fn content_length(mut self, length: u64) -> Response {
+        self.length = Some(length);
+        self
+    }
+
+    pub fn head(mut self) -> Response {
+        self.length = Some(self.length.unwrap_or(self.body.len() as u64));
+        self.body = ResponseBody::default();
+        self.stream = None;
+        self
+    }
+
+    pub fn charset(mut self, charset: Option<&str>) -> Response {
+        for (name, value) in self.headers.iter_mut() {
+            if name.eq_ignore_ascii_case("Content-Type") {
+                *value = mime::with_charset(value, charset);
+            }
+        }
+        self
+    }
+
+    pub(crate) fn version(mut self, version: &str) -> Response {
+        self.version = match version {
+            "HTTP/1.0" => "HTTP/1.0",
+            _ => "HTTP/1.1",
+        };
+        self
+    }
+
+    pub fn header_value(&self, name: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|(k, _)| k.eq_ignore_ascii_case(name))
+            .map(|(_, v)| v.as_str())
+    }
+
+    pub(crate) fn body_len(&self) -> usize {
+        match &self.stream {
+            Some(_) => self.length.unwrap_or(0) as usize,
+            None => self.body.len(),
+        }
+    }
+
+    pub(crate) fn closes(&self) -> bool {
+        self.header_value("Connection")
+            .is_some_and(|v| v.eq_ignore_ascii_case("close"))
+    }
+
+    pub fn status(&self) -> StatusCode {
+        self.status
+    }
+
+    pub fn write_to(&self, w: &mut impl Write) -> io::Result<()> {
+        self.write_with(w, &mut Vec::new(), false)
+    }
+
+    /// `write_to`, building the head in `head` so a connection can reuse
+    /// one buffer for every response, and sending head and a small body
+    /// with one vectored write when `w` supports it.
+    pub(crate) fn write_with(
+        &self,
+        w: &mut impl Write,
+        head: &mut Vec<u8>,
+        vectored: bool,
+    ) -> io::Result<()> {
+        head.clear();
+        write!(head, "{} {}\r\n", self.version, self.status)?;
+        for (name, value) in self.headers.iter() {
+            write!(head, "{}: {}\r\n", name, value)?;
+        }
+        if self.status.allows_body() {
+            let length = self.length.unwrap_or(self.body.len() as u64);
+            write!(head, "Content-Length: {}\r\n", length)?;
+        }
+        head.extend_from_slice(b"\r\n");
+
+        if let Some(stream) = self.stream.as_ref().filter(|_| self.status.allows_body()) {
+            let length = self.length.unwrap_or(0);
+            w.write_all(head)?;
+            let mut stream = stream.borrow_mut();
+            if io::copy(&mut stream.by_ref().take(length), w)? < length {
+                return Err(io::Error::new(ErrorKind::UnexpectedEof, "body ended early"));
+            }
+            return w.flush();
+        }
+
+        let body = match self.status.allows_body() {
+            true => &self.body[..],
+            false => &[],
+        };
+        buffers::write_joined(w, head, body, vectored)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        file_cache::FileCache,
+        handlers,
+        server::{RequestHandler, Server},
+        static_files::StaticDir,
+    };
+
+    fn get(server: &Server, path: &str) -> Response {
+        let res = server.handle(Request::builder().path(path).build().unwrap());
+        assert_eq!(res.status(), StatusCode::Ok, "{path}");
+        res
+    }
+
+    fn shared(res: &Response) -> &Arc<[u8]> {
+        match &res.body {
+            ResponseBody::Shared(bytes) => bytes,
+            body => panic!("copied into {body:?}"),
+        }
+    }
+
+    #[test]
+    fn cached_files_are_sent_from_the_cache() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(dir.path().join("big.txt"), vec![b'x'; 64 * 1024]).unwrap();
+        let cache = Arc::new(FileCache::new(1 << 20, 1 << 20));
+        let mut server = Server::new("127.0.0.1:0", 2);
+        server.mount_static("/", StaticDir::new(dir.path()).with_cache(cache));
+
+        let first = get(&server, "/big.txt");
+        let second = get(&server, "/big.txt");
+        assert!(Arc::ptr_eq(shared(&first), shared(&second)));
+        // The cache's copy and one per response in flight.
+        assert_eq!(Arc::strong_count(shared(&first)), 3);
+        drop(second);
+        assert_eq!(Arc::strong_count(shared(&first)), 2);
+    }
+
+    #[test]
+    fn built_in_bodies_are_sent_as_they_are() {
+        static PAGE: &str = "<h1>About</h1>";
+        let mut server = Server::new("127.0.0.1:0", 2);
+        server.get("/about", handlers::serve_static_str("text/html", PAGE));
+        let res = get(&server, "/about");
+        match res.body {
+            ResponseBody::Static(bytes) => assert_eq!(bytes.as_ptr(), PAGE.as_ptr()),
+            body => panic!("copied into {body:?}"),
+        }
+    }
+
+    #[cfg(feature = "embed")]
+    #[test]
+    fn embedded_assets_are_sent_as_they_are() {
+        static APP: &[u8] = b"console.log(1)";
+        static ASSETS: &[crate::embedded::Asset] = &[("/app.js", APP, "")];
+        let mut server = Server::new("127.0.0.1:0", 2);
+        server.static_embedded("/", ASSETS);
+        for _ in 0..2 {
+            match get(&server, "/app.js").body {
+                ResponseBody::Static(bytes) => assert_eq!(bytes.as_ptr(), APP.as_ptr()),
+                body => panic!("copied into {body:?}"),
+            }
+        }
+    }
+}
@@ -0,0 +1,102 @@
+use crate::{request::Request, sitemap};
+
+/// The rules `Server::robots` serves. Rules added before any `user_agent`
+/// apply to every crawler (`User-agent: *`).
+#[derive(Clone, Debug, Default)]
+pub struct Robots {
+    groups: Vec<Group>,
+    sitemaps: Vec<String>,
+}
+
+#[derive(Clone, Debug)]
+struct Group {
+    agents: Vec<String>,
+    rules: Vec<(&'static str, String)>,
+}
+
+impl Robots {
+    pub fn new() -> Robots {
+        Robots::default()
+    }
+
+    /// Starts a group of rules for `agent`. Calling it again before adding
+    /// a rule names another agent for the same group.
+    pub fn user_agent(mut self, agent: &str) -> Robots {
+        let agent = single_line(agent);
+        match self.groups.last_mut() {
+            Some(group) if group.rules.is_empty() => group.agents.push(agent),
+            _ => self.groups.push(Group {
+                agents: vec![agent],
+                rules: Vec::new(),
+            }),
+        }
+        self
+    }
+
+    pub fn allow(self, path: &str) -> Robots {
+        self.rule("Allow", path)
+    }
+
+    pub fn disallow(self, path: &str) -> Robots {
+        self.rule("Disallow", path)
+    }
+
+    /// Points crawlers at a sitemap. A path starting with `/` is made
+    /// absolute with the requested scheme and Host, as the format asks.
+    pub fn sitemap(mut self, url: &str) -> Robots {
+        self.sitemaps.push(single_line(url));
+        self
+    }
+
+    fn rule(mut self, field: &'static str, path: &str) -> Robots {
+        if self.groups.is_empty() {
+            self = self.user_agent("*");
+        }
+        if let Some(group) = self.groups.last_mut() {
+            group.rules.push((field, single_line(path)));
+        }
+        self
+    }
+
+    /// The robots.txt text. A group with no rules gets an empty
+    /// `Disallow:`, which allows everything, and so does an empty file.
+    pub(crate) fn render(&self, req: &Request) -> String {
+        let mut out = String::new();
+        let everyone = [Group {
+            agents: vec![String::from("*")],
+            rules: Vec::new(),
+        }];
+        let groups = if self.groups.is_empty() {
+            &everyone[..]
+        } else {
+            &self.groups[..]
+        };
+        for (i, group) in groups.iter().enumerate() {
+            if i > 0 {
+                out.push('\n');
+            }
+            for agent in group.agents.iter() {
+                out.push_str(&format!("User-agent: {agent}\n"));
+            }
+            if group.rules.is_empty() {
+                out.push_str("Disallow:\n");
+            }
+            for (field, path) in group.rules.iter() {
+                out.push_str(&format!("{field}: {path}\n"));
+            }
+        }
+        if !self.sitemaps.is_empty() {
+            let origin = sitemap::origin(req);
+            out.push('\n');
+            for url in self.sitemaps.iter() {
+                out.push_str(&format!("Sitemap: {}\n", sitemap::absolute(&origin, url)));
+            }
+        }
+        out
+    }
+}
+
+/// Line breaks would start a new directive, so they are dropped.
+fn single_line(s: &str) -> String {
+    s.chars().filter(|c| !matches!(c, '\r' | '\n')).collect()
+}
@@ -0,0 +1,89 @@
+use crate::{metrics::Metrics, shutdown::Shutdown, ThreadPool};
+use log::{error, warn};
+use std::{
+    panic::{self, AssertUnwindSafe},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    thread,
+    time::{Duration, Instant},
+};
+
+/// How long the scheduler sleeps at most before checking for shutdown.
+const POLL: Duration = Duration::from_millis(50);
+
+/// A job from `Server::schedule`.
+#[derive(Clone)]
+pub(crate) struct Job {
+    interval: Duration,
+    run: Arc<dyn Fn() + Send + Sync>,
+    /// Set while a run is on the pool, so the next tick can tell it would
+    /// overlap.
+    running: Arc<AtomicBool>,
+}
+
+impl Job {
+    pub(crate) fn new(interval: Duration, run: Arc<dyn Fn() + Send + Sync>) -> Job {
+        Job {
+            interval,
+            run,
+            running: Arc::default(),
+        }
+    }
+}
+
+/// Clears `running` however the run ends.
+struct Running(Arc<AtomicBool>);
+
+impl Drop for Running {
+    fn drop(&mut self) {
+        self.0.store(false, Ordering::Release);
+    }
+}
+
+/// Hands each job to the pool once per interval, first one interval after
+/// starting, until the server stops. A tick that comes around while the
+/// previous run is still going is skipped and counted. Ticks missed
+/// because the pool was slow to pick a run up are not made up for later.
+pub(crate) fn spawn(
+    jobs: Vec<Job>,
+    pool: Arc<ThreadPool>,
+    shutdown: Arc<Shutdown>,
+    metrics: Arc<Metrics>,
+) -> thread::JoinHandle<()> {
+    thread::spawn(move || {
+        let start = Instant::now();
+        let mut due = jobs
+            .iter()
+            .map(|job| start + job.interval)
+            .collect::<Vec<_>>();
+        while !shutdown.is_stopping() {
+            let now = Instant::now();
+            for (job, due) in jobs.iter().zip(due.iter_mut()) {
+                if *due > now {
+                    continue;
+                }
+                *due += job.interval;
+                if *due <= now {
+                    *due = now + job.interval;
+                }
+                if job.running.swap(true, Ordering::AcqRel) {
+                    metrics.job_overruns.fetch_add(1, Ordering::Relaxed);
+                    warn!("Skipping a scheduled job: its last run is still going");
+                    continue;
+                }
+                metrics.job_runs.fetch_add(1, Ordering::Relaxed);
+                let (run, running) = (Arc::clone(&job.run), Running(Arc::clone(&job.running)));
+                pool.execute(move || {
+                    let _running = running;
+                    if panic::catch_unwind(AssertUnwindSafe(|| run())).is_err() {
+                        error!("A scheduled job panicked");
+                    }
+                });
+            }
+            let next = due.iter().min().copied().unwrap_or(now + POLL);
+            thread::sleep(next.saturating_duration_since(Instant::now()).min(POLL));
+        }
+    })
+}
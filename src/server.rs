@@ -1,24 +1,69 @@
-use crate::ThreadPool;
+#[cfg(feature = "embed")]
+use crate::embedded::{Asset, EmbeddedAssets};
+#[cfg(feature = "poll")]
+use crate::poll;
+#[cfg(feature = "serde")]
+use crate::resource::Resource;
+#[cfg(feature = "signals")]
+use crate::signals::Signal;
+use crate::{
+    access_log::{AccessLog, LogFields, LogFormat, Redaction},
+    assets::AssetManifest,
+    body::BodyReader,
+    buffers::BufferPool,
+    config::ServerBuilder,
+    encoding::json_escape,
+    error::{ConfigError, ServerError},
+    flash,
+    health::HealthStatus,
+    i18n::I18n,
+    metrics::{
+        Metrics, MetricsSnapshot, PhaseClock, PhaseTimings, RouteCounters, RouteStats, TimingHook,
+    },
+    openapi::{self, RouteDoc},
+    proxy::TrustedProxies,
+    request::{is_timeout, ReadError, Request},
+    response::{Response, ResponseBody, StatusCode},
+    robots::Robots,
+    schedule,
+    shutdown::{Shutdown, ShutdownHandle},
+    sitemap::{self, SitemapUrl},
+    sse::{self, SseHandler, SseSender},
+    static_files::StaticDir,
+    stream::{Detached, Listener, Plain, Stream},
+    websocket::{self, WebSocket, WebSocketHandler},
+    ThreadPool,
+};
+use log::{error, info, trace, warn};
 use std::{
+    collections::HashMap,
     error::Error,
     fmt::Display,
     fs,
-    io::{Read, Write},
-    net::{TcpListener, TcpStream},
-    sync::Arc,
+    io::{self, ErrorKind, IsTerminal, Read, Write},
+    net::{SocketAddr, TcpListener},
+    panic::{self, AssertUnwindSafe},
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicU64, AtomicUsize, Ordering},
+        Arc, Mutex,
+    },
+    thread::{self, JoinHandle},
+    time::{Duration, Instant},
 };
-use termion::color;
-
-pub const STATUS_OK: &str = "HTTP/1.1 200 OK";
-pub const STATUS_NOT_FOUND: &str = "HTTP/1.1 404 NOT_FOUND";
-pub const STATUS_INTERNAL_SERVER_ERROR: &str = "HTTP/1.1 500 INTERNAL_SERVER_ERROR";
+#[cfg(feature = "color")]
+use termion::{color, style};
 
-#[derive(Clone, Copy)]
-enum Method {
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub enum Method {
     Get,
     Post,
     Delete,
     Put,
+    Head,
+    /// Parsed so a PATCH to a known path gets a 405 rather than a 400;
+    /// no route can be added for it yet.
+    Patch,
 }
 
 impl Display for Method {
@@ -28,54 +73,261 @@ impl Display for Method {
             Method::Get => "GET",
             Method::Delete => "DELETE",
             Method::Post => "POST",
+            Method::Head => "HEAD",
+            Method::Patch => "PATCH",
         };
         write!(f, "{}", s)
     }
 }
 
-type HandlerFn = fn(TcpStream) -> Result<(), Box<dyn Error>>;
+impl Method {
+    pub(crate) fn parse(s: &str) -> Option<Method> {
+        match s {
+            "GET" => Some(Method::Get),
+            "POST" => Some(Method::Post),
+            "DELETE" => Some(Method::Delete),
+            "PUT" => Some(Method::Put),
+            "HEAD" => Some(Method::Head),
+            "PATCH" => Some(Method::Patch),
+            _ => None,
+        }
+    }
+}
+
+pub(crate) const MAX_HEAD_SIZE: usize = 8 * 1024;
+pub(crate) const MAX_BODY_SIZE: usize = 1024 * 1024;
+
+pub type HandlerResult = Result<Response, Box<dyn Error>>;
+
+type HandlerFn = Arc<dyn Fn(&Request) -> HandlerResult + Send + Sync>;
+
+pub trait HandlerFunc: Fn(&Request) -> HandlerResult + Send + Sync + 'static {}
+
+impl<F> HandlerFunc for F where F: Fn(&Request) -> HandlerResult + Send + Sync + 'static {}
 
 #[derive(Clone)]
 struct Handler {
     method: Method,
     path: String,
-    handler: Arc<HandlerFn>,
+    /// Where the router it came from was mounted, `/` for routes added
+    /// straight to the server.
+    prefix: String,
+    handler: HandlerFn,
+    stats: Arc<RouteCounters>,
+    doc: Option<RouteDoc>,
+    key: PathKey,
+}
+
+/// How a route's path is compared with request paths, worked out once
+/// when it is added so matching never allocates.
+#[derive(Clone, Debug)]
+enum PathKey {
+    Exact,
+    /// The path split at its slashes, for a path with a `:name` or
+    /// `*name` segment.
+    Pattern(Vec<Segment>),
+}
+
+#[derive(Clone, Debug)]
+enum Segment {
+    Literal(String),
+    /// `:name`, any one segment that isn't empty.
+    Param(String),
+    /// `*name`, only ever last: whatever follows, slashes included, maybe
+    /// nothing.
+    Rest(String),
+}
+
+impl PathKey {
+    fn parse(path: &str) -> PathKey {
+        let Some(rest) = path.strip_prefix('/') else {
+            return PathKey::Exact;
+        };
+        if !rest.split('/').any(|s| s.starts_with([':', '*'])) {
+            return PathKey::Exact;
+        }
+        let segments = rest
+            .split('/')
+            .map(|s| match s.split_at(s.len().min(1)) {
+                (":", name) => Segment::Param(String::from(name)),
+                ("*", name) => Segment::Rest(String::from(name)),
+                _ => Segment::Literal(String::from(s)),
+            })
+            .collect();
+        PathKey::Pattern(segments)
+    }
+
+    /// Whether `run` can serve a route with this key: placeholders need a
+    /// name, and a `*name` has to be the last segment.
+    fn is_valid(&self) -> bool {
+        let PathKey::Pattern(segments) = self else {
+            return true;
+        };
+        let last = segments.len() - 1;
+        segments
+            .iter()
+            .enumerate()
+            .all(|(i, segment)| match segment {
+                Segment::Literal(_) => true,
+                Segment::Param(name) => !name.is_empty(),
+                Segment::Rest(name) => !name.is_empty() && i == last,
+            })
+    }
+
+    /// Which of several routes matching one path wins, lowest first: a
+    /// literal path, then one with only `:name` placeholders, then one
+    /// ending in `*name`. Ties go to the route added first.
+    fn rank(&self) -> u8 {
+        match self {
+            PathKey::Exact => 0,
+            PathKey::Pattern(s) if matches!(s.last(), Some(Segment::Rest(_))) => 2,
+            PathKey::Pattern(_) => 1,
+        }
+    }
+
+    /// Matches `path` against a pattern, calling `found` with each
+    /// placeholder's name and value.
+    fn walk<'k, 'p>(
+        &'k self,
+        route: &str,
+        path: &'p str,
+        mut found: impl FnMut(&'k str, &'p str),
+    ) -> bool {
+        let PathKey::Pattern(segments) = self else {
+            return route == path;
+        };
+        let Some(mut rest) = path.strip_prefix('/') else {
+            return false;
+        };
+        let last = segments.len() - 1;
+        for (i, segment) in segments.iter().enumerate() {
+            if let Segment::Rest(name) = segment {
+                found(name, rest);
+                return true;
+            }
+            let (part, tail) = match rest.split_once('/') {
+                Some((part, tail)) => (part, Some(tail)),
+                None => (rest, None),
+            };
+            match segment {
+                Segment::Literal(literal) if literal == part => {}
+                Segment::Param(name) if !part.is_empty() => found(name, part),
+                _ => return false,
+            }
+            match tail {
+                Some(tail) if i < last => rest = tail,
+                None => return i == last,
+                Some(_) => return false,
+            }
+        }
+        false
+    }
+
+    /// The placeholders `path` fills in, for a route it matches.
+    fn captures(&self, route: &str, path: &str) -> Vec<(String, String)> {
+        let mut params = Vec::new();
+        self.walk(route, path, |name, value| {
+            params.push((String::from(name), String::from(value)))
+        });
+        params
+    }
+}
+
+/// Every handler in the order it was added, plus a map from method and
+/// literal path to handler so most lookups are one hash no matter how
+/// many routes there are. Only the placeholder routes are scanned, and
+/// only after the map misses, in `PathKey::rank` order.
+#[derive(Clone, Default)]
+struct RouteTable {
+    handlers: Vec<Handler>,
+    exact: HashMap<Method, HashMap<String, usize>>,
+    patterns: Vec<usize>,
+}
+
+impl RouteTable {
+    /// When a literal path is added twice for one method, the first one
+    /// keeps serving it.
+    fn push(&mut self, handler: Handler) {
+        let i = self.handlers.len();
+        match handler.key {
+            PathKey::Exact => {
+                let paths = self.exact.entry(handler.method).or_default();
+                paths.entry(handler.path.clone()).or_insert(i);
+            }
+            PathKey::Pattern(_) => {
+                let rank = handler.key.rank();
+                let at = self
+                    .patterns
+                    .partition_point(|&p| self.handlers[p].key.rank() <= rank);
+                self.patterns.insert(at, i);
+            }
+        }
+        self.handlers.push(handler);
+    }
+
+    fn find(&self, method: Method, path: &str) -> Option<&Handler> {
+        let exact = self.exact.get(&method).and_then(|paths| paths.get(path));
+        if let Some(&i) = exact {
+            return Some(&self.handlers[i]);
+        }
+        self.patterns
+            .iter()
+            .map(|&i| &self.handlers[i])
+            .find(|ep| ep.check(method, path))
+    }
+
+    fn iter(&self) -> std::slice::Iter<'_, Handler> {
+        self.handlers.iter()
+    }
+
+    fn len(&self) -> usize {
+        self.handlers.len()
+    }
+
+    /// For attaching docs; the path and method stay as they were.
+    fn last_mut(&mut self) -> Option<&mut Handler> {
+        self.handlers.last_mut()
+    }
 }
 
 impl Display for Handler {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        let c_method = format!("{}{}", color::Fg(color::Blue), self.method);
-        let c_path = format!("{}{}", color::Fg(color::Green), self.path);
-        write!(f, "{} {} {}", c_method, c_path, color::Fg(color::White))
+        write!(f, "{} {}", self.method, self.path)
     }
 }
 
 impl Handler {
-    fn new(path: &str, method: Method, handler: fn(TcpStream)) -> Handler {
+    fn new(
+        path: &str,
+        prefix: &str,
+        method: Method,
+        handler: HandlerFn,
+        stats: Arc<RouteCounters>,
+    ) -> Handler {
         Handler {
             method,
-            handler: Arc::new(handler),
+            handler,
             path: String::from(path),
+            prefix: String::from(prefix),
+            stats,
+            doc: None,
+            key: PathKey::parse(path),
         }
     }
 
-    fn http_str(&self) -> String {
-        format!("{} {} HTTP/1.1\r\n", self.method, self.path)
-    }
-
-    fn check(&self, buffer: &[u8; 1024]) -> bool {
-        buffer.starts_with(self.http_str().as_bytes())
+    fn check(&self, method: Method, path: &str) -> bool {
+        self.method == method && self.key.walk(&self.path, path, |_, _| {})
     }
 }
 
 pub trait RequestHandler {
-    fn get(&mut self, path: &str, h: HandlerFn) -> &mut Self;
+    fn get(&mut self, path: &str, h: impl HandlerFunc) -> &mut Self;
 
-    fn post(&mut self, path: &str, h: HandlerFn) -> &mut Self;
+    fn post(&mut self, path: &str, h: impl HandlerFunc) -> &mut Self;
 
-    fn put(&mut self, path: &str, h: HandlerFn) -> &mut Self;
+    fn put(&mut self, path: &str, h: impl HandlerFunc) -> &mut Self;
 
-    fn delete(&mut self, path: &str, h: HandlerFn) -> &mut Self;
+    fn delete(&mut self, path: &str, h: impl HandlerFunc) -> &mut Self;
 }
 
 #[derive(Clone)]
@@ -83,6 +335,8 @@ struct Route {
     method: Method,
     path: String,
     handler: HandlerFn,
+    doc: Option<RouteDoc>,
+    key: PathKey,
 }
 
 impl Route {
@@ -91,6 +345,8 @@ impl Route {
             method,
             path: String::from(path),
             handler: h,
+            doc: None,
+            key: PathKey::parse(path),
         }
     }
 }
@@ -103,122 +359,2349 @@ impl Router {
     pub fn new() -> Router {
         Router { end_points: vec![] }
     }
+
+    /// Documents the route added last, for `Server::openapi_json`.
+    pub fn describe(&mut self, doc: RouteDoc) -> &mut Self {
+        match self.end_points.last_mut() {
+            Some(route) => route.doc = Some(doc),
+            None => warn!("describe called on a router with no routes"),
+        }
+        self
+    }
+}
+
+impl Default for Router {
+    fn default() -> Self {
+        Router::new()
+    }
 }
 
 impl RequestHandler for Router {
-    fn get(&mut self, path: &str, h: HandlerFn) -> &mut Self {
-        self.end_points.push(Route::new(path, Method::Get, h));
+    fn get(&mut self, path: &str, h: impl HandlerFunc) -> &mut Self {
+        self.end_points
+            .push(Route::new(path, Method::Get, Arc::new(h)));
         self
     }
 
-    fn post(&mut self, path: &str, h: HandlerFn) -> &mut Self {
-        self.end_points.push(Route::new(path, Method::Post, h));
+    fn post(&mut self, path: &str, h: impl HandlerFunc) -> &mut Self {
+        self.end_points
+            .push(Route::new(path, Method::Post, Arc::new(h)));
         self
     }
 
-    fn put(&mut self, path: &str, h: HandlerFn) -> &mut Self {
-        self.end_points.push(Route::new(path, Method::Put, h));
+    fn put(&mut self, path: &str, h: impl HandlerFunc) -> &mut Self {
+        self.end_points
+            .push(Route::new(path, Method::Put, Arc::new(h)));
         self
     }
 
-    fn delete(&mut self, path: &str, h: HandlerFn) -> &mut Self {
-        self.end_points.push(Route::new(path, Method::Delete, h));
+    fn delete(&mut self, path: &str, h: impl HandlerFunc) -> &mut Self {
+        self.end_points
+            .push(Route::new(path, Method::Delete, Arc::new(h)));
         self
     }
 }
 
-pub struct Server {
-    addr: String,
-    end_points: Vec<Handler>,
-    pool_size: usize,
+/// What a method and path resolve to, from `Router::match_route` or
+/// `Server::match_route`. A route path segment `:name` matches any one
+/// segment and a last segment `*name` the rest of the path; a literal
+/// path beats a `:name` route, which beats a `*name` one, and otherwise
+/// the route added first wins. HEAD is served by the GET route; static
+/// mounts match anything under their prefix for GET and HEAD, but only
+/// when no route does.
+pub struct Match<'a> {
+    path: &'a str,
+    target: Target<'a>,
 }
 
-impl RequestHandler for Server {
-    fn get(&mut self, path: &str, h: HandlerFn) -> &mut Self {
-        self.end_points.push(Handler::new(path, Method::Get, h));
-        self
+#[derive(Clone, Copy)]
+enum Target<'a> {
+    Handler(&'a Handler),
+    Route(&'a Route),
+    Static(&'a StaticMount),
+}
+
+impl<'a> Match<'a> {
+    /// The method of the route that matched, so `Get` for a HEAD request.
+    pub fn method(&self) -> Method {
+        match self.target {
+            Target::Handler(h) => h.method,
+            Target::Route(r) => r.method,
+            Target::Static(_) => Method::Get,
+        }
     }
 
-    fn post(&mut self, path: &str, h: HandlerFn) -> &mut Self {
-        self.end_points.push(Handler::new(path, Method::Post, h));
-        self
+    /// The route path as registered, including any mount prefix, or
+    /// `prefix/*` for a static mount.
+    pub fn pattern(&self) -> &'a str {
+        match self.target {
+            Target::Handler(h) => &h.path,
+            Target::Route(r) => &r.path,
+            Target::Static(m) => &m.pattern,
+        }
     }
 
-    fn delete(&mut self, path: &str, h: HandlerFn) -> &mut Self {
-        self.end_points.push(Handler::new(path, Method::Delete, h));
-        self
+    /// Where the route's router was mounted, `/` for routes added straight
+    /// to the server or matched on a `Router` directly.
+    pub fn prefix(&self) -> &'a str {
+        match self.target {
+            Target::Handler(h) => &h.prefix,
+            Target::Route(_) => "/",
+            Target::Static(m) => &m.prefix,
+        }
     }
 
-    fn put(&mut self, path: &str, h: HandlerFn) -> &mut Self {
-        self.end_points.push(Handler::new(path, Method::Put, h));
-        self
+    pub fn is_static(&self) -> bool {
+        matches!(self.target, Target::Static(_))
+    }
+
+    /// The value the path gave a `:name` or `*name` segment.
+    pub fn param(&self, name: &str) -> Option<&'a str> {
+        self.params()
+            .into_iter()
+            .find_map(|(n, value)| (n == name).then_some(value))
+    }
+
+    /// Every placeholder in the pattern with the value the path gave it,
+    /// in order.
+    pub fn params(&self) -> Vec<(&'a str, &'a str)> {
+        let mut params = Vec::new();
+        if let Some(key) = self.target.key() {
+            key.walk(self.pattern(), self.path, |name, value| {
+                params.push((name, value))
+            });
+        }
+        params
     }
 }
 
-impl Server {
-    pub fn new(addr: &str, pool_size: usize) -> Server {
-        Server {
-            addr: String::from(addr),
-            end_points: Vec::new(),
-            pool_size: pool_size.max(2),
+impl<'a> Target<'a> {
+    fn key(self) -> Option<&'a PathKey> {
+        match self {
+            Target::Handler(h) => Some(&h.key),
+            Target::Route(r) => Some(&r.key),
+            Target::Static(_) => None,
         }
     }
+}
 
-    pub fn mount(&mut self, path: &str, router: Router) -> &mut Self {
-        let super_paths: Vec<_> = path.split("/").collect();
-        for end_point in router.end_points.iter() {
-            let base_paths: Vec<_> = end_point.path.split("/").collect();
-            let paths = [&super_paths[..], &base_paths[..]].concat();
-            let paths: Vec<_> = paths.into_iter().filter(|s| !s.is_empty()).collect();
-            let path = "/".to_owned() + &paths.join("/");
-            let handler_fn = end_point.handler.clone();
+/// One row of the flattened routing table from `Server::routes`.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct RouteInfo {
+    pub method: Method,
+    /// The full path as matched, or `prefix/*` for a static mount.
+    pub pattern: String,
+    /// Where the route's router was mounted, `/` for routes added straight
+    /// to the server.
+    pub prefix: String,
+    pub is_static: bool,
+    /// Set with `describe`.
+    pub doc: Option<RouteDoc>,
+}
+
+/// The `debug_routes` page: aligned plain text, or JSON for clients that
+/// ask for it.
+fn route_listing(routes: &[RouteInfo], json: bool) -> Response {
+    if json {
+        let rows = routes
+            .iter()
+            .map(|r| {
+                format!(
+                    r#"{{"method":"{}","pattern":"{}","prefix":"{}","static":{}}}"#,
+                    r.method,
+                    json_escape(&r.pattern),
+                    json_escape(&r.prefix),
+                    r.is_static
+                )
+            })
+            .collect::<Vec<_>>();
+        return Response::new(StatusCode::Ok)
+            .header("Content-Type", "application/json")
+            .header("Cache-Control", "no-store")
+            .body(format!("[{}]", rows.join(",")));
+    }
+    let width = routes.iter().map(|r| r.pattern.len()).max().unwrap_or(0);
+    let mut out = String::new();
+    for r in routes {
+        let pattern = format!("{:width$}", r.pattern);
+        let kind = if r.is_static { " (static)" } else { "" };
+        out.push_str(&format!(
+            "{:7} {pattern}  {}{kind}\n",
+            r.method.to_string(),
+            r.prefix
+        ));
+    }
+    Response::new(StatusCode::Ok)
+        .header("Content-Type", "text/plain")
+        .header("Cache-Control", "no-store")
+        .body(out)
+}
+
+/// HEAD requests are served by GET routes.
+fn route_method(method: Method) -> Method {
+    match method {
+        Method::Head => Method::Get,
+        method => method,
+    }
+}
 
-            self.end_points
-                .push(Handler::new(&path, end_point.method, handler_fn));
+/// The one matcher behind dispatch and both `match_route` methods.
+fn find_target<'a>(
+    end_points: &'a RouteTable,
+    statics: &'a [Arc<StaticMount>],
+    method: Method,
+    path: &str,
+) -> Option<Target<'a>> {
+    let wanted = route_method(method);
+    if let Some(ep) = end_points.find(wanted, path) {
+        return Some(Target::Handler(ep));
+    }
+    if !matches!(method, Method::Get | Method::Head) {
+        return None;
+    }
+    statics
+        .iter()
+        .find(|m| m.strip(path).is_some())
+        .map(|m| Target::Static(m))
+}
+
+impl Router {
+    /// Resolves a path against this router's own routes, before mounting.
+    pub fn match_route<'a>(&'a self, method: Method, path: &'a str) -> Option<Match<'a>> {
+        let wanted = route_method(method);
+        self.end_points
+            .iter()
+            .filter(|r| r.method == wanted && r.key.walk(&r.path, path, |_, _| {}))
+            .min_by_key(|r| r.key.rank())
+            .map(|r| Match {
+                path,
+                target: Target::Route(r),
+            })
+    }
+}
+
+enum StaticSource {
+    Dir(StaticDir),
+    #[cfg(feature = "embed")]
+    Embedded(EmbeddedAssets),
+}
+
+struct StaticMount {
+    prefix: String,
+    /// `prefix/*`, the name the mount goes by in route stats and matches.
+    pattern: String,
+    source: StaticSource,
+    stats: Arc<RouteCounters>,
+}
+
+impl StaticMount {
+    fn strip<'a>(&self, path: &'a str) -> Option<&'a str> {
+        if self.prefix == "/" {
+            return Some(path);
         }
-        self
+        let rest = path.strip_prefix(&self.prefix)?;
+        (rest.is_empty() || rest.starts_with('/')).then_some(rest)
     }
 
-    fn log(&self) -> Result<(), Box<dyn Error>> {
-        clearscreen::clear()?;
-        println!("Server running...\n");
-        for ep in self.end_points.iter() {
-            println!("{ep}");
+    fn serve(&self, req: &Request, ctx: &Context) -> Response {
+        let rel = self.strip(req.path()).unwrap_or_default();
+        let res = match &self.source {
+            StaticSource::Dir(dir) => dir.serve(req, rel),
+            #[cfg(feature = "embed")]
+            StaticSource::Embedded(assets) => Ok(assets.serve(req, rel)),
+        };
+        let res = match res {
+            Ok(Some(res)) => res,
+            Ok(None) => ctx.error(StatusCode::NotFound),
+            Err(e) => {
+                error!("Error serving {}: {:?}", req.path(), e);
+                ctx.error(StatusCode::InternalServerError)
+            }
+        };
+        res.charset(ctx.charset.as_deref())
+    }
+}
+
+fn join_paths(super_path: &str, path: &str) -> String {
+    let super_paths: Vec<_> = super_path.split('/').collect();
+    let base_paths: Vec<_> = path.split('/').collect();
+    let paths = [&super_paths[..], &base_paths[..]].concat();
+    let paths: Vec<_> = paths.into_iter().filter(|s| !s.is_empty()).collect();
+    "/".to_owned() + &paths.join("/")
+}
+
+#[derive(Clone, Default)]
+struct ErrorPages {
+    pages: HashMap<StatusCode, Arc<str>>,
+}
+
+impl ErrorPages {
+    fn response(&self, status: StatusCode) -> Response {
+        let body = match (self.pages.get(&status), status) {
+            (Some(page), _) => ResponseBody::Shared(Arc::clone(page).into()),
+            (None, StatusCode::NotFound) => {
+                ResponseBody::Static(include_bytes!("../static/404.html"))
+            }
+            (None, StatusCode::InternalServerError) => {
+                ResponseBody::Static(include_bytes!("../static/error.html"))
+            }
+            (None, status) => format!("<!DOCTYPE html>\n<h1>{}</h1>\n", status).into(),
+        };
+        Response::new(status)
+            .header("Content-Type", "text/html")
+            .body(body)
+    }
+}
+
+/// A route whose handler keeps the connection once the response head is
+/// out, instead of returning a `Response`.
+#[derive(Clone)]
+enum Takeover {
+    WebSocket(WebSocketHandler),
+    EventStream(SseHandler),
+}
+
+pub(crate) struct Context {
+    end_points: RouteTable,
+    statics: Vec<Arc<StaticMount>>,
+    pages: ErrorPages,
+    charset: Option<String>,
+    read_timeout: Duration,
+    write_timeout: Duration,
+    keep_alive_timeout: Duration,
+    max_requests: usize,
+    max_connections: Option<usize>,
+    shed_above: Option<usize>,
+    shed_exempt: Vec<String>,
+    max_head_size: usize,
+    max_body_size: usize,
+    connections: AtomicUsize,
+    /// Where keep-alive connections go between requests, under `poll`.
+    #[cfg(feature = "poll")]
+    parking: Option<Arc<poll::Parking>>,
+    nodelay: bool,
+    proxies: TrustedProxies,
+    shutdown: Arc<Shutdown>,
+    access_log: Option<Arc<AccessLog>>,
+    log_fields: Option<LogFields>,
+    slow_threshold: Option<Duration>,
+    redaction: Redaction,
+    on_timing: Option<TimingHook>,
+    streaming: Vec<String>,
+    metrics: Arc<Metrics>,
+    redirect: Option<HttpsRedirect>,
+    takeovers: Vec<(String, Takeover)>,
+    flash_key: Arc<[u8]>,
+    i18n: Option<Arc<I18n>>,
+    buffers: BufferPool,
+}
+
+struct HttpsRedirect {
+    host: Option<String>,
+}
+
+impl HttpsRedirect {
+    fn response(&self, req: &Request, ctx: &Context) -> Response {
+        let host = match (&self.host, req.header("Host")) {
+            (Some(host), _) => host.as_str(),
+            (None, Some(host)) if !host.is_empty() => strip_port(host),
+            (None, _) => return ctx.error(StatusCode::BadRequest),
+        };
+
+        let mut location = format!("https://{host}{}", req.path());
+        if let Some(query) = req.query() {
+            location.push('?');
+            location.push_str(query);
         }
-        println!("\nserving on - {}", self.addr);
-        Ok(())
+        Response::new(StatusCode::MovedPermanently).header("Location", &location)
+    }
+}
+
+fn strip_port(host: &str) -> &str {
+    match host.rsplit_once(':') {
+        Some((name, port))
+            if !port.is_empty()
+                && port.bytes().all(|b| b.is_ascii_digit())
+                && (name.ends_with(']') || !name.contains(':')) =>
+        {
+            name
+        }
+        _ => host,
+    }
+}
+
+static NEXT_REQUEST_ID: AtomicU64 = AtomicU64::new(1);
+
+pub(crate) const MIN_ACCEPT_BACKOFF: Duration = Duration::from_millis(10);
+const MAX_ACCEPT_BACKOFF: Duration = Duration::from_secs(1);
+
+/// Threads that answer connections turned away by `max_connections` or
+/// `shed_above`, so neither the accept loop nor the busy workers have to.
+const REJECT_WORKERS: usize = 2;
+
+/// Past this many turned-away connections waiting for those threads,
+/// further ones are just closed.
+const MAX_PENDING_REJECTS: usize = 64;
+
+#[derive(Debug, PartialEq, Eq)]
+pub(crate) enum AcceptError {
+    /// The connection died before we got to it; just take the next one.
+    Transient,
+    /// Out of file descriptors or memory; back off so the loop doesn't spin.
+    Exhausted,
+    /// The listener itself is unusable.
+    Fatal,
+}
+
+impl AcceptError {
+    pub(crate) fn classify(e: &io::Error) -> AcceptError {
+        match e.kind() {
+            ErrorKind::ConnectionAborted
+            | ErrorKind::ConnectionReset
+            | ErrorKind::ConnectionRefused
+            | ErrorKind::Interrupted
+            | ErrorKind::WouldBlock
+            | ErrorKind::TimedOut
+            | ErrorKind::PermissionDenied => AcceptError::Transient,
+            ErrorKind::InvalidInput | ErrorKind::NotConnected | ErrorKind::Unsupported => {
+                AcceptError::Fatal
+            }
+            _ => AcceptError::Exhausted,
+        }
+    }
+}
+
+/// Runs a blocking accept loop per listener: the first on the calling
+/// thread, the others on threads of their own, which come back to be
+/// joined once the first stops.
+fn accept_all(
+    listeners: Vec<(Listener, Arc<Context>)>,
+    pool: &Arc<ThreadPool>,
+    rejects: &Arc<ThreadPool>,
+) -> (io::Result<()>, Vec<JoinHandle<()>>) {
+    let mut listeners = listeners.into_iter();
+    let (first, ctx) = listeners.next().expect("server has at least one address");
+    let accepting = listeners
+        .map(|(listener, ctx)| {
+            let (pool, rejects) = (Arc::clone(pool), Arc::clone(rejects));
+            thread::spawn(move || {
+                if let Err(e) = ctx.accept(listener, &pool, &rejects) {
+                    error!("Error accepting connections: {:?}", e);
+                }
+            })
+        })
+        .collect();
+    (ctx.accept(first, pool, rejects), accepting)
+}
+
+/// What a connection brings to a worker when it has been read from before:
+/// the start of its next request, and how many it has had.
+#[derive(Default)]
+pub(crate) struct Progress {
+    pub(crate) buffer: Vec<u8>,
+    pub(crate) served: usize,
+}
+
+/// Counts a connection as open for as long as it's held, whichever thread
+/// the connection is on.
+pub(crate) struct ConnectionGuard {
+    ctx: Arc<Context>,
+}
+
+impl ConnectionGuard {
+    pub(crate) fn new(ctx: Arc<Context>) -> ConnectionGuard {
+        ctx.connections.fetch_add(1, Ordering::SeqCst);
+        ctx.metrics.active.fetch_add(1, Ordering::Relaxed);
+        ConnectionGuard { ctx }
+    }
+
+    #[cfg(feature = "poll")]
+    pub(crate) fn ctx(&self) -> &Arc<Context> {
+        &self.ctx
+    }
+}
+
+impl Drop for ConnectionGuard {
+    fn drop(&mut self) {
+        self.ctx.connections.fetch_sub(1, Ordering::SeqCst);
+        self.ctx.metrics.active.fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
+impl Context {
+    fn error(&self, status: StatusCode) -> Response {
+        self.pages.response(status).charset(self.charset.as_deref())
+    }
+
+    fn find_target(&self, req: &Request) -> Option<Target<'_>> {
+        find_target(&self.end_points, &self.statics, req.method(), req.path())
+    }
+
+    /// Decides whether a request sent with `Expect: 100-continue` is worth
+    /// reading the body for.
+    fn expect_continue(&self, req: &Request) -> Result<(), StatusCode> {
+        if self.redirect.is_some()
+            || self.find_target(req).is_some()
+            || !self.allowed(req.path()).is_empty()
+        {
+            Ok(())
+        } else {
+            Err(StatusCode::NotFound)
+        }
+    }
+
+    /// The methods a route would serve `path` for, as an Allow header
+    /// value for a 405. Empty when no route matches the path at all; static
+    /// mounts don't count, since the file may well not exist.
+    fn allowed(&self, path: &str) -> String {
+        let methods = [
+            Method::Get,
+            Method::Head,
+            Method::Post,
+            Method::Put,
+            Method::Delete,
+        ];
+        methods
+            .into_iter()
+            .filter(|&m| self.end_points.find(route_method(m), path).is_some())
+            .map(|m| m.to_string())
+            .collect::<Vec<_>>()
+            .join(", ")
     }
 
-    pub fn run(&self) -> Result<(), Box<dyn Error>> {
-        let listener = TcpListener::bind(&self.addr)?;
-        let pool = ThreadPool::new(self.pool_size);
-        self.log()?;
-        for stream in listener.incoming() {
-            let mut stream = stream?;
-            let mut buffer = [0; 1024];
-            stream.read(&mut buffer)?;
-
-            if let Some(ep) = self.end_points.iter().find(|&x| x.check(&buffer)) {
-                let f = ep.handler.clone();
-                pool.execute(move || {
-                    if let Err(e) = f(stream) {
-                        eprintln!("Error executing: {:?}", e)
+    /// Whether the request's body should be left on the connection for the
+    /// handler to read itself.
+    fn streams_body(&self, req: &Request) -> bool {
+        matches!(self.find_target(req), Some(Target::Handler(ep)) if self.streaming.contains(&ep.path))
+    }
+
+    /// Also hands back the counters of whatever served the request, so the
+    /// caller can record it once the response is written.
+    fn dispatch(
+        &self,
+        req: &mut Request,
+        clock: &mut PhaseClock,
+    ) -> (Response, Option<&RouteCounters>) {
+        let mut stats = None;
+        let target = match &self.redirect {
+            Some(_) => None,
+            None => self.find_target(req),
+        };
+        if let Some(Target::Handler(ep)) = target {
+            if let PathKey::Pattern(_) = ep.key {
+                req.params = ep.key.captures(&ep.path, req.path());
+            }
+        }
+        let req = &*req;
+        clock.timings.route = clock.lap();
+        let res = match (&self.redirect, target) {
+            (Some(redirect), _) => redirect.response(req, self),
+            (None, Some(Target::Handler(ep))) => {
+                stats = Some(ep.stats.as_ref());
+                match panic::catch_unwind(AssertUnwindSafe(|| (ep.handler)(req))) {
+                    Ok(Ok(res)) => res,
+                    Ok(Err(e)) => {
+                        error!("Error executing {}: {:?}", ep.path, e);
+                        self.error(StatusCode::InternalServerError)
+                    }
+                    Err(_) => {
+                        error!("Handler for {} panicked", ep.path);
+                        self.error(StatusCode::InternalServerError)
                     }
-                });
+                }
+            }
+            (None, Some(Target::Static(mount))) => {
+                stats = Some(mount.stats.as_ref());
+                mount.serve(req, self)
+            }
+            (None, Some(Target::Route(_)) | None) => {
+                #[cfg(feature = "tracing")]
+                tracing::debug!("no route matched");
+                stats = Some(self.metrics.not_found(req.method()));
+                match self.allowed(req.path()) {
+                    allow if allow.is_empty() => self.error(StatusCode::NotFound),
+                    allow => self
+                        .error(StatusCode::MethodNotAllowed)
+                        .header("Allow", &allow),
+                }
+            }
+        };
+
+        let res = self.flash_cookie(req, res);
+        trace!("{} {} -> {}", req.method(), req.path(), res.status());
+        if req.method() == Method::Head {
+            return (res.head(), stats);
+        }
+        (res, stats)
+    }
+
+    /// Fills in what the server knows about a request beyond its bytes: the
+    /// client address and scheme, honouring trusted proxies, and its id.
+    fn identify(&self, req: &mut Request, secure: bool) {
+        req.client_ip = self.proxies.client_ip(req);
+        req.scheme = match self.proxies.forwarded_proto(req) {
+            Some(proto) if proto.eq_ignore_ascii_case("https") => "https",
+            Some(_) => "http",
+            None if secure => "https",
+            None => "http",
+        };
+        req.id = NEXT_REQUEST_ID.fetch_add(1, Ordering::Relaxed);
+        if let Some(value) = req.cookie(flash::COOKIE) {
+            req.flashes = flash::decode(value, &self.flash_key);
+        }
+        if let Some(i18n) = &self.i18n {
+            req.lang = Some(i18n.resolve(req));
+            req.i18n = Some(Arc::clone(i18n));
+        }
+    }
+
+    /// Writes the flash cookie for what the response added, keeping any
+    /// the request brought that no handler read, or clears it once they
+    /// have been read or if it didn't verify.
+    fn flash_cookie(&self, req: &Request, mut res: Response) -> Response {
+        let added = res.take_flashes();
+        let read = req.flashes_read.load(Ordering::Relaxed);
+        let secure = req.scheme() == "https";
+        if !added.is_empty() {
+            let mut flashes = if read {
+                Vec::new()
             } else {
-                let content = fs::read_to_string("static/404.html")?;
+                req.flashes.clone()
+            };
+            flashes.extend(added);
+            let value = flash::encode(&flashes, &self.flash_key);
+            return res.header("Set-Cookie", &flash::set_cookie(Some(&value), secure));
+        }
+        if req.cookie(flash::COOKIE).is_some() && (read || req.flashes.is_empty()) {
+            return res.header("Set-Cookie", &flash::set_cookie(None, secure));
+        }
+        res
+    }
+
+    /// Everything that happens once a response has gone out: metrics, route
+    /// stats, the slow-request warning, the access log and the timing hook.
+    fn record(
+        &self,
+        req: &Request,
+        res: &Response,
+        stats: Option<&RouteCounters>,
+        elapsed: Duration,
+        body_in: u64,
+        clock: &mut PhaseClock,
+    ) {
+        self.metrics.record(
+            req.method(),
+            res.status().code(),
+            elapsed,
+            body_in as usize,
+            res.body_len(),
+        );
+        if let Some(stats) = stats {
+            stats.record(res.status().code(), elapsed);
+        }
+        if self.slow_threshold.is_some_and(|t| elapsed > t) {
+            warn!(
+                "slow request: {} {} took {:?} (remote {})",
+                req.method(),
+                self.redaction.target(req),
+                elapsed,
+                req.client_ip()
+                    .map(|ip| ip.to_string())
+                    .unwrap_or_else(|| String::from("-")),
+            );
+        }
+        if let Some(log) = &self.access_log {
+            log.record(req, res, elapsed, self.log_fields.as_ref(), &self.redaction);
+        }
+        if let Some(on_timing) = &self.on_timing {
+            on_timing(req, &clock.finish());
+        }
+    }
+
+    /// Does nothing on this thread but accept and hand off: connections go
+    /// to `pool`, and ones over `max_connections` or `shed_above` to
+    /// `rejects` for their 503, since writing it can block on a slow client
+    /// or a TLS handshake.
+    fn accept(
+        self: &Arc<Self>,
+        listener: Listener,
+        pool: &ThreadPool,
+        rejects: &ThreadPool,
+    ) -> io::Result<()> {
+        let mut backoff = MIN_ACCEPT_BACKOFF;
+        while !self.shutdown.is_stopping() {
+            let accepted = listener.accept();
+            if self.shutdown.is_stopping() {
+                break;
+            }
+            let stream = match accepted {
+                Ok(stream) => {
+                    backoff = MIN_ACCEPT_BACKOFF;
+                    stream
+                }
+                Err(e) => match AcceptError::classify(&e) {
+                    AcceptError::Transient => continue,
+                    AcceptError::Exhausted => {
+                        warn!("Error accepting connection, retrying in {backoff:?}: {e}");
+                        thread::sleep(backoff);
+                        backoff = (backoff * 2).min(MAX_ACCEPT_BACKOFF);
+                        continue;
+                    }
+                    AcceptError::Fatal => return Err(e),
+                },
+            };
+
+            if self.over_limit() {
+                self.turn_away(stream, rejects);
+                continue;
+            }
+            let guard = ConnectionGuard::new(Arc::clone(self));
+            self.hand_off(guard, stream, Progress::default(), pool, rejects);
+        }
+        Ok(())
+    }
+
+    /// How long the poll loop waits for a request head before giving up:
+    /// the keep-alive timeout while an idle connection has sent nothing,
+    /// the read timeout once a request has started.
+    #[cfg(feature = "poll")]
+    pub(crate) fn head_timeout(&self, idle: bool) -> Duration {
+        if idle {
+            self.keep_alive_timeout
+        } else {
+            self.read_timeout
+        }
+    }
+
+    /// Whether the poll loop has read enough to hand the connection to a
+    /// worker: a whole head, or more than one may be.
+    #[cfg(feature = "poll")]
+    pub(crate) fn head_ready(&self, buffer: &[u8]) -> bool {
+        buffer.len() > self.max_head_size || buffer.windows(4).any(|w| w == b"\r\n\r\n")
+    }
+
+    /// Tells a connection that sent part of a head and then stalled that
+    /// it took too long, as `Request::read_from` does on a blocking worker.
+    #[cfg(feature = "poll")]
+    pub(crate) fn timed_out(&self, w: &mut impl Write) {
+        let _ = self
+            .error(StatusCode::RequestTimeout)
+            .header("Connection", "close")
+            .write_to(w);
+    }
+
+    #[cfg(feature = "poll")]
+    pub(crate) fn buffers(&self) -> &BufferPool {
+        &self.buffers
+    }
+
+    #[cfg(feature = "poll")]
+    pub(crate) fn count_idle(&self, idle: bool) {
+        if idle {
+            self.metrics.idle.fetch_add(1, Ordering::Relaxed);
+        } else {
+            self.metrics.idle.fetch_sub(1, Ordering::Relaxed);
+        }
+    }
 
-                let res = format!(
-                    "{}\r\nContent=Length: {}\r\n\r\n{}",
-                    STATUS_NOT_FOUND,
-                    content.len(),
-                    content
-                );
+    pub(crate) fn over_limit(&self) -> bool {
+        let live = self.connections.load(Ordering::SeqCst);
+        self.max_connections.is_some_and(|max| live >= max)
+    }
+
+    /// Hands a connection over `max_connections` to `rejects` for its 503.
+    pub(crate) fn turn_away(self: &Arc<Self>, stream: Box<dyn Stream>, rejects: &ThreadPool) {
+        if rejects.queued() >= MAX_PENDING_REJECTS {
+            return;
+        }
+        let ctx = Arc::clone(self);
+        rejects.execute(move || {
+            let mut stream = stream;
+            if let Err(e) = ctx.reject(&mut stream) {
+                warn!("Error rejecting connection: {:?}", e);
+            }
+        });
+    }
+
+    /// Queues a connection for a worker, or sheds it to `rejects` when
+    /// `shed_above` says the queue is deep enough already.
+    pub(crate) fn hand_off(
+        self: &Arc<Self>,
+        guard: ConnectionGuard,
+        stream: Box<dyn Stream>,
+        progress: Progress,
+        pool: &ThreadPool,
+        rejects: &ThreadPool,
+    ) {
+        if self.shed_above.is_some_and(|max| pool.queued() > max) {
+            if rejects.queued() >= MAX_PENDING_REJECTS {
+                return;
+            }
+            rejects.execute(move || {
+                let ctx = Arc::clone(&guard.ctx);
+                if let Err(e) = ctx.handle_connection(stream, progress, Duration::ZERO, true) {
+                    warn!("Error shedding connection: {:?}", e);
+                }
+            });
+            return;
+        }
+
+        let queued_at = Instant::now();
+        pool.execute(move || {
+            let waited = queued_at.elapsed();
+            guard.ctx.metrics.record_queue_wait(waited);
+            let served = panic::catch_unwind(AssertUnwindSafe(|| {
+                guard.ctx.handle_connection(stream, progress, waited, false)
+            }));
+            match served {
+                Ok(Ok(())) => {}
+                Ok(Err(e)) => warn!("Error handling connection: {:?}", e),
+                Err(_) => error!("Connection handler panicked"),
+            }
+        });
+    }
+
+    fn reject(&self, stream: &mut Box<dyn Stream>) -> io::Result<()> {
+        // A TLS stream has to finish its handshake first, so bound the reads too.
+        stream.set_read_timeout(Some(self.write_timeout))?;
+        stream.set_write_timeout(Some(self.write_timeout))?;
+        self.error(StatusCode::ServiceUnavailable)
+            .header("Retry-After", "1")
+            .header("Connection", "close")
+            .write_to(stream)
+    }
+
+    fn takeover_for(&self, req: &Request) -> Option<Takeover> {
+        if self.redirect.is_some() || req.method() != Method::Get {
+            return None;
+        }
+        self.takeovers
+            .iter()
+            .find(|(path, _)| path == req.path())
+            .map(|(_, takeover)| takeover.clone())
+    }
 
-                stream.write(res.as_bytes())?;
-                stream.flush()?;
+    /// Hands the connection to a WebSocket or event stream handler.
+    fn take_over(
+        &self,
+        req: &Request,
+        stream: Box<dyn Stream>,
+        buffer: Vec<u8>,
+        takeover: Takeover,
+        clock: &mut PhaseClock,
+    ) -> Result<(), Box<dyn Error>> {
+        match takeover {
+            Takeover::WebSocket(handler) => self.upgrade(req, stream, buffer, handler, clock),
+            Takeover::EventStream(handler) => self.event_stream(req, stream, handler, clock),
+        }
+    }
+
+    /// Sends the event stream head and runs `handler` on this worker until
+    /// it returns or the client disconnects. The connection closes after.
+    fn event_stream(
+        &self,
+        req: &Request,
+        mut stream: Box<dyn Stream>,
+        handler: SseHandler,
+        clock: &mut PhaseClock,
+    ) -> Result<(), Box<dyn Error>> {
+        let started = Instant::now();
+        stream.write_all(sse::head(req.version()).as_bytes())?;
+        stream.flush()?;
+        clock.timings.write = clock.lap();
+        let res = Response::new(StatusCode::Ok).header("Content-Type", "text/event-stream");
+        self.record(req, &res, None, started.elapsed(), 0, clock);
+
+        let mut sender = SseSender::new(stream, Arc::clone(&self.shutdown));
+        match panic::catch_unwind(AssertUnwindSafe(|| handler(req, &mut sender))) {
+            Ok(Ok(())) => {}
+            Ok(Err(e)) if !sender.is_open() => {
+                trace!("event stream {} ended: {e}", req.path());
             }
+            Ok(Err(e)) => warn!("Event stream handler for {} failed: {e}", req.path()),
+            Err(_) => error!("Event stream handler for {} panicked", req.path()),
+        }
+        Ok(())
+    }
+
+    /// Completes the WebSocket handshake and runs `handler` on this worker
+    /// until it returns, then closes the socket. A request that can't be
+    /// upgraded gets an error response and the connection is closed.
+    fn upgrade(
+        &self,
+        req: &Request,
+        mut stream: Box<dyn Stream>,
+        buffer: Vec<u8>,
+        handler: WebSocketHandler,
+        clock: &mut PhaseClock,
+    ) -> Result<(), Box<dyn Error>> {
+        let started = Instant::now();
+        let res = match websocket::handshake(req) {
+            Ok(res) => res,
+            Err(res) => res.header("Connection", "close"),
+        };
+        let res = res.version(req.version());
+        res.write_to(&mut stream)?;
+        clock.timings.write = clock.lap();
+        self.record(req, &res, None, started.elapsed(), 0, clock);
+        if res.status() != StatusCode::SwitchingProtocols {
+            return Ok(());
         }
+
+        stream.set_read_timeout(None)?;
+        let mut socket = WebSocket::new(stream, buffer, self.max_body_size);
+        let code = match panic::catch_unwind(AssertUnwindSafe(|| handler(req, &mut socket))) {
+            Ok(Ok(())) => websocket::CLOSE_NORMAL,
+            Ok(Err(e)) => {
+                warn!("WebSocket handler for {} failed: {e}", req.path());
+                websocket::CLOSE_INTERNAL_ERROR
+            }
+            Err(_) => {
+                error!("WebSocket handler for {} panicked", req.path());
+                websocket::CLOSE_INTERNAL_ERROR
+            }
+        };
+        socket.finish(code);
         Ok(())
     }
+
+    /// Serves requests on `stream` until it closes, or under `poll` until
+    /// it goes idle and is handed back to the poll loop. With `shed`,
+    /// answers one request, with a 503 unless its path is in `shed_exempt`.
+    fn handle_connection(
+        self: &Arc<Self>,
+        mut stream: Box<dyn Stream>,
+        progress: Progress,
+        waited: Duration,
+        shed: bool,
+    ) -> Result<(), Box<dyn Error>> {
+        let mut clock = PhaseClock::new(self.on_timing.is_some());
+        clock.timings.queue = waited;
+        let mut buffer = self.buffers.checkout();
+        if !progress.buffer.is_empty() {
+            let pooled = std::mem::replace(&mut *buffer, progress.buffer);
+            self.buffers.give_back(pooled);
+        }
+        let mut out = self.buffers.checkout();
+        let mut served = progress.served;
+        let remote_addr = stream.peer_addr();
+        let secure = stream.is_secure();
+        stream.set_write_timeout(Some(self.write_timeout))?;
+        if self.nodelay {
+            stream.set_nodelay(true)?;
+        }
+        let mut closer = None;
+        loop {
+            let timeout = if served > 0 {
+                self.keep_alive_timeout
+            } else {
+                self.read_timeout
+            };
+            stream.set_read_timeout(Some(timeout))?;
+
+            if served > 0 && closer.is_none() {
+                closer = stream.closer().map(Arc::new);
+            }
+            let parked = match closer.as_ref().filter(|_| served > 0) {
+                Some(closer) => match self.shutdown.park(closer) {
+                    Some(parked) => Some(parked),
+                    None => return Ok(()),
+                },
+                None => None,
+            };
+            if parked.is_some() {
+                self.metrics.idle.fetch_add(1, Ordering::Relaxed);
+            }
+            let read = Request::read_from(
+                &mut stream,
+                &mut buffer,
+                self.max_head_size,
+                self.max_body_size,
+                |req| self.expect_continue(req),
+                |req| self.streams_body(req),
+                &mut clock,
+            );
+            if parked.is_some() {
+                self.metrics.idle.fetch_sub(1, Ordering::Relaxed);
+            }
+            drop(parked);
+            let mut req = match read {
+                Ok(req) => req,
+                Err(ReadError::Closed) => return Ok(()),
+                Err(ReadError::Io(e)) if is_timeout(&e) => return Ok(()),
+                Err(ReadError::Io(e)) => return Err(e.into()),
+                Err(ReadError::Status(status)) => {
+                    #[cfg(feature = "tracing")]
+                    tracing::debug!(status = status.code(), "rejected unreadable request");
+                    let res = self.error(status).header("Connection", "close");
+                    res.write_to(&mut stream)?;
+                    return Ok(());
+                }
+            };
+            req.remote_addr = remote_addr;
+            self.identify(&mut req, secure);
+            served += 1;
+            if log::log_enabled!(log::Level::Trace) {
+                trace!("request {}:\n{}", req.id(), self.redaction.dump(&req));
+            }
+
+            #[cfg(feature = "tracing")]
+            let span = tracing::info_span!(
+                "request",
+                method = %req.method(),
+                path = req.path(),
+                remote_addr = ?req.remote_addr(),
+                request_id = req.id(),
+                status = tracing::field::Empty,
+            );
+            #[cfg(feature = "tracing")]
+            let _entered = span.enter();
+            clock.timings.parse = clock.lap();
+
+            if shed && !self.shed_exempt.iter().any(|path| path == req.path()) {
+                self.metrics.shed.fetch_add(1, Ordering::Relaxed);
+                let res = self
+                    .error(StatusCode::ServiceUnavailable)
+                    .header("Retry-After", "1")
+                    .header("Connection", "close")
+                    .version(req.version());
+                res.write_to(&mut stream)?;
+                let body_in = req.body().len() as u64;
+                self.record(&req, &res, None, Duration::ZERO, body_in, &mut clock);
+                return Ok(());
+            }
+
+            if let Some(takeover) = self.takeover_for(&req) {
+                let buffer = std::mem::take(&mut *buffer);
+                return self.take_over(&req, stream, buffer, takeover, &mut clock);
+            }
+
+            let streamed = self.streams_body(&req);
+            if streamed {
+                stream.set_read_timeout(Some(self.read_timeout))?;
+                let conn = std::mem::replace(&mut stream, Box::new(Detached));
+                let reader = BodyReader::new(conn, std::mem::take(&mut *buffer), req.framing);
+                req.streaming = Some(Mutex::new(reader));
+            }
+
+            let started = Instant::now();
+            let (res, stats) = self.dispatch(&mut req, &mut clock);
+            clock.timings.handle = clock.lap();
+            let mut body_in = req.body().len() as u64;
+            let mut drained = true;
+            if let Some(reader) = req.streaming.take() {
+                let reader = reader.into_inner().unwrap_or_else(|e| e.into_inner());
+                let (conn, rest, read, complete) = reader.finish(self.max_body_size as u64);
+                (stream, *buffer, body_in, drained) = (conn, rest, read, complete);
+            }
+            let mut res = res.version(req.version());
+            #[cfg(feature = "tracing")]
+            span.record("status", res.status().code());
+            let keep_alive = req.keep_alive()
+                && !shed
+                && drained
+                && !res.closes()
+                && served < self.max_requests
+                && !self.shutdown.is_stopping();
+            if !keep_alive && !res.closes() {
+                res = res.header("Connection", "close");
+            } else if keep_alive && req.version() == "HTTP/1.0" {
+                res = res.header("Connection", "keep-alive");
+            }
+
+            let vectored = stream.vectored_writes();
+            let written = res.write_with(&mut stream, &mut out, vectored);
+            clock.timings.write = clock.lap();
+            // A write that timed out or hit a closed socket still counts,
+            // and is exactly the kind of request the slow log is for.
+            self.record(&req, &res, stats, started.elapsed(), body_in, &mut clock);
+            written?;
+            if !keep_alive {
+                return Ok(());
+            }
+            #[cfg(feature = "poll")]
+            if let (Some(parking), true) = (&self.parking, buffer.is_empty()) {
+                if let Some(socket) = stream.pollable() {
+                    let guard = ConnectionGuard::new(Arc::clone(self));
+                    parking.hand_back(guard, socket, served);
+                    return Ok(());
+                }
+            }
+        }
+    }
+}
+
+#[derive(Clone, Copy)]
+enum Paint {
+    Prefix,
+    Method,
+    Path,
+}
+
+#[cfg(feature = "color")]
+fn paint(text: &str, kind: Paint, colored: bool) -> String {
+    if !colored {
+        return String::from(text);
+    }
+    let fg = match kind {
+        Paint::Prefix => format!("{}{}", style::Bold, color::Fg(color::Yellow)),
+        Paint::Method => color::Fg(color::Blue).to_string(),
+        Paint::Path => color::Fg(color::Green).to_string(),
+    };
+    format!("{fg}{text}{}{}", color::Fg(color::Reset), style::Reset)
+}
+
+#[cfg(not(feature = "color"))]
+fn paint(text: &str, _kind: Paint, _colored: bool) -> String {
+    String::from(text)
+}
+
+/// What the server logs about its routes at startup.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum BannerMode {
+    /// Clear the terminal first, as long as stdout is one or a
+    /// `Server::banner_writer` is set.
+    Clear,
+    Plain,
+    Off,
+}
+
+/// Whether the banner uses ANSI colors. `Auto` colors only when stderr, where
+/// loggers usually write, is a terminal.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum ColorPolicy {
+    Auto,
+    Always,
+    Never,
+}
+
+pub struct Server {
+    addrs: Vec<String>,
+    #[cfg(unix)]
+    unix_paths: Vec<PathBuf>,
+    #[cfg(unix)]
+    unix_mode: Option<u32>,
+    listeners: Vec<Listener>,
+    redirects: Vec<(String, Option<String>)>,
+    #[cfg(feature = "tls")]
+    tls: Option<Arc<rustls::ServerConfig>>,
+    end_points: RouteTable,
+    statics: Vec<Arc<StaticMount>>,
+    error_pages: ErrorPages,
+    charset: Option<String>,
+    read_timeout: Duration,
+    write_timeout: Duration,
+    keep_alive_timeout: Duration,
+    max_requests: usize,
+    max_connections: Option<usize>,
+    shed_above: Option<usize>,
+    shed_exempt: Vec<String>,
+    max_head_size: usize,
+    max_body_size: usize,
+    nodelay: bool,
+    #[cfg(feature = "poll")]
+    polled: bool,
+    proxies: TrustedProxies,
+    shutdown: Arc<Shutdown>,
+    shutdown_timeout: Duration,
+    banner: BannerMode,
+    banner_writer: Option<Mutex<Box<dyn Write + Send>>>,
+    color: ColorPolicy,
+    access_log: Option<Arc<AccessLog>>,
+    log_fields: Option<LogFields>,
+    slow_threshold: Option<Duration>,
+    redaction: Redaction,
+    on_timing: Option<TimingHook>,
+    streaming: Vec<String>,
+    metrics: Arc<Metrics>,
+    takeovers: Vec<(String, Takeover)>,
+    flash_key: Arc<[u8]>,
+    i18n: Option<Arc<I18n>>,
+    assets: AssetManifest,
+    jobs: Vec<schedule::Job>,
+    #[cfg(feature = "socket2")]
+    listen: ListenOptions,
+    pool_size: usize,
+    debug: bool,
+    /// What `debug_routes` and `mount_openapi` show, refreshed each time the routing
+    /// table is handed to a `Context`.
+    route_table: Option<Arc<Mutex<Vec<RouteInfo>>>>,
+}
+
+#[cfg(feature = "socket2")]
+struct ListenOptions {
+    reuse_addr: bool,
+    reuse_port: bool,
+    backlog: i32,
+}
+
+#[cfg(feature = "socket2")]
+impl ListenOptions {
+    fn bind(&self, addr: &str) -> io::Result<TcpListener> {
+        use socket2::{Domain, Socket, Type};
+        use std::net::ToSocketAddrs;
+
+        let mut last_err = None;
+        for addr in addr.to_socket_addrs()? {
+            let socket = Socket::new(Domain::for_address(addr), Type::STREAM, None)?;
+            socket.set_reuse_address(self.reuse_addr)?;
+            #[cfg(all(unix, not(any(target_os = "solaris", target_os = "illumos"))))]
+            socket.set_reuse_port(self.reuse_port)?;
+            match socket.bind(&addr.into()) {
+                Ok(()) => {
+                    socket.listen(self.backlog)?;
+                    return Ok(socket.into());
+                }
+                Err(e) => last_err = Some(e),
+            }
+        }
+        Err(last_err
+            .unwrap_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "no addresses to bind")))
+    }
+}
+
+impl RequestHandler for Server {
+    fn get(&mut self, path: &str, h: impl HandlerFunc) -> &mut Self {
+        self.add_handler(path, "/", Method::Get, Arc::new(h))
+    }
+
+    fn post(&mut self, path: &str, h: impl HandlerFunc) -> &mut Self {
+        self.add_handler(path, "/", Method::Post, Arc::new(h))
+    }
+
+    fn delete(&mut self, path: &str, h: impl HandlerFunc) -> &mut Self {
+        self.add_handler(path, "/", Method::Delete, Arc::new(h))
+    }
+
+    fn put(&mut self, path: &str, h: impl HandlerFunc) -> &mut Self {
+        self.add_handler(path, "/", Method::Put, Arc::new(h))
+    }
+}
+
+impl Server {
+    /// Starts a validated configuration; see `ServerBuilder`.
+    pub fn builder() -> ServerBuilder {
+        ServerBuilder::default()
+    }
+
+    /// A server configured from the environment; see
+    /// `ServerBuilder::from_env` for the variables.
+    pub fn from_env() -> Result<Server, ConfigError> {
+        ServerBuilder::from_env()?.build()
+    }
+
+    #[cfg(unix)]
+    pub fn new_unix(path: impl AsRef<Path>, pool_size: usize) -> Server {
+        let mut server = Server::new("", pool_size);
+        server.addrs.clear();
+        server.bind_unix(path);
+        server
+    }
+
+    pub fn new(addr: &str, pool_size: usize) -> Server {
+        Server {
+            addrs: vec![String::from(addr)],
+            #[cfg(unix)]
+            unix_paths: Vec::new(),
+            #[cfg(unix)]
+            unix_mode: None,
+            listeners: Vec::new(),
+            redirects: Vec::new(),
+            #[cfg(feature = "tls")]
+            tls: None,
+            end_points: RouteTable::default(),
+            statics: Vec::new(),
+            error_pages: ErrorPages::default(),
+            charset: Some(String::from("utf-8")),
+            read_timeout: Duration::from_secs(30),
+            write_timeout: Duration::from_secs(30),
+            keep_alive_timeout: Duration::from_secs(5),
+            max_requests: 100,
+            max_connections: None,
+            shed_above: None,
+            shed_exempt: Vec::new(),
+            max_head_size: MAX_HEAD_SIZE,
+            max_body_size: MAX_BODY_SIZE,
+            nodelay: false,
+            #[cfg(feature = "poll")]
+            polled: true,
+            proxies: TrustedProxies::default(),
+            shutdown: Arc::default(),
+            shutdown_timeout: Duration::from_secs(30),
+            banner: BannerMode::Plain,
+            banner_writer: None,
+            color: ColorPolicy::Auto,
+            access_log: None,
+            log_fields: None,
+            slow_threshold: None,
+            redaction: Redaction::default(),
+            on_timing: None,
+            streaming: Vec::new(),
+            metrics: Arc::default(),
+            takeovers: Vec::new(),
+            flash_key: flash::random_key().into(),
+            i18n: None,
+            assets: AssetManifest::default(),
+            jobs: Vec::new(),
+            #[cfg(feature = "socket2")]
+            listen: ListenOptions {
+                reuse_addr: cfg!(unix),
+                reuse_port: false,
+                backlog: 128,
+            },
+            pool_size: pool_size.max(2),
+            debug: false,
+            route_table: None,
+        }
+    }
+
+    pub fn mount(&mut self, path: &str, router: Router) -> &mut Self {
+        let prefix = join_paths(path, "");
+        for end_point in router.end_points.iter() {
+            let path = join_paths(path, &end_point.path);
+            self.add_handler(&path, &prefix, end_point.method, end_point.handler.clone());
+            if let Some(doc) = &end_point.doc {
+                self.describe(doc.clone());
+            }
+        }
+        self
+    }
+
+    fn add_handler(
+        &mut self,
+        path: &str,
+        prefix: &str,
+        method: Method,
+        handler: HandlerFn,
+    ) -> &mut Self {
+        let stats = self.metrics.route(method, path);
+        let handler = Handler::new(path, prefix, method, handler, stats);
+        self.end_points.push(handler);
+        self
+    }
+
+    fn add_static(&mut self, prefix: String, source: StaticSource) -> &mut Self {
+        let pattern = format!("{}/*", prefix.trim_end_matches('/'));
+        let stats = self.metrics.route(Method::Get, &pattern);
+        self.statics.push(Arc::new(StaticMount {
+            prefix,
+            pattern,
+            source,
+            stats,
+        }));
+        self
+    }
+
+    pub fn mount_static(&mut self, path: &str, dir: StaticDir) -> &mut Self {
+        let prefix = join_paths(path, "");
+        if dir.is_fingerprinted() {
+            if let Err(e) = self.assets.add(&prefix, dir.clone()) {
+                warn!("Could not fingerprint the files mounted at {prefix}: {e}");
+            }
+        }
+        let source = StaticSource::Dir(dir);
+        self.add_static(prefix, source)
+    }
+
+    /// The fingerprinted URL for a file in a `StaticDir::fingerprinted`
+    /// mount, like `/assets/app.3f9ab2c1.css` for `/assets/app.css`, to
+    /// put in templates. Anything else is returned as it is.
+    pub fn asset_url(&self, path: &str) -> String {
+        self.assets.url(path)
+    }
+
+    /// A handle on the fingerprint table for handlers that build URLs at
+    /// request time, and for rescanning after the files change.
+    pub fn assets(&self) -> AssetManifest {
+        self.assets.clone()
+    }
+
+    pub fn error_page(
+        &mut self,
+        status: StatusCode,
+        path: impl AsRef<Path>,
+    ) -> io::Result<&mut Self> {
+        let page = fs::read_to_string(path)?;
+        self.error_pages.pages.insert(status, Arc::from(page));
+        Ok(self)
+    }
+
+    pub fn default_charset(&mut self, charset: Option<&str>) -> &mut Self {
+        self.charset = charset.map(String::from);
+        self
+    }
+
+    pub fn read_timeout(&mut self, timeout: Duration) -> &mut Self {
+        self.read_timeout = timeout.max(Duration::from_millis(1));
+        self
+    }
+
+    pub fn write_timeout(&mut self, timeout: Duration) -> &mut Self {
+        self.write_timeout = timeout.max(Duration::from_millis(1));
+        self
+    }
+
+    /// How long a connection may sit idle between requests before it is
+    /// closed. 5 seconds by default. Stopping the server closes idle
+    /// connections at once rather than waiting this out.
+    pub fn keep_alive_timeout(&mut self, timeout: Duration) -> &mut Self {
+        self.keep_alive_timeout = timeout.max(Duration::from_millis(1));
+        self
+    }
+
+    pub fn max_requests_per_connection(&mut self, max: usize) -> &mut Self {
+        self.max_requests = max.max(1);
+        self
+    }
+
+    pub fn max_connections(&mut self, max: usize) -> &mut Self {
+        self.max_connections = Some(max);
+        self
+    }
+
+    /// Sheds load once more than `queue_depth` connections are waiting for
+    /// a worker: new ones get a 503 with Retry-After straight away instead
+    /// of joining the queue, and count towards `MetricsSnapshot::shed`.
+    pub fn shed_above(&mut self, queue_depth: usize) -> &mut Self {
+        self.shed_above = Some(queue_depth);
+        self
+    }
+
+    /// Keeps answering requests for `path` while shedding load, so health
+    /// and readiness probes still reach an overloaded instance. They are
+    /// served on the threads that write the 503s rather than by the pool.
+    pub fn shed_exempt(&mut self, path: &str) -> &mut Self {
+        self.shed_exempt.push(String::from(path));
+        self
+    }
+
+    /// The most a request line and headers may take before the server
+    /// answers 431. 8 KB by default.
+    pub fn max_head_size(&mut self, max: usize) -> &mut Self {
+        self.max_head_size = max.max(1);
+        self
+    }
+
+    /// The most a buffered body may take, after decompression, before the
+    /// server answers 413. 1 MB by default.
+    pub fn max_body_size(&mut self, max: usize) -> &mut Self {
+        self.max_body_size = max;
+        self
+    }
+
+    pub fn tcp_nodelay(&mut self, nodelay: bool) -> &mut Self {
+        self.nodelay = nodelay;
+        self
+    }
+
+    /// Whether connections wait for their request heads on the poll loop,
+    /// as they do by default, or each take a worker from the start as
+    /// without the `poll` feature. Lets one build run either way.
+    #[cfg(feature = "poll")]
+    pub fn poll_connections(&mut self, polled: bool) -> &mut Self {
+        self.polled = polled;
+        self
+    }
+
+    #[cfg(feature = "socket2")]
+    pub fn reuse_addr(&mut self, reuse: bool) -> &mut Self {
+        self.listen.reuse_addr = reuse;
+        self
+    }
+
+    #[cfg(feature = "socket2")]
+    pub fn reuse_port(&mut self, reuse: bool) -> &mut Self {
+        self.listen.reuse_port = reuse;
+        self
+    }
+
+    #[cfg(feature = "socket2")]
+    pub fn backlog(&mut self, backlog: u32) -> &mut Self {
+        self.listen.backlog = backlog.clamp(1, i32::MAX as u32) as i32;
+        self
+    }
+
+    /// Peers allowed to set the client address and scheme through
+    /// `Forwarded` or `X-Forwarded-For`/`X-Forwarded-Proto`. Entries are IPs,
+    /// CIDR ranges, or `"unix"` for unix socket peers.
+    pub fn trusted_proxies(&mut self, proxies: &[&str]) -> io::Result<&mut Self> {
+        self.proxies = TrustedProxies::parse(proxies)?;
+        Ok(self)
+    }
+
+    pub fn shutdown_timeout(&mut self, timeout: Duration) -> &mut Self {
+        self.shutdown_timeout = timeout;
+        self
+    }
+
+    pub fn banner(&mut self, mode: BannerMode) -> &mut Self {
+        self.banner = mode;
+        self
+    }
+
+    /// Where `BannerMode::Clear` writes its clear-screen sequence instead
+    /// of stdout, which it then does even if `writer` isn't a terminal.
+    pub fn banner_writer(&mut self, writer: impl Write + Send + 'static) -> &mut Self {
+        self.banner_writer = Some(Mutex::new(Box::new(writer)));
+        self
+    }
+
+    pub fn color(&mut self, policy: ColorPolicy) -> &mut Self {
+        self.color = policy;
+        self
+    }
+
+    /// Writes one line per request to `writer`, flushing after each.
+    pub fn access_log(
+        &mut self,
+        writer: impl Write + Send + 'static,
+        format: LogFormat,
+    ) -> &mut Self {
+        self.access_log = Some(Arc::new(AccessLog::new(writer, format)));
+        self
+    }
+
+    /// Adds fields to every line of a `LogFormat::Json` access log.
+    pub fn access_log_fields(
+        &mut self,
+        fields: impl Fn(&Request, &Response) -> Vec<(String, String)> + Send + Sync + 'static,
+    ) -> &mut Self {
+        self.log_fields = Some(Arc::new(fields));
+        self
+    }
+
+    /// Hides these headers' values in every log line, on top of the
+    /// defaults: Authorization, Proxy-Authorization, Cookie, Set-Cookie and
+    /// X-Api-Key. Names match case-insensitively.
+    pub fn redact_headers(&mut self, names: &[&str]) -> &mut Self {
+        for name in names {
+            self.redaction.add_header(name);
+        }
+        self
+    }
+
+    /// Hides the values of these query parameters in logged request
+    /// targets and referers, on top of `token` and `access_token`.
+    pub fn redact_query_keys(&mut self, keys: &[&str]) -> &mut Self {
+        for key in keys {
+            self.redaction.add_query_key(key);
+        }
+        self
+    }
+
+    /// Logs a warning for every request that takes longer than `threshold`
+    /// from dispatch to the last byte written, or to a write that failed,
+    /// whether or not an access log is configured.
+    pub fn slow_request_threshold(&mut self, threshold: Duration) -> &mut Self {
+        self.slow_threshold = Some(threshold);
+        self
+    }
+
+    /// Leaves the body of requests to the route at `path` on the connection
+    /// instead of buffering it, with no size limit: the handler reads it
+    /// through `Request::body_reader`. `path` is the route's full pattern,
+    /// such as `/posts/:id` for every item of a resource. Whatever the
+    /// handler leaves unread is drained before the connection is reused,
+    /// or the connection is closed if that is more than the usual body
+    /// limit.
+    pub fn stream_body(&mut self, path: &str) -> &mut Self {
+        self.streaming.push(String::from(path));
+        self
+    }
+
+    /// Calls `hook` after every response with where the request's time went:
+    /// pool queue wait, parsing, route lookup, the handler and the write.
+    /// Without a hook the phases are not timed at all.
+    pub fn on_timing(
+        &mut self,
+        hook: impl Fn(&Request, &PhaseTimings) + Send + Sync + 'static,
+    ) -> &mut Self {
+        self.on_timing = Some(Arc::new(hook));
+        self
+    }
+
+    /// Counters for everything served so far, across all listeners. Cheap
+    /// enough to poll; take another snapshot to see fresh numbers.
+    pub fn metrics(&self) -> MetricsSnapshot {
+        self.metrics.snapshot()
+    }
+
+    /// Answers GET `path` with whatever `probe` reports: 200 for healthy or
+    /// degraded, 503 for unhealthy, with a small JSON body either way.
+    pub fn health_check(
+        &mut self,
+        path: &str,
+        probe: impl Fn() -> HealthStatus + Send + Sync + 'static,
+    ) -> &mut Self {
+        self.get(path, move |_req| Ok(probe().response()))
+    }
+
+    /// A readiness probe that needs no setup: 200 while the server is
+    /// accepting, 503 once a graceful shutdown has begun so load balancers
+    /// stop sending traffic while in-flight requests drain.
+    pub fn readiness_check(&mut self, path: &str) -> &mut Self {
+        let shutdown = Arc::clone(&self.shutdown);
+        self.health_check(path, move || {
+            if shutdown.is_stopping() {
+                HealthStatus::Unhealthy(String::from("shutting down"))
+            } else {
+                HealthStatus::Healthy
+            }
+        })
+    }
+
+    /// Hit counts, 5xx counts and latency for every registered route,
+    /// keyed by the pattern rather than the concrete path. Requests that
+    /// matched nothing are grouped under `metrics::NOT_FOUND_ROUTE`.
+    pub fn route_stats(&self) -> Vec<RouteStats> {
+        self.metrics.route_stats()
+    }
+
+    /// Serves `Server::metrics` at `path` in the Prometheus text format.
+    pub fn mount_metrics(&mut self, path: &str) -> &mut Self {
+        let metrics = Arc::clone(&self.metrics);
+        self.get(path, move |_req| {
+            Ok(Response::new(StatusCode::Ok)
+                .header("Content-Type", "text/plain; version=0.0.4")
+                .header("Cache-Control", "no-store")
+                .body(metrics.snapshot().to_prometheus()))
+        })
+    }
+
+    /// Accepts WebSocket connections at `path` and hands each one to
+    /// `handler`. Plain requests to the path get 426 Upgrade Required.
+    ///
+    /// A socket keeps its pool worker until the handler returns, so every
+    /// open socket is one fewer worker for ordinary requests: size the pool
+    /// for the expected number of sockets plus normal traffic, and use
+    /// `max_connections` to keep a flood of sockets from starving it.
+    /// Reads block with no timeout unless the handler sets one, and
+    /// shutdown waits at most `shutdown_timeout` for handlers to return.
+    pub fn websocket(
+        &mut self,
+        path: &str,
+        handler: impl Fn(&Request, &mut WebSocket) -> io::Result<()> + Send + Sync + 'static,
+    ) -> &mut Self {
+        let handler = Takeover::WebSocket(Arc::new(handler));
+        self.takeovers.push((String::from(path), handler));
+        self
+    }
+
+    /// Serves a Server-Sent Events stream at `path`: each GET gets a
+    /// `text/event-stream` response and `handler` then writes events
+    /// through the `SseSender` until it returns, at which point the
+    /// connection closes. Like `websocket`, each open stream holds a pool
+    /// worker for its whole life, so loop on `SseSender::is_open` and size
+    /// the pool for the streams you expect.
+    pub fn sse(
+        &mut self,
+        path: &str,
+        handler: impl Fn(&Request, &mut SseSender) -> io::Result<()> + Send + Sync + 'static,
+    ) -> &mut Self {
+        let handler = Takeover::EventStream(Arc::new(handler));
+        self.takeovers.push((String::from(path), handler));
+        self
+    }
+
+    /// Serves a JSON collection at `path` backed by a `MemStore`:
+    /// `GET path` lists it, `POST path` adds an item and answers 201 with
+    /// its URL in Location, and `GET`, `PUT` and `DELETE` on `path/:id`
+    /// read, replace and remove one. Unknown ids are a 404, other methods
+    /// a 405 with Allow, and bodies that don't parse or fail a
+    /// `Resource::before_create` hook a 400. A store
+    /// goes in as it is; wrap it in a `Resource` to add hooks. A literal
+    /// route such as `get("path/new")` still wins over the item route.
+    #[cfg(feature = "serde")]
+    pub fn resource<T>(&mut self, path: &str, resource: impl Into<Resource<T>>) -> &mut Self
+    where
+        T: serde::Serialize + serde::de::DeserializeOwned + Clone + Send + Sync + 'static,
+    {
+        let resource = Arc::new(resource.into());
+        let base = join_paths(path, "");
+        let item = join_paths(&base, ":id");
+        let routes: [(&str, Method, HandlerFn); 5] = [
+            (&base, Method::Get, {
+                let r = Arc::clone(&resource);
+                Arc::new(move |req| r.list(req))
+            }),
+            (&base, Method::Post, {
+                let r = Arc::clone(&resource);
+                Arc::new(move |req| r.create(req))
+            }),
+            (&item, Method::Get, {
+                let r = Arc::clone(&resource);
+                Arc::new(move |req| r.show(req))
+            }),
+            (&item, Method::Put, {
+                let r = Arc::clone(&resource);
+                Arc::new(move |req| r.replace(req))
+            }),
+            (&item, Method::Delete, {
+                let r = Arc::clone(&resource);
+                Arc::new(move |req| r.destroy(req))
+            }),
+        ];
+        for (path, method, handler) in routes {
+            self.add_handler(path, "/", method, handler);
+        }
+        self
+    }
+
+    /// Serves `/robots.txt` from the rules `configure` adds, such as
+    /// `|r| r.disallow("/admin").sitemap("/sitemap.xml")`.
+    pub fn robots(&mut self, configure: impl FnOnce(Robots) -> Robots) -> &mut Self {
+        let robots = configure(Robots::new());
+        self.get("/robots.txt", move |req| {
+            Ok(Response::new(StatusCode::Ok)
+                .header("Content-Type", "text/plain")
+                .body(robots.render(req)))
+        })
+    }
+
+    /// Serves a sitemap at `path` listing what `urls` returns. It is called
+    /// for every request, so the sitemap follows the data it's built
+    /// from; past `sitemap::MAX_URLS` the rest are left out and a warning
+    /// logged.
+    pub fn sitemap(
+        &mut self,
+        path: &str,
+        urls: impl Fn() -> Vec<SitemapUrl> + Send + Sync + 'static,
+    ) -> &mut Self {
+        self.get(path, move |req| {
+            Ok(Response::new(StatusCode::Ok)
+                .header("Content-Type", "application/xml")
+                .body(sitemap::render(req, &urls())))
+        })
+    }
+
+    /// Runs `job` on the thread pool every `interval` while the server
+    /// runs, starting one interval after `run` or `spawn`. A run still
+    /// going when the next is due makes that tick a skip, counted in
+    /// `MetricsSnapshot::job_overruns`, so runs never overlap. A panic is
+    /// logged and the job runs again next time. On shutdown no new runs
+    /// start, and one in progress is waited for like any request.
+    pub fn schedule(
+        &mut self,
+        interval: Duration,
+        job: impl Fn() + Send + Sync + 'static,
+    ) -> &mut Self {
+        if interval.is_zero() {
+            warn!("Not scheduling a job with a zero interval");
+            return self;
+        }
+        self.jobs.push(schedule::Job::new(interval, Arc::new(job)));
+        self
+    }
+
+    /// The key flash cookies are signed with. Each server makes up a random
+    /// one by default; set a shared key when several processes serve the
+    /// same site, so a flash set by one verifies on another.
+    pub fn flash_key(&mut self, key: &[u8]) -> &mut Self {
+        self.flash_key = key.into();
+        self
+    }
+
+    /// Serves the site in `available` languages, `default` when nothing
+    /// better matches. Each request's language is resolved once, from a
+    /// `?lang=` naming one of them or else Accept-Language, and shows up
+    /// as `Request::lang`. Static mounts then prefer `page.fr.html` to
+    /// `page.html` for French, and `Response::render_localized` does the
+    /// same for templates; both set `Content-Language` and
+    /// `Vary: Accept-Language`.
+    pub fn i18n(&mut self, default: &str, available: &[&str]) -> &mut Self {
+        self.i18n = Some(Arc::new(I18n::new(default, available)));
+        self
+    }
+
+    /// Allows development-only endpoints such as `debug_routes`. Off by
+    /// default.
+    pub fn debug(&mut self, enabled: bool) -> &mut Self {
+        self.debug = enabled;
+        self
+    }
+
+    /// Serves the routing table from `routes` at `path`, as text or, for an
+    /// `Accept: application/json` request, JSON. Routes added later still
+    /// show up. Without `debug(true)` this logs a warning and registers
+    /// nothing, so a production build can't expose it by accident.
+    pub fn debug_routes(&mut self, path: &str) -> &mut Self {
+        if !self.debug {
+            warn!("Not serving the route listing at {path}: debug mode is off");
+            return self;
+        }
+        let table = Arc::clone(self.route_table.get_or_insert_with(Arc::default));
+        self.get(path, move |req| {
+            let json = req
+                .header("Accept")
+                .is_some_and(|accept| accept.contains("application/json"));
+            let routes = table.lock().unwrap_or_else(|e| e.into_inner());
+            Ok(route_listing(&routes, json))
+        })
+    }
+
+    /// Every route and static mount, routes first as added, then static
+    /// mounts. Literal routes are tried before `resource` item routes
+    /// whatever their order here, and static mounts come last.
+    pub fn routes(&self) -> Vec<RouteInfo> {
+        let routes = self.end_points.iter().map(|ep| RouteInfo {
+            method: ep.method,
+            pattern: ep.path.clone(),
+            prefix: ep.prefix.clone(),
+            is_static: false,
+            doc: ep.doc.clone(),
+        });
+        let statics = self.statics.iter().map(|m| RouteInfo {
+            method: Method::Get,
+            pattern: m.pattern.clone(),
+            prefix: m.prefix.clone(),
+            is_static: true,
+            doc: None,
+        });
+        routes.chain(statics).collect()
+    }
+
+    /// Documents the route added last, for `openapi_json`.
+    pub fn describe(&mut self, doc: RouteDoc) -> &mut Self {
+        match self.end_points.last_mut() {
+            Some(ep) => ep.doc = Some(doc),
+            None => warn!("describe called before any route was added"),
+        }
+        self
+    }
+
+    /// A minimal OpenAPI 3.0 document for the routes: one operation per
+    /// method and path, path parameters from `:name` and `*name` segments,
+    /// and generic content entries for the types given to `describe`.
+    /// Static mounts are left out.
+    pub fn openapi_json(&self, title: &str, version: &str) -> String {
+        openapi::document(title, version, &self.routes())
+    }
+
+    /// Serves `openapi_json` at `path`, including routes added later.
+    pub fn mount_openapi(&mut self, path: &str, title: &str, version: &str) -> &mut Self {
+        let table = Arc::clone(self.route_table.get_or_insert_with(Arc::default));
+        let (title, version) = (String::from(title), String::from(version));
+        self.get(path, move |_req| {
+            let routes = table.lock().unwrap_or_else(|e| e.into_inner());
+            Ok(Response::new(StatusCode::Ok)
+                .header("Content-Type", "application/json")
+                .body(openapi::document(&title, &version, &routes)))
+        })
+    }
+
+    pub fn shutdown_handle(&self) -> ShutdownHandle {
+        ShutdownHandle {
+            inner: Arc::clone(&self.shutdown),
+        }
+    }
+
+    #[cfg(feature = "signals")]
+    pub fn graceful_on_signals(&mut self, signals: &[Signal]) -> io::Result<&mut Self> {
+        crate::signals::install(signals, Arc::clone(&self.shutdown))?;
+        Ok(self)
+    }
+
+    pub fn favicon(&mut self, path: impl AsRef<Path>) -> io::Result<&mut Self> {
+        let icon: Arc<[u8]> = fs::read(path)?.into();
+        let content_type = if icon.starts_with(b"\x89PNG\r\n\x1a\n") {
+            "image/png"
+        } else {
+            "image/x-icon"
+        };
+
+        Ok(self.get("/favicon.ico", move |_req| {
+            Ok(Response::new(StatusCode::Ok)
+                .header("Content-Type", content_type)
+                .header("Cache-Control", "public, max-age=604800")
+                .body(Arc::clone(&icon)))
+        }))
+    }
+
+    pub fn favicon_none(&mut self) -> &mut Self {
+        self.get("/favicon.ico", |_req| {
+            Ok(Response::new(StatusCode::NoContent)
+                .header("Cache-Control", "public, max-age=604800"))
+        })
+    }
+
+    #[cfg(feature = "embed")]
+    pub fn static_embedded(&mut self, path: &str, assets: &'static [Asset]) -> &mut Self {
+        let prefix = join_paths(path, "");
+        let source = StaticSource::Embedded(EmbeddedAssets::new(assets));
+        self.add_static(prefix, source)
+    }
+
+    #[cfg(feature = "socket2")]
+    fn listen_on(&self, addr: &str) -> io::Result<TcpListener> {
+        self.listen.bind(addr)
+    }
+
+    #[cfg(not(feature = "socket2"))]
+    fn listen_on(&self, addr: &str) -> io::Result<TcpListener> {
+        TcpListener::bind(addr)
+    }
+
+    fn bind_all(&self) -> Result<Vec<Listener>, ServerError> {
+        let mut listeners = Vec::with_capacity(self.addrs.len());
+        for addr in self.addrs.iter() {
+            let listener = self.listen_on(addr).map_err(|source| ServerError::Bind {
+                addr: addr.clone(),
+                source,
+            })?;
+            #[cfg(feature = "tls")]
+            if let Some(config) = &self.tls {
+                listeners.push(Listener::Tls(listener, Arc::clone(config)));
+                continue;
+            }
+            listeners.push(Listener::Tcp(listener));
+        }
+        #[cfg(unix)]
+        for path in self.unix_paths.iter() {
+            let listener = crate::stream::bind_unix(path, self.unix_mode).map_err(|source| {
+                ServerError::Bind {
+                    addr: format!("unix:{}", path.display()),
+                    source,
+                }
+            })?;
+            listeners.push(listener);
+        }
+        Ok(listeners)
+    }
+
+    /// Nothing here can stop the server starting; failures are warnings.
+    fn log(&self, listeners: &[Listener]) {
+        let addrs = listeners
+            .iter()
+            .filter_map(|l| {
+                l.describe()
+                    .map_err(|e| warn!("Could not read a listener's address: {e}"))
+                    .ok()
+            })
+            .collect::<Vec<_>>();
+
+        if self.banner == BannerMode::Clear {
+            let clear = |out: &mut dyn Write| {
+                out.write_all(b"\x1b[H\x1b[2J\x1b[3J")
+                    .and_then(|_| out.flush())
+            };
+            let cleared = match &self.banner_writer {
+                Some(writer) => clear(&mut **writer.lock().unwrap_or_else(|e| e.into_inner())),
+                None if io::stdout().is_terminal() => clear(&mut io::stdout()),
+                None => Ok(()),
+            };
+            if let Err(e) = cleared {
+                warn!("Could not clear the terminal: {e}");
+            }
+        }
+        let colored = match self.color {
+            ColorPolicy::Always => true,
+            ColorPolicy::Never => false,
+            ColorPolicy::Auto => io::stderr().is_terminal(),
+        };
+        if self.banner == BannerMode::Off {
+            info!("serving on - {}", addrs.join(", "));
+        } else {
+            info!("Server running...\n\n{}", self.banner_text(&addrs, colored));
+        }
+    }
+
+    /// Routes grouped under the prefix they were mounted at, in the order
+    /// they were added, followed by totals and the bound addresses.
+    fn banner_text(&self, addrs: &[String], colored: bool) -> String {
+        let mut groups: Vec<(&str, Vec<&Handler>)> = Vec::new();
+        for ep in self.end_points.iter() {
+            match groups.iter_mut().find(|(prefix, _)| *prefix == ep.prefix) {
+                Some((_, eps)) => eps.push(ep),
+                None => groups.push((&ep.prefix, vec![ep])),
+            }
+        }
+        let width = self
+            .end_points
+            .iter()
+            .map(|ep| ep.method.to_string().len())
+            .max()
+            .unwrap_or(0);
+
+        let mut out = String::new();
+        for (prefix, eps) in groups {
+            out.push_str(&paint(prefix, Paint::Prefix, colored));
+            out.push('\n');
+            for ep in eps {
+                let child = match ep.path.strip_prefix(prefix) {
+                    Some(rest) if prefix != "/" && rest.is_empty() => "/",
+                    Some(rest) if prefix != "/" => rest,
+                    _ => &ep.path,
+                };
+                let method = format!("{:width$}", ep.method.to_string());
+                out.push_str(&format!(
+                    "  {} {}\n",
+                    paint(&method, Paint::Method, colored),
+                    paint(child, Paint::Path, colored)
+                ));
+            }
+        }
+        for mount in self.statics.iter() {
+            out.push_str(&format!(
+                "{} (static files)\n",
+                paint(&mount.prefix, Paint::Prefix, colored)
+            ));
+        }
+
+        let plural = |n: usize, word: &str| match n {
+            1 => format!("{n} {word}"),
+            _ => format!("{n} {word}s"),
+        };
+        out.push_str(&format!(
+            "\n{}, {}\nserving on - {}",
+            plural(self.end_points.len(), "route"),
+            plural(self.statics.len(), "static mount"),
+            addrs.join(", ")
+        ));
+        out
+    }
+
+    pub fn bind(&mut self) -> Result<&mut Self, ServerError> {
+        if self.listeners.is_empty() {
+            self.listeners = self.bind_all()?;
+        }
+        Ok(self)
+    }
+
+    pub fn local_addr(&self) -> Option<SocketAddr> {
+        self.local_addrs().into_iter().next()
+    }
+
+    pub fn local_addrs(&self) -> Vec<SocketAddr> {
+        self.listeners
+            .iter()
+            .filter_map(|l| match l {
+                Listener::Tcp(l) => l.local_addr().ok(),
+                #[cfg(feature = "tls")]
+                Listener::Tls(l, _) => l.local_addr().ok(),
+                #[cfg(unix)]
+                Listener::Unix(..) => None,
+            })
+            .collect()
+    }
+
+    pub fn bind_also(&mut self, addr: &str) -> &mut Self {
+        self.addrs.push(String::from(addr));
+        self
+    }
+
+    #[cfg(feature = "tls")]
+    pub fn with_tls(
+        &mut self,
+        cert: impl AsRef<Path>,
+        key: impl AsRef<Path>,
+    ) -> io::Result<&mut Self> {
+        self.tls = Some(crate::tls::load_config(cert.as_ref(), key.as_ref())?);
+        Ok(self)
+    }
+
+    pub fn redirect_to_https(&mut self, addr: &str, target_host: Option<&str>) -> &mut Self {
+        self.redirects
+            .push((String::from(addr), target_host.map(String::from)));
+        self
+    }
+
+    #[cfg(unix)]
+    pub fn bind_unix(&mut self, path: impl AsRef<Path>) -> &mut Self {
+        self.unix_paths.push(path.as_ref().to_path_buf());
+        self
+    }
+
+    #[cfg(unix)]
+    pub fn unix_socket_mode(&mut self, mode: u32) -> &mut Self {
+        self.unix_mode = Some(mode);
+        self
+    }
+
+    pub fn run(&self) -> Result<(), ServerError> {
+        if let Some(ep) = self
+            .end_points
+            .iter()
+            .find(|ep| !ep.path.starts_with('/') || !ep.key.is_valid())
+        {
+            return Err(ServerError::InvalidRoute(ep.path.clone()));
+        }
+
+        let listeners = if self.listeners.is_empty() {
+            self.bind_all()?
+        } else {
+            self.listeners
+                .iter()
+                .map(Listener::try_clone)
+                .collect::<io::Result<_>>()?
+        };
+
+        #[cfg(feature = "poll")]
+        let parking = match self.polled {
+            true => Some(Arc::new(poll::Parking::new()?)),
+            false => None,
+        };
+        let mut redirects = Vec::with_capacity(self.redirects.len());
+        for (addr, host) in self.redirects.iter() {
+            let listener = TcpListener::bind(addr).map_err(|source| ServerError::Bind {
+                addr: addr.clone(),
+                source,
+            })?;
+            let ctx = Arc::new(Context {
+                end_points: RouteTable::default(),
+                statics: Vec::new(),
+                takeovers: Vec::new(),
+                redirect: Some(HttpsRedirect { host: host.clone() }),
+                #[cfg(feature = "poll")]
+                parking: parking.clone(),
+                ..self.context()
+            });
+            redirects.push((Listener::Tcp(listener), ctx));
+        }
+
+        let pool = Arc::new(ThreadPool::with_queue(
+            self.pool_size,
+            Arc::clone(&self.metrics.queued),
+        ));
+        let rejects = Arc::new(ThreadPool::new(REJECT_WORKERS));
+        let ctx = Arc::new(Context {
+            #[cfg(feature = "poll")]
+            parking: parking.clone(),
+            ..self.context()
+        });
+        self.log(&listeners);
+        let scheduler = (!self.jobs.is_empty()).then(|| {
+            schedule::spawn(
+                self.jobs.clone(),
+                Arc::clone(&pool),
+                Arc::clone(&self.shutdown),
+                Arc::clone(&self.metrics),
+            )
+        });
+        for (listener, _) in redirects.iter() {
+            info!("redirecting to https - {}", listener.describe()?);
+        }
+
+        let wakers = listeners
+            .iter()
+            .chain(redirects.iter().map(|(l, _)| l))
+            .map(Listener::waker)
+            .collect::<io::Result<_>>()?;
+        self.shutdown.register(wakers);
+
+        let listeners: Vec<_> = listeners
+            .into_iter()
+            .map(|l| (l, Arc::clone(&ctx)))
+            .chain(redirects)
+            .collect();
+        #[cfg(feature = "poll")]
+        let (res, accepting) = match &parking {
+            Some(parking) => {
+                let res = poll::run(&listeners, parking, &pool, &rejects, &self.shutdown);
+                (res, Vec::new())
+            }
+            None => accept_all(listeners, &pool, &rejects),
+        };
+        #[cfg(not(feature = "poll"))]
+        let (res, accepting) = accept_all(listeners, &pool, &rejects);
+        self.shutdown.trigger();
+        info!("shutting down");
+        for t in accepting.into_iter().chain(scheduler) {
+            let _ = t.join();
+        }
+
+        let pool = Arc::into_inner(pool).expect("accept loops hold no pool after joining");
+        let drained = pool.join_timeout(self.shutdown_timeout);
+        if let Some(rejects) = Arc::into_inner(rejects) {
+            // Written alongside the drain above; a straggler is left behind.
+            rejects.join_timeout(self.shutdown_timeout);
+        }
+        if !drained {
+            warn!("shutdown timed out with connections still open");
+        }
+        res?;
+        if !drained {
+            return Err(ServerError::Shutdown);
+        }
+        Ok(())
+    }
+
+    /// Runs one request through routing, handlers, error pages and logging
+    /// on the calling thread, without binding anything, and returns the
+    /// response that would have been written. Connection handling such as
+    /// keep-alive and timeouts is the only part skipped, which makes this
+    /// the way to test a routing table.
+    pub fn handle(&self, mut req: Request) -> Response {
+        let ctx = self.context();
+        let secure = req.scheme() == "https";
+        ctx.identify(&mut req, secure);
+        let mut clock = PhaseClock::new(ctx.on_timing.is_some());
+        clock.start();
+        clock.timings.parse = clock.lap();
+
+        let started = Instant::now();
+        let (res, stats) = ctx.dispatch(&mut req, &mut clock);
+        clock.timings.handle = clock.lap();
+        let res = res.version(req.version());
+        let body_in = req.body().len() as u64;
+        ctx.record(&req, &res, stats, started.elapsed(), body_in, &mut clock);
+        res
+    }
+
+    /// Serves requests from `conn` until it closes or a response ends the
+    /// connection, on the calling thread. This is the same read, dispatch
+    /// and write loop sockets get, so pipelining and keep-alive rules apply;
+    /// timeouts do not, and requests have no remote address.
+    pub fn serve_connection(
+        &self,
+        conn: impl Read + Write + Send + 'static,
+    ) -> Result<(), Box<dyn Error>> {
+        Arc::new(self.context()).handle_connection(
+            Box::new(Plain(conn)),
+            Progress::default(),
+            Duration::ZERO,
+            false,
+        )
+    }
+
+    /// Resolves a method and path against every route and static mount the
+    /// server has, exactly as a request would be dispatched.
+    pub fn match_route<'a>(&'a self, method: Method, path: &'a str) -> Option<Match<'a>> {
+        find_target(&self.end_points, &self.statics, method, path)
+            .map(|target| Match { path, target })
+    }
+
+    /// Swaps every configured listener for one plain loopback socket on a
+    /// port the OS picks, for `TestClient`.
+    pub(crate) fn ephemeral(&mut self) {
+        self.addrs = vec![String::from("127.0.0.1:0")];
+        #[cfg(unix)]
+        self.unix_paths.clear();
+        self.listeners.clear();
+        self.redirects.clear();
+        #[cfg(feature = "tls")]
+        {
+            self.tls = None;
+        }
+        self.banner = BannerMode::Off;
+    }
+
+    /// Binds and runs the server on a background thread.
+    pub fn spawn(mut self) -> Result<ServerHandle, ServerError> {
+        self.bind()?;
+        let addrs = self.local_addrs();
+        let shutdown = self.shutdown_handle();
+        let metrics = Arc::clone(&self.metrics);
+        let thread = thread::Builder::new()
+            .name(String::from("server"))
+            .spawn(move || self.run())?;
+
+        Ok(ServerHandle {
+            addrs,
+            shutdown,
+            metrics,
+            thread: Some(thread),
+        })
+    }
+
+    fn context(&self) -> Context {
+        if let Some(table) = &self.route_table {
+            *table.lock().unwrap_or_else(|e| e.into_inner()) = self.routes();
+        }
+        Context {
+            end_points: self.end_points.clone(),
+            statics: self.statics.clone(),
+            pages: self.error_pages.clone(),
+            charset: self.charset.clone(),
+            read_timeout: self.read_timeout,
+            write_timeout: self.write_timeout,
+            keep_alive_timeout: self.keep_alive_timeout,
+            max_requests: self.max_requests,
+            max_connections: self.max_connections,
+            shed_above: self.shed_above,
+            shed_exempt: self.shed_exempt.clone(),
+            max_head_size: self.max_head_size,
+            max_body_size: self.max_body_size,
+            connections: AtomicUsize::new(0),
+            #[cfg(feature = "poll")]
+            parking: None,
+            nodelay: self.nodelay,
+            proxies: self.proxies.clone(),
+            shutdown: Arc::clone(&self.shutdown),
+            access_log: self.access_log.clone(),
+            log_fields: self.log_fields.clone(),
+            slow_threshold: self.slow_threshold,
+            redaction: self.redaction.clone(),
+            on_timing: self.on_timing.clone(),
+            streaming: self.streaming.clone(),
+            metrics: Arc::clone(&self.metrics),
+            redirect: None,
+            takeovers: self.takeovers.clone(),
+            flash_key: Arc::clone(&self.flash_key),
+            i18n: self.i18n.clone(),
+            // A read and a write buffer for every worker.
+            buffers: BufferPool::new(self.pool_size * 2),
+        }
+    }
+}
+
+/// A server running on its own thread. Dropping the handle shuts the server
+/// down and waits for it, so a forgotten handle never leaks a listener.
+pub struct ServerHandle {
+    addrs: Vec<SocketAddr>,
+    shutdown: ShutdownHandle,
+    metrics: Arc<Metrics>,
+    thread: Option<JoinHandle<Result<(), ServerError>>>,
+}
+
+impl ServerHandle {
+    pub fn local_addr(&self) -> Option<SocketAddr> {
+        self.addrs.first().copied()
+    }
+
+    pub fn local_addrs(&self) -> &[SocketAddr] {
+        &self.addrs
+    }
+
+    pub fn shutdown(&self) {
+        self.shutdown.shutdown();
+    }
+
+    pub fn metrics(&self) -> MetricsSnapshot {
+        self.metrics.snapshot()
+    }
+
+    /// Waits for the server to stop, returning whatever `run` returned.
+    pub fn join(mut self) -> Result<(), ServerError> {
+        match self.thread.take().map(JoinHandle::join) {
+            Some(Ok(res)) => res,
+            Some(Err(_)) => Err(io::Error::other("server thread panicked").into()),
+            None => Ok(()),
+        }
+    }
+}
+
+impl Drop for ServerHandle {
+    fn drop(&mut self) {
+        if let Some(thread) = self.thread.take() {
+            self.shutdown.shutdown();
+            let _ = thread.join();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn accept_errors_are_classified() {
+        let kind = |kind: ErrorKind| AcceptError::classify(&io::Error::from(kind));
+        assert_eq!(kind(ErrorKind::ConnectionAborted), AcceptError::Transient);
+        assert_eq!(kind(ErrorKind::ConnectionReset), AcceptError::Transient);
+        assert_eq!(kind(ErrorKind::InvalidInput), AcceptError::Fatal);
+        assert_eq!(kind(ErrorKind::OutOfMemory), AcceptError::Exhausted);
+        // EMFILE: the process is out of file descriptors.
+        let emfile = io::Error::from_raw_os_error(24);
+        assert_eq!(AcceptError::classify(&emfile), AcceptError::Exhausted);
+    }
 }
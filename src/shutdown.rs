@@ -0,0 +1,116 @@
+use crate::stream::Closer;
+use std::{
+    collections::HashMap,
+    net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr, TcpStream},
+    sync::{
+        atomic::{AtomicBool, AtomicU64, Ordering},
+        Arc, Mutex,
+    },
+    time::Duration,
+};
+#[cfg(unix)]
+use std::{os::unix::net::UnixStream, path::PathBuf};
+
+pub(crate) enum Waker {
+    Tcp(SocketAddr),
+    #[cfg(unix)]
+    Unix(PathBuf),
+}
+
+impl Waker {
+    /// Connects to the listener so a thread blocked in `accept` gets to see
+    /// that the server is stopping.
+    fn wake(&self) {
+        match self {
+            Waker::Tcp(addr) => {
+                let mut addr = *addr;
+                if addr.ip().is_unspecified() {
+                    addr.set_ip(match addr.ip() {
+                        IpAddr::V4(_) => IpAddr::V4(Ipv4Addr::LOCALHOST),
+                        IpAddr::V6(_) => IpAddr::V6(Ipv6Addr::LOCALHOST),
+                    });
+                }
+                let _ = TcpStream::connect_timeout(&addr, Duration::from_secs(1));
+            }
+            #[cfg(unix)]
+            Waker::Unix(path) => {
+                let _ = UnixStream::connect(path);
+            }
+        }
+    }
+}
+
+#[derive(Default)]
+pub(crate) struct Shutdown {
+    stopping: AtomicBool,
+    wakers: Mutex<Vec<Waker>>,
+    idle: Mutex<HashMap<u64, Arc<Closer>>>,
+    next_idle: AtomicU64,
+}
+
+/// A keep-alive connection on the list `trigger` closes, for as long as
+/// this is held.
+pub(crate) struct Parked<'a> {
+    shutdown: &'a Shutdown,
+    id: u64,
+}
+
+impl Drop for Parked<'_> {
+    fn drop(&mut self) {
+        let mut idle = self.shutdown.idle.lock().unwrap_or_else(|e| e.into_inner());
+        idle.remove(&self.id);
+    }
+}
+
+impl Shutdown {
+    pub(crate) fn is_stopping(&self) -> bool {
+        self.stopping.load(Ordering::SeqCst)
+    }
+
+    pub(crate) fn register(&self, wakers: Vec<Waker>) {
+        self.wakers.lock().unwrap().extend(wakers);
+    }
+
+    /// Lists a connection waiting for its next request, so that stopping
+    /// closes it instead of leaving it to the keep-alive timeout. `None`
+    /// once stopping, when the connection should close now.
+    pub(crate) fn park(&self, closer: &Arc<Closer>) -> Option<Parked<'_>> {
+        let mut idle = self.idle.lock().unwrap_or_else(|e| e.into_inner());
+        if self.is_stopping() {
+            return None;
+        }
+        let id = self.next_idle.fetch_add(1, Ordering::Relaxed);
+        idle.insert(id, Arc::clone(closer));
+        Some(Parked { shutdown: self, id })
+    }
+
+    pub(crate) fn trigger(&self) {
+        let wakers = {
+            let mut wakers = self.wakers.lock().unwrap();
+            self.stopping.store(true, Ordering::SeqCst);
+            std::mem::take(&mut *wakers)
+        };
+        for waker in wakers.iter() {
+            waker.wake();
+        }
+        let idle = self.idle.lock().unwrap_or_else(|e| e.into_inner());
+        for closer in idle.values() {
+            closer.close();
+        }
+    }
+}
+
+/// Stops a running server from another thread. Clone it freely; the first
+/// `shutdown` wins and later calls do nothing.
+#[derive(Clone)]
+pub struct ShutdownHandle {
+    pub(crate) inner: Arc<Shutdown>,
+}
+
+impl ShutdownHandle {
+    /// Stops accepting connections. `run` then waits for in-flight requests
+    /// to finish, up to the server's shutdown timeout, and returns `Ok`.
+    pub fn shutdown(&self) {
+        self.inner.trigger();
+    }
+}
@@ -0,0 +1,103 @@
+use crate::shutdown::Shutdown;
+use std::{io, sync::Arc};
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Signal {
+    Int,
+    Term,
+}
+
+/// Ties the given signals to `shutdown`. The first one starts a graceful
+/// shutdown; a second one exits the process straight away.
+#[cfg(unix)]
+pub(crate) fn install(signals: &[Signal], shutdown: Arc<Shutdown>) -> io::Result<()> {
+    unix::install(signals, shutdown)
+}
+
+/// Signals only exist on unix; elsewhere this does nothing.
+#[cfg(not(unix))]
+pub(crate) fn install(_signals: &[Signal], _shutdown: Arc<Shutdown>) -> io::Result<()> {
+    Ok(())
+}
+
+#[cfg(unix)]
+mod unix {
+    use super::Signal;
+    use crate::shutdown::Shutdown;
+    use std::{
+        io,
+        sync::{
+            atomic::{AtomicI32, AtomicUsize, Ordering},
+            Arc, Mutex, OnceLock,
+        },
+        thread,
+    };
+
+    static RECEIVED: AtomicUsize = AtomicUsize::new(0);
+    static PIPE: AtomicI32 = AtomicI32::new(-1);
+    static TARGETS: Mutex<Vec<Arc<Shutdown>>> = Mutex::new(Vec::new());
+    static WATCHER: OnceLock<io::Result<()>> = OnceLock::new();
+
+    // Only async-signal-safe calls in here: the real work happens on the
+    // watcher thread once it reads the byte from the pipe.
+    extern "C" fn on_signal(_: libc::c_int) {
+        if RECEIVED.fetch_add(1, Ordering::SeqCst) > 0 {
+            unsafe { libc::_exit(1) };
+        }
+        let fd = PIPE.load(Ordering::SeqCst);
+        if fd >= 0 {
+            unsafe { libc::write(fd, b"x".as_ptr().cast(), 1) };
+        }
+    }
+
+    fn start_watcher() -> io::Result<()> {
+        let mut fds = [0; 2];
+        if unsafe { libc::pipe(fds.as_mut_ptr()) } != 0 {
+            return Err(io::Error::last_os_error());
+        }
+        PIPE.store(fds[1], Ordering::SeqCst);
+
+        thread::Builder::new()
+            .name(String::from("signals"))
+            .spawn(move || {
+                let mut byte = 0u8;
+                loop {
+                    let n = unsafe { libc::read(fds[0], (&mut byte as *mut u8).cast(), 1) };
+                    if n == 1 {
+                        for shutdown in TARGETS.lock().unwrap().iter() {
+                            shutdown.trigger();
+                        }
+                    } else if n == 0
+                        || io::Error::last_os_error().kind() != io::ErrorKind::Interrupted
+                    {
+                        break;
+                    }
+                }
+            })?;
+        Ok(())
+    }
+
+    pub(super) fn install(signals: &[Signal], shutdown: Arc<Shutdown>) -> io::Result<()> {
+        if let Err(e) = WATCHER.get_or_init(start_watcher) {
+            return Err(io::Error::new(e.kind(), e.to_string()));
+        }
+        TARGETS.lock().unwrap().push(shutdown);
+
+        for signal in signals {
+            let signum = match signal {
+                Signal::Int => libc::SIGINT,
+                Signal::Term => libc::SIGTERM,
+            };
+            unsafe {
+                let mut action: libc::sigaction = std::mem::zeroed();
+                action.sa_sigaction = on_signal as extern "C" fn(libc::c_int) as libc::sighandler_t;
+                action.sa_flags = libc::SA_RESTART;
+                libc::sigemptyset(&mut action.sa_mask);
+                if libc::sigaction(signum, &action, std::ptr::null_mut()) != 0 {
+                    return Err(io::Error::last_os_error());
+                }
+            }
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,107 @@
+use crate::{date::DateTime, encoding::html_escape, request::Request};
+use log::warn;
+use std::{fmt::Display, time::SystemTime};
+
+/// The most URLs one sitemap file may list.
+pub const MAX_URLS: usize = 50_000;
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum ChangeFreq {
+    Always,
+    Hourly,
+    Daily,
+    Weekly,
+    Monthly,
+    Yearly,
+    Never,
+}
+
+impl Display for ChangeFreq {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            ChangeFreq::Always => "always",
+            ChangeFreq::Hourly => "hourly",
+            ChangeFreq::Daily => "daily",
+            ChangeFreq::Weekly => "weekly",
+            ChangeFreq::Monthly => "monthly",
+            ChangeFreq::Yearly => "yearly",
+            ChangeFreq::Never => "never",
+        })
+    }
+}
+
+/// One `<url>` of a sitemap. A `loc` starting with `/` is made absolute
+/// with the scheme and Host of the request for the sitemap.
+#[derive(Clone, Debug)]
+pub struct SitemapUrl {
+    loc: String,
+    lastmod: Option<SystemTime>,
+    changefreq: Option<ChangeFreq>,
+}
+
+impl SitemapUrl {
+    pub fn new(loc: &str) -> SitemapUrl {
+        SitemapUrl {
+            loc: String::from(loc),
+            lastmod: None,
+            changefreq: None,
+        }
+    }
+
+    pub fn lastmod(mut self, lastmod: SystemTime) -> SitemapUrl {
+        self.lastmod = Some(lastmod);
+        self
+    }
+
+    pub fn changefreq(mut self, changefreq: ChangeFreq) -> SitemapUrl {
+        self.changefreq = Some(changefreq);
+        self
+    }
+}
+
+/// The sitemap XML for `urls`, cut off at `MAX_URLS` with a warning.
+pub(crate) fn render(req: &Request, urls: &[SitemapUrl]) -> String {
+    if urls.len() > MAX_URLS {
+        warn!(
+            "Sitemap {} lists {} URLs; only the first {MAX_URLS} are served",
+            req.path(),
+            urls.len()
+        );
+    }
+    let origin = origin(req);
+    let mut out = String::from(r#"<?xml version="1.0" encoding="UTF-8"?>"#);
+    out.push_str("\n<urlset xmlns=\"http://www.sitemaps.org/schemas/sitemap/0.9\">\n");
+    for url in urls.iter().take(MAX_URLS) {
+        out.push_str("<url><loc>");
+        out.push_str(&html_escape(&absolute(&origin, &url.loc)));
+        out.push_str("</loc>");
+        if let Some(lastmod) = url.lastmod {
+            let lastmod = DateTime::from_system_time(lastmod).rfc3339();
+            out.push_str(&format!("<lastmod>{lastmod}</lastmod>"));
+        }
+        if let Some(changefreq) = url.changefreq {
+            out.push_str(&format!("<changefreq>{changefreq}</changefreq>"));
+        }
+        out.push_str("</url>\n");
+    }
+    out.push_str("</urlset>\n");
+    out
+}
+
+/// `scheme://host` for the site `req` was sent to, going by its Host
+/// header.
+pub(crate) fn origin(req: &Request) -> String {
+    let host = req
+        .header("Host")
+        .filter(|h| !h.is_empty())
+        .unwrap_or("localhost");
+    format!("{}://{host}", req.scheme())
+}
+
+pub(crate) fn absolute(origin: &str, url: &str) -> String {
+    if url.starts_with('/') {
+        format!("{origin}{url}")
+    } else {
+        String::from(url)
+    }
+}
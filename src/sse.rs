@@ -0,0 +1,146 @@
+use crate::{request::Request, shutdown::Shutdown, stream::Stream};
+use std::{
+    io::{self, ErrorKind, Write},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        mpsc::{self, RecvTimeoutError},
+        Arc, Mutex,
+    },
+    thread,
+    time::Duration,
+};
+
+pub(crate) type SseHandler = Arc<dyn Fn(&Request, &mut SseSender) -> io::Result<()> + Send + Sync>;
+
+/// How often a comment line goes out, so proxies don't time an idle stream
+/// out and a vanished client is noticed.
+const KEEP_ALIVE: Duration = Duration::from_secs(15);
+
+/// The head of an event stream. No Content-Length: the body runs until
+/// the connection closes.
+pub(crate) fn head(version: &str) -> String {
+    format!(
+        "{version} 200 OK\r\nContent-Type: text/event-stream\r\nCache-Control: no-cache\r\nX-Accel-Buffering: no\r\nConnection: close\r\n\r\n"
+    )
+}
+
+/// Writes events to one `Server::sse` client. Every write is flushed
+/// straight away. Once a write fails, the client is gone: `is_open` turns
+/// false and further sends fail with `BrokenPipe`.
+pub struct SseSender {
+    stream: Arc<Mutex<Box<dyn Stream>>>,
+    closed: Arc<AtomicBool>,
+    shutdown: Arc<Shutdown>,
+    keep_alive: mpsc::Sender<Duration>,
+}
+
+impl SseSender {
+    pub(crate) fn new(stream: Box<dyn Stream>, shutdown: Arc<Shutdown>) -> SseSender {
+        let stream = Arc::new(Mutex::new(stream));
+        let closed = Arc::new(AtomicBool::new(false));
+        let (keep_alive, interval) = mpsc::channel();
+        {
+            let (stream, closed) = (Arc::clone(&stream), Arc::clone(&closed));
+            thread::spawn(move || keep_alive_loop(&stream, &closed, interval));
+        }
+        SseSender {
+            stream,
+            closed,
+            shutdown,
+            keep_alive,
+        }
+    }
+
+    /// False once the client has disconnected or the server is shutting
+    /// down; a handler's send loop should stop then.
+    pub fn is_open(&self) -> bool {
+        !self.closed.load(Ordering::Relaxed) && !self.shutdown.is_stopping()
+    }
+
+    /// Sends one event. Each line of `data` gets its own `data:` field, so
+    /// embedded newlines survive; `event` names the event type and can't
+    /// contain a line break.
+    pub fn send(&mut self, event: Option<&str>, data: &str) -> io::Result<()> {
+        self.write(&frame(None, event, data)?)
+    }
+
+    /// Like `send`, with an id the browser hands back in `Last-Event-ID`
+    /// when it reconnects.
+    pub fn send_with_id(&mut self, id: &str, event: Option<&str>, data: &str) -> io::Result<()> {
+        self.write(&frame(Some(id), event, data)?)
+    }
+
+    /// How long the browser waits before reconnecting if the stream drops.
+    pub fn retry(&mut self, after: Duration) -> io::Result<()> {
+        self.write(&format!("retry: {}\n\n", after.as_millis()))
+    }
+
+    /// A comment line, ignored by `EventSource`.
+    pub fn comment(&mut self, text: &str) -> io::Result<()> {
+        let lines = text.lines().map(|l| format!(": {l}\n")).collect::<String>();
+        self.write(&format!("{lines}\n"))
+    }
+
+    /// Changes how often the keep-alive comment is sent. Fifteen seconds
+    /// by default.
+    pub fn keep_alive(&mut self, interval: Duration) {
+        let _ = self.keep_alive.send(interval);
+    }
+
+    fn write(&mut self, text: &str) -> io::Result<()> {
+        write_to(&self.stream, &self.closed, text)
+    }
+}
+
+fn frame(id: Option<&str>, event: Option<&str>, data: &str) -> io::Result<String> {
+    let mut out = String::new();
+    for (name, value) in [("id", id), ("event", event)] {
+        let Some(value) = value else { continue };
+        if value.contains(['\r', '\n']) {
+            return Err(io::Error::new(
+                ErrorKind::InvalidInput,
+                format!("SSE {name} contains a line break"),
+            ));
+        }
+        out.push_str(&format!("{name}: {value}\n"));
+    }
+    for line in data.split("\r\n").flat_map(|l| l.split(['\r', '\n'])) {
+        out.push_str(&format!("data: {line}\n"));
+    }
+    out.push('\n');
+    Ok(out)
+}
+
+fn write_to(stream: &Mutex<Box<dyn Stream>>, closed: &AtomicBool, text: &str) -> io::Result<()> {
+    if closed.load(Ordering::Relaxed) {
+        return Err(ErrorKind::BrokenPipe.into());
+    }
+    let mut stream = stream.lock().unwrap_or_else(|e| e.into_inner());
+    let res = stream
+        .write_all(text.as_bytes())
+        .and_then(|_| stream.flush());
+    if res.is_err() {
+        closed.store(true, Ordering::Relaxed);
+    }
+    res
+}
+
+/// Runs until the sender is dropped or the client goes away.
+fn keep_alive_loop(
+    stream: &Mutex<Box<dyn Stream>>,
+    closed: &AtomicBool,
+    interval: mpsc::Receiver<Duration>,
+) {
+    let mut every = KEEP_ALIVE;
+    loop {
+        match interval.recv_timeout(every) {
+            Ok(new) => every = new,
+            Err(RecvTimeoutError::Timeout) => {
+                if write_to(stream, closed, ": keep-alive\n\n").is_err() {
+                    return;
+                }
+            }
+            Err(RecvTimeoutError::Disconnected) => return,
+        }
+    }
+}
@@ -0,0 +1,619 @@
+use crate::{
+    assets::Fingerprints,
+    date::DateTime,
+    encoding::{fnv1a, html_escape, percent_decode, percent_encode},
+    file_cache::{CachedFile, FileCache},
+    i18n, mime,
+    request::Request,
+    response::{Response, ResponseBody, StatusCode},
+    server::Method,
+};
+use std::{
+    fs::{self, File, Metadata},
+    io::{self, Seek, SeekFrom},
+    path::{Path, PathBuf},
+    sync::{Arc, RwLock},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+/// Sent with fingerprinted files: their contents never change under that
+/// name, so caches may keep them for a year without asking.
+const IMMUTABLE: &str = "public, max-age=31536000, immutable";
+
+#[derive(Clone)]
+pub struct StaticDir {
+    root: PathBuf,
+    listing: bool,
+    hidden: bool,
+    index_file: String,
+    spa_fallback: bool,
+    fallback_file: Option<PathBuf>,
+    cache_control: Option<String>,
+    ext_cache_control: Vec<(String, String)>,
+    prefix_cache_control: Vec<(String, String)>,
+    cache: Option<Arc<FileCache>>,
+    precompressed: bool,
+    exclude: Vec<String>,
+    fingerprints: Option<Arc<RwLock<Fingerprints>>>,
+}
+
+pub fn static_dir(root: impl AsRef<Path>) -> StaticDir {
+    StaticDir::new(root)
+}
+
+impl StaticDir {
+    pub fn new(root: impl AsRef<Path>) -> StaticDir {
+        StaticDir {
+            root: root.as_ref().to_path_buf(),
+            listing: false,
+            hidden: false,
+            index_file: String::from("index.html"),
+            spa_fallback: false,
+            fallback_file: None,
+            cache_control: None,
+            ext_cache_control: Vec::new(),
+            prefix_cache_control: Vec::new(),
+            cache: None,
+            precompressed: false,
+            exclude: Vec::new(),
+            fingerprints: None,
+        }
+    }
+
+    pub fn with_listing(mut self, listing: bool) -> StaticDir {
+        self.listing = listing;
+        self
+    }
+
+    pub fn with_hidden(mut self, hidden: bool) -> StaticDir {
+        self.hidden = hidden;
+        self
+    }
+
+    pub fn index_file(mut self, name: &str) -> StaticDir {
+        self.index_file = String::from(name);
+        self
+    }
+
+    pub fn spa_fallback(mut self, enabled: bool) -> StaticDir {
+        self.spa_fallback = enabled;
+        self
+    }
+
+    pub fn fallback_file(mut self, path: impl AsRef<Path>) -> StaticDir {
+        self.spa_fallback = true;
+        self.fallback_file = Some(path.as_ref().to_path_buf());
+        self
+    }
+
+    pub fn cache_control(mut self, value: &str) -> StaticDir {
+        self.cache_control = Some(String::from(value));
+        self
+    }
+
+    pub fn cache_control_for_ext(mut self, ext: &str, value: &str) -> StaticDir {
+        let ext = ext.trim_start_matches('.').to_ascii_lowercase();
+        self.ext_cache_control.push((ext, String::from(value)));
+        self
+    }
+
+    pub fn cache_control_for_prefix(mut self, prefix: &str, value: &str) -> StaticDir {
+        let prefix = prefix.trim_matches('/');
+        self.prefix_cache_control
+            .push((String::from(prefix), String::from(value)));
+        self
+    }
+
+    pub fn with_cache(mut self, cache: Arc<FileCache>) -> StaticDir {
+        self.cache = Some(cache);
+        self
+    }
+
+    pub fn with_precompressed(mut self, precompressed: bool) -> StaticDir {
+        self.precompressed = precompressed;
+        self
+    }
+
+    pub fn exclude(mut self, pattern: &str) -> StaticDir {
+        self.exclude
+            .push(String::from(pattern.trim_start_matches('/')));
+        self
+    }
+
+    /// Hashes the files when the directory is mounted, so each can also
+    /// be fetched as `name.<hash>.ext` with an immutable Cache-Control.
+    /// `Server::asset_url` gives those names. Requests for the plain names
+    /// get `no-cache` unless a `cache_control` rule says otherwise.
+    pub fn fingerprinted(mut self, enabled: bool) -> StaticDir {
+        self.fingerprints = enabled.then(Arc::default);
+        self
+    }
+
+    pub(crate) fn is_fingerprinted(&self) -> bool {
+        self.fingerprints.is_some()
+    }
+
+    pub(crate) fn fingerprint_of(&self, rel: &str) -> Option<String> {
+        let fingerprints = self.fingerprints.as_ref()?;
+        let fingerprints = fingerprints.read().unwrap_or_else(|e| e.into_inner());
+        fingerprints.forward.get(rel).cloned()
+    }
+
+    /// Rebuilds the fingerprint table from what is on disk now, leaving
+    /// out hidden and excluded files.
+    pub(crate) fn scan_fingerprints(&self) -> io::Result<()> {
+        let Some(fingerprints) = &self.fingerprints else {
+            return Ok(());
+        };
+        let mut table = Fingerprints::default();
+        let mut dirs = vec![(self.root.clone(), String::new())];
+        while let Some((dir, dir_rel)) = dirs.pop() {
+            for entry in fs::read_dir(&dir)? {
+                let entry = entry?;
+                let name = entry.file_name().to_string_lossy().into_owned();
+                let rel = join_rel(&dir_rel, &name);
+                if self.excluded(&rel) {
+                    continue;
+                }
+                let path = entry.path();
+                if path.is_dir() {
+                    dirs.push((path, rel));
+                } else if path.is_file() {
+                    let hash = fnv1a(&fs::read(&path)?) as u32;
+                    let fingerprinted = match rel.rsplit_once('.') {
+                        Some((stem, ext)) if !stem.ends_with('/') && !ext.contains('/') => {
+                            format!("{stem}.{hash:08x}.{ext}")
+                        }
+                        _ => format!("{rel}.{hash:08x}"),
+                    };
+                    table.reverse.insert(fingerprinted.clone(), rel.clone());
+                    table.forward.insert(rel, fingerprinted);
+                }
+            }
+        }
+        *fingerprints.write().unwrap_or_else(|e| e.into_inner()) = table;
+        Ok(())
+    }
+
+    pub(crate) fn serve(&self, req: &Request, rel: &str) -> io::Result<Option<Response>> {
+        if let Some(fingerprints) = &self.fingerprints {
+            let real = percent_decode(rel.trim_start_matches('/')).and_then(|rel| {
+                let fingerprints = fingerprints.read().unwrap_or_else(|e| e.into_inner());
+                fingerprints.reverse.get(&rel).cloned()
+            });
+            if let Some(path) = real.and_then(|real| self.resolve_decoded(&real)) {
+                return self.file_as(req, &path, true).map(Some);
+            }
+        }
+        let path = match self.resolve(rel) {
+            Some(path) => path,
+            None => return self.fallback(req, rel),
+        };
+
+        if !path.is_dir() {
+            return self.file(req, &path).map(Some);
+        }
+
+        let index = path.join(&self.index_file);
+        if !index.is_file() && !self.listing {
+            return Ok(None);
+        }
+
+        if !req.path().ends_with('/') {
+            let location = match req.query() {
+                Some(query) => format!("{}/?{}", req.path(), query),
+                None => format!("{}/", req.path()),
+            };
+            let res = Response::new(StatusCode::MovedPermanently).header("Location", &location);
+            return Ok(Some(res));
+        }
+
+        if index.is_file() {
+            return self.file(req, &index).map(Some);
+        }
+        self.listing(req.path(), rel, &path).map(Some)
+    }
+
+    fn fallback(&self, req: &Request, rel: &str) -> io::Result<Option<Response>> {
+        let last = rel.rsplit('/').next().unwrap_or_default();
+        if !self.spa_fallback || last.contains('.') || !prefers_html(req.header("Accept")) {
+            return Ok(None);
+        }
+
+        let path = match &self.fallback_file {
+            Some(file) => self.root.join(file),
+            None => self.root.join(&self.index_file),
+        };
+        if !path.is_file() {
+            return Ok(None);
+        }
+        self.file(req, &path).map(Some)
+    }
+
+    fn file(&self, req: &Request, path: &Path) -> io::Result<Response> {
+        self.file_as(req, path, false)
+    }
+
+    fn file_as(&self, req: &Request, path: &Path, fingerprinted: bool) -> io::Result<Response> {
+        // Only pages get language variants; scripts and images are shared.
+        let localized = match mime::from_path(path) {
+            "text/html" => i18n::localize(req, path),
+            _ => None,
+        };
+        let path = localized.as_ref().map_or(path, |(variant, _)| variant);
+        let (file_path, encoding) = self.negotiate(req, path);
+        let file_path = file_path.as_path();
+
+        let head = req.method() == Method::Head;
+        // A range is read straight from disk, so the cache never loads a
+        // whole file to send part of it.
+        let cached = match &self.cache {
+            Some(cache) if !head && req.header("Range").is_none() => cache.load(file_path)?,
+            _ => None,
+        };
+        let source = match cached {
+            Some(file) => Source::Cached(file),
+            None if head => Source::Meta(fs::metadata(file_path)?),
+            None => Source::Disk(File::open(file_path)?),
+        };
+        let (etag, modified, len) = match &source {
+            Source::Cached(file) => (file.etag.clone(), file.modified, file.body.len() as u64),
+            Source::Disk(file) => validators(&file.metadata()?)?,
+            Source::Meta(meta) => validators(meta)?,
+        };
+
+        let range = match req.header("Range") {
+            Some(range) => parse_range(range, len),
+            None => Ok(None),
+        };
+
+        let mut res = if req.if_none_match(&etag) {
+            Response::new(StatusCode::NotModified)
+        } else if range.is_err() {
+            Response::new(StatusCode::RangeNotSatisfiable)
+                .header("Content-Range", &format!("bytes */{}", len))
+        } else if let Ok(Some((start, end))) = range {
+            let res = Response::new(StatusCode::PartialContent)
+                .header("Content-Type", mime::from_path(path))
+                .header("Content-Range", &format!("bytes {}-{}/{}", start, end, len));
+            match source {
+                Source::Cached(file) if !head => {
+                    res.body(&file.body[start as usize..=end as usize])
+                }
+                Source::Disk(mut file) if !head => {
+                    file.seek(SeekFrom::Start(start))?;
+                    res.body_stream(file, end - start + 1)
+                }
+                _ => res.content_length(end - start + 1),
+            }
+        } else {
+            let res = Response::new(StatusCode::Ok)
+                .header("Content-Type", mime::from_path(path))
+                .header("Accept-Ranges", "bytes");
+            match source {
+                Source::Cached(file) if !head => {
+                    res.body(ResponseBody::Shared(Arc::clone(&file.body)))
+                }
+                Source::Disk(file) if !head => res.body_stream(file, len),
+                _ => res.content_length(len),
+            }
+        };
+
+        if let Some(encoding) = encoding {
+            res = res.header("Content-Encoding", encoding);
+        }
+        if let Some((_, lang)) = &localized {
+            res = res.header("Content-Language", lang);
+        }
+        match (self.precompressed, localized.is_some()) {
+            (true, true) => res = res.header("Vary", "Accept-Encoding, Accept-Language"),
+            (true, false) => res = res.header("Vary", "Accept-Encoding"),
+            (false, true) => res = res.header("Vary", "Accept-Language"),
+            (false, false) => {}
+        }
+        res = res.header("ETag", &etag).header(
+            "Last-Modified",
+            &DateTime::from_system_time(modified).http(),
+        );
+        let cache_control = match self.cache_control_of(path) {
+            _ if fingerprinted => Some(IMMUTABLE),
+            None if self.fingerprints.is_some() => Some("no-cache"),
+            value => value,
+        };
+        if let Some(value) = cache_control {
+            res = res.header("Cache-Control", value);
+        }
+        Ok(res)
+    }
+
+    fn negotiate(&self, req: &Request, path: &Path) -> (PathBuf, Option<&'static str>) {
+        if !self.precompressed {
+            return (path.to_path_buf(), None);
+        }
+
+        let accept = req.header("Accept-Encoding").unwrap_or_default();
+        for (encoding, ext) in [("br", "br"), ("gzip", "gz")] {
+            if !accepts_encoding(accept, encoding) {
+                continue;
+            }
+            let mut sibling = path.as_os_str().to_owned();
+            sibling.push(".");
+            sibling.push(ext);
+            let sibling = PathBuf::from(sibling);
+            if sibling.is_file() {
+                return (sibling, Some(encoding));
+            }
+        }
+        (path.to_path_buf(), None)
+    }
+
+    fn relative(&self, path: &Path) -> Option<String> {
+        let root = self.root.canonicalize().ok()?;
+        let path = path.canonicalize().ok()?;
+        let rel = path.strip_prefix(root).ok()?;
+        let rel: Vec<_> = rel.iter().map(|s| s.to_string_lossy()).collect();
+        Some(rel.join("/"))
+    }
+
+    fn excluded(&self, rel: &str) -> bool {
+        let segments: Vec<_> = rel.split('/').filter(|s| !s.is_empty()).collect();
+        if !self.hidden && segments.iter().any(|s| s.starts_with('.')) {
+            return true;
+        }
+
+        self.exclude.iter().any(|pattern| {
+            if pattern.contains('/') {
+                glob_match(pattern.as_bytes(), rel.as_bytes())
+            } else {
+                segments
+                    .iter()
+                    .any(|s| glob_match(pattern.as_bytes(), s.as_bytes()))
+            }
+        })
+    }
+
+    fn cache_control_of(&self, path: &Path) -> Option<&str> {
+        let rel = self.relative(path)?;
+
+        let by_prefix = self
+            .prefix_cache_control
+            .iter()
+            .filter(|(prefix, _)| in_prefix(&rel, prefix))
+            .max_by_key(|(prefix, _)| prefix.len());
+        if let Some((_, value)) = by_prefix {
+            return Some(value);
+        }
+
+        let ext = path
+            .extension()
+            .and_then(|e| e.to_str())
+            .map(|e| e.to_ascii_lowercase());
+        let by_ext = self
+            .ext_cache_control
+            .iter()
+            .find(|(e, _)| Some(e) == ext.as_ref());
+        if let Some((_, value)) = by_ext {
+            return Some(value);
+        }
+
+        self.cache_control.as_deref()
+    }
+
+    fn resolve(&self, rel: &str) -> Option<PathBuf> {
+        self.resolve_decoded(&percent_decode(rel)?)
+    }
+
+    fn resolve_decoded(&self, decoded: &str) -> Option<PathBuf> {
+        let mut path = self.root.clone();
+        for segment in decoded.split('/').filter(|s| !s.is_empty()) {
+            if segment == "." || segment == ".." || segment.contains(['\\', '\0']) {
+                return None;
+            }
+            path.push(segment);
+        }
+
+        let root = self.root.canonicalize().ok()?;
+        let path = path.canonicalize().ok()?;
+        if !path.starts_with(&root) || self.excluded(&self.relative(&path)?) {
+            return None;
+        }
+        Some(path)
+    }
+
+    fn listing(&self, req_path: &str, rel: &str, dir: &Path) -> io::Result<Response> {
+        let dir_rel = self.relative(dir).unwrap_or_default();
+        let mut entries: Vec<(String, Metadata)> = Vec::new();
+        for entry in fs::read_dir(dir)? {
+            let entry = entry?;
+            let name = entry.file_name().to_string_lossy().into_owned();
+            let rel = join_rel(&dir_rel, &name);
+            if self.excluded(&rel) {
+                continue;
+            }
+            entries.push((name, entry.metadata()?));
+        }
+        entries.sort_by(|a, b| b.1.is_dir().cmp(&a.1.is_dir()).then(a.0.cmp(&b.0)));
+
+        let decoded = percent_decode(req_path).unwrap_or_default();
+        let segments: Vec<_> = decoded.split('/').filter(|s| !s.is_empty()).collect();
+        let title = html_escape(&format!("/{}", segments.join("/")));
+        let base: String = segments
+            .iter()
+            .map(|s| format!("/{}", percent_encode(s)))
+            .collect();
+
+        let mut rows = String::new();
+        if rel.split('/').any(|s| !s.is_empty()) {
+            let parent = &base[..base.rfind('/').unwrap_or(0)];
+            rows.push_str(&format!(
+                "<tr><td><a href=\"{}/\">../</a></td><td></td><td></td></tr>\n",
+                parent
+            ));
+        }
+
+        for (name, meta) in entries.iter() {
+            let slash = if meta.is_dir() { "/" } else { "" };
+            let size = if meta.is_dir() {
+                String::from("-")
+            } else {
+                meta.len().to_string()
+            };
+            let mtime = meta
+                .modified()
+                .map(|t| DateTime::from_system_time(t).short())
+                .unwrap_or_default();
+
+            rows.push_str(&format!(
+                "<tr><td><a href=\"{}/{}{}\">{}{}</a></td><td>{}</td><td>{}</td></tr>\n",
+                base,
+                percent_encode(name),
+                slash,
+                html_escape(name),
+                slash,
+                size,
+                mtime
+            ));
+        }
+
+        let body = format!(
+            "<!DOCTYPE html>\n<html lang=\"en\">\n<head>\n<meta charset=\"UTF-8\">\n\
+             <title>Index of {0}</title>\n</head>\n<body>\n<h1>Index of {0}</h1>\n\
+             <table>\n<tr><th>Name</th><th>Size</th><th>Modified</th></tr>\n{1}</table>\n\
+             </body>\n</html>\n",
+            title, rows
+        );
+
+        Ok(Response::new(StatusCode::Ok)
+            .header("Content-Type", "text/html")
+            .body(body))
+    }
+}
+
+/// Where `file_as` gets a file's bytes from. A HEAD request only needs
+/// the file's metadata, so the file is never opened.
+enum Source {
+    Cached(Arc<CachedFile>),
+    Disk(File),
+    Meta(Metadata),
+}
+
+/// The ETag, Last-Modified time and length of a file on disk.
+fn validators(meta: &Metadata) -> io::Result<(String, SystemTime, u64)> {
+    let modified = meta.modified()?;
+    Ok((weak_etag(meta.len(), modified), modified, meta.len()))
+}
+
+pub(crate) fn weak_etag(len: u64, modified: SystemTime) -> String {
+    let secs = modified
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0);
+    format!("W/\"{:x}-{:x}\"", len, secs)
+}
+
+/// Whether `rel` is `prefix` or under it, a whole segment at a time, so
+/// `assets` doesn't take in `assets2/app.css`. The empty prefix is the root.
+fn in_prefix(rel: &str, prefix: &str) -> bool {
+    prefix.is_empty()
+        || rel
+            .strip_prefix(prefix)
+            .is_some_and(|rest| rest.is_empty() || rest.starts_with('/'))
+}
+
+fn join_rel(dir: &str, name: &str) -> String {
+    if dir.is_empty() {
+        String::from(name)
+    } else {
+        format!("{}/{}", dir, name)
+    }
+}
+
+fn glob_match(pattern: &[u8], text: &[u8]) -> bool {
+    match pattern {
+        [] => text.is_empty(),
+        [b'*', b'*', rest @ ..] => match rest.strip_prefix(b"/") {
+            Some(rest) => (0..=text.len())
+                .filter(|&i| i == 0 || text[i - 1] == b'/')
+                .any(|i| glob_match(rest, &text[i..])),
+            None => (0..=text.len()).any(|i| glob_match(rest, &text[i..])),
+        },
+        [b'*', rest @ ..] => (0..=text.len())
+            .take_while(|&i| i == 0 || text[i - 1] != b'/')
+            .any(|i| glob_match(rest, &text[i..])),
+        [b'?', rest @ ..] => {
+            matches!(text.first(), Some(c) if *c != b'/') && glob_match(rest, &text[1..])
+        }
+        [c, rest @ ..] => text.first() == Some(c) && glob_match(rest, &text[1..]),
+    }
+}
+
+fn parse_range(header: &str, len: u64) -> Result<Option<(u64, u64)>, ()> {
+    let spec = match header.trim().strip_prefix("bytes=") {
+        Some(spec) if !spec.contains(',') => spec.trim(),
+        _ => return Ok(None),
+    };
+    let (start, end) = spec.split_once('-').ok_or(())?;
+
+    let (start, end) = if start.is_empty() {
+        let suffix: u64 = end.parse().map_err(|_| ())?;
+        if suffix == 0 {
+            return Err(());
+        }
+        (len.saturating_sub(suffix), len.saturating_sub(1))
+    } else {
+        let start: u64 = start.parse().map_err(|_| ())?;
+        let end = match end {
+            "" => len.saturating_sub(1),
+            end => end
+                .parse::<u64>()
+                .map_err(|_| ())?
+                .min(len.saturating_sub(1)),
+        };
+        (start, end)
+    };
+
+    if len == 0 || start > end || start >= len {
+        return Err(());
+    }
+    Ok(Some((start, end)))
+}
+
+fn accepts_encoding(accept: &str, encoding: &str) -> bool {
+    let mut explicit = None;
+    let mut wildcard = None;
+    for item in accept.split(',') {
+        let mut parts = item.split(';').map(str::trim);
+        let name = parts.next().unwrap_or_default();
+        let q = parts
+            .filter_map(|p| p.strip_prefix("q="))
+            .find_map(|q| q.parse::<f32>().ok())
+            .unwrap_or(1.0);
+
+        if name.eq_ignore_ascii_case(encoding) {
+            explicit = Some(q);
+        } else if name == "*" {
+            wildcard = Some(q);
+        }
+    }
+    explicit.or(wildcard).is_some_and(|q| q > 0.0)
+}
+
+fn prefers_html(accept: Option<&str>) -> bool {
+    let mut html = 0.0;
+    let mut other: f32 = 0.0;
+    for item in accept.unwrap_or_default().split(',') {
+        let mut parts = item.split(';').map(str::trim);
+        let media = parts.next().unwrap_or_default();
+        let q = parts
+            .filter_map(|p| p.strip_prefix("q="))
+            .find_map(|q| q.parse::<f32>().ok())
+            .unwrap_or(1.0);
+
+        if media.eq_ignore_ascii_case("text/html") {
+            html = q;
+        } else {
+            other = other.max(q);
+        }
+    }
+    html > 0.0 && html >= other
+}
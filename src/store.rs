@@ -0,0 +1,157 @@
+use std::{
+    collections::HashMap,
+    hash::Hash,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, RwLock, RwLockReadGuard, RwLockWriteGuard,
+    },
+};
+
+/// A map shared between handlers, for keeping posts and users in memory.
+/// Clones share the same data, so capture one in every handler that needs
+/// it; reads run in parallel and writes take the lock briefly. A poisoned
+/// lock is recovered rather than propagated: a panic inside `update` can
+/// leave that one value half-changed, but never the map itself.
+pub struct MemStore<K, V> {
+    map: Arc<RwLock<HashMap<K, V>>>,
+    next_id: Arc<AtomicU64>,
+}
+
+impl<K, V> Clone for MemStore<K, V> {
+    fn clone(&self) -> Self {
+        MemStore {
+            map: Arc::clone(&self.map),
+            next_id: Arc::clone(&self.next_id),
+        }
+    }
+}
+
+impl<K: Eq + Hash, V> Default for MemStore<K, V> {
+    fn default() -> Self {
+        MemStore::new()
+    }
+}
+
+impl<K: Eq + Hash, V> MemStore<K, V> {
+    pub fn new() -> MemStore<K, V> {
+        MemStore {
+            map: Arc::default(),
+            next_id: Arc::new(AtomicU64::new(1)),
+        }
+    }
+
+    /// A fresh id, counting up from 1 and never repeated for this store,
+    /// even across `save` and `load`.
+    pub fn next_id(&self) -> u64 {
+        self.next_id.fetch_add(1, Ordering::Relaxed)
+    }
+
+    /// Returns the value `key` had before, if any.
+    pub fn insert(&self, key: K, value: V) -> Option<V> {
+        self.write().insert(key, value)
+    }
+
+    pub fn get(&self, key: &K) -> Option<V>
+    where
+        V: Clone,
+    {
+        self.read().get(key).cloned()
+    }
+
+    /// Changes the value at `key` in place. False if there was none.
+    pub fn update(&self, key: &K, f: impl FnOnce(&mut V)) -> bool {
+        match self.write().get_mut(key) {
+            Some(value) => {
+                f(value);
+                true
+            }
+            None => false,
+        }
+    }
+
+    pub fn remove(&self, key: &K) -> Option<V> {
+        self.write().remove(key)
+    }
+
+    /// Every value `filter` accepts, cloned, in no particular order.
+    pub fn list(&self, filter: impl Fn(&V) -> bool) -> Vec<V>
+    where
+        V: Clone,
+    {
+        self.read()
+            .values()
+            .filter(|v| filter(v))
+            .cloned()
+            .collect()
+    }
+
+    /// Every key and value, cloned, in no particular order.
+    pub fn entries(&self) -> Vec<(K, V)>
+    where
+        K: Clone,
+        V: Clone,
+    {
+        self.read()
+            .iter()
+            .map(|(k, v)| (k.clone(), v.clone()))
+            .collect()
+    }
+
+    pub fn len(&self) -> usize {
+        self.read().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.read().is_empty()
+    }
+
+    fn read(&self) -> RwLockReadGuard<'_, HashMap<K, V>> {
+        self.map.read().unwrap_or_else(|e| e.into_inner())
+    }
+
+    fn write(&self) -> RwLockWriteGuard<'_, HashMap<K, V>> {
+        self.map.write().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<K, V> MemStore<K, V>
+where
+    K: Eq + Hash + serde::Serialize + serde::de::DeserializeOwned,
+    V: serde::Serialize + serde::de::DeserializeOwned,
+{
+    /// Writes a JSON snapshot of the store to `path`. The file is written
+    /// next to it first and renamed over it, so a crash mid-save leaves
+    /// the previous snapshot intact.
+    pub fn save(&self, path: impl AsRef<std::path::Path>) -> std::io::Result<()> {
+        let path = path.as_ref();
+        let json = {
+            let map = self.read();
+            let entries = map.iter().collect::<Vec<_>>();
+            serde_json::to_vec(&serde_json::json!({
+                "next_id": self.next_id.load(Ordering::Relaxed),
+                "entries": entries,
+            }))?
+        };
+        let mut tmp = path.as_os_str().to_owned();
+        tmp.push(".tmp");
+        std::fs::write(&tmp, json)?;
+        std::fs::rename(&tmp, path)
+    }
+
+    /// A store holding what `save` wrote to `path`.
+    pub fn load(path: impl AsRef<std::path::Path>) -> std::io::Result<MemStore<K, V>> {
+        use serde_json::Value;
+
+        let invalid = |what: &str| std::io::Error::new(std::io::ErrorKind::InvalidData, what);
+        let mut snapshot: Value = serde_json::from_slice(&std::fs::read(path)?)?;
+        let next_id = snapshot["next_id"]
+            .as_u64()
+            .ok_or_else(|| invalid("snapshot has no next_id"))?;
+        let entries: Vec<(K, V)> = serde_json::from_value(snapshot["entries"].take())?;
+        Ok(MemStore {
+            map: Arc::new(RwLock::new(entries.into_iter().collect())),
+            next_id: Arc::new(AtomicU64::new(next_id.max(1))),
+        })
+    }
+}
@@ -0,0 +1,425 @@
+use crate::shutdown::Waker;
+#[cfg(feature = "poll")]
+use polling::{Event, Poller};
+#[cfg(unix)]
+use std::{
+    fs,
+    os::unix::{
+        fs::{FileTypeExt, PermissionsExt},
+        net::{UnixListener, UnixStream},
+    },
+    path::{Path, PathBuf},
+};
+use std::{
+    io::{self, Read, Write},
+    net::{Shutdown, SocketAddr, TcpListener, TcpStream},
+    sync::Arc,
+    time::Duration,
+};
+
+pub(crate) trait Stream: Read + Write + Send {
+    fn set_read_timeout(&self, timeout: Option<Duration>) -> io::Result<()>;
+
+    fn set_write_timeout(&self, timeout: Option<Duration>) -> io::Result<()>;
+
+    fn set_nodelay(&self, _nodelay: bool) -> io::Result<()> {
+        Ok(())
+    }
+
+    fn peer_addr(&self) -> Option<SocketAddr> {
+        None
+    }
+
+    fn is_secure(&self) -> bool {
+        false
+    }
+
+    /// Whether `write_vectored` sends every slice in one call, rather than
+    /// just the first as the default does.
+    fn vectored_writes(&self) -> bool {
+        false
+    }
+
+    /// A second handle on the socket, for closing it from another thread.
+    fn closer(&self) -> Option<Closer> {
+        None
+    }
+
+    /// A second handle on the socket for the poll loop to watch, when it
+    /// is one the loop can read requests from.
+    #[cfg(feature = "poll")]
+    fn pollable(&self) -> Option<PollSocket> {
+        None
+    }
+}
+
+/// Closes a connection out from under the thread blocked reading it, which
+/// then sees the end of the stream.
+pub(crate) enum Closer {
+    Tcp(TcpStream),
+    #[cfg(unix)]
+    Unix(UnixStream),
+}
+
+impl Closer {
+    pub(crate) fn close(&self) {
+        let _ = match self {
+            Closer::Tcp(sock) => sock.shutdown(Shutdown::Both),
+            #[cfg(unix)]
+            Closer::Unix(sock) => sock.shutdown(Shutdown::Both),
+        };
+    }
+}
+
+/// Stands in for a connection while a handler has it for streaming the
+/// request body.
+pub(crate) struct Detached;
+
+impl Read for Detached {
+    fn read(&mut self, _buf: &mut [u8]) -> io::Result<usize> {
+        Ok(0)
+    }
+}
+
+impl Write for Detached {
+    fn write(&mut self, _buf: &[u8]) -> io::Result<usize> {
+        Err(io::ErrorKind::NotConnected.into())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl Stream for Detached {
+    fn set_read_timeout(&self, _timeout: Option<Duration>) -> io::Result<()> {
+        Ok(())
+    }
+
+    fn set_write_timeout(&self, _timeout: Option<Duration>) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Any reader/writer as a connection, for `Server::serve_connection`.
+/// Timeouts are ignored and there is no peer address.
+pub(crate) struct Plain<S>(pub(crate) S);
+
+impl<S: Read> Read for Plain<S> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.0.read(buf)
+    }
+}
+
+impl<S: Write> Write for Plain<S> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0.write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.0.flush()
+    }
+}
+
+impl<S: Read + Write + Send> Stream for Plain<S> {
+    fn set_read_timeout(&self, _timeout: Option<Duration>) -> io::Result<()> {
+        Ok(())
+    }
+
+    fn set_write_timeout(&self, _timeout: Option<Duration>) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl Stream for TcpStream {
+    fn set_read_timeout(&self, timeout: Option<Duration>) -> io::Result<()> {
+        TcpStream::set_read_timeout(self, timeout)
+    }
+
+    fn set_write_timeout(&self, timeout: Option<Duration>) -> io::Result<()> {
+        TcpStream::set_write_timeout(self, timeout)
+    }
+
+    fn set_nodelay(&self, nodelay: bool) -> io::Result<()> {
+        TcpStream::set_nodelay(self, nodelay)
+    }
+
+    fn peer_addr(&self) -> Option<SocketAddr> {
+        TcpStream::peer_addr(self).ok()
+    }
+
+    fn vectored_writes(&self) -> bool {
+        true
+    }
+
+    fn closer(&self) -> Option<Closer> {
+        self.try_clone().ok().map(Closer::Tcp)
+    }
+
+    #[cfg(feature = "poll")]
+    fn pollable(&self) -> Option<PollSocket> {
+        self.try_clone().ok().map(PollSocket::Tcp)
+    }
+}
+
+#[cfg(unix)]
+impl Stream for UnixStream {
+    fn set_read_timeout(&self, timeout: Option<Duration>) -> io::Result<()> {
+        UnixStream::set_read_timeout(self, timeout)
+    }
+
+    fn set_write_timeout(&self, timeout: Option<Duration>) -> io::Result<()> {
+        UnixStream::set_write_timeout(self, timeout)
+    }
+
+    fn vectored_writes(&self) -> bool {
+        true
+    }
+
+    fn closer(&self) -> Option<Closer> {
+        self.try_clone().ok().map(Closer::Unix)
+    }
+
+    #[cfg(feature = "poll")]
+    fn pollable(&self) -> Option<PollSocket> {
+        self.try_clone().ok().map(PollSocket::Unix)
+    }
+}
+
+pub(crate) enum Listener {
+    Tcp(TcpListener),
+    #[cfg(feature = "tls")]
+    Tls(TcpListener, Arc<rustls::ServerConfig>),
+    #[cfg(unix)]
+    Unix(UnixListener, Arc<SocketFile>),
+}
+
+impl Listener {
+    pub(crate) fn accept(&self) -> io::Result<Box<dyn Stream>> {
+        match self {
+            Listener::Tcp(l) => Ok(Box::new(l.accept()?.0)),
+            #[cfg(feature = "tls")]
+            Listener::Tls(l, config) => crate::tls::accept(l, config),
+            #[cfg(unix)]
+            Listener::Unix(l, _) => Ok(Box::new(l.accept()?.0)),
+        }
+    }
+
+    pub(crate) fn try_clone(&self) -> io::Result<Listener> {
+        match self {
+            Listener::Tcp(l) => Ok(Listener::Tcp(l.try_clone()?)),
+            #[cfg(feature = "tls")]
+            Listener::Tls(l, config) => Ok(Listener::Tls(l.try_clone()?, Arc::clone(config))),
+            #[cfg(unix)]
+            Listener::Unix(l, file) => Ok(Listener::Unix(l.try_clone()?, Arc::clone(file))),
+        }
+    }
+
+    pub(crate) fn waker(&self) -> io::Result<Waker> {
+        match self {
+            Listener::Tcp(l) => Ok(Waker::Tcp(l.local_addr()?)),
+            #[cfg(feature = "tls")]
+            Listener::Tls(l, _) => Ok(Waker::Tcp(l.local_addr()?)),
+            #[cfg(unix)]
+            Listener::Unix(_, file) => Ok(Waker::Unix(file.0.clone())),
+        }
+    }
+
+    pub(crate) fn describe(&self) -> io::Result<String> {
+        match self {
+            Listener::Tcp(l) => Ok(l.local_addr()?.to_string()),
+            #[cfg(feature = "tls")]
+            Listener::Tls(l, _) => Ok(format!("{} (tls)", l.local_addr()?)),
+            #[cfg(unix)]
+            Listener::Unix(_, file) => Ok(format!("unix:{}", file.0.display())),
+        }
+    }
+}
+
+/// What the poll loop got from a listener: a plain socket to read the
+/// request head from itself, or a TLS stream, whose handshake only runs
+/// blocking and so goes straight to a worker.
+#[cfg(feature = "poll")]
+pub(crate) enum Accepted {
+    Socket(PollSocket),
+    #[cfg_attr(not(feature = "tls"), allow(dead_code))]
+    Stream(Box<dyn Stream>),
+}
+
+#[cfg(feature = "poll")]
+impl Listener {
+    pub(crate) fn accept_polled(&self) -> io::Result<Accepted> {
+        match self {
+            Listener::Tcp(l) => Ok(Accepted::Socket(PollSocket::Tcp(l.accept()?.0))),
+            #[cfg(feature = "tls")]
+            Listener::Tls(l, config) => Ok(Accepted::Stream(crate::tls::accept(l, config)?)),
+            #[cfg(unix)]
+            Listener::Unix(l, _) => Ok(Accepted::Socket(PollSocket::Unix(l.accept()?.0))),
+        }
+    }
+
+    pub(crate) fn set_nonblocking(&self, nonblocking: bool) -> io::Result<()> {
+        match self {
+            Listener::Tcp(l) => l.set_nonblocking(nonblocking),
+            #[cfg(feature = "tls")]
+            Listener::Tls(l, _) => l.set_nonblocking(nonblocking),
+            #[cfg(unix)]
+            Listener::Unix(l, _) => l.set_nonblocking(nonblocking),
+        }
+    }
+
+    /// Starts reporting readiness to `poller` under `key`, once; `rewatch`
+    /// arms it again.
+    pub(crate) fn watch(&self, poller: &Poller, key: usize) -> io::Result<()> {
+        // SAFETY: the poll loop calls `unwatch` before the listener drops.
+        unsafe {
+            match self {
+                Listener::Tcp(l) => poller.add(l, Event::readable(key)),
+                #[cfg(feature = "tls")]
+                Listener::Tls(l, _) => poller.add(l, Event::readable(key)),
+                #[cfg(unix)]
+                Listener::Unix(l, _) => poller.add(l, Event::readable(key)),
+            }
+        }
+    }
+
+    pub(crate) fn rewatch(&self, poller: &Poller, key: usize) -> io::Result<()> {
+        match self {
+            Listener::Tcp(l) => poller.modify(l, Event::readable(key)),
+            #[cfg(feature = "tls")]
+            Listener::Tls(l, _) => poller.modify(l, Event::readable(key)),
+            #[cfg(unix)]
+            Listener::Unix(l, _) => poller.modify(l, Event::readable(key)),
+        }
+    }
+
+    pub(crate) fn unwatch(&self, poller: &Poller) -> io::Result<()> {
+        match self {
+            Listener::Tcp(l) => poller.delete(l),
+            #[cfg(feature = "tls")]
+            Listener::Tls(l, _) => poller.delete(l),
+            #[cfg(unix)]
+            Listener::Unix(l, _) => poller.delete(l),
+        }
+    }
+}
+
+/// A plain connection while the poll loop waits for its request head.
+#[cfg(feature = "poll")]
+pub(crate) enum PollSocket {
+    Tcp(TcpStream),
+    #[cfg(unix)]
+    Unix(UnixStream),
+}
+
+#[cfg(feature = "poll")]
+impl PollSocket {
+    pub(crate) fn set_nonblocking(&self, nonblocking: bool) -> io::Result<()> {
+        match self {
+            PollSocket::Tcp(s) => s.set_nonblocking(nonblocking),
+            #[cfg(unix)]
+            PollSocket::Unix(s) => s.set_nonblocking(nonblocking),
+        }
+    }
+
+    /// Like `Listener::watch`.
+    pub(crate) fn watch(&self, poller: &Poller, key: usize) -> io::Result<()> {
+        // SAFETY: the poll loop calls `unwatch` before the socket drops.
+        unsafe {
+            match self {
+                PollSocket::Tcp(s) => poller.add(s, Event::readable(key)),
+                #[cfg(unix)]
+                PollSocket::Unix(s) => poller.add(s, Event::readable(key)),
+            }
+        }
+    }
+
+    pub(crate) fn rewatch(&self, poller: &Poller, key: usize) -> io::Result<()> {
+        match self {
+            PollSocket::Tcp(s) => poller.modify(s, Event::readable(key)),
+            #[cfg(unix)]
+            PollSocket::Unix(s) => poller.modify(s, Event::readable(key)),
+        }
+    }
+
+    pub(crate) fn unwatch(&self, poller: &Poller) -> io::Result<()> {
+        match self {
+            PollSocket::Tcp(s) => poller.delete(s),
+            #[cfg(unix)]
+            PollSocket::Unix(s) => poller.delete(s),
+        }
+    }
+
+    /// Back to blocking, for a worker.
+    pub(crate) fn into_stream(self) -> io::Result<Box<dyn Stream>> {
+        self.set_nonblocking(false)?;
+        match self {
+            PollSocket::Tcp(s) => Ok(Box::new(s)),
+            #[cfg(unix)]
+            PollSocket::Unix(s) => Ok(Box::new(s)),
+        }
+    }
+}
+
+#[cfg(feature = "poll")]
+impl Read for PollSocket {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        match self {
+            PollSocket::Tcp(s) => s.read(buf),
+            #[cfg(unix)]
+            PollSocket::Unix(s) => s.read(buf),
+        }
+    }
+}
+
+#[cfg(feature = "poll")]
+impl Write for PollSocket {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match self {
+            PollSocket::Tcp(s) => s.write(buf),
+            #[cfg(unix)]
+            PollSocket::Unix(s) => s.write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Removes the socket file once the last listener using it is dropped.
+#[cfg(unix)]
+pub(crate) struct SocketFile(PathBuf);
+
+#[cfg(unix)]
+impl Drop for SocketFile {
+    fn drop(&mut self) {
+        let _ = fs::remove_file(&self.0);
+    }
+}
+
+#[cfg(unix)]
+pub(crate) fn bind_unix(path: &Path, mode: Option<u32>) -> io::Result<Listener> {
+    if let Ok(meta) = fs::symlink_metadata(path) {
+        if !meta.file_type().is_socket() {
+            return Err(io::Error::new(
+                io::ErrorKind::AlreadyExists,
+                "path exists and is not a socket",
+            ));
+        }
+        if UnixStream::connect(path).is_ok() {
+            return Err(io::Error::new(
+                io::ErrorKind::AddrInUse,
+                "socket is in use by another process",
+            ));
+        }
+        fs::remove_file(path)?;
+    }
+
+    let listener = UnixListener::bind(path)?;
+    let file = Arc::new(SocketFile(path.to_path_buf()));
+    if let Some(mode) = mode {
+        fs::set_permissions(path, fs::Permissions::from_mode(mode))?;
+    }
+    Ok(Listener::Unix(listener, file))
+}
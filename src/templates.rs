@@ -0,0 +1,99 @@
+use crate::{encoding::html_escape, error::TemplateError};
+use std::{collections::HashMap, fs, path::Path, str::FromStr};
+
+/// A parsed template with `{{ name }}` placeholders, HTML-escaped on
+/// output, and `{{{ name }}}` ones inserted as they are. Names are letters,
+/// digits, `_`, `-` and `.`. Parsing once and rendering many times is the
+/// intended use: a `Template` is cheap to clone and can be shared between
+/// handlers behind an `Arc`.
+#[derive(Clone, Debug)]
+pub struct Template {
+    parts: Vec<Part>,
+}
+
+#[derive(Clone, Debug)]
+enum Part {
+    Text(String),
+    Escaped(String),
+    Raw(String),
+}
+
+impl Template {
+    pub fn from_file(path: impl AsRef<Path>) -> Result<Template, TemplateError> {
+        let path = path.as_ref();
+        let source = fs::read_to_string(path).map_err(|source| TemplateError::Io {
+            path: path.to_path_buf(),
+            source,
+        })?;
+        source.parse()
+    }
+
+    /// Fills in every placeholder from `vars`. A placeholder with no value
+    /// is an error rather than an empty string, so typos show up.
+    pub fn render(&self, vars: &HashMap<&str, String>) -> Result<String, TemplateError> {
+        let mut out = String::new();
+        for part in self.parts.iter() {
+            match part {
+                Part::Text(text) => out.push_str(text),
+                Part::Escaped(name) | Part::Raw(name) => {
+                    let value = vars
+                        .get(name.as_str())
+                        .ok_or_else(|| TemplateError::MissingVariable(name.clone()))?;
+                    match part {
+                        Part::Raw(_) => out.push_str(value),
+                        _ => out.push_str(&html_escape(value)),
+                    }
+                }
+            }
+        }
+        Ok(out)
+    }
+}
+
+impl FromStr for Template {
+    type Err = TemplateError;
+
+    fn from_str(source: &str) -> Result<Template, TemplateError> {
+        let mut parts = Vec::new();
+        let mut rest = source;
+        while let Some(start) = rest.find("{{") {
+            if start > 0 {
+                parts.push(Part::Text(String::from(&rest[..start])));
+            }
+            let line = source[..source.len() - rest.len() + start]
+                .matches('\n')
+                .count()
+                + 1;
+            let raw = rest[start..].starts_with("{{{");
+            let (open, close) = if raw { ("{{{", "}}}") } else { ("{{", "}}") };
+            let tag = &rest[start + open.len()..];
+            let Some(end) = tag.find(close) else {
+                return Err(TemplateError::Syntax {
+                    line,
+                    message: format!("`{open}` is never closed with `{close}`"),
+                });
+            };
+            let name = tag[..end].trim();
+            let valid = !name.is_empty()
+                && name
+                    .chars()
+                    .all(|c| c.is_alphanumeric() || matches!(c, '_' | '-' | '.'));
+            if !valid {
+                return Err(TemplateError::Syntax {
+                    line,
+                    message: format!("{:?} is not a variable name", name),
+                });
+            }
+            parts.push(if raw {
+                Part::Raw(String::from(name))
+            } else {
+                Part::Escaped(String::from(name))
+            });
+            rest = &tag[end + close.len()..];
+        }
+        if !rest.is_empty() {
+            parts.push(Part::Text(String::from(rest)));
+        }
+        Ok(Template { parts })
+    }
+}
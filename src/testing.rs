@@ -0,0 +1,247 @@
+//! Running a server on a throwaway port and talking to it over real
+//! sockets, for integration tests that can run in parallel, or feeding it
+//! bytes in memory through `Duplex` and `Server::serve_connection`.
+
+use crate::{
+    error::ServerError,
+    server::{Method, Server, ServerHandle},
+};
+use std::{
+    io::{self, Cursor, Read, Write},
+    net::{SocketAddr, TcpStream},
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+/// A server bound to `127.0.0.1:0` and running on a background thread.
+/// Dropping the client shuts the server down.
+pub struct TestClient {
+    addr: SocketAddr,
+    handle: ServerHandle,
+    timeout: Duration,
+}
+
+/// A response as read off the wire.
+#[derive(Clone, Debug)]
+pub struct TestResponse {
+    pub status: u16,
+    pub headers: Vec<(String, String)>,
+    pub body: Vec<u8>,
+}
+
+impl TestResponse {
+    /// The first value of a header, matched case-insensitively.
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|(k, _)| k.eq_ignore_ascii_case(name))
+            .map(|(_, v)| v.as_str())
+    }
+
+    /// The body as text, with invalid UTF-8 replaced.
+    pub fn text(&self) -> String {
+        String::from_utf8_lossy(&self.body).into_owned()
+    }
+}
+
+impl TestClient {
+    /// Starts `server` on an ephemeral port. Whatever addresses, unix sockets
+    /// and TLS it was configured with are replaced by a plain loopback
+    /// listener, and the banner is turned off.
+    pub fn start(mut server: Server) -> Result<TestClient, ServerError> {
+        server.ephemeral();
+        let handle = server.spawn()?;
+        let addr = handle
+            .local_addr()
+            .ok_or_else(|| io::Error::other("server has no address"))?;
+        Ok(TestClient {
+            addr,
+            handle,
+            timeout: Duration::from_secs(5),
+        })
+    }
+
+    pub fn addr(&self) -> SocketAddr {
+        self.addr
+    }
+
+    /// How long a request may take before it fails. Five seconds by default.
+    pub fn timeout(&mut self, timeout: Duration) -> &mut Self {
+        self.timeout = timeout;
+        self
+    }
+
+    pub fn handle(&self) -> &ServerHandle {
+        &self.handle
+    }
+
+    pub fn get(&self, path: &str) -> io::Result<TestResponse> {
+        self.request(Method::Get, path, &[], &[])
+    }
+
+    pub fn head(&self, path: &str) -> io::Result<TestResponse> {
+        self.request(Method::Head, path, &[], &[])
+    }
+
+    pub fn delete(&self, path: &str) -> io::Result<TestResponse> {
+        self.request(Method::Delete, path, &[], &[])
+    }
+
+    pub fn post(&self, path: &str, body: &[u8], content_type: &str) -> io::Result<TestResponse> {
+        self.request(Method::Post, path, &[("Content-Type", content_type)], body)
+    }
+
+    pub fn put(&self, path: &str, body: &[u8], content_type: &str) -> io::Result<TestResponse> {
+        self.request(Method::Put, path, &[("Content-Type", content_type)], body)
+    }
+
+    /// Sends one request on a fresh connection. Host, Content-Length and
+    /// `Connection: close` are added.
+    pub fn request(
+        &self,
+        method: Method,
+        path: &str,
+        headers: &[(&str, &str)],
+        body: &[u8],
+    ) -> io::Result<TestResponse> {
+        let mut stream = TcpStream::connect_timeout(&self.addr, self.timeout)?;
+        stream.set_read_timeout(Some(self.timeout))?;
+        stream.set_write_timeout(Some(self.timeout))?;
+
+        let mut head = format!("{method} {path} HTTP/1.1\r\nHost: {}\r\n", self.addr);
+        for (name, value) in headers {
+            head.push_str(&format!("{name}: {value}\r\n"));
+        }
+        if !body.is_empty() {
+            head.push_str(&format!("Content-Length: {}\r\n", body.len()));
+        }
+        head.push_str("Connection: close\r\n\r\n");
+        stream.write_all(head.as_bytes())?;
+        stream.write_all(body)?;
+
+        let mut raw = Vec::new();
+        stream.read_to_end(&mut raw)?;
+        parse_response(&raw, method == Method::Head)
+    }
+}
+
+fn parse_response(raw: &[u8], head_only: bool) -> io::Result<TestResponse> {
+    parse_one(raw, head_only).map(|(res, _)| res)
+}
+
+/// One response off the front of `raw`, and how many bytes it took. Without
+/// a Content-Length the body runs to the end, unless the status never has
+/// one.
+fn parse_one(raw: &[u8], head_only: bool) -> io::Result<(TestResponse, usize)> {
+    let invalid = |why: &str| io::Error::new(io::ErrorKind::InvalidData, why.to_string());
+    let end = raw
+        .windows(4)
+        .position(|w| w == b"\r\n\r\n")
+        .ok_or_else(|| invalid("response head never ended"))?;
+    let head = std::str::from_utf8(&raw[..end]).map_err(|_| invalid("response head not UTF-8"))?;
+    let mut lines = head.split("\r\n");
+    let status = lines
+        .next()
+        .and_then(|line| line.split(' ').nth(1))
+        .and_then(|code| code.parse().ok())
+        .ok_or_else(|| invalid("bad status line"))?;
+    let headers: Vec<(String, String)> = lines
+        .filter_map(|line| line.split_once(':'))
+        .map(|(k, v)| (String::from(k.trim()), String::from(v.trim())))
+        .collect();
+
+    let mut body = raw[end + 4..].to_vec();
+    let length = headers
+        .iter()
+        .find(|(k, _)| k.eq_ignore_ascii_case("Content-Length"))
+        .and_then(|(_, v)| v.parse::<usize>().ok());
+    if head_only || matches!(status, 100..=199 | 204 | 304) {
+        body.clear();
+    } else if let Some(length) = length {
+        if body.len() < length {
+            return Err(invalid("response body cut short"));
+        }
+        body.truncate(length);
+    }
+    let used = end + 4 + body.len();
+    Ok((
+        TestResponse {
+            status,
+            headers,
+            body,
+        },
+        used,
+    ))
+}
+
+/// An in-memory connection: reads come from a fixed input, writes collect
+/// in a buffer that stays readable through clones after the server has
+/// dropped its end. Reading past the input is a clean close.
+#[derive(Clone)]
+pub struct Duplex {
+    input: Arc<Mutex<Cursor<Vec<u8>>>>,
+    output: Arc<Mutex<Vec<u8>>>,
+    read_size: usize,
+}
+
+impl Duplex {
+    pub fn new(input: impl Into<Vec<u8>>) -> Duplex {
+        Duplex {
+            input: Arc::new(Mutex::new(Cursor::new(input.into()))),
+            output: Arc::default(),
+            read_size: usize::MAX,
+        }
+    }
+
+    /// Hands out at most `n` bytes per read, to exercise partial reads.
+    pub fn read_size(mut self, n: usize) -> Duplex {
+        self.read_size = n.max(1);
+        self
+    }
+
+    /// Everything written so far.
+    pub fn output(&self) -> Vec<u8> {
+        self.output
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .clone()
+    }
+
+    /// The output split into responses. Bodies are cut by Content-Length,
+    /// so this copes with pipelined replies.
+    pub fn responses(&self) -> io::Result<Vec<TestResponse>> {
+        let output = self.output();
+        let mut rest = output.as_slice();
+        let mut responses = Vec::new();
+        while !rest.is_empty() {
+            let (res, used) = parse_one(rest, false)?;
+            responses.push(res);
+            rest = &rest[used..];
+        }
+        Ok(responses)
+    }
+}
+
+impl Read for Duplex {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let n = buf.len().min(self.read_size);
+        self.input
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .read(&mut buf[..n])
+    }
+}
+
+impl Write for Duplex {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.output
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
@@ -0,0 +1,308 @@
+use crate::{
+    request::Request,
+    response::{Response, StatusCode},
+    server::HandlerFunc,
+};
+use log::warn;
+use std::{
+    collections::HashMap,
+    hash::Hash,
+    net::IpAddr,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+/// How often `check` sweeps out entries that have gone quiet.
+const SWEEP_EVERY: Duration = Duration::from_secs(60);
+
+/// The default for `LoginThrottle::max_entries`.
+pub const MAX_ENTRIES: usize = 100_000;
+
+/// How many failures one key gets for free, and how the lockout grows
+/// after that: `base_delay` for the first failure over, doubling for
+/// each one after, up to `max_delay`.
+#[derive(Clone, Copy, Debug)]
+pub struct BackoffLimits {
+    pub free_attempts: u32,
+    pub base_delay: Duration,
+    pub max_delay: Duration,
+}
+
+/// What `LoginThrottle::check` says about an attempt.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Decision {
+    Allow,
+    /// Locked out; try again after this long.
+    Deny {
+        retry_after: Duration,
+    },
+}
+
+impl Decision {
+    pub fn is_allowed(&self) -> bool {
+        *self == Decision::Allow
+    }
+
+    /// A 429 with `Retry-After` in whole seconds, or `None` when allowed.
+    pub fn response(&self) -> Option<Response> {
+        let Decision::Deny { retry_after } = self else {
+            return None;
+        };
+        let secs = retry_after.as_secs() + u64::from(retry_after.subsec_nanos() > 0);
+        Some(
+            Response::new(StatusCode::TooManyRequests)
+                .header("Retry-After", &secs.max(1).to_string())
+                .header("Content-Type", "application/json")
+                .body(r#"{"error":"too many failed logins, try again later"}"#),
+        )
+    }
+}
+
+/// Failed-login tracking for a login route, kept separately per username
+/// and per client IP so that both many IPs trying one account and one IP
+/// trying many accounts get slowed down. IPv6 clients are counted by /64,
+/// since one host usually has a whole one. Clones share their state.
+///
+/// Use `protect` to wrap the login handler, or call `check`,
+/// `record_failure` and `record_success` from the handler itself.
+#[derive(Clone)]
+pub struct LoginThrottle {
+    username: BackoffLimits,
+    ip: BackoffLimits,
+    forget_after: Duration,
+    max_entries: usize,
+    state: Arc<Mutex<State>>,
+}
+
+struct State {
+    usernames: HashMap<String, Entry>,
+    ips: HashMap<IpAddr, Entry>,
+    last_sweep: Instant,
+}
+
+#[derive(Clone, Copy)]
+struct Entry {
+    failures: u32,
+    last_failure: Instant,
+    locked_until: Option<Instant>,
+}
+
+impl Default for LoginThrottle {
+    fn default() -> Self {
+        LoginThrottle::new()
+    }
+}
+
+impl LoginThrottle {
+    /// Five free failures per username and twenty per IP, lockouts from
+    /// one second up to fifteen minutes and an hour, and counts forgotten
+    /// after an hour without failures.
+    pub fn new() -> LoginThrottle {
+        LoginThrottle {
+            username: BackoffLimits {
+                free_attempts: 5,
+                base_delay: Duration::from_secs(1),
+                max_delay: Duration::from_secs(15 * 60),
+            },
+            ip: BackoffLimits {
+                free_attempts: 20,
+                base_delay: Duration::from_secs(1),
+                max_delay: Duration::from_secs(60 * 60),
+            },
+            forget_after: Duration::from_secs(60 * 60),
+            max_entries: MAX_ENTRIES,
+            state: Arc::new(Mutex::new(State {
+                usernames: HashMap::new(),
+                ips: HashMap::new(),
+                last_sweep: Instant::now(),
+            })),
+        }
+    }
+
+    pub fn username_limits(mut self, limits: BackoffLimits) -> LoginThrottle {
+        self.username = limits;
+        self
+    }
+
+    pub fn ip_limits(mut self, limits: BackoffLimits) -> LoginThrottle {
+        self.ip = limits;
+        self
+    }
+
+    /// How long after its last failure a username or IP starts over.
+    pub fn forget_after(mut self, forget_after: Duration) -> LoginThrottle {
+        self.forget_after = forget_after;
+        self
+    }
+
+    /// How many usernames, and separately how many IPs, are tracked at
+    /// once, since an attacker picks the usernames. A failure for a new
+    /// one when full first drops entries that have gone quiet, then the
+    /// eighth that failed longest ago, locked out or not.
+    pub fn max_entries(mut self, max_entries: usize) -> LoginThrottle {
+        self.max_entries = max_entries.max(1);
+        self
+    }
+
+    /// Whether an attempt for `username` from `ip` may go ahead. Usernames
+    /// compare ignoring case; an empty one, or no IP (a unix socket
+    /// client), skips that half of the check.
+    pub fn check(&self, ip: Option<IpAddr>, username: &str) -> Decision {
+        let now = Instant::now();
+        let mut state = self.state.lock().unwrap_or_else(|e| e.into_inner());
+        if now.duration_since(state.last_sweep) >= SWEEP_EVERY {
+            state.last_sweep = now;
+            let forget_after = self.forget_after;
+            let stale = |e: &Entry| e.stale(now, forget_after);
+            state.usernames.retain(|_, e| !stale(e));
+            state.ips.retain(|_, e| !stale(e));
+        }
+        let username = username_key(username);
+        let locked = [
+            username.and_then(|u| state.usernames.get(&u).copied()),
+            ip.and_then(|ip| state.ips.get(&ip_key(ip)).copied()),
+        ];
+        let until = locked
+            .into_iter()
+            .flatten()
+            .filter_map(|e| e.locked_until)
+            .max();
+        match until {
+            Some(until) if until > now => Decision::Deny {
+                retry_after: until - now,
+            },
+            _ => Decision::Allow,
+        }
+    }
+
+    /// Counts a failed attempt against both `username` and `ip`.
+    pub fn record_failure(&self, ip: Option<IpAddr>, username: &str) {
+        let now = Instant::now();
+        let mut state = self.state.lock().unwrap_or_else(|e| e.into_inner());
+        if let Some(username) = username_key(username) {
+            if self.fail(&mut state.usernames, username.clone(), &self.username, now) {
+                warn!("Throttling logins for {username:?}");
+            }
+        }
+        if let Some(ip) = ip {
+            if self.fail(&mut state.ips, ip_key(ip), &self.ip, now) {
+                warn!("Throttling logins from {ip}");
+            }
+        }
+    }
+
+    /// Clears the count for `username`. The IP's count stays: one good
+    /// password doesn't vouch for the rest of what that client tried.
+    pub fn record_success(&self, _ip: Option<IpAddr>, username: &str) {
+        if let Some(username) = username_key(username) {
+            let mut state = self.state.lock().unwrap_or_else(|e| e.into_inner());
+            state.usernames.remove(&username);
+        }
+    }
+
+    /// Wraps a login handler. The username comes from the form field
+    /// `username_field`. Locked-out attempts get a 429 without reaching
+    /// `handler`. A 401 or 403 from it counts as a failure and any 2xx or
+    /// 3xx as a success, so a handler that reports bad passwords some
+    /// other way should use the imperative calls instead.
+    pub fn protect(&self, username_field: &str, handler: impl HandlerFunc) -> impl HandlerFunc {
+        let (throttle, field) = (self.clone(), String::from(username_field));
+        move |req: &Request| {
+            let username = req
+                .form()
+                .ok()
+                .and_then(|form| form.get(&field).map(String::from))
+                .unwrap_or_default();
+            let ip = req.client_ip();
+            if let Some(res) = throttle.check(ip, &username).response() {
+                return Ok(res);
+            }
+            let res = handler(req)?;
+            match res.status().code() {
+                401 | 403 => throttle.record_failure(ip, &username),
+                200..=399 => throttle.record_success(ip, &username),
+                _ => {}
+            }
+            Ok(res)
+        }
+    }
+
+    /// Counts a failure for `key`; true when it starts a lockout.
+    fn fail<K: Hash + Eq>(
+        &self,
+        entries: &mut HashMap<K, Entry>,
+        key: K,
+        limits: &BackoffLimits,
+        now: Instant,
+    ) -> bool {
+        if entries.len() >= self.max_entries && !entries.contains_key(&key) {
+            self.make_room(entries, now);
+        }
+        let fresh = Entry {
+            failures: 0,
+            last_failure: now,
+            locked_until: None,
+        };
+        let entry = entries.entry(key).or_insert(fresh);
+        if entry.stale(now, self.forget_after) {
+            *entry = fresh;
+        }
+        entry.failures = entry.failures.saturating_add(1);
+        entry.last_failure = now;
+        let Some(over) = entry
+            .failures
+            .checked_sub(limits.free_attempts.saturating_add(1))
+        else {
+            return false;
+        };
+        let delay = limits
+            .base_delay
+            .saturating_mul(2u32.saturating_pow(over.min(31)))
+            .min(limits.max_delay);
+        entry.locked_until = Some(now + delay);
+        over == 0
+    }
+
+    /// Drops stale entries, then the oldest eighth if that wasn't enough,
+    /// so a flood of new keys pays for a scan only every so often.
+    fn make_room<K: Hash + Eq>(&self, entries: &mut HashMap<K, Entry>, now: Instant) {
+        entries.retain(|_, e| !e.stale(now, self.forget_after));
+        if entries.len() < self.max_entries {
+            return;
+        }
+        let mut times: Vec<Instant> = entries.values().map(|e| e.last_failure).collect();
+        let evict = (times.len() / 8).max(1);
+        let (_, &mut cutoff, _) = times.select_nth_unstable(evict - 1);
+        entries.retain(|_, e| e.last_failure > cutoff);
+        warn!(
+            "Login throttle full at {} entries; forgot the oldest",
+            self.max_entries
+        );
+    }
+}
+
+impl Entry {
+    fn stale(&self, now: Instant, forget_after: Duration) -> bool {
+        let quiet = now.duration_since(self.last_failure) >= forget_after;
+        quiet && self.locked_until.is_none_or(|until| until <= now)
+    }
+}
+
+fn username_key(username: &str) -> Option<String> {
+    let username = username.trim();
+    (!username.is_empty()).then(|| username.to_lowercase())
+}
+
+fn ip_key(ip: IpAddr) -> IpAddr {
+    match ip {
+        IpAddr::V4(_) => ip,
+        IpAddr::V6(v6) => match v6.to_ipv4_mapped() {
+            Some(v4) => IpAddr::V4(v4),
+            None => {
+                let mut segments = v6.segments();
+                segments[4..].fill(0);
+                IpAddr::V6(segments.into())
+            }
+        },
+    }
+}
@@ -0,0 +1,111 @@
+use crate::stream::{Closer, Stream};
+use rustls::{
+    pki_types::{pem::PemObject, CertificateDer, PrivateKeyDer, ServerName},
+    ClientConfig, ClientConnection, RootCertStore, ServerConfig, ServerConnection, StreamOwned,
+};
+use std::{
+    io::{self, ErrorKind, Read, Write},
+    net::{SocketAddr, TcpListener, TcpStream},
+    path::Path,
+    sync::{Arc, OnceLock},
+    time::Duration,
+};
+
+pub(crate) fn load_config(cert: &Path, key: &Path) -> io::Result<Arc<ServerConfig>> {
+    let certs = CertificateDer::pem_file_iter(cert)
+        .and_then(|certs| certs.collect::<Result<Vec<_>, _>>())
+        .map_err(|e| io::Error::new(ErrorKind::InvalidData, format!("{}: {e}", cert.display())))?;
+    let key = PrivateKeyDer::from_pem_file(key)
+        .map_err(|e| io::Error::new(ErrorKind::InvalidData, format!("{}: {e}", key.display())))?;
+
+    let mut config =
+        ServerConfig::builder_with_provider(Arc::new(rustls::crypto::ring::default_provider()))
+            .with_safe_default_protocol_versions()
+            .and_then(|b| b.with_no_client_auth().with_single_cert(certs, key))
+            .map_err(|e| io::Error::new(ErrorKind::InvalidInput, e))?;
+    config.alpn_protocols = vec![b"http/1.1".to_vec()];
+    Ok(Arc::new(config))
+}
+
+pub(crate) fn accept(
+    listener: &TcpListener,
+    config: &Arc<ServerConfig>,
+) -> io::Result<Box<dyn Stream>> {
+    let (sock, _) = listener.accept()?;
+    // Under `poll` the listener is non-blocking, and on some platforms so
+    // is what it accepts; the handshake needs a blocking socket.
+    #[cfg(feature = "poll")]
+    sock.set_nonblocking(false)?;
+    let conn = ServerConnection::new(Arc::clone(config)).map_err(io::Error::other)?;
+    Ok(Box::new(TlsStream(StreamOwned::new(conn, sock))))
+}
+
+/// The handshake runs lazily on the first read or write, so a client that
+/// fails it only costs the worker that picked up the connection.
+struct TlsStream(StreamOwned<ServerConnection, TcpStream>);
+
+impl Read for TlsStream {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        match self.0.read(buf) {
+            // Plenty of clients hang up without sending close_notify.
+            Err(e) if e.kind() == ErrorKind::UnexpectedEof => Ok(0),
+            res => res,
+        }
+    }
+}
+
+impl Write for TlsStream {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0.write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.0.flush()
+    }
+}
+
+impl Stream for TlsStream {
+    fn set_read_timeout(&self, timeout: Option<Duration>) -> io::Result<()> {
+        self.0.sock.set_read_timeout(timeout)
+    }
+
+    fn set_write_timeout(&self, timeout: Option<Duration>) -> io::Result<()> {
+        self.0.sock.set_write_timeout(timeout)
+    }
+
+    fn set_nodelay(&self, nodelay: bool) -> io::Result<()> {
+        self.0.sock.set_nodelay(nodelay)
+    }
+
+    fn peer_addr(&self) -> Option<SocketAddr> {
+        self.0.sock.peer_addr().ok()
+    }
+
+    fn is_secure(&self) -> bool {
+        true
+    }
+
+    fn closer(&self) -> Option<Closer> {
+        self.0.sock.try_clone().ok().map(Closer::Tcp)
+    }
+}
+
+/// Wraps `sock` in TLS to `host`, verified against the Mozilla roots
+/// bundled in `webpki-roots`.
+pub(crate) fn connect(host: &str, sock: TcpStream) -> io::Result<impl Read + Write> {
+    static CONFIG: OnceLock<Result<Arc<ClientConfig>, String>> = OnceLock::new();
+    let config = CONFIG
+        .get_or_init(|| {
+            let roots = RootCertStore::from_iter(webpki_roots::TLS_SERVER_ROOTS.iter().cloned());
+            ClientConfig::builder_with_provider(Arc::new(rustls::crypto::ring::default_provider()))
+                .with_safe_default_protocol_versions()
+                .map(|b| Arc::new(b.with_root_certificates(roots).with_no_client_auth()))
+                .map_err(|e| e.to_string())
+        })
+        .clone()
+        .map_err(io::Error::other)?;
+    let name = ServerName::try_from(host.to_owned())
+        .map_err(|e| io::Error::new(ErrorKind::InvalidInput, e))?;
+    let conn = ClientConnection::new(config, name).map_err(io::Error::other)?;
+    Ok(StreamOwned::new(conn, sock))
+}
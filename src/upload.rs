@@ -0,0 +1,150 @@
+use crate::{error::UploadError, form::UploadedFile, mime};
+use std::path::Path;
+
+/// What a file must be for `validate` to accept it. An empty list allows
+/// anything; types and extensions (without the dot) compare ignoring case.
+#[derive(Clone, Debug)]
+pub struct UploadPolicy {
+    pub max_bytes: usize,
+    pub allowed_types: Vec<String>,
+    pub allowed_extensions: Vec<String>,
+}
+
+impl UploadPolicy {
+    /// PNG, JPEG, GIF and WebP images up to `max_bytes`, such as avatars.
+    pub fn images(max_bytes: usize) -> UploadPolicy {
+        UploadPolicy {
+            max_bytes,
+            allowed_types: ["image/png", "image/jpeg", "image/gif", "image/webp"]
+                .map(String::from)
+                .to_vec(),
+            allowed_extensions: ["png", "jpg", "jpeg", "gif", "webp"]
+                .map(String::from)
+                .to_vec(),
+        }
+    }
+
+    /// Checks the size, the declared Content-Type and the file name's
+    /// extension. When either claims an image type `mime::sniff` knows,
+    /// the magic bytes have to agree, so a renamed executable fails even
+    /// with an image Content-Type. Files sent as `application/octet-stream`
+    /// are judged by their contents alone.
+    pub fn validate(&self, file: &UploadedFile) -> Result<(), UploadError> {
+        if file.data.len() > self.max_bytes {
+            return Err(UploadError::TooLarge {
+                size: file.data.len(),
+                max: self.max_bytes,
+            });
+        }
+        let sniffed = mime::sniff(&file.data);
+        let declared = file
+            .content_type
+            .as_deref()
+            .map(mime::essence)
+            .filter(|ct| !ct.is_empty() && ct != "application/octet-stream");
+
+        let kind = declared.clone().or_else(|| sniffed.map(String::from));
+        if !self.allowed_types.is_empty()
+            && !kind
+                .as_ref()
+                .is_some_and(|kind| allowed(&self.allowed_types, kind))
+        {
+            return Err(UploadError::TypeNotAllowed(kind));
+        }
+        if let Some(declared) = declared {
+            if mime::extension_for(&declared).is_some() && sniffed != Some(declared.as_str()) {
+                return Err(UploadError::ContentMismatch {
+                    claimed: declared,
+                    sniffed,
+                });
+            }
+        }
+
+        let ext = file
+            .file_name
+            .as_deref()
+            .and_then(|name| extension(base_name(name)))
+            .map(|ext| ext.to_ascii_lowercase());
+        if !self.allowed_extensions.is_empty()
+            && !ext.as_ref().is_some_and(|ext| {
+                let exts = self.allowed_extensions.iter();
+                exts.map(|a| a.trim_start_matches('.'))
+                    .any(|a| a.eq_ignore_ascii_case(ext))
+            })
+        {
+            return Err(UploadError::ExtensionNotAllowed(ext));
+        }
+        if let Some(ext) = ext {
+            let implied = mime::from_path(Path::new(&format!("file.{ext}")));
+            if mime::extension_for(implied).is_some() && sniffed != Some(implied) {
+                return Err(UploadError::ContentMismatch {
+                    claimed: format!(".{ext}"),
+                    sniffed,
+                });
+            }
+        }
+        Ok(())
+    }
+}
+
+impl UploadedFile {
+    /// A name that is safe to store the file under: the last component of
+    /// the client's name, without control or shell-special characters or
+    /// leading dots, cut to 100 characters, and given the extension of
+    /// the sniffed type when there is one. Falls back to `upload`. Two
+    /// uploads can still end up with the same name.
+    pub fn safe_file_name(&self) -> String {
+        let name = self
+            .file_name
+            .as_deref()
+            .map(base_name)
+            .unwrap_or_default()
+            .chars()
+            .filter(|c| !c.is_control() && !matches!(c, '"' | '<' | '>' | ':' | '|' | '?' | '*'))
+            .collect::<String>();
+        let name = name.trim().trim_start_matches('.');
+        let (stem, ext) = match extension(name) {
+            Some(ext) => (&name[..name.len() - ext.len() - 1], Some(ext)),
+            None => (name, None),
+        };
+        let ext = mime::sniff(&self.data)
+            .and_then(mime::extension_for)
+            .map(String::from)
+            .or_else(|| {
+                ext.filter(|e| e.len() <= 10 && e.chars().all(|c| c.is_ascii_alphanumeric()))
+                    .map(|e| e.to_ascii_lowercase())
+            });
+        let stem = stem
+            .trim_end_matches(['.', ' '])
+            .chars()
+            .take(100)
+            .collect::<String>();
+        let stem = if stem.is_empty() {
+            String::from("upload")
+        } else {
+            stem
+        };
+        match ext {
+            Some(ext) => format!("{stem}.{ext}"),
+            None => stem,
+        }
+    }
+}
+
+fn allowed(list: &[String], kind: &str) -> bool {
+    list.iter().any(|t| t.eq_ignore_ascii_case(kind))
+}
+
+/// Browsers on Windows may send the whole path; only the last part counts.
+fn base_name(name: &str) -> &str {
+    name.rsplit(['/', '\\']).next().unwrap_or_default()
+}
+
+fn extension(name: &str) -> Option<&str> {
+    match name.rsplit_once('.') {
+        Some((stem, ext)) if !stem.trim_start_matches('.').is_empty() && !ext.is_empty() => {
+            Some(ext)
+        }
+        _ => None,
+    }
+}
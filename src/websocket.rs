@@ -0,0 +1,378 @@
+use crate::{
+    buffers,
+    encoding::{base64_decode, base64_encode, sha1},
+    request::Request,
+    response::{Response, StatusCode},
+    stream::Stream,
+};
+use std::{
+    io::{self, ErrorKind, Read},
+    sync::Arc,
+    time::Duration,
+};
+
+/// Appended to the client's key before hashing, per RFC 6455.
+const ACCEPT_GUID: &str = "258EAFA5-E914-47DA-95CA-C5AB0DC85B11";
+
+pub(crate) type WebSocketHandler =
+    Arc<dyn Fn(&Request, &mut WebSocket) -> io::Result<()> + Send + Sync>;
+
+const CONTINUATION: u8 = 0x0;
+const TEXT: u8 = 0x1;
+const BINARY: u8 = 0x2;
+const CLOSE: u8 = 0x8;
+const PING: u8 = 0x9;
+const PONG: u8 = 0xA;
+
+/// Close codes from RFC 6455 section 7.4.1.
+pub const CLOSE_NORMAL: u16 = 1000;
+pub const CLOSE_PROTOCOL_ERROR: u16 = 1002;
+pub const CLOSE_INVALID_DATA: u16 = 1007;
+pub const CLOSE_TOO_BIG: u16 = 1009;
+pub const CLOSE_INTERNAL_ERROR: u16 = 1011;
+
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub enum Message {
+    Text(String),
+    Binary(Vec<u8>),
+    /// Already answered with a pong by the time `recv` returns it.
+    Ping(Vec<u8>),
+    Pong(Vec<u8>),
+    /// The peer started or finished the close handshake. `None` if it gave
+    /// no status code.
+    Close(Option<CloseFrame>),
+}
+
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct CloseFrame {
+    pub code: u16,
+    pub reason: String,
+}
+
+/// The 101 that accepts `req` as a WebSocket, or the error response to
+/// send instead: 426 unless it asks to upgrade to WebSocket version 13,
+/// 400 if its key is not 16 base64-encoded bytes.
+pub(crate) fn handshake(req: &Request) -> Result<Response, Response> {
+    let has_token = |name: &str, token: &str| {
+        req.header(name)
+            .is_some_and(|v| v.split(',').any(|t| t.trim().eq_ignore_ascii_case(token)))
+    };
+    if !has_token("Upgrade", "websocket") || !has_token("Connection", "upgrade") {
+        return Err(Response::new(StatusCode::UpgradeRequired)
+            .header("Upgrade", "websocket")
+            .header("Content-Type", "text/plain")
+            .body("This endpoint only speaks WebSocket\n"));
+    }
+    if req.header("Sec-WebSocket-Version").map(str::trim) != Some("13") {
+        return Err(Response::new(StatusCode::UpgradeRequired)
+            .header("Upgrade", "websocket")
+            .header("Sec-WebSocket-Version", "13"));
+    }
+    let key = req.header("Sec-WebSocket-Key").map(str::trim);
+    let valid = key
+        .and_then(base64_decode)
+        .is_some_and(|decoded| decoded.len() == 16);
+    let (Some(key), true) = (key, valid) else {
+        return Err(Response::new(StatusCode::BadRequest));
+    };
+    let accept = base64_encode(&sha1(format!("{key}{ACCEPT_GUID}").as_bytes()));
+    Ok(Response::new(StatusCode::SwitchingProtocols)
+        .header("Upgrade", "websocket")
+        .header("Connection", "Upgrade")
+        .header("Sec-WebSocket-Accept", &accept))
+}
+
+struct Frame {
+    fin: bool,
+    opcode: u8,
+    payload: Vec<u8>,
+}
+
+/// An accepted WebSocket connection. Client frames must be masked, text
+/// must be UTF-8 and no message may be larger than the server's
+/// `max_body_size`; a peer that breaks one of those rules gets a close
+/// frame with the matching code and `recv` returns an `InvalidData` error.
+pub struct WebSocket {
+    stream: Box<dyn Stream>,
+    /// Read from the socket but not parsed into frames yet.
+    buffer: Vec<u8>,
+    /// The opcode and payload so far of a fragmented message.
+    partial: Option<(u8, Vec<u8>)>,
+    max_message_size: usize,
+    sent_close: bool,
+    received_close: bool,
+}
+
+impl WebSocket {
+    /// `buffer` holds whatever the client sent after the handshake request
+    /// in the same read.
+    pub(crate) fn new(
+        stream: Box<dyn Stream>,
+        buffer: Vec<u8>,
+        max_message_size: usize,
+    ) -> WebSocket {
+        WebSocket {
+            stream,
+            buffer,
+            partial: None,
+            max_message_size,
+            sent_close: false,
+            received_close: false,
+        }
+    }
+
+    /// Socket reads block forever by default.
+    pub fn set_read_timeout(&self, timeout: Option<Duration>) -> io::Result<()> {
+        self.stream.set_read_timeout(timeout)
+    }
+
+    /// The next message, with fragments joined. Pings are answered before
+    /// they're returned, and a close from the peer is answered too.
+    /// `None` once the close handshake is done or the peer disconnected.
+    pub fn recv(&mut self) -> io::Result<Option<Message>> {
+        while !self.received_close {
+            let Some(frame) = self.read_frame()? else {
+                self.received_close = true;
+                break;
+            };
+            if frame.opcode >= CLOSE && (!frame.fin || frame.payload.len() > 125) {
+                return Err(self.fail(
+                    CLOSE_PROTOCOL_ERROR,
+                    "fragmented or oversized control frame",
+                ));
+            }
+            match frame.opcode {
+                CLOSE => return self.closed_by_peer(frame.payload).map(Some),
+                PING => {
+                    if !self.sent_close {
+                        self.write_frame(PONG, &frame.payload)?;
+                    }
+                    return Ok(Some(Message::Ping(frame.payload)));
+                }
+                PONG => return Ok(Some(Message::Pong(frame.payload))),
+                TEXT | BINARY if self.partial.is_some() => {
+                    return Err(
+                        self.fail(CLOSE_PROTOCOL_ERROR, "new message inside a fragmented one")
+                    );
+                }
+                TEXT | BINARY => self.partial = Some((frame.opcode, frame.payload)),
+                CONTINUATION => match &mut self.partial {
+                    Some((_, payload))
+                        if payload.len() + frame.payload.len() > self.max_message_size =>
+                    {
+                        return Err(self.fail(CLOSE_TOO_BIG, "message too large"));
+                    }
+                    Some((_, payload)) => payload.extend_from_slice(&frame.payload),
+                    None => {
+                        return Err(
+                            self.fail(CLOSE_PROTOCOL_ERROR, "continuation without a message")
+                        )
+                    }
+                },
+                _ => return Err(self.fail(CLOSE_PROTOCOL_ERROR, "unknown opcode")),
+            }
+            if frame.fin {
+                let (opcode, payload) = self.partial.take().expect("a data frame was just read");
+                return match opcode {
+                    TEXT => match String::from_utf8(payload) {
+                        Ok(text) => Ok(Some(Message::Text(text))),
+                        Err(_) => Err(self.fail(CLOSE_INVALID_DATA, "text message is not UTF-8")),
+                    },
+                    _ => Ok(Some(Message::Binary(payload))),
+                };
+            }
+        }
+        Ok(None)
+    }
+
+    pub fn send(&mut self, message: Message) -> io::Result<()> {
+        match message {
+            Message::Text(text) => self.send_text(&text),
+            Message::Binary(data) => self.send_binary(&data),
+            Message::Ping(data) => self.ping(&data),
+            Message::Pong(data) => self.write_frame(PONG, &data),
+            Message::Close(Some(frame)) => self.close(frame.code, &frame.reason),
+            Message::Close(None) => self.close(CLOSE_NORMAL, ""),
+        }
+    }
+
+    pub fn send_text(&mut self, text: &str) -> io::Result<()> {
+        self.write_frame(TEXT, text.as_bytes())
+    }
+
+    pub fn send_binary(&mut self, data: &[u8]) -> io::Result<()> {
+        self.write_frame(BINARY, data)
+    }
+
+    /// The matching pong comes back through `recv`.
+    pub fn ping(&mut self, data: &[u8]) -> io::Result<()> {
+        if data.len() > 125 {
+            return Err(io::Error::new(
+                ErrorKind::InvalidInput,
+                "ping payload over 125 bytes",
+            ));
+        }
+        self.write_frame(PING, data)
+    }
+
+    /// Sends a close frame and waits for the peer's, dropping any messages
+    /// that arrive first. Does nothing if a close was already sent. A
+    /// reason past the 123 bytes a close frame has room for is cut at the
+    /// last whole character that fits.
+    pub fn close(&mut self, code: u16, reason: &str) -> io::Result<()> {
+        if self.sent_close {
+            return Ok(());
+        }
+        let mut end = reason.len().min(123);
+        while !reason.is_char_boundary(end) {
+            end -= 1;
+        }
+        let mut payload = code.to_be_bytes().to_vec();
+        payload.extend_from_slice(&reason.as_bytes()[..end]);
+        self.write_frame(CLOSE, &payload)?;
+        self.sent_close = true;
+        while self.recv()?.is_some() {}
+        Ok(())
+    }
+
+    /// Ends the connection once the handler is done with it.
+    pub(crate) fn finish(mut self, code: u16) {
+        if let Err(e) = self.close(code, "") {
+            log::debug!("WebSocket close did not complete: {e}");
+        }
+    }
+
+    fn closed_by_peer(&mut self, payload: Vec<u8>) -> io::Result<Message> {
+        let frame = match payload.len() {
+            0 => None,
+            1 => return Err(self.fail(CLOSE_PROTOCOL_ERROR, "close frame with a one-byte body")),
+            _ => match String::from_utf8(payload[2..].to_vec()) {
+                Ok(reason) => Some(CloseFrame {
+                    code: u16::from_be_bytes([payload[0], payload[1]]),
+                    reason,
+                }),
+                Err(_) => return Err(self.fail(CLOSE_INVALID_DATA, "close reason is not UTF-8")),
+            },
+        };
+        self.received_close = true;
+        if !self.sent_close {
+            let code = frame.as_ref().map_or(CLOSE_NORMAL, |f| f.code);
+            self.write_frame(CLOSE, &code.to_be_bytes())?;
+            self.sent_close = true;
+        }
+        Ok(Message::Close(frame))
+    }
+
+    /// Sends a close frame with `code`, ignoring whether that works, and
+    /// gives up on the connection.
+    fn fail(&mut self, code: u16, why: &str) -> io::Error {
+        if !self.sent_close {
+            let _ = self.write_frame(CLOSE, &code.to_be_bytes());
+            self.sent_close = true;
+        }
+        self.received_close = true;
+        io::Error::new(ErrorKind::InvalidData, why)
+    }
+
+    fn write_frame(&mut self, opcode: u8, payload: &[u8]) -> io::Result<()> {
+        if self.sent_close {
+            return Err(io::Error::new(
+                ErrorKind::NotConnected,
+                "WebSocket is closed",
+            ));
+        }
+        let mut head = vec![0x80 | opcode];
+        match payload.len() {
+            len @ 0..=125 => head.push(len as u8),
+            len @ 126..=0xFFFF => {
+                head.push(126);
+                head.extend_from_slice(&(len as u16).to_be_bytes());
+            }
+            len => {
+                head.push(127);
+                head.extend_from_slice(&(len as u64).to_be_bytes());
+            }
+        }
+        let vectored = self.stream.vectored_writes();
+        buffers::write_joined(&mut self.stream, &mut head, payload, vectored)
+    }
+
+    /// `None` if the connection ended cleanly between frames.
+    fn read_frame(&mut self) -> io::Result<Option<Frame>> {
+        if !self.fill(2)? {
+            if self.buffer.is_empty() {
+                return Ok(None);
+            }
+            return Err(ErrorKind::UnexpectedEof.into());
+        }
+        let (b0, b1) = (self.buffer[0], self.buffer[1]);
+        if b0 & 0x70 != 0 {
+            return Err(self.fail(CLOSE_PROTOCOL_ERROR, "reserved bits set"));
+        }
+        if b1 & 0x80 == 0 {
+            return Err(self.fail(CLOSE_PROTOCOL_ERROR, "client frame is not masked"));
+        }
+        let (len, offset) = match b1 & 0x7F {
+            126 => {
+                self.require(4)?;
+                (
+                    u16::from_be_bytes([self.buffer[2], self.buffer[3]]) as u64,
+                    4,
+                )
+            }
+            127 => {
+                self.require(10)?;
+                let mut bytes = [0; 8];
+                bytes.copy_from_slice(&self.buffer[2..10]);
+                (u64::from_be_bytes(bytes), 10)
+            }
+            len => (len as u64, 2),
+        };
+        if len > self.max_message_size as u64 {
+            return Err(self.fail(CLOSE_TOO_BIG, "message too large"));
+        }
+        let start = offset + 4;
+        let end = start + len as usize;
+        self.require(end)?;
+        let mask = [
+            self.buffer[offset],
+            self.buffer[offset + 1],
+            self.buffer[offset + 2],
+            self.buffer[offset + 3],
+        ];
+        let payload = self.buffer[start..end]
+            .iter()
+            .zip(mask.iter().cycle())
+            .map(|(b, m)| b ^ m)
+            .collect();
+        self.buffer.drain(..end);
+        Ok(Some(Frame {
+            fin: b0 & 0x80 != 0,
+            opcode: b0 & 0x0F,
+            payload,
+        }))
+    }
+
+    /// Like `fill`, but the connection ending here is an error.
+    fn require(&mut self, n: usize) -> io::Result<()> {
+        if !self.fill(n)? {
+            return Err(ErrorKind::UnexpectedEof.into());
+        }
+        Ok(())
+    }
+
+    /// Reads until the buffer holds `n` bytes; false if the peer closed
+    /// the connection first.
+    fn fill(&mut self, n: usize) -> io::Result<bool> {
+        let mut chunk = [0; 4096];
+        while self.buffer.len() < n {
+            match self.stream.read(&mut chunk) {
+                Ok(0) => return Ok(false),
+                Ok(read) => self.buffer.extend_from_slice(&chunk[..read]),
+                Err(e) if e.kind() == ErrorKind::Interrupted => {}
+                Err(e) => return Err(e),
+            }
+        }
+        Ok(true)
+    }
+}
@@ -0,0 +1,239 @@
+//! Access log lines, written to an in-memory sink and read back after the
+//! server has shut down.
+
+use regex::Regex;
+use serde_json::{json, Value};
+use simple_social::{
+    access_log::LogFormat,
+    response::{Response, StatusCode},
+    server::{RequestHandler, Server},
+};
+use std::{
+    io::{self, Read, Write},
+    net::TcpStream,
+    sync::{Arc, Mutex},
+};
+
+/// A `Vec<u8>` the test keeps a handle on after the server takes the
+/// writer.
+#[derive(Clone, Default)]
+struct Sink(Arc<Mutex<Vec<u8>>>);
+
+impl Write for Sink {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0.lock().unwrap().write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Sends each raw request head on its own connection, then shuts down
+/// and returns the log lines.
+fn log_lines(format: LogFormat, setup: impl FnOnce(&mut Server), heads: &[&str]) -> Vec<String> {
+    let sink = Sink::default();
+    let mut server = Server::new("127.0.0.1:0", 2);
+    server
+        .access_log(sink.clone(), format)
+        .get("/user", |_| Ok(Response::new(StatusCode::Ok).body("hello")))
+        .get("/empty", |_| Ok(Response::new(StatusCode::NoContent)));
+    setup(&mut server);
+    let handle = server.spawn().unwrap();
+    for head in heads {
+        let mut stream = TcpStream::connect(handle.local_addr().unwrap()).unwrap();
+        stream.write_all(head.as_bytes()).unwrap();
+        stream.read_to_end(&mut Vec::new()).unwrap();
+    }
+    handle.shutdown();
+    handle.join().unwrap();
+
+    let out = String::from_utf8(sink.0.lock().unwrap().clone()).unwrap();
+    assert!(out.ends_with('\n') || out.is_empty(), "{out:?}");
+    out.lines().map(String::from).collect()
+}
+
+const TIME: &str = r"\[\d{2}/[A-Z][a-z]{2}/\d{4}:\d{2}:\d{2}:\d{2} \+0000\]";
+
+#[test]
+fn common_lines_follow_the_format() {
+    let lines = log_lines(
+        LogFormat::Common,
+        |_| {},
+        &[
+            "GET /user?id=7 HTTP/1.1\r\nHost: x\r\nConnection: close\r\n\r\n",
+            "GET /empty HTTP/1.0\r\n\r\n",
+            "GET /missing HTTP/1.1\r\nHost: x\r\nConnection: close\r\n\r\n",
+        ],
+    );
+    let common = Regex::new(&format!(
+        r#"^127\.0\.0\.1 - - {TIME} "(?<request>[^"]*)" (?<status>\d{{3}}) (?<bytes>\d+|-)$"#
+    ))
+    .unwrap();
+    let fields: Vec<(String, String, String)> = lines
+        .iter()
+        .map(|line| {
+            let caps = common
+                .captures(line)
+                .unwrap_or_else(|| panic!("not Common Log Format: {line}"));
+            (
+                caps["request"].to_string(),
+                caps["status"].to_string(),
+                caps["bytes"].to_string(),
+            )
+        })
+        .collect();
+    assert_eq!(fields.len(), 3);
+    assert_eq!(
+        fields[0],
+        ("GET /user?id=7 HTTP/1.1".into(), "200".into(), "5".into())
+    );
+    assert_eq!(
+        fields[1],
+        ("GET /empty HTTP/1.0".into(), "204".into(), "-".into())
+    );
+    assert_eq!(fields[2].0, "GET /missing HTTP/1.1");
+    assert_eq!(fields[2].1, "404");
+}
+
+#[test]
+fn combined_lines_quote_the_referer_and_user_agent() {
+    let lines = log_lines(
+        LogFormat::Combined,
+        |_| {},
+        &[
+            "GET /user HTTP/1.1\r\nHost: x\r\nReferer: https://example.com/a\r\n\
+             User-Agent: curl/8.0 \"quoted\"\r\nConnection: close\r\n\r\n",
+            "GET /user HTTP/1.1\r\nHost: x\r\nConnection: close\r\n\r\n",
+        ],
+    );
+    let combined = Regex::new(&format!(
+        r#"^127\.0\.0\.1 - - {TIME} "GET /user HTTP/1\.1" 200 5 "(?<referer>(?:[^"\\]|\\.)*)" "(?<agent>(?:[^"\\]|\\.)*)"$"#
+    ))
+    .unwrap();
+    let fields: Vec<(String, String)> = lines
+        .iter()
+        .map(|line| {
+            let caps = combined
+                .captures(line)
+                .unwrap_or_else(|| panic!("not Combined Log Format: {line}"));
+            (caps["referer"].to_string(), caps["agent"].to_string())
+        })
+        .collect();
+    assert_eq!(
+        fields,
+        [
+            (
+                "https://example.com/a".into(),
+                r#"curl/8.0 \"quoted\""#.into()
+            ),
+            ("-".into(), "-".into()),
+        ]
+    );
+}
+
+#[test]
+fn json_lines_parse_and_carry_the_schema() {
+    let lines = log_lines(
+        LogFormat::Json,
+        |server| {
+            server.access_log_fields(|req, res| {
+                vec![
+                    (String::from("route"), String::from(req.path())),
+                    (String::from("ok"), (res.status().code() < 400).to_string()),
+                ]
+            });
+        },
+        &[
+            "GET /user HTTP/1.1\r\nHost: x\r\nUser-Agent: say \"hi\" \\ é\r\n\
+             Connection: close\r\n\r\n",
+            "GET /missing HTTP/1.1\r\nHost: x\r\nConnection: close\r\n\r\n",
+        ],
+    );
+    assert_eq!(lines.len(), 2);
+    let ts = Regex::new(r"^\d{4}-\d{2}-\d{2}T\d{2}:\d{2}:\d{2}(\.\d+)?Z$").unwrap();
+    let mut ids = Vec::new();
+    for line in lines.iter() {
+        let entry: Value = serde_json::from_str(line).unwrap();
+        let mut keys: Vec<&str> = entry
+            .as_object()
+            .unwrap()
+            .keys()
+            .map(|k| k.as_str())
+            .collect();
+        keys.sort_unstable();
+        assert_eq!(
+            keys,
+            [
+                "bytes",
+                "duration_ms",
+                "method",
+                "ok",
+                "path",
+                "remote_addr",
+                "request_id",
+                "route",
+                "status",
+                "ts",
+                "user_agent"
+            ]
+        );
+        assert!(ts.is_match(entry["ts"].as_str().unwrap()), "{line}");
+        assert_eq!(entry["method"], "GET");
+        assert_eq!(entry["remote_addr"], "127.0.0.1");
+        assert!(entry["duration_ms"].as_f64().unwrap() >= 0.0);
+        assert!(entry["bytes"].is_u64());
+        ids.push(entry["request_id"].as_u64().unwrap());
+    }
+    assert_ne!(ids[0], ids[1]);
+
+    let found: Value = serde_json::from_str(&lines[0]).unwrap();
+    assert_eq!(
+        (&found["path"], &found["status"], &found["bytes"]),
+        (&json!("/user"), &json!(200), &json!(5))
+    );
+    assert_eq!(found["user_agent"], "say \"hi\" \\ é");
+    assert_eq!(
+        (&found["route"], &found["ok"]),
+        (&json!("/user"), &json!("true"))
+    );
+
+    let missing: Value = serde_json::from_str(&lines[1]).unwrap();
+    assert_eq!(missing["status"], 404);
+    assert_eq!(missing["user_agent"], Value::Null);
+    assert_eq!(missing["ok"], "false");
+}
+
+#[test]
+fn secrets_are_redacted_from_access_lines() {
+    let head = "GET /user?token=tok-1&Key=key-2&page=2 HTTP/1.1\r\nHost: x\r\n\
+                Referer: https://example.com/back?access_token=tok-3&q=rust\r\n\
+                User-Agent: agent-4\r\nAuthorization: Basic auth-5\r\n\
+                Connection: close\r\n\r\n";
+    let setup = |server: &mut Server| {
+        server
+            .redact_headers(&["user-AGENT"])
+            .redact_query_keys(&["key"]);
+    };
+
+    let combined = log_lines(LogFormat::Combined, setup, &[head]);
+    assert_eq!(combined.len(), 1);
+    assert!(
+        combined[0].contains(
+            "\"GET /user?token=[REDACTED]&Key=[REDACTED]&page=2 HTTP/1.1\" 200 5 \
+             \"https://example.com/back?access_token=[REDACTED]&q=rust\" \"[REDACTED]\""
+        ),
+        "{}",
+        combined[0]
+    );
+
+    let json = log_lines(LogFormat::Json, setup, &[head]);
+    let entry: Value = serde_json::from_str(&json[0]).unwrap();
+    assert_eq!(entry["user_agent"], "[REDACTED]");
+
+    for line in combined.iter().chain(json.iter()) {
+        for secret in ["tok-1", "key-2", "tok-3", "agent-4", "auth-5"] {
+            assert!(!line.contains(secret), "{secret} logged: {line}");
+        }
+    }
+}
@@ -0,0 +1,129 @@
+//! Fingerprinted static mounts: the URLs `Server::asset_url` hands out,
+//! the caching each kind of URL gets, and what a rescan changes.
+
+use simple_social::{
+    server::Server,
+    static_files::StaticDir,
+    testing::{TestClient, TestResponse},
+};
+use std::{fs, path::Path};
+
+const IMMUTABLE: &str = "public, max-age=31536000, immutable";
+
+fn server(dir: &Path) -> Server {
+    fs::create_dir(dir.join("js")).unwrap();
+    fs::write(dir.join("app.css"), "body { color: red }").unwrap();
+    fs::write(dir.join("js/app.js"), "console.log(1)").unwrap();
+    fs::write(dir.join("LICENSE"), "MIT").unwrap();
+    fs::write(dir.join(".secret.css"), "hidden").unwrap();
+    let mut server = Server::new("127.0.0.1:0", 2);
+    server.mount_static("/assets", StaticDir::new(dir).fingerprinted(true));
+    server
+}
+
+/// The hash in a fingerprinted `url`, checking it sits where it should.
+fn hash_of<'a>(url: &'a str, stem: &str, ext: &str) -> &'a str {
+    let hash = url
+        .strip_prefix(stem)
+        .and_then(|rest| rest.strip_suffix(ext))
+        .and_then(|rest| rest.strip_prefix('.'))
+        .unwrap_or_else(|| panic!("{url} isn't {stem}.<hash>{ext}"));
+    assert_eq!(hash.len(), 8, "{url}");
+    assert!(hash.bytes().all(|b| b.is_ascii_hexdigit()), "{url}");
+    hash
+}
+
+fn get(client: &TestClient, path: &str) -> TestResponse {
+    client.get(path).unwrap()
+}
+
+#[test]
+fn urls_carry_a_hash_of_the_contents() {
+    let dir = tempfile::tempdir().unwrap();
+    let server = server(dir.path());
+
+    let css = server.asset_url("/assets/app.css");
+    hash_of(&css, "/assets/app", ".css");
+    hash_of(
+        &server.asset_url("/assets/js/app.js"),
+        "/assets/js/app",
+        ".js",
+    );
+    let license = server.asset_url("/assets/LICENSE");
+    assert!(license.starts_with("/assets/LICENSE."), "{license}");
+    assert_eq!(license.len(), "/assets/LICENSE.".len() + 8);
+    assert_eq!(
+        server.asset_url("/assets/app.css?v=2"),
+        format!("{css}?v=2")
+    );
+    // The same bytes always give the same name.
+    assert_eq!(server.assets().url("/assets/app.css"), css);
+
+    for path in [
+        "/assets/missing.css",
+        "/assets/.secret.css",
+        "/static/app.css",
+        "/app.css",
+    ] {
+        assert_eq!(server.asset_url(path), path);
+    }
+}
+
+#[test]
+fn fingerprinted_urls_are_immutable_and_plain_ones_revalidate() {
+    let dir = tempfile::tempdir().unwrap();
+    let server = server(dir.path());
+    let css = server.asset_url("/assets/app.css");
+    let client = TestClient::start(server).unwrap();
+
+    let res = get(&client, &css);
+    assert_eq!(res.status, 200);
+    assert_eq!(res.text(), "body { color: red }");
+    assert_eq!(res.header("Content-Type"), Some("text/css; charset=utf-8"));
+    assert_eq!(res.header("Cache-Control"), Some(IMMUTABLE));
+
+    let res = get(&client, "/assets/app.css");
+    assert_eq!(res.status, 200);
+    assert_eq!(res.text(), "body { color: red }");
+    assert_eq!(res.header("Cache-Control"), Some("no-cache"));
+
+    // A made-up hash is just a missing file.
+    assert_eq!(get(&client, "/assets/app.00000000.css").status, 404);
+}
+
+#[test]
+fn a_rescan_rehashes_modified_files() {
+    let dir = tempfile::tempdir().unwrap();
+    let server = server(dir.path());
+    let assets = server.assets();
+    let old = server.asset_url("/assets/app.css");
+    let js = server.asset_url("/assets/js/app.js");
+    let client = TestClient::start(server).unwrap();
+
+    fs::write(dir.path().join("app.css"), "body { color: blue }").unwrap();
+    // Until the rescan the old name stays.
+    assert_eq!(assets.url("/assets/app.css"), old);
+    assets.rescan().unwrap();
+
+    let new = assets.url("/assets/app.css");
+    assert_ne!(
+        hash_of(&new, "/assets/app", ".css"),
+        hash_of(&old, "/assets/app", ".css")
+    );
+    assert_eq!(assets.url("/assets/js/app.js"), js);
+
+    let res = get(&client, &new);
+    assert_eq!(res.status, 200);
+    assert_eq!(res.text(), "body { color: blue }");
+    assert_eq!(res.header("Cache-Control"), Some(IMMUTABLE));
+    assert_eq!(get(&client, &old).status, 404);
+    assert_eq!(get(&client, &js).status, 200);
+
+    // Files added since the first scan get a name too.
+    fs::write(dir.path().join("print.css"), "@page {}").unwrap();
+    assert_eq!(assets.url("/assets/print.css"), "/assets/print.css");
+    assets.rescan().unwrap();
+    let print = assets.url("/assets/print.css");
+    hash_of(&print, "/assets/print", ".css");
+    assert_eq!(get(&client, &print).text(), "@page {}");
+}
@@ -0,0 +1,276 @@
+//! Connection handling that has to behave the same whichever way the
+//! server waits on sockets. Every test runs once per backend: the
+//! blocking accept loop, and with the `poll` feature the poll loop too.
+
+use simple_social::{
+    response::{Response, StatusCode},
+    server::{RequestHandler, Server, ServerHandle},
+};
+use std::{
+    io::{ErrorKind, Read, Write},
+    net::TcpStream,
+    thread,
+    time::{Duration, Instant},
+};
+
+#[cfg(feature = "poll")]
+const BACKENDS: [&str; 2] = ["blocking", "poll"];
+#[cfg(not(feature = "poll"))]
+const BACKENDS: [&str; 1] = ["blocking"];
+
+/// Runs `test` against a fresh server per backend, built by `setup`. The
+/// test gets the backend's name to put in its assertion messages.
+fn each_backend(setup: impl Fn(&mut Server), test: impl Fn(&ServerHandle, &str)) {
+    for backend in BACKENDS {
+        let mut server = Server::new("127.0.0.1:0", 8);
+        #[cfg(feature = "poll")]
+        server.poll_connections(backend == "poll");
+        server
+            .get("/", |_| Ok(Response::new(StatusCode::Ok).body("hi")))
+            .post("/echo", |req| {
+                Ok(Response::new(StatusCode::Ok).body(req.body().to_vec()))
+            });
+        setup(&mut server);
+        let handle = server.spawn().unwrap();
+        test(&handle, backend);
+        handle.shutdown();
+        handle.join().unwrap();
+    }
+}
+
+/// A client connection that reads responses framed by Content-Length.
+struct Conn {
+    stream: TcpStream,
+    buf: Vec<u8>,
+}
+
+impl Conn {
+    fn open(handle: &ServerHandle) -> Conn {
+        let stream = TcpStream::connect(handle.local_addr().unwrap()).unwrap();
+        stream
+            .set_read_timeout(Some(Duration::from_secs(5)))
+            .unwrap();
+        Conn {
+            stream,
+            buf: Vec::new(),
+        }
+    }
+
+    fn send(&mut self, bytes: &str) {
+        self.stream.write_all(bytes.as_bytes()).unwrap();
+    }
+
+    /// The next response's status and body, or `None` once the server has
+    /// closed the connection.
+    fn response(&mut self) -> Option<(u16, String)> {
+        loop {
+            if let Some(end) = self.buf.windows(4).position(|w| w == b"\r\n\r\n") {
+                let head = String::from_utf8_lossy(&self.buf[..end]).into_owned();
+                let length: usize = head
+                    .lines()
+                    .find_map(|l| l.strip_prefix("Content-Length: "))
+                    .map_or(0, |v| v.parse().unwrap());
+                let start = end + 4;
+                if self.buf.len() >= start + length {
+                    let body = String::from_utf8_lossy(&self.buf[start..start + length]);
+                    let res = (head[9..12].parse().unwrap(), body.into_owned());
+                    self.buf.drain(..start + length);
+                    return Some(res);
+                }
+            }
+            let mut chunk = [0; 4096];
+            match self.stream.read(&mut chunk) {
+                Ok(0) => return None,
+                Ok(n) => self.buf.extend_from_slice(&chunk[..n]),
+                Err(e) if e.kind() == ErrorKind::ConnectionReset => return None,
+                Err(e) => panic!("reading a response: {e}"),
+            }
+        }
+    }
+
+    fn get(&mut self, path: &str) -> Option<(u16, String)> {
+        self.send(&format!("GET {path} HTTP/1.1\r\nHost: x\r\n\r\n"));
+        self.response()
+    }
+}
+
+fn wait_for(backend: &str, what: &str, done: impl Fn() -> bool) {
+    let deadline = Instant::now() + Duration::from_secs(5);
+    while !done() {
+        assert!(
+            Instant::now() < deadline,
+            "{backend}: timed out waiting for {what}"
+        );
+        thread::sleep(Duration::from_millis(5));
+    }
+}
+
+fn ok(body: &str) -> Option<(u16, String)> {
+    Some((200, String::from(body)))
+}
+
+#[test]
+fn requests_share_a_keep_alive_connection() {
+    each_backend(
+        |_| {},
+        |handle, backend| {
+            let mut conn = Conn::open(handle);
+            assert_eq!(conn.get("/"), ok("hi"), "{backend}");
+            assert_eq!(conn.get("/missing").map(|r| r.0), Some(404), "{backend}");
+            assert_eq!(conn.get("/"), ok("hi"), "{backend}");
+            wait_for(backend, "three requests", || handle.metrics().requests == 3);
+        },
+    );
+}
+
+#[test]
+fn pipelined_requests_are_answered_in_order() {
+    each_backend(
+        |_| {},
+        |handle, backend| {
+            let mut conn = Conn::open(handle);
+            conn.send(
+                "GET / HTTP/1.1\r\nHost: x\r\n\r\n\
+                 POST /echo HTTP/1.1\r\nHost: x\r\nContent-Length: 4\r\n\r\nping\
+                 GET /missing HTTP/1.1\r\nHost: x\r\n\r\n",
+            );
+            assert_eq!(conn.response(), ok("hi"), "{backend}");
+            assert_eq!(conn.response(), ok("ping"), "{backend}");
+            assert_eq!(conn.response().map(|r| r.0), Some(404), "{backend}");
+        },
+    );
+}
+
+#[test]
+fn heads_and_bodies_may_arrive_in_pieces() {
+    each_backend(
+        |_| {},
+        |handle, backend| {
+            let mut conn = Conn::open(handle);
+            for piece in ["GET / HT", "TP/1.1\r\nHo", "st: x\r\n", "\r\n"] {
+                conn.send(piece);
+                thread::sleep(Duration::from_millis(20));
+            }
+            assert_eq!(conn.response(), ok("hi"), "{backend}");
+
+            conn.send("POST /echo HTTP/1.1\r\nHost: x\r\nContent-Length: 5\r\n\r\n");
+            thread::sleep(Duration::from_millis(20));
+            conn.send("hel");
+            thread::sleep(Duration::from_millis(20));
+            conn.send("lo");
+            assert_eq!(conn.response(), ok("hello"), "{backend}");
+        },
+    );
+}
+
+#[test]
+fn a_stalled_head_gets_a_408() {
+    each_backend(
+        |server| {
+            server.read_timeout(Duration::from_millis(200));
+        },
+        |handle, backend| {
+            let mut conn = Conn::open(handle);
+            let started = Instant::now();
+            conn.send("GET / HTTP/1.1\r\nHost");
+            assert_eq!(conn.response().map(|r| r.0), Some(408), "{backend}");
+            assert_eq!(conn.response(), None, "{backend}");
+            assert!(started.elapsed() >= Duration::from_millis(200), "{backend}");
+        },
+    );
+}
+
+#[test]
+fn a_silent_connection_is_closed_after_the_read_timeout() {
+    each_backend(
+        |server| {
+            server.read_timeout(Duration::from_millis(200));
+        },
+        |handle, backend| {
+            let mut conn = Conn::open(handle);
+            assert_eq!(conn.response(), None, "{backend}");
+        },
+    );
+}
+
+#[test]
+fn idle_connections_are_counted_and_closed() {
+    each_backend(
+        |server| {
+            server.keep_alive_timeout(Duration::from_millis(300));
+        },
+        |handle, backend| {
+            let mut idle: Vec<Conn> = (0..5).map(|_| Conn::open(handle)).collect();
+            for conn in idle.iter_mut() {
+                assert_eq!(conn.get("/"), ok("hi"), "{backend}");
+            }
+            wait_for(backend, "five idle connections", || {
+                handle.metrics().idle_connections == 5
+            });
+
+            // Idle sockets don't keep a fresh connection from being served.
+            let mut fresh = Conn::open(handle);
+            assert_eq!(fresh.get("/"), ok("hi"), "{backend}");
+            assert_eq!(idle[2].get("/"), ok("hi"), "{backend}");
+
+            for conn in idle.iter_mut().chain([&mut fresh]) {
+                assert_eq!(conn.response(), None, "{backend}");
+            }
+            wait_for(backend, "the idle connections to close", || {
+                let metrics = handle.metrics();
+                (metrics.active_connections, metrics.idle_connections) == (0, 0)
+            });
+        },
+    );
+}
+
+#[test]
+fn connections_past_the_limit_get_a_503() {
+    each_backend(
+        |server| {
+            server.max_connections(2);
+        },
+        |handle, backend| {
+            let mut held: Vec<Conn> = (0..2).map(|_| Conn::open(handle)).collect();
+            for conn in held.iter_mut() {
+                assert_eq!(conn.get("/"), ok("hi"), "{backend}");
+            }
+            let mut over = Conn::open(handle);
+            assert_eq!(over.get("/").map(|r| r.0), Some(503), "{backend}");
+
+            drop(held);
+            wait_for(backend, "the held connections to close", || {
+                handle.metrics().active_connections == 0
+            });
+            assert_eq!(Conn::open(handle).get("/"), ok("hi"), "{backend}");
+        },
+    );
+}
+
+#[test]
+fn shutdown_does_not_wait_for_idle_connections() {
+    for backend in BACKENDS {
+        let mut server = Server::new("127.0.0.1:0", 8);
+        #[cfg(feature = "poll")]
+        server.poll_connections(backend == "poll");
+        server
+            .keep_alive_timeout(Duration::from_secs(60))
+            .get("/", |_| Ok(Response::new(StatusCode::Ok).body("hi")));
+        let handle = server.spawn().unwrap();
+        let mut idle: Vec<Conn> = (0..3).map(|_| Conn::open(&handle)).collect();
+        for conn in idle.iter_mut() {
+            assert_eq!(conn.get("/"), ok("hi"), "{backend}");
+        }
+        wait_for(backend, "three idle connections", || {
+            handle.metrics().idle_connections == 3
+        });
+
+        let started = Instant::now();
+        handle.shutdown();
+        handle.join().unwrap();
+        assert!(started.elapsed() < Duration::from_secs(5), "{backend}");
+        for conn in idle.iter_mut() {
+            assert_eq!(conn.response(), None, "{backend}");
+        }
+    }
+}
@@ -0,0 +1,51 @@
+//! The startup banner, which must never keep the server from starting.
+
+use simple_social::{
+    response::{Response, StatusCode},
+    server::{BannerMode, RequestHandler, Server},
+};
+use std::{
+    io::{self, Read, Write},
+    net::TcpStream,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
+};
+
+/// A terminal that refuses every write.
+struct Broken(Arc<AtomicUsize>);
+
+impl Write for Broken {
+    fn write(&mut self, _: &[u8]) -> io::Result<usize> {
+        self.0.fetch_add(1, Ordering::SeqCst);
+        Err(io::Error::other("not a terminal"))
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Err(io::Error::other("not a terminal"))
+    }
+}
+
+#[test]
+fn run_goes_on_when_clearing_the_screen_fails() {
+    let attempts = Arc::new(AtomicUsize::new(0));
+    let mut server = Server::new("127.0.0.1:0", 2);
+    server
+        .banner(BannerMode::Clear)
+        .banner_writer(Broken(Arc::clone(&attempts)))
+        .get("/", |_| Ok(Response::new(StatusCode::Ok).body("up")));
+    let handle = server.spawn().unwrap();
+
+    let mut stream = TcpStream::connect(handle.local_addr().unwrap()).unwrap();
+    stream
+        .write_all(b"GET / HTTP/1.1\r\nHost: x\r\nConnection: close\r\n\r\n")
+        .unwrap();
+    let mut out = String::new();
+    stream.read_to_string(&mut out).unwrap();
+    assert!(out.starts_with("HTTP/1.1 200 OK\r\n"), "{out}");
+    assert!(attempts.load(Ordering::SeqCst) > 0);
+
+    handle.shutdown();
+    handle.join().unwrap();
+}
@@ -0,0 +1,76 @@
+//! `Request::body_bytes` and `Request::body_text` on bodies they have to
+//! refuse, and on ones too big to want copied.
+
+use simple_social::{error::BodyError, request::Request, response::StatusCode, server::Method};
+
+fn post(content_type: Option<&str>, body: impl Into<Vec<u8>>) -> Request {
+    let mut builder = Request::builder().method(Method::Post).path("/");
+    if let Some(ct) = content_type {
+        builder = builder.header("Content-Type", ct);
+    }
+    builder.body(body).build().unwrap()
+}
+
+#[test]
+fn invalid_utf8_is_a_bad_request() {
+    for bytes in [
+        &b"caf\xe9"[..],
+        b"\xff\xfe",
+        b"half a char: \xe2\x82",
+        b"\xed\xa0\x80 (a surrogate)",
+    ] {
+        let req = post(Some("text/plain"), bytes);
+        assert_eq!(req.body_bytes(), bytes);
+        let err = req.body_text().unwrap_err();
+        assert!(matches!(err, BodyError::InvalidUtf8(_)), "{err:?}");
+        assert_eq!(err.status(), StatusCode::BadRequest);
+    }
+}
+
+#[test]
+fn an_empty_body_is_empty_text() {
+    for content_type in [None, Some("text/plain"), Some("text/plain; charset=utf-8")] {
+        let req = post(content_type, Vec::new());
+        assert_eq!(req.body_bytes(), b"");
+        assert_eq!(req.body_text().unwrap(), "");
+    }
+    let get = Request::builder().path("/").build().unwrap();
+    assert_eq!(get.body_text().unwrap(), "");
+}
+
+#[test]
+fn large_bodies_are_borrowed_not_copied() {
+    let text = "ünïcødé and plenty of it. ".repeat(300_000);
+    let req = post(Some("text/plain; charset=utf-8"), text.clone());
+    assert!(req.body_bytes().len() > 8 << 20);
+    let borrowed = req.body_text().unwrap();
+    assert_eq!(borrowed, text);
+    assert_eq!(borrowed.as_ptr(), req.body_bytes().as_ptr());
+    assert_eq!(req.body().as_ptr(), req.body_bytes().as_ptr());
+}
+
+#[test]
+fn other_charsets_are_unsupported() {
+    for content_type in [
+        "text/plain; charset=iso-8859-1",
+        "text/plain; charset=\"windows-1252\"",
+        "application/json; charset=utf-16",
+    ] {
+        let err = post(Some(content_type), "plain ascii")
+            .body_text()
+            .unwrap_err();
+        assert!(
+            matches!(&err, BodyError::UnsupportedMediaType(Some(ct)) if ct == content_type),
+            "{err:?}"
+        );
+        assert_eq!(err.status(), StatusCode::UnsupportedMediaType);
+    }
+    for content_type in [
+        "text/plain; charset=UTF-8",
+        "text/plain; charset=utf8",
+        "text/plain; charset=US-ASCII",
+        "text/plain",
+    ] {
+        assert_eq!(post(Some(content_type), "ok").body_text().unwrap(), "ok");
+    }
+}
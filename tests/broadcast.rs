@@ -0,0 +1,80 @@
+//! `Broadcaster` fanning out to subscribers that keep up and one that
+//! doesn't.
+
+use simple_social::broadcast::Broadcaster;
+use std::{
+    sync::mpsc::{self, RecvTimeoutError},
+    thread,
+    time::Duration,
+};
+
+const SENT: u64 = 100;
+const CAPACITY: usize = 4;
+
+#[test]
+fn a_slow_subscriber_loses_its_oldest_messages_without_holding_up_the_rest() {
+    let broadcaster = Broadcaster::new(CAPACITY);
+    let (acks, acked) = mpsc::channel();
+    let fast: Vec<_> = (0..2)
+        .map(|_| {
+            let subscriber = broadcaster.subscribe();
+            let acks = acks.clone();
+            thread::spawn(move || {
+                let mut seen = Vec::new();
+                while let Some(n) = subscriber.recv() {
+                    seen.push(n);
+                    acks.send(()).unwrap();
+                }
+                (seen, subscriber.dropped())
+            })
+        })
+        .collect();
+    // Never reads until everything has been sent.
+    let slow = broadcaster.subscribe();
+
+    for n in 0..SENT {
+        assert_eq!(broadcaster.send(n), 3);
+        // Both fast subscribers get each message while the slow one has
+        // read nothing at all.
+        for _ in 0..2 {
+            acked.recv_timeout(Duration::from_secs(5)).unwrap();
+        }
+    }
+
+    assert_eq!(slow.dropped(), SENT - CAPACITY as u64);
+    let kept: Vec<u64> = std::iter::from_fn(|| slow.try_recv()).collect();
+    assert_eq!(kept, [96, 97, 98, 99]);
+
+    // The slow subscriber going away is noticed at the next send.
+    drop(slow);
+    assert_eq!(broadcaster.send(SENT), 2);
+    assert_eq!(broadcaster.subscriber_count(), 2);
+
+    drop(broadcaster);
+    for handle in fast {
+        let (seen, dropped) = handle.join().unwrap();
+        assert_eq!(seen, (0..=SENT).collect::<Vec<_>>());
+        assert_eq!(dropped, 0);
+    }
+}
+
+#[test]
+fn receivers_time_out_and_learn_when_the_broadcaster_is_gone() {
+    let broadcaster = Broadcaster::<&str>::new(2);
+    let subscriber = broadcaster.subscribe();
+    assert_eq!(
+        subscriber.recv_timeout(Duration::from_millis(10)),
+        Err(RecvTimeoutError::Timeout)
+    );
+
+    let sender = broadcaster.clone();
+    drop(broadcaster);
+    sender.send("last");
+    drop(sender);
+    assert_eq!(subscriber.recv(), Some("last"));
+    assert_eq!(subscriber.recv(), None);
+    assert_eq!(
+        subscriber.recv_timeout(Duration::from_millis(10)),
+        Err(RecvTimeoutError::Disconnected)
+    );
+}
@@ -0,0 +1,103 @@
+//! Allocations per request with the connection buffer pool, in a test
+//! binary of its own so the counting allocator only sees this server.
+//! Without the pool every connection allocated a read and a write buffer
+//! of 8KB each; with it, a warmed-up server allocates well under one.
+
+use simple_social::{
+    response::{Response, StatusCode},
+    server::{RequestHandler, Server},
+};
+use std::{
+    alloc::{GlobalAlloc, Layout, System},
+    io::{Read, Write},
+    net::{SocketAddr, TcpStream},
+    sync::atomic::{AtomicUsize, Ordering},
+};
+
+struct Counting;
+
+static CALLS: AtomicUsize = AtomicUsize::new(0);
+static BYTES: AtomicUsize = AtomicUsize::new(0);
+
+unsafe impl GlobalAlloc for Counting {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        CALLS.fetch_add(1, Ordering::Relaxed);
+        BYTES.fetch_add(layout.size(), Ordering::Relaxed);
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout)
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        CALLS.fetch_add(1, Ordering::Relaxed);
+        BYTES.fetch_add(new_size, Ordering::Relaxed);
+        System.realloc(ptr, layout, new_size)
+    }
+}
+
+#[global_allocator]
+static GLOBAL: Counting = Counting;
+
+const REQUEST: &[u8] = b"GET / HTTP/1.1\r\nHost: x\r\n\r\n";
+const LAST: &[u8] = b"GET / HTTP/1.1\r\nHost: x\r\nConnection: close\r\n\r\n";
+
+/// Sends `requests` on one connection, the last asking to close, and reads
+/// to the end without allocating on this side.
+fn exchange(addr: SocketAddr, requests: usize) {
+    let mut stream = TcpStream::connect(addr).unwrap();
+    for _ in 1..requests {
+        stream.write_all(REQUEST).unwrap();
+    }
+    stream.write_all(LAST).unwrap();
+    let mut buf = [0u8; 4096];
+    let mut total = 0;
+    loop {
+        match stream.read(&mut buf).unwrap() {
+            0 => break,
+            n => total += n,
+        }
+    }
+    assert!(total > 0);
+}
+
+/// Allocation calls and bytes per unit of `f`, after a warm-up.
+fn measure(f: impl Fn()) -> (f64, f64) {
+    for _ in 0..20 {
+        f();
+    }
+    let (calls, bytes) = (CALLS.load(Ordering::SeqCst), BYTES.load(Ordering::SeqCst));
+    const RUNS: usize = 200;
+    for _ in 0..RUNS {
+        f();
+    }
+    (
+        (CALLS.load(Ordering::SeqCst) - calls) as f64 / RUNS as f64,
+        (BYTES.load(Ordering::SeqCst) - bytes) as f64 / RUNS as f64,
+    )
+}
+
+#[test]
+fn buffers_are_reused_across_connections_and_requests() {
+    let mut server = Server::new("127.0.0.1:0", 2);
+    server.get("/", |_| Ok(Response::new(StatusCode::Ok).body("hi")));
+    let handle = server.spawn().unwrap();
+    let addr = handle.local_addr().unwrap();
+
+    let (calls, bytes) = measure(|| exchange(addr, 1));
+    assert!(
+        bytes < 8.0 * 1024.0,
+        "{bytes:.0} bytes in {calls:.1} allocations per connection: \
+         the read and write buffers are not being reused"
+    );
+
+    let (calls, bytes) = measure(|| exchange(addr, 10));
+    assert!(
+        bytes < 8.0 * 1024.0,
+        "{bytes:.0} bytes in {calls:.1} allocations for 10 keep-alive requests"
+    );
+
+    handle.shutdown();
+    handle.join().unwrap();
+}
@@ -0,0 +1,166 @@
+//! A server whose workers are all stuck in a slow handler. `Server::new`
+//! never starts fewer than two workers, so asking for one and holding two
+//! requests open saturates the pool.
+
+use simple_social::{
+    response::{Response, StatusCode},
+    server::{RequestHandler, Server, ServerHandle},
+};
+use std::{
+    io::{Read, Write},
+    net::TcpStream,
+    sync::{
+        atomic::{AtomicBool, AtomicUsize, Ordering},
+        Arc,
+    },
+    thread,
+    time::{Duration, Instant},
+};
+
+const WORKERS: usize = 2;
+
+/// A `/slow` route that holds its worker until `release` is called.
+#[derive(Clone, Default)]
+struct Slow {
+    started: Arc<AtomicUsize>,
+    released: Arc<AtomicBool>,
+}
+
+impl Slow {
+    fn server(&self) -> Server {
+        let mut server = Server::new("127.0.0.1:0", 1);
+        let slow = self.clone();
+        server.get("/slow", move |_| {
+            slow.started.fetch_add(1, Ordering::SeqCst);
+            while !slow.released.load(Ordering::SeqCst) {
+                thread::sleep(Duration::from_millis(5));
+            }
+            Ok(Response::new(StatusCode::Ok).body("slow"))
+        });
+        server
+    }
+
+    /// Sends one `/slow` request per worker and waits for them all to be in
+    /// the handler.
+    fn saturate(&self, handle: &ServerHandle) -> Vec<TcpStream> {
+        let held = (0..WORKERS).map(|_| send(handle, "/slow")).collect();
+        wait_for("every worker to be busy", || {
+            self.started.load(Ordering::SeqCst) == WORKERS
+        });
+        held
+    }
+
+    fn release(&self) {
+        self.released.store(true, Ordering::SeqCst);
+    }
+}
+
+fn wait_for(what: &str, done: impl Fn() -> bool) {
+    let deadline = Instant::now() + Duration::from_secs(5);
+    while !done() {
+        assert!(Instant::now() < deadline, "timed out waiting for {what}");
+        thread::sleep(Duration::from_millis(5));
+    }
+}
+
+fn send_raw(handle: &ServerHandle, request: &str) -> TcpStream {
+    let mut stream = TcpStream::connect(handle.local_addr().unwrap()).unwrap();
+    stream.write_all(request.as_bytes()).unwrap();
+    stream
+}
+
+/// Sends a GET for `path` and returns the connection to read the answer
+/// from.
+fn send(handle: &ServerHandle, path: &str) -> TcpStream {
+    let request = format!("GET {path} HTTP/1.1\r\nHost: x\r\nConnection: close\r\n\r\n");
+    send_raw(handle, &request)
+}
+
+fn response(mut stream: TcpStream) -> String {
+    stream
+        .set_read_timeout(Some(Duration::from_secs(5)))
+        .unwrap();
+    let mut out = String::new();
+    stream.read_to_string(&mut out).unwrap();
+    out
+}
+
+fn status(stream: TcpStream) -> String {
+    let out = response(stream);
+    out.get(9..12).unwrap_or(&out).to_owned()
+}
+
+#[test]
+fn unmatched_requests_queue_behind_busy_workers() {
+    let slow = Slow::default();
+    let handle = slow.server().spawn().unwrap();
+    let held = slow.saturate(&handle);
+
+    let mut queued = Vec::new();
+    for i in 0..8 {
+        queued.push((send(&handle, &format!("/missing/{i}")), "404"));
+    }
+    queued.push((send_raw(&handle, "NOT A REQUEST\r\n\r\n"), "400"));
+    let waiting = queued.len();
+    wait_for("every connection to be accepted", || {
+        let metrics = handle.metrics();
+        metrics.active_connections == waiting + WORKERS && metrics.queue_depth == waiting
+    });
+
+    slow.release();
+    for stream in held {
+        assert_eq!(status(stream), "200");
+    }
+    for (stream, want) in queued {
+        assert_eq!(status(stream), want);
+    }
+
+    handle.shutdown();
+    handle.join().unwrap();
+}
+
+#[test]
+fn requests_past_the_threshold_are_shed_at_once() {
+    let slow = Slow::default();
+    let mut server = slow.server();
+    server
+        .get("/fast", |_| Ok(Response::new(StatusCode::Ok).body("fast")))
+        .get("/health", |_| Ok(Response::new(StatusCode::Ok).body("up")))
+        .shed_above(3)
+        .shed_exempt("/health");
+    // Shedding starts once more than three are waiting.
+    let handle = server.spawn().unwrap();
+    let held = slow.saturate(&handle);
+
+    let mut queued = Vec::new();
+    for i in 1..=4 {
+        queued.push(send(&handle, "/fast"));
+        wait_for("the request to queue", || handle.metrics().queue_depth == i);
+    }
+
+    for _ in 0..4 {
+        let started = Instant::now();
+        let shed = response(send(&handle, "/fast"));
+        assert!(shed.starts_with("HTTP/1.1 503"), "{shed}");
+        assert!(shed.contains("Retry-After: 1\r\n"), "{shed}");
+        assert!(started.elapsed() < Duration::from_secs(1));
+    }
+    let health = response(send(&handle, "/health"));
+    assert!(health.starts_with("HTTP/1.1 200"), "{health}");
+    assert!(health.ends_with("up"), "{health}");
+    assert_eq!(handle.metrics().shed, 4);
+    assert_eq!(slow.started.load(Ordering::SeqCst), WORKERS);
+
+    slow.release();
+    for stream in held {
+        assert_eq!(status(stream), "200");
+    }
+    for stream in queued {
+        assert!(response(stream).ends_with("\r\n\r\nfast"));
+    }
+    assert_eq!(status(send(&handle, "/fast")), "200");
+    assert_eq!(handle.metrics().shed, 4);
+
+    handle.shutdown();
+    handle.join().unwrap();
+}
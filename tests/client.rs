@@ -0,0 +1,226 @@
+//! The outgoing client against a simple_social server playing the webhook
+//! receiver.
+
+use simple_social::{
+    client::{http_post, Webhook, MAX_REDIRECTS},
+    error::ClientError,
+    response::{Response, StatusCode},
+    server::{Method, RequestHandler, Server},
+    testing::TestClient,
+};
+use std::{
+    io::{Read, Write},
+    net::TcpListener,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc, Mutex,
+    },
+    thread,
+    time::Duration,
+};
+
+const TIMEOUT: Duration = Duration::from_secs(5);
+
+/// What the receiver saw: method, path, content type and body.
+type Seen = Arc<Mutex<Vec<(Method, String, Option<String>, Vec<u8>)>>>;
+
+fn receiver() -> (TestClient, Seen) {
+    let seen = Seen::default();
+    let mut server = Server::new("127.0.0.1:0", 2);
+    let record = |seen: &Seen| {
+        let seen = Arc::clone(seen);
+        move |req: &simple_social::request::Request| {
+            seen.lock().unwrap().push((
+                req.method(),
+                String::from(req.path()),
+                req.header("Content-Type").map(String::from),
+                req.body().to_vec(),
+            ));
+            Ok(Response::new(StatusCode::Created)
+                .header("X-Received", "yes")
+                .body("thanks"))
+        }
+    };
+    server
+        .post("/hook", record(&seen))
+        .get("/hook", record(&seen))
+        .post("/moved", |_| {
+            Ok(Response::new(StatusCode::SeeOther).header("Location", "hook"))
+        });
+    (TestClient::start(server).unwrap(), seen)
+}
+
+fn url(server: &TestClient, path: &str) -> String {
+    format!("http://{}{path}", server.addr())
+}
+
+#[test]
+fn posts_reach_the_receiver() {
+    let (server, seen) = receiver();
+    let res = http_post(
+        &url(&server, "/hook?source=test"),
+        "application/json",
+        br#"{"post":1}"#,
+        TIMEOUT,
+    )
+    .unwrap();
+    assert_eq!(res.status, 201);
+    assert!(res.is_success());
+    assert_eq!(res.header("x-received"), Some("yes"));
+    assert_eq!(res.text(), "thanks");
+
+    let seen = seen.lock().unwrap();
+    assert_eq!(seen.len(), 1);
+    assert_eq!(seen[0].0, Method::Post);
+    assert_eq!(seen[0].1, "/hook");
+    assert_eq!(seen[0].2.as_deref(), Some("application/json"));
+    assert_eq!(seen[0].3, br#"{"post":1}"#);
+}
+
+#[test]
+fn redirects_are_followed_the_way_browsers_do() {
+    let (server, seen) = receiver();
+    let res = http_post(&url(&server, "/moved"), "text/plain", b"x", TIMEOUT).unwrap();
+    assert_eq!(res.status, 201);
+    // The server has no 307 to send, so a raw one stands in.
+    let kept = reply(
+        format!(
+            "HTTP/1.1 307 Temporary Redirect\r\nLocation: {}\r\nContent-Length: 0\r\n\r\n",
+            url(&server, "/hook")
+        ),
+        1,
+    );
+    let res = http_post(&kept, "text/plain", b"y", TIMEOUT).unwrap();
+    assert_eq!(res.status, 201);
+
+    let seen = seen.lock().unwrap();
+    assert_eq!(
+        (seen[0].0, seen[0].3.len()),
+        (Method::Get, 0),
+        "302 turns into a GET"
+    );
+    assert_eq!(
+        (seen[1].0, seen[1].3.as_slice()),
+        (Method::Post, &b"y"[..]),
+        "307 repeats the POST"
+    );
+    drop(seen);
+
+    let looping = reply(
+        String::from("HTTP/1.1 308 Permanent Redirect\r\nLocation: /again\r\n\r\n"),
+        MAX_REDIRECTS + 1,
+    );
+    let err = http_post(&looping, "text/plain", b"z", TIMEOUT).unwrap_err();
+    assert!(
+        matches!(err, ClientError::TooManyRedirects(MAX_REDIRECTS)),
+        "{err}"
+    );
+}
+
+#[test]
+fn bad_urls_and_dead_hosts_are_errors() {
+    let post = |url: &str| http_post(url, "text/plain", b"", TIMEOUT).unwrap_err();
+    assert!(matches!(
+        post("ftp://example.com/"),
+        ClientError::UnsupportedScheme(_)
+    ));
+    assert!(matches!(
+        post("example.com/hook"),
+        ClientError::InvalidUrl(_)
+    ));
+    assert!(matches!(
+        post("http://user@example.com/"),
+        ClientError::InvalidUrl(_)
+    ));
+    assert!(matches!(
+        post("http://127.0.0.1:notaport/"),
+        ClientError::InvalidUrl(_)
+    ));
+
+    let (server, _) = receiver();
+    let dead = url(&server, "/hook");
+    drop(server);
+    assert!(matches!(post(&dead), ClientError::Io(_)));
+}
+
+/// Answers the next `times` connections with `reply`, closing each after.
+fn reply(reply: String, times: usize) -> String {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+    thread::spawn(move || {
+        for _ in 0..times {
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut request = [0; 1024];
+            let _ = stream.read(&mut request);
+            stream.write_all(reply.as_bytes()).unwrap();
+        }
+    });
+    format!("http://{addr}/")
+}
+
+fn reply_once(raw: &str) -> String {
+    reply(String::from(raw), 1)
+}
+
+#[test]
+fn chunked_and_unframed_replies_are_read_whole() {
+    let chunked = reply_once(
+        "HTTP/1.1 200 OK\r\nTransfer-Encoding: chunked\r\n\r\n3\r\nabc\r\n4;x=y\r\ndefg\r\n0\r\n\r\n",
+    );
+    let res = http_post(&chunked, "text/plain", b"", TIMEOUT).unwrap();
+    assert_eq!(res.text(), "abcdefg");
+
+    let unframed = reply_once("HTTP/1.0 200 OK\r\n\r\nuntil close");
+    let res = http_post(&unframed, "text/plain", b"", TIMEOUT).unwrap();
+    assert_eq!(res.text(), "until close");
+
+    let garbage = reply_once("SMTP ready\r\n\r\n");
+    let err = http_post(&garbage, "text/plain", b"", TIMEOUT).unwrap_err();
+    assert!(matches!(err, ClientError::InvalidResponse(_)), "{err}");
+}
+
+fn flaky(failures: usize, status: StatusCode) -> (TestClient, Arc<AtomicUsize>) {
+    let attempts = Arc::new(AtomicUsize::new(0));
+    let mut server = Server::new("127.0.0.1:0", 2);
+    let counter = Arc::clone(&attempts);
+    server.post("/hook", move |_| {
+        let n = counter.fetch_add(1, Ordering::SeqCst);
+        let status = if n < failures { status } else { StatusCode::Ok };
+        Ok(Response::new(status))
+    });
+    (TestClient::start(server).unwrap(), attempts)
+}
+
+#[test]
+fn webhooks_retry_until_delivered() {
+    let (server, attempts) = flaky(2, StatusCode::ServiceUnavailable);
+    let hook = Webhook::new(&url(&server, "/hook"))
+        .retries(3)
+        .backoff(Duration::from_millis(10));
+    hook.send(b"{}".to_vec());
+    drop(hook);
+    assert_eq!(attempts.load(Ordering::SeqCst), 3);
+}
+
+#[test]
+fn webhooks_take_client_errors_as_final() {
+    let (server, attempts) = flaky(usize::MAX, StatusCode::BadRequest);
+    let hook = Webhook::new(&url(&server, "/hook"))
+        .retries(3)
+        .backoff(Duration::from_millis(10));
+    hook.send(b"{}".to_vec());
+    drop(hook);
+    assert_eq!(attempts.load(Ordering::SeqCst), 1);
+
+    let (server, attempts) = flaky(usize::MAX, StatusCode::InternalServerError);
+    let hook = Webhook::new(&url(&server, "/hook"))
+        .retries(2)
+        .backoff(Duration::from_millis(1));
+    hook.send(b"{}".to_vec());
+    drop(hook);
+    assert_eq!(
+        attempts.load(Ordering::SeqCst),
+        3,
+        "gives up after the last retry"
+    );
+}
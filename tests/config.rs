@@ -0,0 +1,84 @@
+#![cfg(feature = "config")]
+
+use simple_social::{
+    config::ServerConfig,
+    error::ConfigError,
+    response::{Response, StatusCode},
+    server::{RequestHandler, Server},
+    testing::TestClient,
+};
+use std::path::Path;
+
+const FIXTURE: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/fixtures/server.toml");
+
+fn echo(mut server: Server) -> TestClient {
+    server.post("/echo", |req| {
+        Ok(Response::new(StatusCode::Ok).body(req.body().to_vec()))
+    });
+    TestClient::start(server).unwrap()
+}
+
+#[test]
+fn the_fixture_configures_limits_and_mounts() {
+    let client = echo(ServerConfig::from_file(FIXTURE).unwrap().build().unwrap());
+
+    let ok = client.post("/echo", &[b'x'; 16], "text/plain").unwrap();
+    assert_eq!(ok.status, 200);
+    let big = client.post("/echo", &[b'x'; 17], "text/plain").unwrap();
+    assert_eq!(big.status, 413);
+
+    // `dir` is relative to the file, not the working directory.
+    let asset = client.get("/assets/hello.txt").unwrap();
+    assert_eq!((asset.status, asset.text().as_str()), (200, "hello"));
+    assert_eq!(asset.header("Cache-Control"), Some("public, max-age=60"));
+    assert_eq!(asset.header("Content-Type"), Some("text/plain"));
+}
+
+fn invalid(toml: &str) -> (String, String) {
+    match ServerConfig::from_toml(toml) {
+        Err(ConfigError::Invalid { key, message }) => (key, message),
+        Err(e) => panic!("{toml}: {e}"),
+        Ok(_) => panic!("{toml}: accepted"),
+    }
+}
+
+#[test]
+fn typos_name_the_key() {
+    let (key, message) = invalid("[server]\npool_size = 2\nmax_body_sise = 10\n");
+    assert_eq!(key, "server.max_body_sise");
+    assert!(
+        message.contains("unknown field `max_body_sise`"),
+        "{message}"
+    );
+    assert!(message.contains("(line 3)"), "{message}");
+
+    let (key, _) = invalid("[[static]]\nprefix = \"/a\"\ndir = \"a\"\ncache = \"no-store\"\n");
+    assert_eq!(key, "static[0].cache");
+    let (key, _) = invalid("[sever]\npool_size = 2\n");
+    assert_eq!(key, "sever");
+}
+
+#[test]
+fn values_are_checked_where_they_are() {
+    let (key, message) = invalid("[server]\npool_size = \"two\"\n");
+    assert_eq!(key, "server.pool_size");
+    assert!(message.contains("(line 2)"), "{message}");
+
+    let (key, _) = invalid("[log]\nformat = \"xml\"\n");
+    assert_eq!(key, "log.format");
+    let (key, _) = invalid("[[static]]\nprefix = \"assets\"\ndir = \"a\"\n");
+    assert_eq!(key, "static[0].prefix");
+    let (key, _) = invalid("[server]\nlisten = []\n");
+    assert_eq!(key, "server.listen");
+}
+
+#[test]
+fn a_missing_file_names_the_path() {
+    let err = ServerConfig::from_file("tests/fixtures/nope.toml")
+        .err()
+        .unwrap();
+    let ConfigError::File { path, .. } = &err else {
+        panic!("{err}");
+    };
+    assert_eq!(path, Path::new("tests/fixtures/nope.toml"));
+}
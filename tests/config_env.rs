@@ -0,0 +1,44 @@
+//! Environment overrides on a config file, in a binary of their own since
+//! the environment is shared by every test in one.
+#![cfg(feature = "config")]
+
+use simple_social::{
+    config::ServerConfig,
+    error::ConfigError,
+    response::{Response, StatusCode},
+    server::RequestHandler,
+    testing::TestClient,
+};
+use std::env;
+
+const FIXTURE: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/fixtures/server.toml");
+
+#[test]
+fn the_environment_overrides_the_file() {
+    env::set_var("MAX_BODY_SIZE", "32");
+    let mut server = ServerConfig::from_file(FIXTURE).unwrap().build().unwrap();
+    server.post("/echo", |req| {
+        Ok(Response::new(StatusCode::Ok).body(req.body().to_vec()))
+    });
+    let client = TestClient::start(server).unwrap();
+    assert_eq!(
+        client
+            .post("/echo", &[b'x'; 32], "text/plain")
+            .unwrap()
+            .status,
+        200
+    );
+    assert_eq!(
+        client
+            .post("/echo", &[b'x'; 33], "text/plain")
+            .unwrap()
+            .status,
+        413
+    );
+    env::remove_var("MAX_BODY_SIZE");
+
+    env::set_var("PORT", "eighty");
+    let err = ServerConfig::from_file(FIXTURE).err().unwrap();
+    env::remove_var("PORT");
+    assert!(matches!(err, ConfigError::Env { var: "PORT", .. }), "{err}");
+}
@@ -0,0 +1,126 @@
+//! `Db` against a shared in-memory SQLite database, from many threads at
+//! once through its pool.
+
+#![cfg(feature = "sqlite")]
+
+use simple_social::{
+    db::Db,
+    error::DbError,
+    request::Request,
+    response::{Response, StatusCode},
+    server::{RequestHandler, Server},
+};
+use std::{fs, sync::mpsc, thread, time::Duration};
+
+const THREADS: usize = 8;
+const POSTS: usize = 25;
+
+fn migrated(size: usize) -> Db {
+    let dir = tempfile::tempdir().unwrap();
+    fs::write(
+        dir.path().join("001_posts.sql"),
+        "CREATE TABLE posts (id INTEGER PRIMARY KEY, author INTEGER NOT NULL, title TEXT NOT NULL);",
+    )
+    .unwrap();
+    fs::write(
+        dir.path().join("002_likes.sql"),
+        "CREATE TABLE likes (total INTEGER NOT NULL); INSERT INTO likes VALUES (0);",
+    )
+    .unwrap();
+    let db = Db::open_in_memory(size).unwrap();
+    assert_eq!(db.migrate(dir.path()).unwrap(), 2);
+    assert_eq!(db.migrate(dir.path()).unwrap(), 0);
+    db
+}
+
+#[test]
+fn writers_on_many_threads_share_one_database() {
+    let db = migrated(4);
+    assert_eq!(db.size(), 4);
+    let writers: Vec<_> = (0..THREADS)
+        .map(|author| {
+            let db = db.clone();
+            thread::spawn(move || {
+                for n in 0..POSTS {
+                    db.with_conn(|conn| {
+                        let tx = conn.transaction()?;
+                        tx.execute(
+                            "INSERT INTO posts (author, title) VALUES (?1, ?2)",
+                            (author, format!("post {n}")),
+                        )?;
+                        tx.execute("UPDATE likes SET total = total + 1", ())?;
+                        tx.commit()
+                    })
+                    .unwrap();
+                    // Reads in between see whole transactions only.
+                    let mine: usize = db
+                        .with_conn(|conn| {
+                            conn.query_row(
+                                "SELECT COUNT(*) FROM posts WHERE author = ?1",
+                                [author],
+                                |row| row.get(0),
+                            )
+                        })
+                        .unwrap();
+                    assert_eq!(mine, n + 1);
+                }
+            })
+        })
+        .collect();
+    for writer in writers {
+        writer.join().unwrap();
+    }
+
+    let (posts, likes): (usize, usize) = db
+        .with_conn(|conn| {
+            conn.query_row(
+                "SELECT (SELECT COUNT(*) FROM posts), (SELECT total FROM likes)",
+                (),
+                |row| Ok((row.get(0)?, row.get(1)?)),
+            )
+        })
+        .unwrap();
+    assert_eq!((posts, likes), (THREADS * POSTS, THREADS * POSTS));
+}
+
+#[test]
+fn a_busy_pool_times_out() {
+    let db = migrated(1).pool_timeout(Duration::from_millis(20));
+    let (held, release) = mpsc::channel::<()>();
+    let (taken, wait) = mpsc::channel();
+    let holder = {
+        let db = db.clone();
+        thread::spawn(move || {
+            db.with_conn(|_| {
+                taken.send(()).unwrap();
+                release.recv().ok();
+                Ok(())
+            })
+        })
+    };
+    wait.recv().unwrap();
+    assert!(matches!(
+        db.with_conn(|_| Ok(())),
+        Err(DbError::PoolTimeout)
+    ));
+    drop(held);
+    holder.join().unwrap().unwrap();
+    // Back in the pool once the holder is done.
+    db.with_conn(|_| Ok(())).unwrap();
+}
+
+#[test]
+fn database_errors_are_500s() {
+    let db = migrated(2);
+    let mut server = Server::new("127.0.0.1:0", 2);
+    server.get("/posts", move |_| {
+        let titles: Vec<String> = db.with_conn(|conn| {
+            let mut stmt = conn.prepare("SELECT title FROM no_such_table")?;
+            let rows = stmt.query_map((), |row| row.get(0))?;
+            rows.collect()
+        })?;
+        Ok(Response::new(StatusCode::Ok).body(titles.join("\n")))
+    });
+    let req = Request::builder().path("/posts").build().unwrap();
+    assert_eq!(server.handle(req).status(), StatusCode::InternalServerError);
+}
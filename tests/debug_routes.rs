@@ -0,0 +1,78 @@
+//! The development route listing from `Server::debug_routes`.
+
+use simple_social::{
+    response::{Response, StatusCode},
+    server::{Method, RequestHandler, Router, Server},
+    static_files::StaticDir,
+    testing::TestClient,
+};
+
+fn server(debug: bool) -> Server {
+    let ok = |_: &_| Ok(Response::new(StatusCode::Ok));
+    let mut api = Router::new();
+    api.get("/users/:id", ok).delete("/users/:id", ok);
+
+    let mut server = Server::new("127.0.0.1:0", 2);
+    server
+        .debug(debug)
+        .get("/", ok)
+        .post("/posts", ok)
+        .mount("/api", api)
+        .mount_static("/assets", StaticDir::new("static"))
+        .debug_routes("/__routes");
+    // Added after the listing, and still in it.
+    server.put("/posts/:id", ok);
+    server
+}
+
+#[test]
+fn the_listing_shows_the_routing_table() {
+    let client = TestClient::start(server(true)).unwrap();
+
+    let res = client.get("/__routes").unwrap();
+    assert_eq!(res.status, 200);
+    assert_eq!(res.header("Content-Type"), Some("text/plain"));
+    assert_eq!(res.header("Cache-Control"), Some("no-store"));
+    assert_eq!(
+        res.text(),
+        "GET     /               /\n\
+         POST    /posts          /\n\
+         GET     /api/users/:id  /api\n\
+         DELETE  /api/users/:id  /api\n\
+         GET     /__routes       /\n\
+         PUT     /posts/:id      /\n\
+         GET     /assets/*       /assets (static)\n"
+    );
+
+    let res = client
+        .request(
+            Method::Get,
+            "/__routes",
+            &[("Accept", "application/json")],
+            &[],
+        )
+        .unwrap();
+    assert_eq!(res.header("Content-Type"), Some("application/json"));
+    let rows: serde_json::Value = serde_json::from_slice(&res.body).unwrap();
+    assert_eq!(rows.as_array().unwrap().len(), 7);
+    assert_eq!(
+        rows[2],
+        serde_json::json!({"method": "GET", "pattern": "/api/users/:id", "prefix": "/api", "static": false})
+    );
+    assert_eq!(
+        rows[6],
+        serde_json::json!({"method": "GET", "pattern": "/assets/*", "prefix": "/assets", "static": true})
+    );
+}
+
+#[test]
+fn nothing_is_served_outside_debug_mode() {
+    let server = server(false);
+    assert!(!server
+        .routes()
+        .iter()
+        .any(|route| route.pattern == "/__routes"));
+    let client = TestClient::start(server).unwrap();
+    assert_eq!(client.get("/__routes").unwrap().status, 404);
+    assert_eq!(client.get("/").unwrap().status, 200);
+}
@@ -0,0 +1,79 @@
+//! Whole request/response cycles through `Server::serve_connection` on an
+//! in-memory `Duplex`, cut into reads of every size.
+
+use simple_social::{
+    response::{Response, StatusCode},
+    server::{RequestHandler, Server},
+    testing::Duplex,
+};
+
+const PIPELINED: &str = "GET /posts/1 HTTP/1.1\r\nHost: x\r\n\r\n\
+     POST /echo HTTP/1.1\r\nHost: x\r\nContent-Length: 11\r\n\r\nhello world\
+     POST /echo HTTP/1.1\r\nHost: x\r\nTransfer-Encoding: chunked\r\n\r\n\
+     3\r\nabc\r\n4\r\ndefg\r\n0\r\n\r\n\
+     GET /posts/2 HTTP/1.1\r\nHost: x\r\nConnection: close\r\n\r\n\
+     GET /posts/3 HTTP/1.1\r\nHost: x\r\n\r\n";
+
+fn server() -> Server {
+    let mut server = Server::new("127.0.0.1:0", 2);
+    server
+        .get("/posts/:id", |req| {
+            Ok(Response::new(StatusCode::Ok).body(format!("post {}", req.param("id").unwrap())))
+        })
+        .post("/echo", |req| {
+            Ok(Response::new(StatusCode::Ok).body(req.body().to_vec()))
+        });
+    server
+}
+
+fn serve(raw: &str, read_size: usize) -> Duplex {
+    let conn = Duplex::new(raw).read_size(read_size);
+    server().serve_connection(conn.clone()).unwrap();
+    conn
+}
+
+#[test]
+fn pipelined_requests_are_answered_in_order_at_any_read_size() {
+    let whole = serve(PIPELINED, usize::MAX).output();
+    for read_size in [1, 2, 3, 7, 64] {
+        let conn = serve(PIPELINED, read_size);
+        assert_eq!(conn.output(), whole, "read size {read_size}");
+    }
+
+    let responses = serve(PIPELINED, 5).responses().unwrap();
+    let bodies: Vec<String> = responses.iter().map(|res| res.text()).collect();
+    // The request after `Connection: close` is never read.
+    assert_eq!(bodies, ["post 1", "hello world", "abcdefg", "post 2"]);
+    assert!(responses.iter().all(|res| res.status == 200));
+    // Keep-alive is the HTTP/1.1 default, so only the close is announced.
+    let connection: Vec<_> = responses
+        .iter()
+        .map(|res| res.header("Connection"))
+        .collect();
+    assert_eq!(connection, [None, None, None, Some("close")]);
+}
+
+#[test]
+fn a_clean_close_between_requests_ends_the_connection() {
+    let conn = serve("GET /posts/1 HTTP/1.1\r\nHost: x\r\n\r\n", 4);
+    let responses = conn.responses().unwrap();
+    assert_eq!(responses.len(), 1);
+    assert_eq!(responses[0].text(), "post 1");
+}
+
+#[test]
+fn a_request_cut_off_mid_way_is_a_400() {
+    for cut in [
+        "GET /posts/1 HTTP/1.1\r\nHost: x",
+        "POST /echo HTTP/1.1\r\nHost: x\r\nContent-Length: 11\r\n\r\nhello",
+        "POST /echo HTTP/1.1\r\nHost: x\r\nTransfer-Encoding: chunked\r\n\r\n3\r\nab",
+    ] {
+        for read_size in [1, 3, usize::MAX] {
+            let raw = format!("GET /posts/9 HTTP/1.1\r\nHost: x\r\n\r\n{cut}");
+            let responses = serve(&raw, read_size).responses().unwrap();
+            let statuses: Vec<u16> = responses.iter().map(|res| res.status).collect();
+            assert_eq!(statuses, [200, 400], "{cut:?} in reads of {read_size}");
+            assert_eq!(responses[0].text(), "post 9");
+        }
+    }
+}
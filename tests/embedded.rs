@@ -0,0 +1,69 @@
+//! Files compiled into the binary with `static_embedded`.
+#![cfg(feature = "embed")]
+
+use simple_social::{
+    embedded::Asset,
+    server::{Method, Server},
+    testing::TestClient,
+};
+
+const PIXEL: &[u8] = include_bytes!("fixtures/embedded/pixel.png");
+const INDEX: &[u8] = include_bytes!("fixtures/embedded/index.html");
+
+static ASSETS: &[Asset] = &[("/index.html", INDEX, ""), ("/img/pixel.png", PIXEL, "")];
+
+fn client() -> TestClient {
+    let mut server = Server::new("127.0.0.1:0", 2);
+    server.static_embedded("/app", ASSETS);
+    TestClient::start(server).unwrap()
+}
+
+#[test]
+fn an_embedded_image_is_served_byte_for_byte() {
+    let res = client().get("/app/img/pixel.png").unwrap();
+    assert_eq!(res.status, 200);
+    assert_eq!(res.header("Content-Type"), Some("image/png"));
+    assert_eq!(res.body, PIXEL);
+    assert!(res.body.starts_with(b"\x89PNG\r\n\x1a\n"));
+}
+
+#[test]
+fn an_embedded_page_is_served_and_revalidated() {
+    let client = client();
+    let res = client.get("/app/").unwrap();
+    assert_eq!(res.status, 200);
+    assert!(res.header("Content-Type").unwrap().starts_with("text/html"));
+    assert_eq!(res.body, INDEX);
+    assert_eq!(client.get("/app/index.html").unwrap().body, INDEX);
+
+    let etag = res.header("ETag").unwrap();
+    let cached = client
+        .request(
+            Method::Get,
+            "/app/index.html",
+            &[("If-None-Match", etag)],
+            b"",
+        )
+        .unwrap();
+    assert_eq!((cached.status, cached.body.len()), (304, 0));
+    assert_eq!(client.get("/app/missing.png").unwrap().status, 404);
+}
+
+#[test]
+fn embedded_text_gets_the_server_charset() {
+    for (charset, html) in [
+        (Some("utf-8"), "text/html; charset=utf-8"),
+        (Some("iso-8859-1"), "text/html; charset=iso-8859-1"),
+        (None, "text/html"),
+    ] {
+        let mut server = Server::new("127.0.0.1:0", 2);
+        server
+            .default_charset(charset)
+            .static_embedded("/app", ASSETS);
+        let client = TestClient::start(server).unwrap();
+        let page = client.get("/app/index.html").unwrap();
+        assert_eq!(page.header("Content-Type"), Some(html));
+        let image = client.get("/app/img/pixel.png").unwrap();
+        assert_eq!(image.header("Content-Type"), Some("image/png"));
+    }
+}
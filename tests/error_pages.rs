@@ -0,0 +1,93 @@
+//! Error pages with the working directory moved somewhere without a
+//! `static/` folder. The directory is process-wide, so it all runs in one
+//! test.
+
+use simple_social::{
+    response::{Response, StatusCode},
+    server::{RequestHandler, Server},
+    testing::TestClient,
+};
+use std::{
+    env, fs,
+    io::{self, Read, Write},
+    net::TcpStream,
+};
+use tempfile::TempDir;
+
+fn server() -> Server {
+    let mut server = Server::new("127.0.0.1:0", 2);
+    server
+        .get("/fail", |_| Err(io::Error::other("boom").into()))
+        .get("/ok", |_| Ok(Response::new(StatusCode::Ok).body("ok")))
+        .get("/big", |_| {
+            Ok(Response::new(StatusCode::Ok).body(vec![b'x'; 8 << 20]))
+        });
+    server
+}
+
+#[test]
+fn error_pages_never_depend_on_the_working_directory() {
+    let cwd = TempDir::new().unwrap();
+    env::set_current_dir(cwd.path()).unwrap();
+
+    let client = TestClient::start(server()).unwrap();
+    let missing = client.get("/missing").unwrap();
+    assert_eq!(missing.status, 404);
+    assert!(missing.text().contains("<h1>Page not found</h1>"));
+    let failed = client.get("/fail").unwrap();
+    assert_eq!(failed.status, 500);
+    assert!(failed.text().contains("<h1>Error executing</h1>"));
+    let mut raw = TcpStream::connect(client.addr()).unwrap();
+    raw.write_all(b"NOT A REQUEST\r\n\r\n").unwrap();
+    let mut bad = String::new();
+    raw.read_to_string(&mut bad).unwrap();
+    assert!(bad.starts_with("HTTP/1.1 400"), "{bad}");
+    assert!(bad.contains("<h1>400 Bad Request</h1>"), "{bad}");
+    drop(client);
+
+    fs::create_dir(cwd.path().join("pages")).unwrap();
+    fs::write(cwd.path().join("pages/404.html"), "<p>custom 404</p>").unwrap();
+    fs::write(cwd.path().join("pages/500.html"), "<p>custom 500</p>").unwrap();
+    let mut server = server();
+    server
+        .error_page(StatusCode::NotFound, "pages/404.html")
+        .unwrap()
+        .error_page(StatusCode::InternalServerError, "pages/500.html")
+        .unwrap();
+    // Loaded once, so the files can go away.
+    fs::remove_dir_all(cwd.path().join("pages")).unwrap();
+
+    let client = TestClient::start(server).unwrap();
+    let missing = client.get("/missing").unwrap();
+    assert_eq!(
+        (missing.status, missing.text().as_str()),
+        (404, "<p>custom 404</p>")
+    );
+    assert_eq!(client.get("/ok").unwrap().text(), "ok");
+    let failed = client.get("/fail").unwrap();
+    assert_eq!(
+        (failed.status, failed.text().as_str()),
+        (500, "<p>custom 500</p>")
+    );
+
+    let err = Server::new("127.0.0.1:0", 2)
+        .error_page(StatusCode::NotFound, "pages/404.html")
+        .err()
+        .unwrap();
+    assert_eq!(err.kind(), io::ErrorKind::NotFound);
+}
+
+#[test]
+fn a_client_gone_mid_write_leaves_the_server_running() {
+    let client = TestClient::start(server()).unwrap();
+    for _ in 0..3 {
+        let mut raw = TcpStream::connect(client.addr()).unwrap();
+        raw.write_all(b"GET /big HTTP/1.1\r\nHost: x\r\n\r\n")
+            .unwrap();
+        raw.read_exact(&mut [0; 16]).unwrap();
+        drop(raw);
+    }
+    assert_eq!(client.get("/ok").unwrap().text(), "ok");
+    assert_eq!(client.get("/missing").unwrap().status, 404);
+    assert_eq!(client.get("/ok").unwrap().text(), "ok");
+}
@@ -0,0 +1,99 @@
+//! Clients that send `Expect: 100-continue` and hold the body back until
+//! the server says it wants it.
+
+use simple_social::{
+    response::{Response, StatusCode},
+    server::{RequestHandler, Server, ServerHandle},
+};
+use std::{
+    io::{Read, Write},
+    net::TcpStream,
+    time::Duration,
+};
+
+fn spawn() -> ServerHandle {
+    let mut server = Server::new("127.0.0.1:0", 2);
+    server.max_body_size(16).post("/echo", |req| {
+        Ok(Response::new(StatusCode::Ok).body(req.body().to_vec()))
+    });
+    server.spawn().unwrap()
+}
+
+fn connect(handle: &ServerHandle) -> TcpStream {
+    let stream = TcpStream::connect(handle.local_addr().unwrap()).unwrap();
+    stream
+        .set_read_timeout(Some(Duration::from_secs(5)))
+        .unwrap();
+    stream
+}
+
+/// Reads until `stream` has sent a full head, and returns it. A response
+/// body, if any, is left unread.
+fn read_head(stream: &mut TcpStream) -> String {
+    let mut head = Vec::new();
+    let mut byte = [0];
+    while !head.ends_with(b"\r\n\r\n") {
+        assert_eq!(stream.read(&mut byte).unwrap(), 1, "closed mid-head");
+        head.push(byte[0]);
+    }
+    String::from_utf8(head).unwrap()
+}
+
+#[test]
+fn the_body_is_sent_after_100_continue() {
+    let handle = spawn();
+    let mut stream = connect(&handle);
+    stream
+        .write_all(
+            b"POST /echo HTTP/1.1\r\nHost: x\r\nExpect: 100-continue\r\n\
+              Content-Length: 5\r\nConnection: close\r\n\r\n",
+        )
+        .unwrap();
+    assert_eq!(read_head(&mut stream), "HTTP/1.1 100 Continue\r\n\r\n");
+
+    stream.write_all(b"hello").unwrap();
+    let mut out = String::new();
+    stream.read_to_string(&mut out).unwrap();
+    assert!(out.starts_with("HTTP/1.1 200 OK\r\n"), "{out}");
+    assert!(out.ends_with("\r\n\r\nhello"), "{out}");
+
+    handle.shutdown();
+    handle.join().unwrap();
+}
+
+#[test]
+fn an_oversized_body_is_refused_before_it_is_sent() {
+    let handle = spawn();
+    let mut stream = connect(&handle);
+    stream
+        .write_all(
+            b"POST /echo HTTP/1.1\r\nHost: x\r\nExpect: 100-continue\r\n\
+              Content-Length: 1000\r\n\r\n",
+        )
+        .unwrap();
+    // No body has been written, so this only returns if the server answers
+    // from the head alone.
+    let head = read_head(&mut stream);
+    assert!(head.starts_with("HTTP/1.1 413 "), "{head}");
+    assert!(!head.contains("100 Continue"));
+
+    handle.shutdown();
+    handle.join().unwrap();
+}
+
+#[test]
+fn an_unknown_path_is_refused_before_the_body_is_sent() {
+    let handle = spawn();
+    let mut stream = connect(&handle);
+    stream
+        .write_all(
+            b"POST /nowhere HTTP/1.1\r\nHost: x\r\nExpect: 100-continue\r\n\
+              Content-Length: 5\r\n\r\n",
+        )
+        .unwrap();
+    let head = read_head(&mut stream);
+    assert!(head.starts_with("HTTP/1.1 404 "), "{head}");
+
+    handle.shutdown();
+    handle.join().unwrap();
+}
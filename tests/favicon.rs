@@ -0,0 +1,63 @@
+//! `favicon` and `favicon_none`.
+
+use simple_social::{server::Server, testing::TestClient};
+use std::{fs, io::ErrorKind};
+use tempfile::TempDir;
+
+const PIXEL: &str = concat!(
+    env!("CARGO_MANIFEST_DIR"),
+    "/tests/fixtures/embedded/pixel.png"
+);
+
+#[test]
+fn a_png_icon_is_served_as_png() {
+    let mut server = Server::new("127.0.0.1:0", 2);
+    server.favicon(PIXEL).unwrap();
+    let res = TestClient::start(server)
+        .unwrap()
+        .get("/favicon.ico")
+        .unwrap();
+    assert_eq!(res.status, 200);
+    assert_eq!(res.header("Content-Type"), Some("image/png"));
+    assert_eq!(res.header("Cache-Control"), Some("public, max-age=604800"));
+    assert_eq!(res.body, fs::read(PIXEL).unwrap());
+}
+
+#[test]
+fn anything_else_is_served_as_an_icon() {
+    let dir = TempDir::new().unwrap();
+    let path = dir.path().join("favicon.ico");
+    fs::write(&path, b"\x00\x00\x01\x00icon").unwrap();
+    let mut server = Server::new("127.0.0.1:0", 2);
+    server.favicon(&path).unwrap();
+    // Read once up front, so later changes on disk don't matter.
+    fs::remove_file(&path).unwrap();
+    let res = TestClient::start(server)
+        .unwrap()
+        .get("/favicon.ico")
+        .unwrap();
+    assert_eq!(res.header("Content-Type"), Some("image/x-icon"));
+    assert_eq!(res.body, b"\x00\x00\x01\x00icon");
+}
+
+#[test]
+fn a_missing_icon_fails_straight_away() {
+    let dir = TempDir::new().unwrap();
+    let mut server = Server::new("127.0.0.1:0", 2);
+    let err = server.favicon(dir.path().join("nope.ico")).err().unwrap();
+    assert_eq!(err.kind(), ErrorKind::NotFound);
+    assert!(server.routes().is_empty());
+}
+
+#[test]
+fn favicon_none_is_an_empty_cacheable_answer() {
+    let mut server = Server::new("127.0.0.1:0", 2);
+    server.favicon_none();
+    let res = TestClient::start(server)
+        .unwrap()
+        .get("/favicon.ico")
+        .unwrap();
+    assert_eq!((res.status, res.body.len()), (204, 0));
+    assert_eq!(res.header("Content-Length"), None);
+    assert_eq!(res.header("Cache-Control"), Some("public, max-age=604800"));
+}
@@ -0,0 +1,96 @@
+//! RSS and Atom output for a fixed feed, down to the byte.
+
+use simple_social::{
+    feed::{Feed, FeedBuilder, FeedItem},
+    response::Response,
+};
+use std::time::{Duration, SystemTime};
+
+/// 2023-11-14 22:13:20 UTC, a Tuesday.
+fn tuesday() -> SystemTime {
+    SystemTime::UNIX_EPOCH + Duration::from_secs(1_700_000_000)
+}
+
+/// 2000-02-29 08:05:09 UTC, a leap day.
+fn leap_day() -> SystemTime {
+    SystemTime::UNIX_EPOCH + Duration::from_secs(951_811_509)
+}
+
+fn feed() -> Feed {
+    FeedBuilder::new("Fish & Chips <daily>", "https://example.com/?a=1&b=2")
+        .description("Posts about \"food\" & <things>")
+        .author("Ada & Co")
+        .updated(tuesday())
+        .item(
+            FeedItem::new("A < B & C", "https://example.com/posts/2")
+                .id("tag:example.com,2023:post-2")
+                .published(tuesday())
+                .content("<p>Bold &amp; <b>brave</b></p>"),
+        )
+        .item(FeedItem::new("Leap & bound", "https://example.com/posts/1").published(leap_day()))
+        .build()
+}
+
+#[test]
+fn rss_escapes_text_and_uses_rfc_822_dates() {
+    assert_eq!(
+        feed().to_rss(),
+        "<?xml version=\"1.0\" encoding=\"utf-8\"?>\n\
+         <rss version=\"2.0\"><channel>\
+         <title>Fish &amp; Chips &lt;daily&gt;</title>\
+         <link>https://example.com/?a=1&amp;b=2</link>\
+         <description>Posts about &quot;food&quot; &amp; &lt;things&gt;</description>\
+         <lastBuildDate>Tue, 14 Nov 2023 22:13:20 GMT</lastBuildDate>\
+         <item><title>A &lt; B &amp; C</title>\
+         <link>https://example.com/posts/2</link>\
+         <guid isPermaLink=\"false\">tag:example.com,2023:post-2</guid>\
+         <pubDate>Tue, 14 Nov 2023 22:13:20 GMT</pubDate>\
+         <description>&lt;p&gt;Bold &amp;amp; &lt;b&gt;brave&lt;/b&gt;&lt;/p&gt;</description></item>\
+         <item><title>Leap &amp; bound</title>\
+         <link>https://example.com/posts/1</link>\
+         <guid>https://example.com/posts/1</guid>\
+         <pubDate>Tue, 29 Feb 2000 08:05:09 GMT</pubDate></item>\
+         </channel></rss>\n"
+    );
+}
+
+#[test]
+fn atom_escapes_text_and_uses_rfc_3339_dates() {
+    assert_eq!(
+        feed().to_atom(),
+        "<?xml version=\"1.0\" encoding=\"utf-8\"?>\n\
+         <feed xmlns=\"http://www.w3.org/2005/Atom\">\
+         <title>Fish &amp; Chips &lt;daily&gt;</title>\
+         <subtitle>Posts about &quot;food&quot; &amp; &lt;things&gt;</subtitle>\
+         <link href=\"https://example.com/?a=1&amp;b=2\"/>\
+         <id>https://example.com/?a=1&amp;b=2</id>\
+         <updated>2023-11-14T22:13:20Z</updated>\
+         <author><name>Ada &amp; Co</name></author>\
+         <entry><title>A &lt; B &amp; C</title>\
+         <link href=\"https://example.com/posts/2\"/>\
+         <id>tag:example.com,2023:post-2</id>\
+         <updated>2023-11-14T22:13:20Z</updated>\
+         <published>2023-11-14T22:13:20Z</published>\
+         <content type=\"html\">&lt;p&gt;Bold &amp;amp; &lt;b&gt;brave&lt;/b&gt;&lt;/p&gt;</content></entry>\
+         <entry><title>Leap &amp; bound</title>\
+         <link href=\"https://example.com/posts/1\"/>\
+         <id>https://example.com/posts/1</id>\
+         <updated>2000-02-29T08:05:09Z</updated>\
+         <published>2000-02-29T08:05:09Z</published></entry>\
+         </feed>\n"
+    );
+}
+
+#[test]
+fn the_responses_say_which_format_they_are() {
+    let rss = Response::rss(&feed());
+    assert_eq!(
+        rss.header_value("Content-Type"),
+        Some("application/rss+xml")
+    );
+    let atom = Response::atom(&feed());
+    assert_eq!(
+        atom.header_value("Content-Type"),
+        Some("application/atom+xml")
+    );
+}
@@ -0,0 +1,132 @@
+use simple_social::{
+    flash::FlashLevel,
+    response::{Response, StatusCode},
+    server::{Method, RequestHandler, Server},
+    testing::{TestClient, TestResponse},
+};
+use std::collections::BTreeMap;
+
+/// Just enough of a browser to carry cookies from one request to the next.
+#[derive(Default)]
+struct Jar(BTreeMap<String, String>);
+
+impl Jar {
+    fn send(&mut self, client: &TestClient, method: Method, path: &str) -> TestResponse {
+        let cookie = self
+            .0
+            .iter()
+            .map(|(k, v)| format!("{k}={v}"))
+            .collect::<Vec<_>>()
+            .join("; ");
+        let headers: &[(&str, &str)] = match cookie.is_empty() {
+            true => &[],
+            false => &[("Cookie", &cookie)],
+        };
+        let res = client.request(method, path, headers, &[]).unwrap();
+        for (name, value) in &res.headers {
+            if !name.eq_ignore_ascii_case("Set-Cookie") {
+                continue;
+            }
+            let (pair, attrs) = value.split_once(';').unwrap_or((value, ""));
+            let (k, v) = pair.split_once('=').unwrap();
+            if attrs.contains("Max-Age=0") {
+                self.0.remove(k);
+            } else {
+                self.0.insert(String::from(k), String::from(v));
+            }
+        }
+        res
+    }
+}
+
+fn server() -> Server {
+    let mut server = Server::new("127.0.0.1:0", 2);
+    server
+        .post("/posts", |_| {
+            Ok(Response::new(StatusCode::SeeOther)
+                .header("Location", "/")
+                // The cookie's own separators, to show they're escaped.
+                .flash(FlashLevel::Success, "Post \"A & B: 50%\" created!")
+                .flash(FlashLevel::Info, "It may take a minute to show up"))
+        })
+        .get("/", |req| {
+            let shown: Vec<String> = req
+                .flashes()
+                .iter()
+                .map(|f| format!("{}: {}", f.level, f.message))
+                .collect();
+            Ok(Response::new(StatusCode::Ok).body(shown.join("\n")))
+        })
+        .get("/style.css", |_| Ok(Response::new(StatusCode::Ok).body("")));
+    server.flash_key(b"shared secret");
+    server
+}
+
+#[test]
+fn flashes_show_once_after_a_redirect() {
+    let client = TestClient::start(server()).unwrap();
+    let mut jar = Jar::default();
+
+    let created = jar.send(&client, Method::Post, "/posts");
+    assert_eq!(created.status, 303);
+    assert!(jar.0.contains_key("flash"));
+
+    // Requests that don't read them leave them for the page that does.
+    let css = jar.send(&client, Method::Get, "/style.css");
+    assert_eq!(css.header("Set-Cookie"), None);
+
+    let page = jar.send(&client, Method::Get, "/");
+    assert_eq!(
+        page.text(),
+        "success: Post \"A & B: 50%\" created!\ninfo: It may take a minute to show up"
+    );
+    assert!(jar.0.is_empty(), "reading them clears the cookie");
+    assert_eq!(jar.send(&client, Method::Get, "/").text(), "");
+}
+
+#[test]
+fn a_tampered_cookie_shows_nothing() {
+    let client = TestClient::start(server()).unwrap();
+    let mut jar = Jar::default();
+    jar.send(&client, Method::Post, "/posts");
+    let value = jar.0.get_mut("flash").unwrap();
+    *value = value.replace("success", "error");
+
+    assert_eq!(jar.send(&client, Method::Get, "/").text(), "");
+    assert!(jar.0.is_empty());
+}
+
+#[test]
+fn servers_sharing_a_key_read_each_others_flashes() {
+    let first = TestClient::start(server()).unwrap();
+    let second = TestClient::start(server()).unwrap();
+    let mut jar = Jar::default();
+    jar.send(&first, Method::Post, "/posts");
+    assert!(jar
+        .send(&second, Method::Get, "/")
+        .text()
+        .starts_with("success: Post"));
+}
+
+#[test]
+fn oversized_flashes_are_cut_to_fit_a_cookie() {
+    let mut server = server();
+    server.post("/long", |_| {
+        Ok(Response::new(StatusCode::SeeOther)
+            .header("Location", "/")
+            .flash(FlashLevel::Warning, &"ü".repeat(2000))
+            .flash(FlashLevel::Info, "dropped"))
+    });
+    let client = TestClient::start(server).unwrap();
+    let mut jar = Jar::default();
+
+    let res = jar.send(&client, Method::Post, "/long");
+    let cookie = res.header("Set-Cookie").unwrap();
+    assert!(cookie.len() < 4096, "{} bytes", cookie.len());
+
+    let shown = jar.send(&client, Method::Get, "/").text();
+    let message = shown.strip_prefix("warning: ").unwrap();
+    assert!(message.chars().all(|c| c == 'ü'));
+    assert!(message.chars().count() < 2000);
+    assert!(!shown.contains("dropped"));
+}
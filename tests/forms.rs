@@ -0,0 +1,94 @@
+//! `multipart/form-data` bodies, read back through `Request::form`.
+
+use simple_social::{
+    error::BodyError,
+    request::Request,
+    response::{Response, StatusCode},
+    server::{Method, RequestHandler, Server},
+};
+
+const BOUNDARY: &str = "----simple-social-7MA4YWxkTrZu0gW";
+
+/// Bytes that would trip up a text-only parser: a NUL, a CRLF, something
+/// that looks like a boundary, and bytes that aren't UTF-8.
+const PIXEL: &[u8] = b"\x89PNG\r\n\x1a\n\0\0\0\rIHDR\r\n------simple\xff\xfe\x00";
+
+fn body() -> Vec<u8> {
+    let mut body = Vec::new();
+    for (name, value) in [("title", "Hello, world"), ("tags", "rust, web")] {
+        body.extend_from_slice(
+            format!(
+                "--{BOUNDARY}\r\nContent-Disposition: form-data; name=\"{name}\"\r\n\r\n{value}\r\n"
+            )
+            .as_bytes(),
+        );
+    }
+    body.extend_from_slice(
+        format!(
+            "--{BOUNDARY}\r\nContent-Disposition: form-data; name=\"avatar\"; \
+             filename=\"me.png\"\r\nContent-Type: image/png\r\n\r\n"
+        )
+        .as_bytes(),
+    );
+    body.extend_from_slice(PIXEL);
+    body.extend_from_slice(format!("\r\n--{BOUNDARY}--\r\n").as_bytes());
+    body
+}
+
+fn request(body: Vec<u8>) -> Request {
+    Request::builder()
+        .method(Method::Post)
+        .path("/upload")
+        .header(
+            "Content-Type",
+            &format!("multipart/form-data; boundary={BOUNDARY}"),
+        )
+        .body(body)
+        .build()
+        .unwrap()
+}
+
+#[test]
+fn fields_and_a_binary_file_come_back_intact() {
+    let form = request(body()).form().unwrap();
+    assert_eq!(
+        form.fields(),
+        [
+            (String::from("title"), String::from("Hello, world")),
+            (String::from("tags"), String::from("rust, web")),
+        ]
+    );
+    assert_eq!(form.files().len(), 1);
+    let avatar = form.file("avatar").unwrap();
+    assert_eq!(avatar.file_name.as_deref(), Some("me.png"));
+    assert_eq!(avatar.content_type.as_deref(), Some("image/png"));
+    assert_eq!(avatar.data, PIXEL);
+    assert!(form.file("title").is_none());
+}
+
+#[test]
+fn a_truncated_payload_is_a_400() {
+    let mut server = Server::new("127.0.0.1:0", 2);
+    server.post("/upload", |req| match req.form() {
+        Ok(form) => Ok(Response::new(StatusCode::Ok).body(form.files().len().to_string())),
+        Err(e) => Ok(e.response()),
+    });
+    let full = body();
+    assert_eq!(
+        server.handle(request(full.clone())).status(),
+        StatusCode::Ok
+    );
+
+    // Cut off in the file's data, in a part's headers, and before the
+    // closing delimiter.
+    let file_at = full.windows(4).position(|w| w == b"\x89PNG").unwrap();
+    for cut in [file_at + 6, file_at - 30, full.len() - 8] {
+        let truncated = full[..cut].to_vec();
+        assert!(matches!(
+            request(truncated.clone()).form(),
+            Err(BodyError::Malformed(_))
+        ));
+        let res = server.handle(request(truncated));
+        assert_eq!(res.status(), StatusCode::BadRequest, "cut at {cut}");
+    }
+}
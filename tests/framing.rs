@@ -0,0 +1,112 @@
+//! How request bodies are framed: chunked, Content-Length, and the
+//! combinations that must be refused.
+
+use simple_social::{
+    response::{Response, StatusCode},
+    server::{RequestHandler, Server},
+    testing::{Duplex, TestResponse},
+};
+
+fn serve(raw: &str, read_size: usize) -> Vec<TestResponse> {
+    let mut server = Server::new("127.0.0.1:0", 2);
+    server.max_body_size(16 * 1024).post("/echo", |req| {
+        Ok(Response::new(StatusCode::Ok).body(req.body().to_vec()))
+    });
+    let conn = Duplex::new(raw).read_size(read_size);
+    let _ = server.serve_connection(conn.clone());
+    conn.responses().unwrap()
+}
+
+fn post(headers: &str, body: &str) -> String {
+    format!("POST /echo HTTP/1.1\r\nHost: x\r\n{headers}\r\n{body}")
+}
+
+fn status(headers: &str, body: &str) -> u16 {
+    serve(&post(headers, body), usize::MAX)[0].status
+}
+
+#[test]
+fn chunks_of_any_size_are_joined() {
+    let big = "y".repeat(0x1000);
+    let body = format!(
+        "1\r\na\r\n10;name=value\r\n{}\r\n1000\r\n{big}\r\n0\r\nX-Trailer: skipped\r\n\r\n",
+        "b".repeat(16)
+    );
+    let raw = post("Transfer-Encoding: chunked\r\n", &body) + &post("Content-Length: 2\r\n", "ok");
+    for read_size in [usize::MAX, 7, 1] {
+        let res = serve(&raw, read_size);
+        assert_eq!(res.len(), 2, "read size {read_size}");
+        assert_eq!(res[0].status, 200);
+        assert_eq!(res[0].text(), format!("a{}{big}", "b".repeat(16)));
+        assert_eq!(
+            res[1].text(),
+            "ok",
+            "the next request starts after the trailers"
+        );
+    }
+}
+
+#[test]
+fn an_empty_chunked_body_is_empty() {
+    let res = serve(
+        &post("Transfer-Encoding: chunked\r\n", "0\r\n\r\n"),
+        usize::MAX,
+    );
+    assert_eq!((res[0].status, res[0].body.len()), (200, 0));
+}
+
+#[test]
+fn malformed_chunks_are_refused() {
+    for body in ["zz\r\nab\r\n0\r\n\r\n", "2\r\nabc\r\n0\r\n\r\n", "\r\n\r\n"] {
+        assert_eq!(
+            status("Transfer-Encoding: chunked\r\n", body),
+            400,
+            "{body:?}"
+        );
+    }
+}
+
+#[test]
+fn chunked_bodies_count_toward_the_limit() {
+    let chunk = format!("2000\r\n{}\r\n", "z".repeat(0x2000));
+    let body = format!("{chunk}{chunk}{chunk}0\r\n\r\n");
+    assert_eq!(status("Transfer-Encoding: chunked\r\n", &body), 413);
+}
+
+#[test]
+fn transfer_encoding_values_are_read_together() {
+    let cases = [
+        ("Transfer-Encoding: gzip, chunked\r\n", 501),
+        (
+            "Transfer-Encoding: gzip\r\nTransfer-Encoding: chunked\r\n",
+            501,
+        ),
+        ("Transfer-Encoding: chunked, gzip\r\n", 400),
+        (
+            "transfer-encoding: chunked\r\nTransfer-Encoding: identity\r\n",
+            400,
+        ),
+        ("Transfer-Encoding: chunked, chunked\r\n", 400),
+        ("Transfer-Encoding: \r\n", 400),
+        ("Transfer-Encoding: Chunked\r\n", 200),
+    ];
+    for (headers, expected) in cases {
+        assert_eq!(status(headers, "0\r\n\r\n"), expected, "{headers:?}");
+    }
+}
+
+#[test]
+fn content_length_must_be_unambiguous() {
+    let cases = [
+        ("Content-Length: 2\r\nTransfer-Encoding: chunked\r\n", 400),
+        ("Content-Length: 2\r\nContent-Length: 3\r\n", 400),
+        ("Content-Length: 2, 3\r\n", 400),
+        ("Content-Length: +2\r\n", 400),
+        ("Content-Length: \r\n", 400),
+        ("Content-Length: 2\r\ncontent-length: 2\r\n", 200),
+        ("Content-Length: 2, 2\r\n", 200),
+    ];
+    for (headers, expected) in cases {
+        assert_eq!(status(headers, "ok"), expected, "{headers:?}");
+    }
+}
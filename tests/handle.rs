@@ -0,0 +1,111 @@
+//! `Server::handle` against the same server behind a real socket: apart
+//! from connection handling, the responses are the same.
+
+use simple_social::{
+    request::Request,
+    response::{Response, StatusCode},
+    server::{BannerMode, Method, RequestHandler, Router, Server},
+};
+use std::{
+    io::{Read, Write},
+    net::{SocketAddr, TcpStream},
+};
+
+fn server() -> Server {
+    let mut api = Router::new();
+    api.get("/posts/:id", |req| {
+        let id = req.param("id").unwrap_or_default();
+        Ok(Response::new(StatusCode::Ok)
+            .header("Content-Type", "application/json")
+            .body(format!("{{\"id\":\"{id}\"}}")))
+    })
+    .post("/posts", |req| {
+        Ok(Response::new(StatusCode::Created)
+            .header("Location", "/api/posts/1")
+            .body(req.body().to_vec()))
+    });
+
+    let mut server = Server::new("127.0.0.1:0", 2);
+    server
+        .banner(BannerMode::Off)
+        .get("/", |_| Ok(Response::new(StatusCode::Ok).body("home")))
+        .get("/broken", |_| Err("the database is down".into()))
+        .delete("/posts/:id", |_| {
+            Ok(Response::status_only(StatusCode::NoContent))
+        })
+        .mount("/api", api);
+    server
+}
+
+/// The status line, the headers but `Connection` in order, and the body.
+fn normalize(raw: &[u8]) -> String {
+    let text = String::from_utf8_lossy(raw);
+    let (head, body) = text.split_once("\r\n\r\n").unwrap();
+    let mut out: Vec<&str> = head
+        .lines()
+        .filter(|line| !line.to_ascii_lowercase().starts_with("connection:"))
+        .collect();
+    out.push(body);
+    out.join("\n")
+}
+
+fn over_socket(addr: SocketAddr, method: Method, path: &str, body: &str) -> String {
+    let mut stream = TcpStream::connect(addr).unwrap();
+    write!(
+        stream,
+        "{method} {path} HTTP/1.1\r\nHost: x\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{body}",
+        body.len()
+    )
+    .unwrap();
+    let mut raw = Vec::new();
+    stream.read_to_end(&mut raw).unwrap();
+    normalize(&raw)
+}
+
+fn in_process(server: &Server, method: Method, path: &str, body: &str) -> String {
+    let req = Request::builder()
+        .method(method)
+        .path(path)
+        .header("Host", "x")
+        .header("Content-Length", &body.len().to_string())
+        .header("Connection", "close")
+        .body(body)
+        .build()
+        .unwrap();
+    let mut raw = Vec::new();
+    server.handle(req).write_to(&mut raw).unwrap();
+    normalize(&raw)
+}
+
+#[test]
+fn handle_answers_like_the_socket_does() {
+    let cases = [
+        (Method::Get, "/", ""),
+        (Method::Get, "/api/posts/42?draft", ""),
+        (Method::Post, "/api/posts", "{\"title\":\"hi\"}"),
+        (Method::Delete, "/posts/7", ""),
+        (Method::Get, "/broken", ""),
+        (Method::Get, "/missing", ""),
+        (Method::Put, "/api/posts/42", "x"),
+    ];
+
+    let local = server();
+    let handle = server().spawn().unwrap();
+    let addr = handle.local_addr().unwrap();
+    for (method, path, body) in cases {
+        let wire = over_socket(addr, method, path, body);
+        assert_eq!(
+            in_process(&local, method, path, body),
+            wire,
+            "{method} {path}"
+        );
+    }
+    handle.shutdown();
+    handle.join().unwrap();
+
+    let statuses: Vec<String> = cases
+        .iter()
+        .map(|&(method, path, body)| in_process(&local, method, path, body)[9..12].to_string())
+        .collect();
+    assert_eq!(statuses, ["200", "200", "201", "204", "500", "404", "405"]);
+}
@@ -0,0 +1,70 @@
+//! The ready-made handlers in `handlers`: a file read per request and a
+//! page fixed at registration.
+
+use simple_social::{
+    handlers::{serve_file, serve_static_str},
+    server::{RequestHandler, Server},
+    testing::TestClient,
+};
+use std::fs;
+
+#[test]
+fn serve_file_reads_the_file_on_every_request() {
+    let dir = tempfile::tempdir().unwrap();
+    let css = dir.path().join("site.css");
+    fs::write(&css, "body { color: red }").unwrap();
+
+    let mut server = Server::new("127.0.0.1:0", 2);
+    server
+        .get("/site.css", serve_file(&css))
+        .get("/gone.css", serve_file(dir.path().join("gone.css")));
+    let client = TestClient::start(server).unwrap();
+
+    let res = client.get("/site.css").unwrap();
+    assert_eq!(res.status, 200);
+    assert_eq!(res.header("Content-Type"), Some("text/css"));
+    assert_eq!(res.text(), "body { color: red }");
+
+    fs::write(&css, "body { color: blue }").unwrap();
+    assert_eq!(
+        client.get("/site.css").unwrap().text(),
+        "body { color: blue }"
+    );
+
+    // A file that was never there, and one removed after registration.
+    fs::remove_file(&css).unwrap();
+    for path in ["/gone.css", "/site.css"] {
+        let res = client.get(path).unwrap();
+        assert_eq!(res.status, 404, "{path}");
+        assert_eq!(res.header("Content-Type"), Some("text/html"), "{path}");
+    }
+}
+
+#[test]
+fn serve_static_str_needs_no_filesystem() {
+    let mut server = Server::new("127.0.0.1:0", 2);
+    server
+        .get(
+            "/robots.txt",
+            serve_static_str("text/plain", "User-agent: *\n"),
+        )
+        .get(
+            "/about",
+            serve_static_str("text/html; charset=utf-8", "<h1>Über</h1>"),
+        );
+    let client = TestClient::start(server).unwrap();
+
+    let res = client.get("/robots.txt").unwrap();
+    assert_eq!(res.status, 200);
+    assert_eq!(res.header("Content-Type"), Some("text/plain"));
+    assert_eq!(res.text(), "User-agent: *\n");
+
+    let res = client.get("/about").unwrap();
+    assert_eq!(res.header("Content-Type"), Some("text/html; charset=utf-8"));
+    assert_eq!(res.header("Content-Length"), Some("14"));
+    assert_eq!(res.text(), "<h1>Über</h1>");
+
+    let head = client.head("/about").unwrap();
+    assert_eq!(head.status, 200);
+    assert!(head.body.is_empty());
+}
@@ -0,0 +1,100 @@
+//! `health_check` and `readiness_check` answers.
+
+use serde_json::{json, Value};
+use simple_social::{health::HealthStatus, request::Request, server::Server};
+use std::{
+    io::{Read, Write},
+    net::TcpStream,
+    sync::{Arc, Mutex},
+    thread,
+    time::{Duration, Instant},
+};
+
+fn get(server: &Server, path: &str) -> (u16, Value) {
+    let res = server.handle(Request::builder().path(path).build().unwrap());
+    assert_eq!(res.header_value("Content-Type"), Some("application/json"));
+    assert_eq!(res.header_value("Cache-Control"), Some("no-store"));
+    let mut raw = Vec::new();
+    res.write_to(&mut raw).unwrap();
+    let start = raw.windows(4).position(|w| w == b"\r\n\r\n").unwrap() + 4;
+    let body = serde_json::from_slice(&raw[start..]).unwrap();
+    (res.status().code(), body)
+}
+
+fn wait_for(what: &str, done: impl Fn() -> bool) {
+    let deadline = Instant::now() + Duration::from_secs(5);
+    while !done() {
+        assert!(Instant::now() < deadline, "timed out waiting for {what}");
+        thread::sleep(Duration::from_millis(5));
+    }
+}
+
+#[test]
+fn each_status_has_its_code_and_body() {
+    let status = Arc::new(Mutex::new(HealthStatus::Healthy));
+    let mut server = Server::new("127.0.0.1:0", 2);
+    let probe = Arc::clone(&status);
+    server.health_check("/health", move || probe.lock().unwrap().clone());
+
+    assert_eq!(get(&server, "/health"), (200, json!({"status": "healthy"})));
+
+    *status.lock().unwrap() = HealthStatus::Degraded(String::from("cache \"cold\""));
+    assert_eq!(
+        get(&server, "/health"),
+        (
+            200,
+            json!({"status": "degraded", "message": "cache \"cold\""})
+        )
+    );
+
+    *status.lock().unwrap() = HealthStatus::Unhealthy(String::from("db down"));
+    assert_eq!(
+        get(&server, "/health"),
+        (503, json!({"status": "unhealthy", "message": "db down"}))
+    );
+}
+
+#[test]
+fn readiness_fails_once_shutdown_begins() {
+    let mut server = Server::new("127.0.0.1:0", 2);
+    server.readiness_check("/ready");
+    assert_eq!(get(&server, "/ready"), (200, json!({"status": "healthy"})));
+
+    server.shutdown_handle().shutdown();
+    assert_eq!(
+        get(&server, "/ready"),
+        (
+            503,
+            json!({"status": "unhealthy", "message": "shutting down"})
+        )
+    );
+}
+
+#[test]
+fn a_request_in_flight_during_the_drain_sees_503() {
+    let mut server = Server::new("127.0.0.1:0", 2);
+    server.readiness_check("/ready");
+    let stop = server.shutdown_handle();
+    let handle = server.spawn().unwrap();
+    let mut stream = TcpStream::connect(handle.local_addr().unwrap()).unwrap();
+    stream
+        .set_read_timeout(Some(Duration::from_secs(5)))
+        .unwrap();
+    stream
+        .write_all(b"GET /ready HTTP/1.1\r\nHost: x\r\nContent-Length: 2\r\n\r\n")
+        .unwrap();
+    wait_for("the request to be picked up", || {
+        handle.metrics().active_connections == 1
+    });
+
+    // The worker is waiting on the body when the drain starts.
+    thread::sleep(Duration::from_millis(50));
+    stop.shutdown();
+    stream.write_all(b"{}").unwrap();
+    let mut out = String::new();
+    stream.read_to_string(&mut out).unwrap();
+    assert!(out.starts_with("HTTP/1.1 503"), "{out}");
+    assert!(out.contains("Connection: close\r\n"), "{out}");
+    assert!(out.ends_with(r#"{"status":"unhealthy","message":"shutting down"}"#));
+    handle.join().unwrap();
+}
@@ -0,0 +1,98 @@
+//! HTTP/1.0 clients, which close after each response unless they ask
+//! for keep-alive.
+
+use simple_social::{
+    response::{Response, StatusCode},
+    server::{RequestHandler, Server},
+    testing::TestClient,
+};
+use std::{
+    io::{Read, Write},
+    net::TcpStream,
+    time::Duration,
+};
+
+fn client() -> TestClient {
+    let mut server = Server::new("127.0.0.1:0", 2);
+    server
+        .get("/", |_| Ok(Response::new(StatusCode::Ok).body("hi")))
+        .get("/bye", |_| {
+            Ok(Response::new(StatusCode::Ok)
+                .header("Connection", "close")
+                .body("bye"))
+        });
+    TestClient::start(server).unwrap()
+}
+
+fn connect(client: &TestClient) -> TcpStream {
+    let stream = TcpStream::connect(client.addr()).unwrap();
+    stream
+        .set_read_timeout(Some(Duration::from_secs(5)))
+        .unwrap();
+    stream
+}
+
+fn connection_headers(response: &str) -> Vec<&str> {
+    response
+        .lines()
+        .filter(|l| l.to_ascii_lowercase().starts_with("connection:"))
+        .collect()
+}
+
+#[test]
+fn a_handler_that_closes_sends_one_connection_header() {
+    let client = client();
+    let mut stream = connect(&client);
+    stream
+        .write_all(b"GET /bye HTTP/1.0\r\nConnection: keep-alive\r\n\r\n")
+        .unwrap();
+    let mut out = String::new();
+    stream.read_to_string(&mut out).unwrap();
+    assert!(out.ends_with("\r\n\r\nbye"), "{out}");
+    assert_eq!(connection_headers(&out), ["Connection: close"]);
+}
+
+#[test]
+fn a_plain_request_is_answered_in_kind_and_closed() {
+    let client = client();
+    let mut stream = connect(&client);
+    stream.write_all(b"GET / HTTP/1.0\r\n\r\n").unwrap();
+    let mut out = String::new();
+    stream.read_to_string(&mut out).unwrap();
+    assert!(out.starts_with("HTTP/1.0 200 OK\r\n"), "{out}");
+    assert!(out.ends_with("\r\n\r\nhi"), "{out}");
+    assert_eq!(connection_headers(&out), ["Connection: close"]);
+}
+
+#[test]
+fn keep_alive_is_honoured() {
+    let client = client();
+    let mut stream = connect(&client);
+    for _ in 0..3 {
+        stream
+            .write_all(b"GET / HTTP/1.0\r\nConnection: keep-alive\r\n\r\n")
+            .unwrap();
+        let mut out = Vec::new();
+        let mut buf = [0; 1024];
+        while !out.ends_with(b"\r\n\r\nhi") {
+            let n = stream.read(&mut buf).unwrap();
+            assert!(n > 0, "closed after a keep-alive request");
+            out.extend_from_slice(&buf[..n]);
+        }
+        let out = String::from_utf8(out).unwrap();
+        assert!(out.starts_with("HTTP/1.0 200 OK\r\n"), "{out}");
+        assert_eq!(connection_headers(&out), ["Connection: keep-alive"]);
+    }
+}
+
+#[test]
+fn http_2_is_not_supported() {
+    let client = client();
+    let mut stream = connect(&client);
+    stream
+        .write_all(b"GET / HTTP/2.0\r\nHost: x\r\n\r\n")
+        .unwrap();
+    let mut out = String::new();
+    stream.read_to_string(&mut out).unwrap();
+    assert!(out.contains(" 505 HTTP Version Not Supported\r\n"), "{out}");
+}
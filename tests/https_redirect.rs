@@ -0,0 +1,115 @@
+//! The plain-HTTP listener added by `redirect_to_https`, which answers
+//! everything with a redirect to the same URL over https.
+
+use simple_social::{
+    response::{Response, StatusCode},
+    server::{RequestHandler, Server, ServerHandle},
+};
+use std::{
+    io::{Read, Write},
+    net::{SocketAddr, TcpListener, TcpStream},
+    thread,
+    time::{Duration, Instant},
+};
+
+/// An address nothing is listening on, for the redirect listener, which
+/// `ServerHandle::local_addrs` doesn't report.
+fn free_addr() -> SocketAddr {
+    TcpListener::bind("127.0.0.1:0")
+        .unwrap()
+        .local_addr()
+        .unwrap()
+}
+
+fn spawn(target_host: Option<&str>) -> (ServerHandle, SocketAddr) {
+    let redirect = free_addr();
+    let mut server = Server::new("127.0.0.1:0", 2);
+    server
+        .redirect_to_https(&redirect.to_string(), target_host)
+        .get("/", |_| Ok(Response::new(StatusCode::Ok).body("hi")));
+    let handle = server.spawn().unwrap();
+    (handle, redirect)
+}
+
+/// Sends `head` to `addr` and returns the whole response, retrying the
+/// connection while the listener is still being bound.
+fn send(addr: SocketAddr, head: &str) -> String {
+    let deadline = Instant::now() + Duration::from_secs(5);
+    let mut stream = loop {
+        match TcpStream::connect(addr) {
+            Ok(stream) => break stream,
+            Err(e) => assert!(Instant::now() < deadline, "connecting to {addr}: {e}"),
+        }
+        thread::sleep(Duration::from_millis(5));
+    };
+    stream.write_all(head.as_bytes()).unwrap();
+    let mut out = String::new();
+    stream.read_to_string(&mut out).unwrap();
+    out
+}
+
+fn location(response: &str) -> Option<&str> {
+    response.lines().find_map(|l| l.strip_prefix("Location: "))
+}
+
+#[test]
+fn path_and_query_are_kept() {
+    let (handle, redirect) = spawn(None);
+    let out = send(
+        redirect,
+        "GET /posts/7?sort=new&page=2 HTTP/1.1\r\nHost: example.com:8080\r\nConnection: close\r\n\r\n",
+    );
+    assert!(out.starts_with("HTTP/1.1 301 "), "{out}");
+    assert_eq!(
+        location(&out),
+        Some("https://example.com/posts/7?sort=new&page=2")
+    );
+
+    let out = send(
+        redirect,
+        "POST /login HTTP/1.1\r\nHost: [::1]:8080\r\nContent-Length: 0\r\nConnection: close\r\n\r\n",
+    );
+    assert_eq!(location(&out), Some("https://[::1]/login"));
+
+    // The main listener isn't affected.
+    let out = send(
+        handle.local_addr().unwrap(),
+        "GET / HTTP/1.1\r\nHost: x\r\nConnection: close\r\n\r\n",
+    );
+    assert!(out.ends_with("\r\n\r\nhi"), "{out}");
+
+    handle.shutdown();
+    handle.join().unwrap();
+}
+
+#[test]
+fn a_target_host_replaces_the_request_host() {
+    let (handle, redirect) = spawn(Some("secure.example.com"));
+    let out = send(
+        redirect,
+        "GET /a?b=c HTTP/1.1\r\nHost: example.com\r\nConnection: close\r\n\r\n",
+    );
+    assert_eq!(location(&out), Some("https://secure.example.com/a?b=c"));
+
+    let out = send(redirect, "GET /a HTTP/1.0\r\n\r\n");
+    assert_eq!(location(&out), Some("https://secure.example.com/a"));
+
+    handle.shutdown();
+    handle.join().unwrap();
+}
+
+#[test]
+fn a_missing_host_is_a_bad_request() {
+    let (handle, redirect) = spawn(None);
+    for head in [
+        "GET /a HTTP/1.0\r\n\r\n",
+        "GET /a HTTP/1.0\r\nHost: \r\n\r\n",
+    ] {
+        let out = send(redirect, head);
+        assert!(out.starts_with("HTTP/1.0 400 "), "{out}");
+        assert_eq!(location(&out), None);
+    }
+
+    handle.shutdown();
+    handle.join().unwrap();
+}
@@ -0,0 +1,107 @@
+//! `Server::i18n`: picking a language per request, and the localized files
+//! and templates sent for it.
+
+use simple_social::{
+    response::{Response, StatusCode},
+    server::{Method, RequestHandler, Server},
+    static_files::StaticDir,
+    testing::{TestClient, TestResponse},
+};
+use std::{collections::HashMap, fs, path::Path};
+
+fn client(dir: &Path) -> TestClient {
+    for (name, text) in [
+        ("index.html", "Hello"),
+        ("index.fr.html", "Bonjour"),
+        ("about.html", "About us"),
+        ("page.html", "<p>Hi {{ name }}</p>"),
+        ("page.de.html", "<p>Hallo {{ name }}</p>"),
+    ] {
+        fs::write(dir.join(name), text).unwrap();
+    }
+    let page = dir.join("page.html");
+    let mut server = Server::new("127.0.0.1:0", 2);
+    server
+        .i18n("en", &["en", "fr", "de"])
+        .get("/lang", |req| {
+            Ok(Response::new(StatusCode::Ok).body(String::from(req.lang().unwrap_or("none"))))
+        })
+        .get("/page", move |req| {
+            let vars = HashMap::from([("name", String::from("Ada"))]);
+            Ok(Response::render_localized(req, &page, &vars)?)
+        })
+        .mount_static("/", StaticDir::new(dir));
+    TestClient::start(server).unwrap()
+}
+
+fn get(client: &TestClient, path: &str, accept_language: Option<&str>) -> TestResponse {
+    let headers: Vec<_> = accept_language
+        .map(|value| ("Accept-Language", value))
+        .into_iter()
+        .collect();
+    client.request(Method::Get, path, &headers, &[]).unwrap()
+}
+
+#[test]
+fn the_language_comes_from_accept_language() {
+    let dir = tempfile::tempdir().unwrap();
+    let client = client(dir.path());
+    for (header, lang) in [
+        (Some("fr-CA,fr;q=0.9,en;q=0.8"), "fr"),
+        (Some("de-DE, en;q=0.5"), "de"),
+        (Some("en-GB"), "en"),
+        (Some("ja, zh;q=0.8"), "en"),
+        (Some("fr;q=0, de;q=0.1"), "de"),
+        (None, "en"),
+    ] {
+        assert_eq!(get(&client, "/lang", header).text(), lang, "{header:?}");
+    }
+}
+
+#[test]
+fn files_fall_back_to_the_default_language() {
+    let dir = tempfile::tempdir().unwrap();
+    let client = client(dir.path());
+    for (path, header, body, lang) in [
+        ("/index.html", "fr", "Bonjour", "fr"),
+        ("/index.html", "de", "Hello", "en"),
+        ("/index.html", "en", "Hello", "en"),
+        ("/about.html", "fr", "About us", "en"),
+    ] {
+        let res = get(&client, path, Some(header));
+        assert_eq!(res.status, 200, "{path} in {header}");
+        assert_eq!(res.text(), body, "{path} in {header}");
+        assert_eq!(
+            res.header("Content-Language"),
+            Some(lang),
+            "{path} in {header}"
+        );
+        assert_eq!(res.header("Vary"), Some("Accept-Language"), "{path}");
+    }
+
+    let res = get(&client, "/page", Some("de"));
+    assert_eq!(res.text(), "<p>Hallo Ada</p>");
+    assert_eq!(res.header("Content-Language"), Some("de"));
+    let res = get(&client, "/page", Some("fr"));
+    assert_eq!(res.text(), "<p>Hi Ada</p>");
+    assert_eq!(res.header("Content-Language"), Some("en"));
+    assert_eq!(res.header("Vary"), Some("Accept-Language"));
+}
+
+#[test]
+fn a_lang_query_wins_over_the_header() {
+    let dir = tempfile::tempdir().unwrap();
+    let client = client(dir.path());
+    assert_eq!(get(&client, "/lang?lang=de", Some("fr")).text(), "de");
+    assert_eq!(get(&client, "/lang?lang=FR", Some("de")).text(), "fr");
+    // Only languages the site has count; anything else leaves the header
+    // to decide.
+    assert_eq!(get(&client, "/lang?lang=es", Some("fr")).text(), "fr");
+    assert_eq!(get(&client, "/lang?lang=", None).text(), "en");
+
+    let res = get(&client, "/index.html?lang=fr", Some("de"));
+    assert_eq!(res.text(), "Bonjour");
+    assert_eq!(res.header("Content-Language"), Some("fr"));
+    let res = get(&client, "/page?lang=de", Some("fr"));
+    assert_eq!(res.text(), "<p>Hallo Ada</p>");
+}
@@ -0,0 +1,98 @@
+#![cfg(feature = "serde")]
+
+use serde::Deserialize;
+use simple_social::{error::BodyError, request::Request, server::Method};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+struct Post {
+    title: String,
+    #[serde(default)]
+    tags: Vec<String>,
+}
+
+fn post(content_type: &str, body: impl Into<Vec<u8>>) -> Request {
+    Request::builder()
+        .method(Method::Post)
+        .path("/posts")
+        .header("Content-Type", content_type)
+        .body(body)
+        .build()
+        .unwrap()
+}
+
+#[test]
+fn parses_json_and_json_suffixed_bodies() {
+    let req = post(
+        "application/json; charset=utf-8",
+        r#"{"title":"hi","tags":["a"]}"#,
+    );
+    let expected = Post {
+        title: String::from("hi"),
+        tags: vec![String::from("a")],
+    };
+    assert_eq!(req.json::<Post>().unwrap(), expected);
+    let req = post("application/merge-patch+json", r#"{"title":"hi"}"#);
+    assert_eq!(req.json::<Post>().unwrap().title, "hi");
+}
+
+#[test]
+fn a_missing_field_reports_where() {
+    let req = post("application/json", r#"{"body":"no title"}"#);
+    let err = req.json::<Post>().unwrap_err();
+    let BodyError::Json {
+        message,
+        line,
+        column,
+    } = &err
+    else {
+        panic!("{err:?}");
+    };
+    assert_eq!(message, "missing field `title`");
+    assert_eq!((*line, *column), (1, 19));
+    assert_eq!(err.to_string(), "missing field `title` at line 1 column 19");
+    assert_eq!(
+        err.response().status(),
+        simple_social::response::StatusCode::BadRequest
+    );
+}
+
+#[test]
+fn invalid_utf8_and_other_types_are_refused() {
+    let err = post("application/json", b"{\"title\":\"\xff\"}".to_vec())
+        .json::<Post>()
+        .unwrap_err();
+    assert!(matches!(err, BodyError::InvalidUtf8(_)), "{err:?}");
+
+    let err = post("text/plain", r#"{"title":"hi"}"#)
+        .json::<Post>()
+        .unwrap_err();
+    assert!(matches!(err, BodyError::UnsupportedMediaType(Some(ct)) if ct == "text/plain"));
+}
+
+static PARSES: AtomicUsize = AtomicUsize::new(0);
+
+/// Counts how often it is deserialized.
+#[derive(Debug, Clone, PartialEq)]
+struct Counted(Post);
+
+impl<'de> Deserialize<'de> for Counted {
+    fn deserialize<D: serde::Deserializer<'de>>(d: D) -> Result<Self, D::Error> {
+        PARSES.fetch_add(1, Ordering::Relaxed);
+        Post::deserialize(d).map(Counted)
+    }
+}
+
+#[test]
+fn a_second_call_returns_the_first_parse() {
+    let req = post("application/json", r#"{"title":"twice"}"#);
+    let first = req.json::<Counted>().unwrap();
+    let second = req.json::<Counted>().unwrap();
+    assert_eq!(first, second);
+    assert_eq!(PARSES.load(Ordering::Relaxed), 1);
+
+    // A different target type gets its own parse, errors included.
+    assert_eq!(req.json::<Post>().unwrap().title, "twice");
+    assert!(req.json::<Vec<u8>>().is_err());
+    assert!(req.json::<Vec<u8>>().is_err());
+}
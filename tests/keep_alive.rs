@@ -0,0 +1,131 @@
+//! Keep-alive connections left idle past `keep_alive_timeout`.
+
+use simple_social::{
+    response::{Response, StatusCode},
+    server::{RequestHandler, Server, ServerHandle},
+};
+use std::{
+    io::{ErrorKind, Read, Write},
+    net::TcpStream,
+    thread,
+    time::{Duration, Instant},
+};
+
+const TIMEOUT: Duration = Duration::from_millis(300);
+
+fn spawn() -> ServerHandle {
+    let mut server = Server::new("127.0.0.1:0", 2);
+    server
+        .keep_alive_timeout(TIMEOUT)
+        .get("/", |_| Ok(Response::new(StatusCode::Ok).body("hi")));
+    server.spawn().unwrap()
+}
+
+fn connect(handle: &ServerHandle) -> TcpStream {
+    let stream = TcpStream::connect(handle.local_addr().unwrap()).unwrap();
+    stream
+        .set_read_timeout(Some(Duration::from_secs(5)))
+        .unwrap();
+    stream
+}
+
+/// Sends a keep-alive GET and reads its whole response, which is all the
+/// server sends back.
+fn get(stream: &mut TcpStream) -> String {
+    stream
+        .write_all(b"GET / HTTP/1.1\r\nHost: x\r\n\r\n")
+        .unwrap();
+    let mut out = Vec::new();
+    let mut buf = [0; 1024];
+    while !out.ends_with(b"\r\n\r\nhi") {
+        let n = stream.read(&mut buf).unwrap();
+        assert!(n > 0, "closed mid-response");
+        out.extend_from_slice(&buf[..n]);
+    }
+    String::from_utf8(out).unwrap()
+}
+
+/// Whether the server has closed `stream`, waiting at most `wait`.
+fn closed_within(stream: &mut TcpStream, wait: Duration) -> bool {
+    stream.set_read_timeout(Some(wait)).unwrap();
+    match stream.read(&mut [0; 1]) {
+        Ok(0) => true,
+        Ok(_) => panic!("unexpected bytes"),
+        Err(e) if matches!(e.kind(), ErrorKind::WouldBlock | ErrorKind::TimedOut) => false,
+        Err(e) => e.kind() == ErrorKind::ConnectionReset,
+    }
+}
+
+fn wait_for(what: &str, done: impl Fn() -> bool) {
+    let deadline = Instant::now() + Duration::from_secs(5);
+    while !done() {
+        assert!(Instant::now() < deadline, "timed out waiting for {what}");
+        thread::sleep(Duration::from_millis(5));
+    }
+}
+
+#[test]
+fn idle_connections_are_closed_and_active_ones_survive() {
+    let handle = spawn();
+    let mut idle = connect(&handle);
+    let mut active = connect(&handle);
+    assert!(get(&mut idle).starts_with("HTTP/1.1 200"));
+    let idle_since = Instant::now();
+    wait_for("the connection to count as idle", || {
+        handle.metrics().idle_connections >= 1
+    });
+
+    // Requests every third of the timeout, for three times as long.
+    for _ in 0..9 {
+        assert!(get(&mut active).starts_with("HTTP/1.1 200"));
+        thread::sleep(TIMEOUT / 3);
+    }
+    assert!(closed_within(&mut idle, Duration::from_secs(2)));
+    assert!(idle_since.elapsed() >= TIMEOUT);
+    assert!(get(&mut active).starts_with("HTTP/1.1 200"));
+
+    wait_for("the closed connection to be dropped", || {
+        handle.metrics().active_connections == 1
+    });
+    drop(active);
+    wait_for("both connections to be dropped", || {
+        let metrics = handle.metrics();
+        (metrics.active_connections, metrics.idle_connections) == (0, 0)
+    });
+
+    handle.shutdown();
+    handle.join().unwrap();
+}
+
+#[test]
+fn a_connection_is_not_closed_before_its_timeout() {
+    let handle = spawn();
+    let mut stream = connect(&handle);
+    get(&mut stream);
+    assert!(!closed_within(&mut stream, TIMEOUT / 2));
+    assert!(get(&mut stream).ends_with("hi"));
+    assert!(closed_within(&mut stream, TIMEOUT * 4));
+
+    handle.shutdown();
+    handle.join().unwrap();
+}
+
+#[test]
+fn shutdown_closes_idle_connections_at_once() {
+    let mut server = Server::new("127.0.0.1:0", 2);
+    server
+        .keep_alive_timeout(Duration::from_secs(60))
+        .get("/", |_| Ok(Response::new(StatusCode::Ok).body("hi")));
+    let handle = server.spawn().unwrap();
+    let mut stream = connect(&handle);
+    get(&mut stream);
+    wait_for("the connection to count as idle", || {
+        handle.metrics().idle_connections == 1
+    });
+
+    let started = Instant::now();
+    handle.shutdown();
+    handle.join().unwrap();
+    assert!(started.elapsed() < Duration::from_secs(5));
+    assert!(closed_within(&mut stream, Duration::from_secs(1)));
+}
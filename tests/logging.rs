@@ -0,0 +1,334 @@
+//! What the server sends through the `log` facade. Every test in this
+//! file shares one process-wide logger, so each picks out its own records
+//! by something only it logs, such as its address.
+
+use log::{Level, LevelFilter, Log, Metadata, Record};
+use simple_social::{
+    response::{Response, StatusCode},
+    server::{BannerMode, ColorPolicy, HandlerFunc, Method, RequestHandler, Router, Server},
+};
+use std::{
+    io::{Read, Write},
+    net::{TcpListener, TcpStream},
+    sync::{Mutex, Once},
+    thread,
+    time::{Duration, Instant},
+};
+
+struct Capture;
+
+static RECORDS: Mutex<Vec<(Level, String)>> = Mutex::new(Vec::new());
+
+impl Log for Capture {
+    fn enabled(&self, _: &Metadata) -> bool {
+        true
+    }
+
+    fn log(&self, record: &Record) {
+        let line = record.args().to_string();
+        RECORDS.lock().unwrap().push((record.level(), line));
+    }
+
+    fn flush(&self) {}
+}
+
+fn capture() {
+    static INSTALL: Once = Once::new();
+    INSTALL.call_once(|| {
+        log::set_logger(&Capture).unwrap();
+        log::set_max_level(LevelFilter::Trace);
+    });
+}
+
+/// The captured records whose message contains `needle`.
+fn records(needle: &str) -> Vec<(Level, String)> {
+    RECORDS
+        .lock()
+        .unwrap()
+        .iter()
+        .filter(|(_, line)| line.contains(needle))
+        .cloned()
+        .collect()
+}
+
+fn ok() -> impl HandlerFunc {
+    |_| Ok(Response::new(StatusCode::Ok))
+}
+
+fn server() -> Server {
+    let mut server = Server::new("127.0.0.1:0", 2);
+    server.get("/", ok());
+    server
+}
+
+#[test]
+fn startup_logs_the_addresses_at_info() {
+    capture();
+    let redirect = TcpListener::bind("127.0.0.1:0")
+        .unwrap()
+        .local_addr()
+        .unwrap();
+    let mut server = server();
+    server
+        .banner(BannerMode::Off)
+        .redirect_to_https(&redirect.to_string(), None);
+    let handle = server.spawn().unwrap();
+    let addr = handle.local_addr().unwrap();
+    handle.shutdown();
+    handle.join().unwrap();
+
+    assert_eq!(
+        records(&addr.to_string()),
+        [(Level::Info, format!("serving on - {addr}"))]
+    );
+    assert_eq!(
+        records(&redirect.to_string()),
+        [(Level::Info, format!("redirecting to https - {redirect}"))]
+    );
+    assert!(records("shutting down").contains(&(Level::Info, String::from("shutting down"))));
+}
+
+#[test]
+fn the_plain_banner_is_one_info_record() {
+    capture();
+    let handle = server().spawn().unwrap();
+    let addr = handle.local_addr().unwrap();
+    handle.shutdown();
+    handle.join().unwrap();
+
+    let startup = records(&addr.to_string());
+    assert_eq!(startup.len(), 1, "{startup:?}");
+    let (level, banner) = &startup[0];
+    assert_eq!(*level, Level::Info);
+    assert!(banner.starts_with("Server running...\n\n"), "{banner}");
+    assert!(
+        banner.ends_with(&format!("serving on - {addr}")),
+        "{banner}"
+    );
+}
+
+/// The banner text `server` logs at startup, after the "Server running"
+/// line and with its own address replaced by `ADDR`.
+fn banner(server: Server) -> String {
+    capture();
+    let handle = server.spawn().unwrap();
+    let addr = handle.local_addr().unwrap().to_string();
+    handle.shutdown();
+    handle.join().unwrap();
+    let (_, banner) = records(&addr).pop().unwrap();
+    banner
+        .strip_prefix("Server running...\n\n")
+        .unwrap()
+        .replace(&addr, "ADDR")
+}
+
+#[test]
+fn colors_follow_the_policy() {
+    let colored = |policy| {
+        let mut server = server();
+        server.color(policy);
+        banner(server)
+    };
+    let plain = "/\n  GET /\n\n1 route, 0 static mounts\nserving on - ADDR";
+    assert_eq!(colored(ColorPolicy::Never), plain);
+
+    let always = colored(ColorPolicy::Always);
+    #[cfg(feature = "color")]
+    assert_eq!(
+        always,
+        "\x1b[1m\x1b[38;5;3m/\x1b[39m\x1b[m\n  \
+         \x1b[38;5;4mGET\x1b[39m\x1b[m \x1b[38;5;2m/\x1b[39m\x1b[m\n\n\
+         1 route, 0 static mounts\nserving on - ADDR"
+    );
+    #[cfg(not(feature = "color"))]
+    assert_eq!(always, plain);
+}
+
+/// The slow-request warnings logged for `target`.
+fn slow_warnings(target: &str) -> Vec<String> {
+    records(&format!("slow request: GET {target} took"))
+        .into_iter()
+        .map(|(level, line)| {
+            assert_eq!(level, Level::Warn, "{line}");
+            line
+        })
+        .collect()
+}
+
+#[test]
+fn each_slow_request_is_logged_once() {
+    capture();
+    let mut server = Server::new("127.0.0.1:0", 2);
+    server
+        .slow_request_threshold(Duration::from_millis(1))
+        .get("/sleep", |_| {
+            thread::sleep(Duration::from_millis(5));
+            Ok(Response::new(StatusCode::Ok))
+        });
+    let handle = server.spawn().unwrap();
+    let mut stream = TcpStream::connect(handle.local_addr().unwrap()).unwrap();
+    for n in 0..3 {
+        stream
+            .write_all(format!("GET /sleep?n={n} HTTP/1.1\r\nHost: x\r\n\r\n").as_bytes())
+            .unwrap();
+        let mut head = Vec::new();
+        while !head.ends_with(b"\r\n\r\n") {
+            let mut byte = [0];
+            stream.read_exact(&mut byte).unwrap();
+            head.push(byte[0]);
+        }
+    }
+    drop(stream);
+    handle.shutdown();
+    handle.join().unwrap();
+
+    for n in 0..3 {
+        let warnings = slow_warnings(&format!("/sleep?n={n}"));
+        assert_eq!(warnings.len(), 1, "{warnings:?}");
+        assert!(
+            warnings[0].ends_with("(remote 127.0.0.1)"),
+            "{}",
+            warnings[0]
+        );
+    }
+}
+
+#[test]
+fn a_response_write_that_times_out_is_logged_once() {
+    capture();
+    let mut server = Server::new("127.0.0.1:0", 2);
+    server
+        .slow_request_threshold(Duration::from_millis(1))
+        .write_timeout(Duration::from_millis(100))
+        .get("/huge", |_| {
+            Ok(Response::new(StatusCode::Ok).body(vec![b'x'; 64 << 20]))
+        });
+    let handle = server.spawn().unwrap();
+    // Asks for the body and never reads it, so the write stalls.
+    let mut stream = TcpStream::connect(handle.local_addr().unwrap()).unwrap();
+    stream
+        .write_all(b"GET /huge?stalled HTTP/1.1\r\nHost: x\r\n\r\n")
+        .unwrap();
+    let deadline = Instant::now() + Duration::from_secs(5);
+    while slow_warnings("/huge?stalled").is_empty() {
+        assert!(Instant::now() < deadline, "no slow-request warning");
+        thread::sleep(Duration::from_millis(10));
+    }
+    drop(stream);
+    handle.shutdown();
+    handle.join().unwrap();
+
+    let warnings = slow_warnings("/huge?stalled");
+    assert_eq!(warnings.len(), 1, "{warnings:?}");
+}
+
+#[test]
+fn secrets_are_redacted_from_request_dumps_and_slow_warnings() {
+    capture();
+    let mut server = Server::new("127.0.0.1:0", 2);
+    server
+        .slow_request_threshold(Duration::ZERO)
+        .redact_headers(&["X-Session"])
+        .redact_query_keys(&["sig"])
+        .get("/vault", |req| {
+            let seen = format!(
+                "{} {} {}",
+                req.header("Authorization").unwrap_or("-"),
+                req.header("X-Session").unwrap_or("-"),
+                req.query().unwrap_or("-"),
+            );
+            Ok(Response::new(StatusCode::Ok).body(seen))
+        });
+    let handle = server.spawn().unwrap();
+    let mut stream = TcpStream::connect(handle.local_addr().unwrap()).unwrap();
+    stream
+        .write_all(
+            b"GET /vault?TOKEN=tok-1&sig=sig-2&page=2 HTTP/1.1\r\nHost: x\r\n\
+              authorization: Bearer auth-3\r\nCOOKIE: id=cookie-4\r\n\
+              x-SESSION: session-5\r\nX-Other: visible\r\nConnection: close\r\n\r\n",
+        )
+        .unwrap();
+    let mut out = String::new();
+    stream.read_to_string(&mut out).unwrap();
+    handle.shutdown();
+    handle.join().unwrap();
+
+    // Handlers see the request as it was sent.
+    assert!(
+        out.ends_with("\r\n\r\nBearer auth-3 session-5 TOKEN=tok-1&sig=sig-2&page=2"),
+        "{out}"
+    );
+
+    let logged = records("/vault?");
+    let target = "/vault?TOKEN=[REDACTED]&sig=[REDACTED]&page=2";
+    let dump = logged
+        .iter()
+        .find(|(level, _)| *level == Level::Trace)
+        .map(|(_, line)| line)
+        .expect("no request dump");
+    assert!(
+        dump.contains(&format!("\nGET {target} HTTP/1.1\n")),
+        "{dump}"
+    );
+    for header in [
+        "authorization: [REDACTED]",
+        "COOKIE: [REDACTED]",
+        "x-SESSION: [REDACTED]",
+        "X-Other: visible",
+    ] {
+        assert!(dump.contains(header), "{header} missing from {dump}");
+    }
+    assert_eq!(slow_warnings(target).len(), 1);
+    for (_, line) in logged.iter() {
+        for secret in ["tok-1", "sig-2", "auth-3", "cookie-4", "session-5"] {
+            assert!(!line.contains(secret), "{secret} logged: {line}");
+        }
+    }
+}
+
+#[test]
+fn the_banner_groups_routes_by_mount() {
+    let mut api = Router::new();
+    api.get("/users", ok())
+        .post("/users", ok())
+        .delete("/users/:id", ok());
+    let mut admin = Router::new();
+    admin.get("/", ok()).get("/stats", ok());
+    let mut server = server();
+    server
+        .color(ColorPolicy::Never)
+        .mount("/api", api)
+        .mount("/admin", admin);
+
+    let expected = "\
+/
+  GET    /
+/api
+  GET    /users
+  POST   /users
+  DELETE /users/:id
+/admin
+  GET    /
+  GET    /stats
+
+6 routes, 0 static mounts
+serving on - ADDR";
+    assert_eq!(banner(server), expected);
+}
+
+#[test]
+fn a_refused_route_listing_is_a_warning() {
+    capture();
+    let mut server = server();
+    server.debug_routes("/__refused_routes");
+    assert_eq!(
+        records("/__refused_routes"),
+        [(
+            Level::Warn,
+            String::from("Not serving the route listing at /__refused_routes: debug mode is off")
+        )]
+    );
+    assert!(server
+        .match_route(Method::Get, "/__refused_routes")
+        .is_none());
+}
@@ -0,0 +1,291 @@
+//! What `Server::route_stats` and the Prometheus text say after a known
+//! set of requests, and the scraped text checked against the format.
+
+use regex::Regex;
+use simple_social::{
+    metrics::{RouteStats, NOT_FOUND_ROUTE},
+    request::Request,
+    response::{Response, StatusCode},
+    server::{Method, RequestHandler, Server},
+};
+use std::{
+    collections::HashMap,
+    io::{Read, Write},
+    net::{SocketAddr, TcpStream},
+    sync::{mpsc, Mutex},
+    thread,
+    time::Duration,
+};
+
+fn get(server: &Server, path: &str) -> StatusCode {
+    let req = Request::builder().path(path).build().unwrap();
+    server.handle(req).status()
+}
+
+fn stats<'a>(stats: &'a [RouteStats], method: Method, route: &str) -> &'a RouteStats {
+    stats
+        .iter()
+        .find(|r| r.method == method && r.route == route)
+        .unwrap_or_else(|| panic!("no stats for {method} {route}"))
+}
+
+#[test]
+fn param_routes_are_counted_by_pattern() {
+    let mut server = Server::new("127.0.0.1:0", 2);
+    server.get("/users/:id", |req| match req.param("id") {
+        Some("0") => Err("no user zero".into()),
+        _ => Ok(Response::new(StatusCode::Ok)),
+    });
+    for id in ["1", "2", "3", "42", "0"] {
+        get(&server, &format!("/users/{id}"));
+    }
+    get(&server, "/nowhere");
+    get(&server, "/users/1/posts");
+
+    let all = server.route_stats();
+    let users = stats(&all, Method::Get, "/users/:id");
+    assert_eq!((users.hits, users.errors), (5, 1));
+    assert!(!all
+        .iter()
+        .any(|r| r.route.starts_with("/users/") && r.route != "/users/:id"));
+    let missing = stats(&all, Method::Get, NOT_FOUND_ROUTE);
+    assert_eq!((missing.hits, missing.errors), (2, 0));
+
+    let text = server.metrics().to_prometheus();
+    assert!(text
+        .contains("simple_social_route_requests_total{method=\"GET\",route=\"/users/:id\"} 5\n"));
+    assert!(
+        text.contains("simple_social_route_errors_total{method=\"GET\",route=\"/users/:id\"} 1\n")
+    );
+    assert!(text.contains(&format!(
+        "simple_social_route_requests_total{{method=\"GET\",route=\"{NOT_FOUND_ROUTE}\"}} 2\n"
+    )));
+    assert!(!text.contains("route=\"/users/42\""));
+}
+
+#[test]
+fn a_known_mix_of_requests_adds_up() {
+    let mut server = Server::new("127.0.0.1:0", 2);
+    server
+        .get("/", |_| Ok(Response::new(StatusCode::Ok).body("hi")))
+        .get("/fail", |_| Err("broken".into()))
+        .post("/echo", |req| {
+            Ok(Response::new(StatusCode::Ok).body(req.body().to_vec()))
+        });
+    for _ in 0..3 {
+        get(&server, "/");
+    }
+    get(&server, "/missing");
+    get(&server, "/missing");
+    get(&server, "/fail");
+    let head = Request::builder().method(Method::Head).path("/").build();
+    server.handle(head.unwrap());
+    let post = Request::builder()
+        .method(Method::Post)
+        .path("/echo")
+        .body("ping")
+        .build();
+    server.handle(post.unwrap());
+
+    let snapshot = server.metrics();
+    assert_eq!(snapshot.requests, 8);
+    let by_method = |method| {
+        snapshot
+            .by_method
+            .iter()
+            .find(|(m, _)| *m == method)
+            .map_or(0, |(_, n)| *n)
+    };
+    assert_eq!(
+        [
+            Method::Get,
+            Method::Head,
+            Method::Post,
+            Method::Put,
+            Method::Delete
+        ]
+        .map(by_method),
+        [6, 1, 1, 0, 0]
+    );
+    assert_eq!(snapshot.by_status, [(200, 5), (404, 2), (500, 1)]);
+    assert_eq!(snapshot.latency.iter().map(|(_, n)| n).sum::<u64>(), 8);
+    assert_eq!(snapshot.bytes_in, 4);
+    assert!(snapshot.bytes_out >= 3 * 2 + 4, "{}", snapshot.bytes_out);
+
+    let root = stats(&snapshot.routes, Method::Get, "/");
+    assert_eq!((root.hits, root.errors), (4, 0));
+    let fail = stats(&snapshot.routes, Method::Get, "/fail");
+    assert_eq!((fail.hits, fail.errors), (1, 1));
+    let echo = stats(&snapshot.routes, Method::Post, "/echo");
+    assert_eq!((echo.hits, echo.errors), (1, 0));
+}
+
+/// Checks `text` line by line against the Prometheus text format and
+/// returns each sample's value, keyed by the series as written.
+fn parse_exposition(text: &str) -> HashMap<String, f64> {
+    let help = Regex::new(r"^# HELP ([a-zA-Z_:][a-zA-Z0-9_:]*) \S.*$").unwrap();
+    let kind =
+        Regex::new(r"^# TYPE ([a-zA-Z_:][a-zA-Z0-9_:]*) (counter|gauge|histogram)$").unwrap();
+    let sample = Regex::new(
+        r#"^(?<name>[a-zA-Z_:][a-zA-Z0-9_:]*)(?<labels>\{[a-zA-Z_][a-zA-Z0-9_]*="(?:[^"\\\n]|\\.)*"(?:,[a-zA-Z_][a-zA-Z0-9_]*="(?:[^"\\\n]|\\.)*")*\})? (?<value>\S+)$"#,
+    )
+    .unwrap();
+    assert!(text.ends_with('\n'), "no trailing newline");
+
+    let mut families = Vec::new();
+    let mut current: Option<(String, String)> = None;
+    let mut samples = HashMap::new();
+    let mut lines = text.lines().enumerate();
+    while let Some((n, line)) = lines.next() {
+        if let Some(caps) = help.captures(line) {
+            let name = caps[1].to_string();
+            let (_, next) = lines
+                .next()
+                .unwrap_or_else(|| panic!("HELP without TYPE: {line}"));
+            let typed = kind
+                .captures(next)
+                .unwrap_or_else(|| panic!("line {}: expected TYPE: {next}", n + 2));
+            assert_eq!(typed[1], name, "TYPE for another family");
+            assert!(!families.contains(&name), "{name} declared twice");
+            families.push(name.clone());
+            current = Some((name, typed[2].to_string()));
+            continue;
+        }
+        let caps = sample
+            .captures(line)
+            .unwrap_or_else(|| panic!("line {}: not a sample: {line:?}", n + 1));
+        let (family, kind) = current
+            .as_ref()
+            .unwrap_or_else(|| panic!("sample before any TYPE: {line}"));
+        let name = &caps["name"];
+        let belongs = match kind.as_str() {
+            "histogram" => ["_bucket", "_sum", "_count"]
+                .iter()
+                .any(|suffix| name.strip_suffix(suffix) == Some(family)),
+            _ => name == family,
+        };
+        assert!(belongs, "{name} under the {family} family");
+        let value: f64 = caps["value"]
+            .parse()
+            .unwrap_or_else(|_| panic!("bad value: {line}"));
+        assert!(value >= 0.0, "{line}");
+        let series = format!("{name}{}", caps.name("labels").map_or("", |m| m.as_str()));
+        assert!(samples.insert(series, value).is_none(), "repeated: {line}");
+    }
+
+    // Histogram buckets only grow, and +Inf agrees with _count.
+    for (series, count) in samples.iter() {
+        let Some(base) = series.split('{').next().unwrap().strip_suffix("_count") else {
+            continue;
+        };
+        let labels = series
+            .split_once('{')
+            .map_or("", |(_, l)| l.trim_end_matches('}'));
+        let sep = if labels.is_empty() { "" } else { "," };
+        let prefix = format!("{base}_bucket{{{labels}{sep}le=\"");
+        let mut buckets: Vec<(f64, f64)> = samples
+            .iter()
+            .filter_map(|(s, v)| {
+                let le = s.strip_prefix(&prefix)?.strip_suffix("\"}")?;
+                let le = if le == "+Inf" {
+                    f64::INFINITY
+                } else {
+                    le.parse().unwrap()
+                };
+                Some((le, *v))
+            })
+            .collect();
+        buckets.sort_by(|a, b| a.0.total_cmp(&b.0));
+        assert!(!buckets.is_empty(), "no buckets for {series}");
+        assert!(buckets.windows(2).all(|w| w[0].1 <= w[1].1), "{series}");
+        assert_eq!(
+            buckets.last().unwrap(),
+            &(f64::INFINITY, *count),
+            "{series}"
+        );
+    }
+    samples
+}
+
+fn scrape(addr: SocketAddr) -> String {
+    let mut stream = TcpStream::connect(addr).unwrap();
+    stream
+        .write_all(b"GET /metrics HTTP/1.1\r\nHost: x\r\nConnection: close\r\n\r\n")
+        .unwrap();
+    let mut out = String::new();
+    stream.read_to_string(&mut out).unwrap();
+    assert!(out.starts_with("HTTP/1.1 200 OK\r\n"), "{out}");
+    assert!(
+        out.contains("\r\nContent-Type: text/plain; version=0.0.4\r\n"),
+        "{out}"
+    );
+    let (_, body) = out.split_once("\r\n\r\n").unwrap();
+    body.to_string()
+}
+
+#[test]
+fn the_scraped_text_is_valid_while_requests_are_in_flight() {
+    let (started, handler_started) = mpsc::channel();
+    let (release, released) = mpsc::channel::<()>();
+    let released = Mutex::new(released);
+    let mut server = Server::new("127.0.0.1:0", 4);
+    server
+        .mount_metrics("/metrics")
+        .get("/", |_| Ok(Response::new(StatusCode::Ok).body("hi")))
+        .get("/slow", move |_| {
+            started.send(()).unwrap();
+            released.lock().unwrap().recv().unwrap();
+            Ok(Response::new(StatusCode::Ok).body("done"))
+        });
+    let handle = server.spawn().unwrap();
+    let addr = handle.local_addr().unwrap();
+    parse_exposition(&scrape(addr));
+
+    let slow: Vec<_> = (0..2)
+        .map(|_| {
+            thread::spawn(move || {
+                let mut stream = TcpStream::connect(addr).unwrap();
+                stream
+                    .write_all(b"GET /slow HTTP/1.1\r\nHost: x\r\nConnection: close\r\n\r\n")
+                    .unwrap();
+                let mut out = String::new();
+                stream.read_to_string(&mut out).unwrap();
+                out
+            })
+        })
+        .collect();
+    for _ in 0..2 {
+        handler_started
+            .recv_timeout(Duration::from_secs(5))
+            .unwrap();
+    }
+
+    let during = parse_exposition(&scrape(addr));
+    assert!(
+        during["simple_social_active_connections"] >= 3.0,
+        "{during:?}"
+    );
+    assert_eq!(
+        during.get("simple_social_route_requests_total{method=\"GET\",route=\"/slow\"}"),
+        Some(&0.0)
+    );
+
+    release.send(()).unwrap();
+    release.send(()).unwrap();
+    for client in slow {
+        assert!(client.join().unwrap().ends_with("\r\n\r\ndone"));
+    }
+    let after = parse_exposition(&scrape(addr));
+    assert_eq!(
+        after["simple_social_route_requests_total{method=\"GET\",route=\"/slow\"}"],
+        2.0
+    );
+    assert_eq!(
+        after["simple_social_route_duration_seconds_count{method=\"GET\",route=\"/slow\"}"],
+        2.0
+    );
+    assert!(after["simple_social_requests_total{method=\"GET\"}"] >= 4.0);
+
+    handle.shutdown();
+    handle.join().unwrap();
+}
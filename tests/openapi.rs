@@ -0,0 +1,113 @@
+//! The OpenAPI document, read back as JSON.
+
+use serde_json::{json, Value};
+use simple_social::{
+    openapi::RouteDoc,
+    response::{Response, StatusCode},
+    server::{HandlerFunc, RequestHandler, Router, Server},
+    testing::TestClient,
+};
+
+fn ok() -> impl HandlerFunc {
+    |_| Ok(Response::new(StatusCode::Ok))
+}
+
+fn server() -> Server {
+    let mut posts = Router::new();
+    posts
+        .get("/:id", ok())
+        .describe(RouteDoc::new("One post").response("application/json"));
+    let mut server = Server::new("127.0.0.1:0", 2);
+    server
+        .get("/posts", ok())
+        .describe(RouteDoc::new("List posts").response("application/json"))
+        .post("/posts", ok())
+        .describe(
+            RouteDoc::new("Add a post")
+                .request("application/json")
+                .response("application/json"),
+        )
+        .get("/users/:user/posts/:post", ok())
+        .get("/files/*path", ok())
+        .mount("/posts", posts);
+    server
+}
+
+fn parameters(operation: &Value) -> Vec<&str> {
+    operation["parameters"]
+        .as_array()
+        .map_or(Vec::new(), |params| {
+            params.iter().map(|p| p["name"].as_str().unwrap()).collect()
+        })
+}
+
+#[test]
+fn placeholders_become_path_parameters() {
+    let doc: Value = serde_json::from_str(&server().openapi_json("Posts", "1.0")).unwrap();
+    assert_eq!(doc["openapi"], "3.0.3");
+    assert_eq!(doc["info"], json!({"title": "Posts", "version": "1.0"}));
+
+    let mut paths: Vec<&str> = doc["paths"]
+        .as_object()
+        .unwrap()
+        .keys()
+        .map(|k| k.as_str())
+        .collect();
+    paths.sort_unstable();
+    assert_eq!(
+        paths,
+        [
+            "/files/{path}",
+            "/posts",
+            "/posts/{id}",
+            "/users/{user}/posts/{post}"
+        ]
+    );
+
+    let nested = &doc["paths"]["/users/{user}/posts/{post}"]["get"];
+    assert_eq!(parameters(nested), ["user", "post"]);
+    for param in nested["parameters"].as_array().unwrap() {
+        assert_eq!(param["in"], "path");
+        assert_eq!(param["required"], true);
+        assert_eq!(param["schema"], json!({"type": "string"}));
+    }
+    assert_eq!(parameters(&doc["paths"]["/files/{path}"]["get"]), ["path"]);
+    assert_eq!(parameters(&doc["paths"]["/posts/{id}"]["get"]), ["id"]);
+    assert!(doc["paths"]["/posts"]["get"].get("parameters").is_none());
+}
+
+#[test]
+fn describe_fills_in_operations() {
+    let doc: Value = serde_json::from_str(&server().openapi_json("Posts", "1.0")).unwrap();
+    let list = &doc["paths"]["/posts"]["get"];
+    assert_eq!(list["summary"], "List posts");
+    assert_eq!(list["operationId"], "get_posts");
+    assert!(list["responses"]["200"]["content"]["application/json"].is_object());
+    assert!(list.get("requestBody").is_none());
+
+    let add = &doc["paths"]["/posts"]["post"];
+    assert_eq!(add["summary"], "Add a post");
+    assert_eq!(add["requestBody"]["required"], true);
+    assert!(add["requestBody"]["content"]["application/json"].is_object());
+
+    assert_eq!(doc["paths"]["/posts/{id}"]["get"]["summary"], "One post");
+    let files = &doc["paths"]["/files/{path}"]["get"];
+    assert!(files.get("summary").is_none());
+    assert!(files["responses"]["200"].get("content").is_none());
+}
+
+#[test]
+fn the_mounted_document_includes_later_routes() {
+    let mut server = server();
+    server.mount_openapi("/openapi.json", "Posts", "2.0");
+    server.delete("/posts/:id", ok());
+    let client = TestClient::start(server).unwrap();
+
+    let res = client.get("/openapi.json").unwrap();
+    assert_eq!(res.status, 200);
+    assert_eq!(res.header("Content-Type"), Some("application/json"));
+    let doc: Value = serde_json::from_str(&res.text()).unwrap();
+    assert_eq!(doc["info"]["version"], "2.0");
+    assert_eq!(parameters(&doc["paths"]["/posts/{id}"]["delete"]), ["id"]);
+    assert!(doc["paths"]["/openapi.json"]["get"].is_object());
+}
@@ -0,0 +1,118 @@
+//! `Pagination` parsing a list request and writing its `Link` headers.
+
+use simple_social::{
+    pagination::{PageDefaults, Pagination},
+    request::Request,
+    response::{Response, StatusCode},
+};
+
+/// 45 items, 10 to a page: five pages, the last one short.
+const TOTAL: u64 = 45;
+
+fn pagination(target: &str) -> Pagination {
+    let req = Request::builder().path(target).build().unwrap();
+    Pagination::from_request(&req, PageDefaults::default()).unwrap()
+}
+
+/// The `Link` header for `target`, split into its entries.
+fn links(target: &str) -> Vec<String> {
+    let res = pagination(target).apply(TOTAL, Response::new(StatusCode::Ok));
+    assert_eq!(res.header_value("X-Total-Count"), Some("45"));
+    res.header_value("Link")
+        .unwrap()
+        .split(", ")
+        .map(String::from)
+        .collect()
+}
+
+#[test]
+fn the_first_page_has_no_prev() {
+    let page = pagination("/posts?per_page=10");
+    assert_eq!((page.page(), page.offset(), page.limit()), (1, 0, 10));
+    assert_eq!(
+        links("/posts?per_page=10"),
+        [
+            "</posts?page=1&per_page=10>; rel=\"first\"",
+            "</posts?page=2&per_page=10>; rel=\"next\"",
+            "</posts?page=5&per_page=10>; rel=\"last\"",
+        ]
+    );
+}
+
+#[test]
+fn a_middle_page_links_both_ways() {
+    let page = pagination("/posts?page=3&per_page=10");
+    assert_eq!((page.page(), page.offset(), page.limit()), (3, 20, 10));
+    assert_eq!(
+        links("/posts?page=3&per_page=10"),
+        [
+            "</posts?page=1&per_page=10>; rel=\"first\"",
+            "</posts?page=2&per_page=10>; rel=\"prev\"",
+            "</posts?page=4&per_page=10>; rel=\"next\"",
+            "</posts?page=5&per_page=10>; rel=\"last\"",
+        ]
+    );
+}
+
+#[test]
+fn the_last_page_has_no_next() {
+    assert_eq!(pagination("/posts?page=5&per_page=10").offset(), 40);
+    assert_eq!(
+        links("/posts?page=5&per_page=10"),
+        [
+            "</posts?page=1&per_page=10>; rel=\"first\"",
+            "</posts?page=4&per_page=10>; rel=\"prev\"",
+            "</posts?page=5&per_page=10>; rel=\"last\"",
+        ]
+    );
+}
+
+#[test]
+fn out_of_range_pages_point_back_at_real_ones() {
+    // Past the end: empty, with prev on the last real page.
+    let page = pagination("/posts?page=40&per_page=10");
+    assert_eq!(page.offset(), 390);
+    assert_eq!(
+        links("/posts?page=40&per_page=10"),
+        [
+            "</posts?page=1&per_page=10>; rel=\"first\"",
+            "</posts?page=5&per_page=10>; rel=\"prev\"",
+            "</posts?page=5&per_page=10>; rel=\"last\"",
+        ]
+    );
+    // Page zero is page one, and per_page is clamped into 1..=100.
+    assert_eq!(pagination("/posts?page=0").page(), 1);
+    assert_eq!(pagination("/posts?per_page=0").per_page(), 1);
+    assert_eq!(pagination("/posts?per_page=5000").per_page(), 100);
+    assert_eq!(pagination("/posts").per_page(), 20);
+
+    let req = Request::builder().path("/posts?page=two").build().unwrap();
+    let err = Pagination::from_request(&req, PageDefaults::default()).unwrap_err();
+    assert_eq!(err.response().status(), StatusCode::BadRequest);
+    assert!(
+        err.to_string().contains("page must be a whole number"),
+        "{err}"
+    );
+}
+
+#[test]
+fn other_query_parameters_are_kept() {
+    assert_eq!(
+        links("/posts?tag=rust&page=2&q=caf%C3%A9+au+lait&per_page=10&draft"),
+        [
+            "</posts?tag=rust&q=caf%C3%A9+au+lait&draft&page=1&per_page=10>; rel=\"first\"",
+            "</posts?tag=rust&q=caf%C3%A9+au+lait&draft&page=1&per_page=10>; rel=\"prev\"",
+            "</posts?tag=rust&q=caf%C3%A9+au+lait&draft&page=3&per_page=10>; rel=\"next\"",
+            "</posts?tag=rust&q=caf%C3%A9+au+lait&draft&page=5&per_page=10>; rel=\"last\"",
+        ]
+    );
+    // Nothing else to keep and no request query at all.
+    let res = pagination("/users").apply(0, Response::new(StatusCode::Ok));
+    assert_eq!(res.header_value("X-Total-Count"), Some("0"));
+    assert_eq!(
+        res.header_value("Link"),
+        Some(
+            "</users?page=1&per_page=20>; rel=\"first\", </users?page=1&per_page=20>; rel=\"last\""
+        )
+    );
+}
@@ -0,0 +1,96 @@
+//! Query strings deserialized with `Request::query_as`.
+
+#![cfg(feature = "serde")]
+
+use serde::Deserialize;
+use simple_social::{
+    request::Request,
+    response::{Response, StatusCode},
+    server::{RequestHandler, Server},
+};
+
+#[derive(Deserialize, Debug, PartialEq)]
+struct Search {
+    #[serde(default)]
+    ids: Vec<u64>,
+    #[serde(default)]
+    tags: Vec<String>,
+    #[serde(default)]
+    draft: bool,
+    q: Option<String>,
+}
+
+fn search(query: &str) -> Search {
+    request(query).query_as().unwrap()
+}
+
+fn request(query: &str) -> Request {
+    Request::builder()
+        .path(&format!("/search?{query}"))
+        .build()
+        .unwrap()
+}
+
+#[test]
+fn repeated_keys_fill_a_vec() {
+    assert_eq!(search("ids=1&ids=2&ids=3").ids, [1, 2, 3]);
+    assert_eq!(search("ids=7").ids, [7]);
+    assert_eq!(search("").ids, [] as [u64; 0]);
+    // Other keys in between don't split the run.
+    let found = search("ids=1&q=rust&ids=2");
+    assert_eq!(found.ids, [1, 2]);
+    assert_eq!(found.q.as_deref(), Some("rust"));
+    assert!(request("ids=1&ids=x").query_as::<Search>().is_err());
+}
+
+#[test]
+fn bracketed_keys_fill_a_vec() {
+    assert_eq!(search("tags[]=a&tags[]=b").tags, ["a", "b"]);
+    assert_eq!(search("tags%5B%5D=a+b&tags=c%26d").tags, ["a b", "c&d"]);
+    assert_eq!(search("tags[]=").tags, [""]);
+}
+
+#[test]
+fn bare_keys_are_true_flags() {
+    assert!(search("draft").draft);
+    assert!(search("q=x&draft").draft);
+    assert!(search("draft=true").draft);
+    assert!(!search("draft=false").draft);
+    assert!(!search("q=x").draft);
+    assert!(request("draft=maybe").query_as::<Search>().is_err());
+}
+
+#[test]
+fn unknown_keys_are_ignored_unless_strict() {
+    let found = search("q=rust&page=2&utm_source=feed");
+    assert_eq!(
+        found,
+        Search {
+            ids: Vec::new(),
+            tags: Vec::new(),
+            draft: false,
+            q: Some(String::from("rust"))
+        }
+    );
+
+    let mut server = Server::new("127.0.0.1:0", 2);
+    server.get("/search", |req| match req.query_as_strict::<Search>() {
+        Ok(found) => Ok(Response::new(StatusCode::Ok).body(format!("{found:?}"))),
+        Err(e) => Ok(e.response()),
+    });
+    assert_eq!(
+        server.handle(request("q=rust&ids=1")).status(),
+        StatusCode::Ok
+    );
+    let res = server.handle(request("q=rust&page=2"));
+    assert_eq!(res.status(), StatusCode::BadRequest);
+    let mut out = Vec::new();
+    res.write_to(&mut out).unwrap();
+    assert!(String::from_utf8(out)
+        .unwrap()
+        .contains("unknown parameter `page`"));
+    assert_eq!(
+        server.handle(request("q=%zz")).status(),
+        StatusCode::BadRequest
+    );
+}
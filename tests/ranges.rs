@@ -0,0 +1,140 @@
+//! Byte ranges on a static mount, in a test binary of their own so the
+//! allocation counter only sees these requests.
+
+use simple_social::{file_cache::FileCache, server::Server, static_files::static_dir};
+use std::{
+    alloc::{GlobalAlloc, Layout, System},
+    fs,
+    io::{Read, Write},
+    net::{SocketAddr, TcpStream},
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
+};
+use tempfile::TempDir;
+
+struct Counting;
+
+static ALLOCATED: AtomicUsize = AtomicUsize::new(0);
+
+unsafe impl GlobalAlloc for Counting {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        ALLOCATED.fetch_add(layout.size(), Ordering::Relaxed);
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout)
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        ALLOCATED.fetch_add(new_size, Ordering::Relaxed);
+        System.realloc(ptr, layout, new_size)
+    }
+}
+
+#[global_allocator]
+static GLOBAL: Counting = Counting;
+
+const SIZE: usize = 10 * 1024 * 1024;
+
+fn byte(i: usize) -> u8 {
+    (i * 7 + i / 251) as u8
+}
+
+/// Sends one GET and checks the body against `byte` as it arrives, so the
+/// client side allocates next to nothing.
+fn fetch(addr: SocketAddr, range: Option<&str>, from: usize) -> (String, usize) {
+    let mut stream = TcpStream::connect(addr).unwrap();
+    let range = range.map(|r| format!("Range: {r}\r\n")).unwrap_or_default();
+    let request =
+        format!("GET /media/big.bin HTTP/1.1\r\nHost: x\r\n{range}Connection: close\r\n\r\n");
+    stream.write_all(request.as_bytes()).unwrap();
+
+    let mut buf = [0u8; 16 * 1024];
+    let mut head = Vec::new();
+    let mut at = from;
+    loop {
+        let n = stream.read(&mut buf).unwrap();
+        if n == 0 {
+            break;
+        }
+        let mut chunk = &buf[..n];
+        if !head.ends_with(b"\r\n\r\n") {
+            let start = head.len().saturating_sub(3);
+            head.extend_from_slice(chunk);
+            let Some(end) = head[start..].windows(4).position(|w| w == b"\r\n\r\n") else {
+                continue;
+            };
+            let end = start + end + 4;
+            chunk = &chunk[chunk.len() - (head.len() - end)..];
+            head.truncate(end);
+        }
+        for b in chunk {
+            assert_eq!(*b, byte(at), "byte {at}");
+            at += 1;
+        }
+    }
+    (String::from_utf8(head).unwrap(), at - from)
+}
+
+#[test]
+fn ranges_stream_from_disk() {
+    let dir = TempDir::new().unwrap();
+    let body: Vec<u8> = (0..SIZE).map(byte).collect();
+    fs::write(dir.path().join("big.bin"), &body).unwrap();
+    drop(body);
+
+    let cache = Arc::new(FileCache::new(64 << 20, 64 << 20));
+    let mut server = Server::new("127.0.0.1:0", 2);
+    let mount = static_dir(dir.path()).with_cache(Arc::clone(&cache));
+    server.mount_static("/media", mount);
+    let handle = server.spawn().unwrap();
+    let addr = handle.local_addr().unwrap();
+
+    let middle = SIZE / 2;
+    let range = format!("bytes={}-{}", middle, middle + 1023);
+    let before = ALLOCATED.load(Ordering::Relaxed);
+    let (head, got) = fetch(addr, Some(&range), middle);
+    let allocated = ALLOCATED.load(Ordering::Relaxed) - before;
+    assert!(head.starts_with("HTTP/1.1 206"), "{head}");
+    assert!(head.contains(&format!(
+        "Content-Range: bytes {}-{}/{SIZE}",
+        middle,
+        middle + 1023
+    )));
+    assert!(head.contains("Content-Length: 1024"));
+    assert_eq!(got, 1024);
+    assert_eq!(
+        cache.size(),
+        0,
+        "a range must not load the file into the cache"
+    );
+    assert!(
+        allocated < 256 * 1024,
+        "allocated {allocated} bytes for a 1KB range"
+    );
+
+    let before = ALLOCATED.load(Ordering::Relaxed);
+    let (head, got) = fetch(addr, Some("bytes=0-"), 0);
+    let allocated = ALLOCATED.load(Ordering::Relaxed) - before;
+    assert!(head.starts_with("HTTP/1.1 206"), "{head}");
+    assert_eq!(got, SIZE);
+    assert!(
+        allocated < 1024 * 1024,
+        "allocated {allocated} bytes for bytes=0-"
+    );
+
+    let (head, _) = fetch(addr, Some(&format!("bytes={SIZE}-")), 0);
+    assert!(head.starts_with("HTTP/1.1 416"), "{head}");
+    assert!(head.contains(&format!("Content-Range: bytes */{SIZE}")));
+
+    let (head, got) = fetch(addr, None, 0);
+    assert!(head.starts_with("HTTP/1.1 200"), "{head}");
+    assert!(head.contains("Accept-Ranges: bytes"));
+    assert_eq!(got, SIZE);
+
+    handle.shutdown();
+    handle.join().unwrap();
+}
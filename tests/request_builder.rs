@@ -0,0 +1,150 @@
+//! `Request::builder` against the parser: the same request built and sent
+//! as bytes looks the same to a handler.
+
+use simple_social::{
+    error::BuildError,
+    request::Request,
+    response::{Response, StatusCode},
+    server::{Method, RequestHandler, Server},
+    testing::Duplex,
+};
+
+/// Everything a handler can read off a request, one thing per line.
+fn describe(req: &Request) -> String {
+    let mut out = format!(
+        "{} {} {:?} {}\n",
+        req.method(),
+        req.path(),
+        req.query(),
+        req.version()
+    );
+    for (name, value) in req.headers() {
+        out.push_str(&format!("{name}: {value}\n"));
+    }
+    out.push_str(&format!(
+        "params {:?}\ncookie {:?}\ntype {:?}\nbody {:?}\n",
+        req.query_params(),
+        req.cookie("session"),
+        req.content_type().map(|ct| ct.essence()),
+        String::from_utf8_lossy(req.body()),
+    ));
+    out
+}
+
+fn server() -> Server {
+    let mut server = Server::new("127.0.0.1:0", 2);
+    for path in ["/", "/search", "/posts"] {
+        server.get(path, |req| {
+            Ok(Response::new(StatusCode::Ok).body(describe(req)))
+        });
+        server.post(path, |req| {
+            Ok(Response::new(StatusCode::Ok).body(describe(req)))
+        });
+    }
+    server
+}
+
+fn body_of(response: &[u8]) -> String {
+    let text = String::from_utf8_lossy(response);
+    let (head, body) = text.split_once("\r\n\r\n").unwrap();
+    assert!(head.contains(" 200 "), "{text}");
+    String::from(body)
+}
+
+/// What the handler saw of `raw` read off a connection, and of `built`.
+fn seen(raw: &str, built: Request) -> (String, String) {
+    let server = server();
+    let conn = Duplex::new(raw);
+    server.serve_connection(conn.clone()).unwrap();
+    let parsed = body_of(&conn.output());
+
+    let mut out = Vec::new();
+    server.handle(built).write_to(&mut out).unwrap();
+    (parsed, body_of(&out))
+}
+
+#[test]
+fn a_get_with_a_query_headers_and_cookies_matches() {
+    let (parsed, built) = seen(
+        "GET /search?q=a%20b&tags=x&tags=y HTTP/1.1\r\nHost: example.com\r\n\
+         Cookie: session=abc; theme=dark\r\nX-Dup: 1\r\nx-dup: 2\r\nConnection: close\r\n\r\n",
+        Request::builder()
+            .path("/search?q=a%20b&tags=x&tags=y")
+            .header("Host", "example.com")
+            .header("Cookie", "session=abc; theme=dark")
+            .header("X-Dup", "1")
+            .header("x-dup", "2")
+            .header("Connection", "close")
+            .build()
+            .unwrap(),
+    );
+    assert_eq!(parsed, built);
+    assert!(built.contains("params [(\"q\", \"a b\"), (\"tags\", \"x\"), (\"tags\", \"y\")]"));
+    assert!(built.contains("cookie Some(\"abc\")"));
+}
+
+#[test]
+fn a_body_gets_the_content_length_the_wire_would_carry() {
+    let (parsed, built) = seen(
+        "POST /posts HTTP/1.1\r\nHost: x\r\nContent-Type: application/json; charset=utf-8\r\n\
+         Connection: close\r\nContent-Length: 13\r\n\r\n{\"title\":\"a\"}",
+        Request::builder()
+            .method(Method::Post)
+            .path("/posts")
+            .header("Host", "x")
+            .header("Content-Type", "application/json; charset=utf-8")
+            .header("Connection", "close")
+            .body("{\"title\":\"a\"}")
+            .build()
+            .unwrap(),
+    );
+    assert_eq!(parsed, built);
+    assert!(built.contains("Content-Length: 13\n"), "{built}");
+}
+
+#[test]
+fn a_chunked_body_is_decoded_like_the_parser_does() {
+    let chunked = "5\r\nhello\r\n7\r\n, world\r\n0\r\n\r\n";
+    let (parsed, built) = seen(
+        &format!(
+            "POST / HTTP/1.1\r\nHost: x\r\nTransfer-Encoding: chunked\r\n\
+             Connection: close\r\n\r\n{chunked}"
+        ),
+        Request::builder()
+            .method(Method::Post)
+            .path("/")
+            .header("Host", "x")
+            .header("Transfer-Encoding", "chunked")
+            .header("Connection", "close")
+            .body(chunked)
+            .build()
+            .unwrap(),
+    );
+    assert_eq!(parsed, built);
+    assert!(built.contains("body \"hello, world\""), "{built}");
+
+    for broken in ["5\r\nhel", "zz\r\nhello\r\n0\r\n\r\n", "5\r\nhello"] {
+        let built = Request::builder()
+            .method(Method::Post)
+            .path("/")
+            .header("Transfer-Encoding", "chunked")
+            .body(broken)
+            .build();
+        assert!(matches!(built, Err(BuildError::InvalidBody)), "{broken:?}");
+    }
+}
+
+#[test]
+fn an_http_1_0_request_matches() {
+    let (parsed, built) = seen(
+        "GET / HTTP/1.0\r\nAccept-Language: de\r\n\r\n",
+        Request::builder()
+            .path("/")
+            .version("HTTP/1.0")
+            .header("Accept-Language", "de")
+            .build()
+            .unwrap(),
+    );
+    assert_eq!(parsed, built);
+    assert!(built.starts_with("GET / None HTTP/1.0\n"), "{built}");
+}
@@ -0,0 +1,92 @@
+//! Request bodies sent with `Content-Encoding`, which the server undoes
+//! before the handler sees them.
+
+#![cfg(feature = "compression")]
+
+use flate2::{write::GzEncoder, Compression};
+use serde_json::{json, Value};
+use simple_social::{
+    response::{Response, StatusCode},
+    server::{RequestHandler, Server},
+    testing::{Duplex, TestResponse},
+};
+use std::io::Write;
+
+const LIMIT: usize = 1 << 20;
+
+fn server() -> Server {
+    let mut server = Server::new("127.0.0.1:0", 2);
+    server.max_body_size(LIMIT).post("/posts", |req| {
+        if req.header("Content-Encoding").is_some() {
+            return Err("the coding reached the handler".into());
+        }
+        let mut post: Value = serde_json::from_slice(req.body())?;
+        post["length"] = json!(req.body().len());
+        Ok(Response::new(StatusCode::Ok)
+            .header("Content-Type", "application/json")
+            .body(post.to_string()))
+    });
+    server
+}
+
+fn gzip(data: &[u8]) -> Vec<u8> {
+    let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+    encoder.write_all(data).unwrap();
+    encoder.finish().unwrap()
+}
+
+fn post(body: &[u8], read_size: usize) -> TestResponse {
+    let mut raw = format!(
+        "POST /posts HTTP/1.1\r\nHost: x\r\nContent-Type: application/json\r\n\
+         Content-Encoding: gzip\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+        body.len()
+    )
+    .into_bytes();
+    raw.extend_from_slice(body);
+    let conn = Duplex::new(raw).read_size(read_size);
+    server().serve_connection(conn.clone()).unwrap();
+    let mut responses = conn.responses().unwrap();
+    assert_eq!(responses.len(), 1);
+    responses.remove(0)
+}
+
+#[test]
+fn a_gzipped_json_body_round_trips() {
+    let sent = json!({"title": "Hello", "tags": ["rust", "web"], "body": "é ".repeat(500)});
+    let plain = sent.to_string();
+    let compressed = gzip(plain.as_bytes());
+    assert!(compressed.len() < plain.len());
+
+    for read_size in [usize::MAX, 7] {
+        let res = post(&compressed, read_size);
+        assert_eq!(res.status, 200, "{}", res.text());
+        let mut echoed: Value = serde_json::from_str(&res.text()).unwrap();
+        assert_eq!(echoed["length"], json!(plain.len()));
+        echoed.as_object_mut().unwrap().remove("length");
+        assert_eq!(echoed, sent);
+    }
+}
+
+#[test]
+fn a_zip_bomb_stops_at_the_body_limit() {
+    // 16 MiB of zeros squeezes into well under the limit.
+    let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+    let zeros = vec![0; 1 << 20];
+    for _ in 0..16 {
+        encoder.write_all(&zeros).unwrap();
+    }
+    let bomb = encoder.finish().unwrap();
+    assert!(bomb.len() < LIMIT / 4, "{} bytes", bomb.len());
+
+    assert_eq!(post(&bomb, usize::MAX).status, 413);
+
+    // A body that decodes to exactly the limit still gets through.
+    let pad = "a".repeat(LIMIT - r#"{"pad":""}"#.len());
+    let res = post(
+        &gzip(json!({ "pad": pad }).to_string().as_bytes()),
+        usize::MAX,
+    );
+    assert_eq!(res.status, 200);
+    let echoed: Value = serde_json::from_str(&res.text()).unwrap();
+    assert_eq!(echoed["length"], json!(LIMIT));
+}
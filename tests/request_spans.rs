@@ -0,0 +1,155 @@
+//! The `tracing` span around each request, seen by a subscriber that
+//! just writes down every span and event.
+
+#![cfg(feature = "tracing")]
+
+use simple_social::{
+    response::{Response, StatusCode},
+    server::{RequestHandler, Server},
+};
+use std::{
+    cell::RefCell,
+    fmt,
+    io::{Read, Write},
+    net::{SocketAddr, TcpStream},
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Mutex,
+    },
+};
+use tracing::{
+    field::{Field, Visit},
+    span::{Attributes, Id, Record},
+    Event, Metadata, Subscriber,
+};
+
+type Fields = Vec<(String, String)>;
+
+static NEXT_ID: AtomicU64 = AtomicU64::new(1);
+static SPANS: Mutex<Vec<(u64, &'static str, Fields)>> = Mutex::new(Vec::new());
+static EVENTS: Mutex<Vec<(Option<u64>, String)>> = Mutex::new(Vec::new());
+
+thread_local! {
+    static ENTERED: RefCell<Vec<u64>> = const { RefCell::new(Vec::new()) };
+}
+
+struct Collect<'a>(&'a mut Fields);
+
+impl Visit for Collect<'_> {
+    fn record_str(&mut self, field: &Field, value: &str) {
+        self.0.push((field.name().to_string(), value.to_string()));
+    }
+
+    fn record_debug(&mut self, field: &Field, value: &dyn fmt::Debug) {
+        self.0
+            .push((field.name().to_string(), format!("{value:?}")));
+    }
+}
+
+struct Recorder;
+
+impl Subscriber for Recorder {
+    fn enabled(&self, _: &Metadata<'_>) -> bool {
+        true
+    }
+
+    fn new_span(&self, span: &Attributes<'_>) -> Id {
+        let id = NEXT_ID.fetch_add(1, Ordering::Relaxed);
+        let mut fields = Vec::new();
+        span.record(&mut Collect(&mut fields));
+        let name = span.metadata().name();
+        SPANS.lock().unwrap().push((id, name, fields));
+        Id::from_u64(id)
+    }
+
+    fn record(&self, span: &Id, values: &Record<'_>) {
+        let mut spans = SPANS.lock().unwrap();
+        if let Some((_, _, fields)) = spans.iter_mut().find(|(id, ..)| *id == span.into_u64()) {
+            values.record(&mut Collect(fields));
+        }
+    }
+
+    fn record_follows_from(&self, _: &Id, _: &Id) {}
+
+    fn event(&self, event: &Event<'_>) {
+        let mut fields = Vec::new();
+        event.record(&mut Collect(&mut fields));
+        let message = fields
+            .into_iter()
+            .find(|(name, _)| name == "message")
+            .map_or_else(String::new, |(_, value)| value);
+        let current = ENTERED.with(|entered| entered.borrow().last().copied());
+        EVENTS.lock().unwrap().push((current, message));
+    }
+
+    fn enter(&self, span: &Id) {
+        ENTERED.with(|entered| entered.borrow_mut().push(span.into_u64()));
+    }
+
+    fn exit(&self, span: &Id) {
+        ENTERED.with(|entered| entered.borrow_mut().retain(|&id| id != span.into_u64()));
+    }
+}
+
+fn get(addr: SocketAddr, path: &str) -> SocketAddr {
+    let mut stream = TcpStream::connect(addr).unwrap();
+    stream
+        .write_all(
+            format!("GET {path} HTTP/1.1\r\nHost: x\r\nConnection: close\r\n\r\n").as_bytes(),
+        )
+        .unwrap();
+    let mut out = String::new();
+    stream.read_to_string(&mut out).unwrap();
+    stream.local_addr().unwrap()
+}
+
+fn field<'a>(fields: &'a Fields, name: &str) -> Option<&'a str> {
+    fields
+        .iter()
+        .find(|(n, _)| n == name)
+        .map(|(_, value)| value.as_str())
+}
+
+#[test]
+fn each_request_gets_a_span_with_its_fields() {
+    tracing::subscriber::set_global_default(Recorder).unwrap();
+    let mut server = Server::new("127.0.0.1:0", 2);
+    server.get("/", |_| Ok(Response::new(StatusCode::Ok)));
+    let handle = server.spawn().unwrap();
+    let addr = handle.local_addr().unwrap();
+    let found_from = get(addr, "/");
+    let missing_from = get(addr, "/missing");
+    handle.shutdown();
+    handle.join().unwrap();
+
+    let spans = SPANS.lock().unwrap();
+    let request = |path: &str| {
+        spans
+            .iter()
+            .find(|(_, name, fields)| *name == "request" && field(fields, "path") == Some(path))
+            .unwrap_or_else(|| panic!("no span for {path}: {spans:?}"))
+    };
+
+    let (_, _, found) = request("/");
+    assert_eq!(field(found, "method"), Some("GET"));
+    assert_eq!(field(found, "status"), Some("200"));
+    let peer = format!("Some({found_from})");
+    assert_eq!(field(found, "remote_addr"), Some(peer.as_str()));
+    let found_id: u64 = field(found, "request_id").unwrap().parse().unwrap();
+
+    let (missing_span, _, missing) = request("/missing");
+    assert_eq!(field(missing, "status"), Some("404"));
+    let peer = format!("Some({missing_from})");
+    assert_eq!(field(missing, "remote_addr"), Some(peer.as_str()));
+    let missing_id: u64 = field(missing, "request_id").unwrap().parse().unwrap();
+    assert_ne!(found_id, missing_id);
+
+    let events = EVENTS.lock().unwrap();
+    assert!(
+        events.contains(&(Some(*missing_span), String::from("no route matched"))),
+        "{events:?}"
+    );
+    assert!(spans
+        .iter()
+        .any(|(_, name, fields)| *name == "worker" && field(fields, "id").is_some()));
+}
@@ -0,0 +1,139 @@
+//! `Server::resource` end to end: every route it adds, and the 405s for
+//! methods it doesn't.
+#![cfg(feature = "serde")]
+
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use simple_social::{
+    resource::Resource,
+    server::{Method, Server},
+    store::MemStore,
+    testing::{TestClient, TestResponse},
+};
+
+#[derive(Clone, Serialize, Deserialize)]
+struct Post {
+    title: String,
+}
+
+fn client() -> TestClient {
+    let posts = Resource::new(MemStore::<u64, Post>::new()).before_create(|post| {
+        match post.title.is_empty() {
+            true => Err(String::from("a post needs a title")),
+            false => Ok(()),
+        }
+    });
+    let mut server = Server::new("127.0.0.1:0", 2);
+    server.resource("/posts", posts);
+    TestClient::start(server).unwrap()
+}
+
+fn json(res: &TestResponse) -> Value {
+    assert_eq!(res.header("Content-Type"), Some("application/json"));
+    serde_json::from_str(&res.text()).unwrap()
+}
+
+fn create(client: &TestClient, title: &str) -> TestResponse {
+    let body = json!({ "title": title }).to_string();
+    client
+        .post("/posts", body.as_bytes(), "application/json")
+        .unwrap()
+}
+
+#[test]
+fn items_are_created_listed_and_shown() {
+    let client = client();
+    assert_eq!(json(&client.get("/posts").unwrap()), json!([]));
+
+    let first = create(&client, "Hello");
+    assert_eq!(first.status, 201);
+    let location = first.header("Location").unwrap().to_owned();
+    let id = json(&first)["id"].as_u64().unwrap();
+    assert_eq!(location, format!("/posts/{id}"));
+    assert_eq!(json(&first), json!({ "id": id, "title": "Hello" }));
+    let second = create(&client, "Again");
+    let second_id = json(&second)["id"].as_u64().unwrap();
+
+    let shown = client.get(&location).unwrap();
+    assert_eq!(shown.status, 200);
+    assert_eq!(json(&shown), json!({ "id": id, "title": "Hello" }));
+    assert_eq!(
+        json(&client.get("/posts").unwrap()),
+        json!([
+            { "id": id, "title": "Hello" },
+            { "id": second_id, "title": "Again" },
+        ])
+    );
+}
+
+#[test]
+fn items_are_replaced_and_deleted() {
+    let client = client();
+    let location = create(&client, "Draft")
+        .header("Location")
+        .unwrap()
+        .to_owned();
+    let body = json!({ "title": "Final" }).to_string();
+    let replaced = client
+        .put(&location, body.as_bytes(), "application/json")
+        .unwrap();
+    assert_eq!(replaced.status, 200);
+    assert_eq!(json(&replaced)["title"], "Final");
+    assert_eq!(json(&client.get(&location).unwrap())["title"], "Final");
+
+    let deleted = client.delete(&location).unwrap();
+    assert_eq!((deleted.status, deleted.body.len()), (204, 0));
+    assert_eq!(client.get(&location).unwrap().status, 404);
+    assert_eq!(client.delete(&location).unwrap().status, 404);
+}
+
+#[test]
+fn unknown_ids_are_a_404() {
+    let client = client();
+    let body = json!({ "title": "x" }).to_string();
+    for res in [
+        client.get("/posts/99").unwrap(),
+        client.get("/posts/not-a-number").unwrap(),
+        client
+            .put("/posts/99", body.as_bytes(), "application/json")
+            .unwrap(),
+        client.delete("/posts/99").unwrap(),
+    ] {
+        assert_eq!(res.status, 404);
+        assert_eq!(json(&res), json!({ "error": "no such item" }));
+    }
+}
+
+#[test]
+fn bad_bodies_are_a_400() {
+    let client = client();
+    let res = client
+        .post("/posts", b"{not json", "application/json")
+        .unwrap();
+    assert_eq!(res.status, 400);
+    let res = create(&client, "");
+    assert_eq!(res.status, 400);
+    assert_eq!(json(&res), json!({ "error": "a post needs a title" }));
+    assert_eq!(json(&client.get("/posts").unwrap()), json!([]));
+}
+
+#[test]
+fn other_methods_get_a_405_with_allow() {
+    let client = client();
+    let res = client.put("/posts", b"{}", "application/json").unwrap();
+    assert_eq!(res.status, 405);
+    assert_eq!(res.header("Allow"), Some("GET, HEAD, POST"));
+
+    let res = client.post("/posts/1", b"{}", "application/json").unwrap();
+    assert_eq!(res.status, 405);
+    assert_eq!(res.header("Allow"), Some("GET, HEAD, PUT, DELETE"));
+
+    let res = client.request(Method::Patch, "/posts/1", &[], b"").unwrap();
+    assert_eq!(res.status, 405);
+    assert_eq!(res.header("Allow"), Some("GET, HEAD, PUT, DELETE"));
+
+    let res = client
+        .request(Method::Patch, "/elsewhere", &[], b"")
+        .unwrap();
+    assert_eq!((res.status, res.header("Allow")), (404, None));
+}
@@ -0,0 +1,158 @@
+//! The text of `/robots.txt` and the XML of a sitemap, as served.
+
+use simple_social::{
+    request::Request,
+    response::{Response, StatusCode},
+    server::Server,
+    sitemap::{ChangeFreq, SitemapUrl, MAX_URLS},
+};
+use std::{
+    sync::atomic::{AtomicU64, Ordering},
+    time::{Duration, SystemTime},
+};
+
+fn get(server: &Server, path: &str) -> (String, String) {
+    let req = Request::builder()
+        .path(path)
+        .header("Host", "social.example")
+        .build()
+        .unwrap();
+    let res = server.handle(req);
+    assert_eq!(res.status(), StatusCode::Ok);
+    let content_type = String::from(res.header_value("Content-Type").unwrap());
+    (content_type, body(res))
+}
+
+fn body(res: Response) -> String {
+    let mut out = Vec::new();
+    res.write_to(&mut out).unwrap();
+    let text = String::from_utf8(out).unwrap();
+    String::from(text.split_once("\r\n\r\n").unwrap().1)
+}
+
+/// Checks that `xml` is one well-formed element after its declaration:
+/// tags nest and close in order, and every `&` starts a known entity.
+fn assert_well_formed(xml: &str) {
+    let body = xml
+        .strip_prefix("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n")
+        .expect("an XML declaration");
+    let mut open: Vec<&str> = Vec::new();
+    let mut rest = body;
+    let mut roots = 0;
+    while let Some(start) = rest.find('<') {
+        check_text(&rest[..start]);
+        let end = rest[start..].find('>').expect("an unclosed tag") + start;
+        let tag = &rest[start + 1..end];
+        if let Some(name) = tag.strip_prefix('/') {
+            assert_eq!(open.pop(), Some(name), "in {xml}");
+        } else {
+            if open.is_empty() {
+                roots += 1;
+            }
+            let name = tag.split_whitespace().next().unwrap();
+            assert!(!tag.ends_with('/'), "{tag}");
+            open.push(name);
+        }
+        rest = &rest[end + 1..];
+    }
+    check_text(rest);
+    assert!(open.is_empty(), "{open:?} never closed");
+    assert_eq!(roots, 1);
+}
+
+fn check_text(text: &str) {
+    assert!(!text.contains('>'), "{text:?}");
+    for (i, _) in text.match_indices('&') {
+        let entity = &text[i..];
+        assert!(
+            ["&amp;", "&lt;", "&gt;", "&quot;", "&#39;"]
+                .iter()
+                .any(|e| entity.starts_with(e)),
+            "{entity:?}"
+        );
+    }
+}
+
+#[test]
+fn robots_txt_groups_rules_by_agent() {
+    let mut server = Server::new("127.0.0.1:0", 2);
+    server.robots(|r| {
+        r.disallow("/admin")
+            .allow("/admin/public")
+            .user_agent("Googlebot")
+            .user_agent("Bingbot")
+            .disallow("/search\n/drafts")
+            .user_agent("EvilBot")
+            .sitemap("/sitemap.xml")
+            .sitemap("https://cdn.example/sitemap-images.xml")
+    });
+    let (content_type, text) = get(&server, "/robots.txt");
+    assert_eq!(content_type, "text/plain");
+    assert_eq!(
+        text,
+        "User-agent: *\n\
+         Disallow: /admin\n\
+         Allow: /admin/public\n\
+         \n\
+         User-agent: Googlebot\n\
+         User-agent: Bingbot\n\
+         Disallow: /search/drafts\n\
+         \n\
+         User-agent: EvilBot\n\
+         Disallow:\n\
+         \n\
+         Sitemap: http://social.example/sitemap.xml\n\
+         Sitemap: https://cdn.example/sitemap-images.xml\n"
+    );
+
+    let mut server = Server::new("127.0.0.1:0", 2);
+    server.robots(|r| r);
+    assert_eq!(get(&server, "/robots.txt").1, "User-agent: *\nDisallow:\n");
+}
+
+#[test]
+fn the_sitemap_is_well_formed_and_follows_the_data() {
+    let posts = AtomicU64::new(1);
+    let mut server = Server::new("127.0.0.1:0", 2);
+    server.sitemap("/sitemap.xml", move || {
+        let newest = posts.fetch_add(1, Ordering::Relaxed);
+        let mut urls = vec![SitemapUrl::new("/")
+            .changefreq(ChangeFreq::Daily)
+            .lastmod(SystemTime::UNIX_EPOCH + Duration::from_secs(1_700_000_000))];
+        urls.extend((1..=newest).map(|id| SitemapUrl::new(&format!("/posts/{id}?tab=a&b=<c>"))));
+        urls
+    });
+
+    let (content_type, xml) = get(&server, "/sitemap.xml");
+    assert_eq!(content_type, "application/xml");
+    assert_well_formed(&xml);
+    assert_eq!(
+        xml,
+        "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n\
+         <urlset xmlns=\"http://www.sitemaps.org/schemas/sitemap/0.9\">\n\
+         <url><loc>http://social.example/</loc><lastmod>2023-11-14T22:13:20Z</lastmod>\
+         <changefreq>daily</changefreq></url>\n\
+         <url><loc>http://social.example/posts/1?tab=a&amp;b=&lt;c&gt;</loc></url>\n\
+         </urlset>\n"
+    );
+
+    // The next request sees the post added since.
+    let (_, xml) = get(&server, "/sitemap.xml");
+    assert_well_formed(&xml);
+    assert!(xml.contains("/posts/2?"), "{xml}");
+}
+
+#[test]
+fn sitemaps_stop_at_the_url_limit() {
+    let mut server = Server::new("127.0.0.1:0", 2);
+    server.sitemap("/sitemap.xml", || {
+        (0..MAX_URLS + 10)
+            .map(|i| SitemapUrl::new(&format!("/p/{i}")))
+            .collect()
+    });
+    let (_, xml) = get(&server, "/sitemap.xml");
+    assert_eq!(xml.matches("<url>").count(), MAX_URLS);
+    assert!(xml.contains(&format!("/p/{}<", MAX_URLS - 1)));
+    assert!(!xml.contains(&format!("/p/{}<", MAX_URLS)));
+    assert!(xml.ends_with("</urlset>\n"));
+}
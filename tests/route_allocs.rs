@@ -0,0 +1,88 @@
+//! Route lookups, in a test binary of their own so the allocation counter
+//! only sees `match_route`.
+
+use simple_social::{
+    response::{Response, StatusCode},
+    server::{Method, RequestHandler, Server},
+};
+use std::{
+    alloc::{GlobalAlloc, Layout, System},
+    hint::black_box,
+    sync::atomic::{AtomicUsize, Ordering},
+};
+
+struct Counting;
+
+static ALLOCATIONS: AtomicUsize = AtomicUsize::new(0);
+
+unsafe impl GlobalAlloc for Counting {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout)
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        System.realloc(ptr, layout, new_size)
+    }
+}
+
+#[global_allocator]
+static GLOBAL: Counting = Counting;
+
+fn server() -> Server {
+    let mut server = Server::new("127.0.0.1:0", 1);
+    for i in 0..100 {
+        server.get(&format!("/route/{i}"), |_| {
+            Ok(Response::new(StatusCode::Ok))
+        });
+    }
+    #[cfg(feature = "serde")]
+    server.resource(
+        "/posts",
+        simple_social::store::MemStore::<u64, String>::new(),
+    );
+    server
+}
+
+/// How many allocations 1000 lookups of `path` make, and whether it matched.
+fn lookups(server: &Server, method: Method, path: &str) -> (usize, bool) {
+    let before = ALLOCATIONS.load(Ordering::Relaxed);
+    let mut found = false;
+    for _ in 0..1000 {
+        found = black_box(server.match_route(method, black_box(path))).is_some();
+    }
+    (ALLOCATIONS.load(Ordering::Relaxed) - before, found)
+}
+
+#[test]
+fn lookups_never_allocate() {
+    let server = server();
+    let mut cases = vec![
+        (Method::Get, "/route/0", true),
+        (Method::Get, "/route/99", true),
+        (Method::Head, "/route/50", true),
+        (Method::Get, "/route/100", false),
+        (Method::Get, "/nowhere/at/all", false),
+        (Method::Post, "/route/0", false),
+    ];
+    if cfg!(feature = "serde") {
+        cases.extend([
+            (Method::Get, "/posts", true),
+            (Method::Get, "/posts/7", true),
+            (Method::Delete, "/posts/7", true),
+            (Method::Get, "/posts/7/comments", false),
+            (Method::Post, "/posts/7", false),
+        ]);
+    }
+
+    for (method, path, hit) in cases {
+        let (allocations, found) = lookups(&server, method, path);
+        assert_eq!(found, hit, "{method} {path}");
+        assert_eq!(allocations, 0, "{method} {path} allocated");
+    }
+}
@@ -0,0 +1,240 @@
+//! Which route a request lands on when more than one could take it.
+
+use simple_social::{
+    error::ServerError,
+    response::{Response, StatusCode},
+    server::{HandlerFunc, Method, RequestHandler, Router, Server},
+    testing::TestClient,
+};
+
+fn says(text: &'static str) -> impl HandlerFunc {
+    move |_| Ok(Response::new(StatusCode::Ok).body(text))
+}
+
+#[test]
+fn the_first_of_two_identical_literals_wins() {
+    let mut server = Server::new("127.0.0.1:0", 2);
+    server
+        .get("/about", says("first"))
+        .get("/about", says("second"));
+    let client = TestClient::start(server).unwrap();
+    assert_eq!(client.get("/about").unwrap().text(), "first");
+}
+
+#[test]
+fn literals_are_matched_per_method() {
+    let mut server = Server::new("127.0.0.1:0", 2);
+    server.get("/form", says("get")).post("/form", says("post"));
+    let client = TestClient::start(server).unwrap();
+    assert_eq!(client.get("/form").unwrap().text(), "get");
+    assert_eq!(
+        client.post("/form", b"", "text/plain").unwrap().text(),
+        "post"
+    );
+    assert_eq!(client.head("/form").unwrap().status, 200);
+}
+
+#[test]
+fn mounted_literals_share_the_table() {
+    let mut api = Router::new();
+    api.get("/status", says("mounted"));
+    let mut server = Server::new("127.0.0.1:0", 2);
+    server.get("/api/status", says("direct")).mount("/api", api);
+    assert_eq!(
+        server
+            .match_route(Method::Get, "/api/status")
+            .unwrap()
+            .prefix(),
+        "/"
+    );
+    let client = TestClient::start(server).unwrap();
+    assert_eq!(client.get("/api/status").unwrap().text(), "direct");
+}
+
+#[test]
+fn a_literal_beats_a_param_which_beats_a_wildcard() {
+    let mut server = Server::new("127.0.0.1:0", 2);
+    server
+        .get("/files/*rest", says("rest"))
+        .get("/files/:name", says("name"))
+        .get("/files/readme", says("readme"));
+    let pattern = |path| server.match_route(Method::Get, path).map(|m| m.pattern());
+    assert_eq!(pattern("/files/readme"), Some("/files/readme"));
+    assert_eq!(pattern("/files/notes"), Some("/files/:name"));
+    assert_eq!(pattern("/files/a/b/c"), Some("/files/*rest"));
+    assert_eq!(pattern("/files/"), Some("/files/*rest"));
+    assert_eq!(pattern("/files"), None);
+
+    let client = TestClient::start(server).unwrap();
+    assert_eq!(client.get("/files/readme").unwrap().text(), "readme");
+    assert_eq!(client.get("/files/notes").unwrap().text(), "name");
+    assert_eq!(client.get("/files/a/b").unwrap().text(), "rest");
+}
+
+#[test]
+fn the_first_of_two_params_of_one_rank_wins() {
+    let mut server = Server::new("127.0.0.1:0", 2);
+    server
+        .get("/users/:id", says("id"))
+        .get("/users/:name", says("name"));
+    let matched = server.match_route(Method::Get, "/users/7").unwrap();
+    assert_eq!(matched.pattern(), "/users/:id");
+    assert_eq!(matched.param("name"), None);
+}
+
+#[test]
+fn params_are_captured() {
+    let mut server = Server::new("127.0.0.1:0", 2);
+    server
+        .get("/users/:user/posts/:post", |req| {
+            let body = format!(
+                "{} {}",
+                req.param("user").unwrap(),
+                req.param("post").unwrap()
+            );
+            Ok(Response::new(StatusCode::Ok).body(body))
+        })
+        .get("/static/*path", |req| {
+            Ok(Response::new(StatusCode::Ok).body(req.param("path").unwrap().to_owned()))
+        });
+    let matched = server
+        .match_route(Method::Get, "/users/ann/posts/42")
+        .unwrap();
+    assert_eq!(matched.params(), [("user", "ann"), ("post", "42")]);
+    assert_eq!(matched.param("post"), Some("42"));
+    let rest = server
+        .match_route(Method::Head, "/static/css/site.css")
+        .unwrap();
+    assert_eq!(rest.params(), [("path", "css/site.css")]);
+    assert_eq!(
+        server
+            .match_route(Method::Get, "/users//posts/1")
+            .map(|m| m.pattern()),
+        None
+    );
+
+    let client = TestClient::start(server).unwrap();
+    assert_eq!(client.get("/users/ann/posts/42").unwrap().text(), "ann 42");
+    assert_eq!(
+        client.get("/static/css/site.css").unwrap().text(),
+        "css/site.css"
+    );
+    assert_eq!(client.get("/users/ann/posts").unwrap().status, 404);
+}
+
+#[test]
+fn a_pattern_only_matches_its_method() {
+    let mut server = Server::new("127.0.0.1:0", 2);
+    server
+        .get("/items/:id", says("get"))
+        .post("/items/*rest", says("post"));
+    assert!(server.match_route(Method::Put, "/items/1").is_none());
+    assert!(server.match_route(Method::Delete, "/items/1").is_none());
+    assert_eq!(
+        server
+            .match_route(Method::Post, "/items/1")
+            .unwrap()
+            .pattern(),
+        "/items/*rest"
+    );
+    assert_eq!(
+        server
+            .match_route(Method::Head, "/items/1")
+            .unwrap()
+            .method(),
+        Method::Get
+    );
+
+    let client = TestClient::start(server).unwrap();
+    let res = client.put("/items/1", b"", "text/plain").unwrap();
+    assert_eq!(res.status, 405);
+    assert_eq!(res.header("Allow"), Some("GET, HEAD, POST"));
+}
+
+#[test]
+fn mounted_patterns_keep_their_prefix() {
+    let mut api = Router::new();
+    api.get("/users/:id", |req| {
+        Ok(Response::new(StatusCode::Ok).body(req.param("id").unwrap().to_owned()))
+    })
+    .get("/docs/*page", says("docs"));
+    let own = api.match_route(Method::Get, "/users/3").unwrap();
+    assert_eq!((own.pattern(), own.prefix()), ("/users/:id", "/"));
+    assert_eq!(own.params(), [("id", "3")]);
+    assert_eq!(
+        api.match_route(Method::Get, "/docs/a/b").unwrap().pattern(),
+        "/docs/*page"
+    );
+
+    let mut server = Server::new("127.0.0.1:0", 2);
+    server.mount("/api/v1", api);
+    let matched = server.match_route(Method::Get, "/api/v1/users/3").unwrap();
+    assert_eq!(matched.pattern(), "/api/v1/users/:id");
+    assert_eq!(matched.prefix(), "/api/v1");
+    assert_eq!(matched.params(), [("id", "3")]);
+    assert!(server.match_route(Method::Get, "/users/3").is_none());
+
+    let client = TestClient::start(server).unwrap();
+    assert_eq!(client.get("/api/v1/users/3").unwrap().text(), "3");
+    assert_eq!(client.get("/api/v1/docs/intro").unwrap().text(), "docs");
+}
+
+#[test]
+fn a_wildcard_must_come_last() {
+    let mut server = Server::new("127.0.0.1:0", 2);
+    server.get("/files/*rest/edit", says("never"));
+    assert!(matches!(server.run(), Err(ServerError::InvalidRoute(_))));
+}
+
+#[cfg(feature = "serde")]
+mod resources {
+    use super::*;
+    use simple_social::store::MemStore;
+
+    fn posts(literal_first: bool) -> Server {
+        let mut server = Server::new("127.0.0.1:0", 2);
+        if literal_first {
+            server.get("/posts/new", says("form"));
+        }
+        server.resource("/posts", MemStore::<u64, String>::new());
+        if !literal_first {
+            server.get("/posts/new", says("form"));
+        }
+        server
+    }
+
+    #[test]
+    fn a_literal_beats_the_item_route_in_either_order() {
+        for literal_first in [true, false] {
+            let server = posts(literal_first);
+            let literal = server.match_route(Method::Get, "/posts/new").unwrap();
+            assert_eq!(literal.pattern(), "/posts/new", "{literal_first}");
+            let head = server.match_route(Method::Head, "/posts/new").unwrap();
+            assert_eq!(head.pattern(), "/posts/new", "{literal_first}");
+
+            let client = TestClient::start(server).unwrap();
+            assert_eq!(client.get("/posts/new").unwrap().text(), "form");
+        }
+    }
+
+    #[test]
+    fn the_item_route_takes_everything_else() {
+        let server = posts(true);
+        let pattern = |method, path| {
+            server
+                .match_route(method, path)
+                .map(|m| m.pattern().to_owned())
+        };
+        assert_eq!(
+            pattern(Method::Get, "/posts/7"),
+            Some(String::from("/posts/:id"))
+        );
+        assert_eq!(
+            pattern(Method::Delete, "/posts/new"),
+            Some(String::from("/posts/:id"))
+        );
+        assert_eq!(pattern(Method::Get, "/posts"), Some(String::from("/posts")));
+        assert_eq!(pattern(Method::Get, "/posts/7/comments"), None);
+        assert_eq!(pattern(Method::Post, "/posts/7"), None);
+    }
+}
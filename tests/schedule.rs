@@ -0,0 +1,90 @@
+//! Jobs from `Server::schedule`, counted against the wall clock.
+
+use simple_social::server::Server;
+use std::{
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
+    thread,
+    time::Duration,
+};
+
+const INTERVAL: Duration = Duration::from_millis(20);
+const WINDOW: Duration = Duration::from_millis(500);
+
+fn counter() -> (Arc<AtomicUsize>, Arc<AtomicUsize>) {
+    let count = Arc::new(AtomicUsize::new(0));
+    (Arc::clone(&count), count)
+}
+
+#[test]
+fn a_job_runs_once_per_interval_until_shutdown() {
+    let (count, runs) = counter();
+    let mut server = Server::new("127.0.0.1:0", 2);
+    server.schedule(INTERVAL, move || {
+        runs.fetch_add(1, Ordering::Relaxed);
+    });
+    let handle = server.spawn().unwrap();
+    thread::sleep(WINDOW);
+    handle.shutdown();
+    let metrics = handle.metrics();
+    handle.join().unwrap();
+
+    // 25 ticks fit in the window; a loaded machine may manage fewer, but
+    // never more.
+    let ran = count.load(Ordering::Relaxed);
+    assert!((8..=25).contains(&ran), "{ran} runs");
+    assert!(metrics.job_runs as usize <= ran + 1, "{metrics:?}");
+    assert_eq!(metrics.job_overruns, 0);
+
+    // Nothing runs after shutdown.
+    thread::sleep(INTERVAL * 5);
+    assert_eq!(count.load(Ordering::Relaxed), ran);
+}
+
+#[test]
+fn a_panicking_job_runs_again_and_others_carry_on() {
+    let (panics, tries) = counter();
+    let (count, runs) = counter();
+    let mut server = Server::new("127.0.0.1:0", 2);
+    server
+        .schedule(INTERVAL, move || {
+            tries.fetch_add(1, Ordering::Relaxed);
+            panic!("scheduled job failed on purpose");
+        })
+        .schedule(INTERVAL, move || {
+            runs.fetch_add(1, Ordering::Relaxed);
+        });
+    let handle = server.spawn().unwrap();
+    thread::sleep(WINDOW);
+    handle.shutdown();
+    handle.join().unwrap();
+
+    assert!(panics.load(Ordering::Relaxed) >= 5, "{panics:?}");
+    assert!(count.load(Ordering::Relaxed) >= 5, "{count:?}");
+}
+
+#[test]
+fn a_run_still_going_skips_the_next_tick() {
+    let (busy, running) = counter();
+    let (overlapped, overlaps) = counter();
+    let mut server = Server::new("127.0.0.1:0", 4);
+    server.schedule(Duration::from_millis(10), move || {
+        if running.fetch_add(1, Ordering::SeqCst) > 0 {
+            overlaps.fetch_add(1, Ordering::SeqCst);
+        }
+        thread::sleep(Duration::from_millis(45));
+        running.fetch_sub(1, Ordering::SeqCst);
+    });
+    let handle = server.spawn().unwrap();
+    thread::sleep(Duration::from_millis(300));
+    let metrics = handle.metrics();
+    handle.shutdown();
+    handle.join().unwrap();
+
+    assert_eq!(overlapped.load(Ordering::SeqCst), 0);
+    assert_eq!(busy.load(Ordering::SeqCst), 0);
+    assert!(metrics.job_runs >= 2, "{metrics:?}");
+    assert!(metrics.job_overruns >= metrics.job_runs, "{metrics:?}");
+}
@@ -0,0 +1,103 @@
+//! `Response::send` and `Response::status_only`: the content type each
+//! body type gets, and the statuses that carry no body at all.
+
+use simple_social::{
+    response::{IntoBody, Response, StatusCode},
+    server::{RequestHandler, Server},
+    testing::Duplex,
+};
+
+fn wire(res: Response) -> String {
+    let mut out = Vec::new();
+    res.write_to(&mut out).unwrap();
+    String::from_utf8(out).unwrap()
+}
+
+fn sent(status: StatusCode, body: impl IntoBody) -> String {
+    wire(Response::send(status, body))
+}
+
+#[test]
+fn text_is_utf8_plain_text() {
+    let expected = "HTTP/1.1 201 Created\r\nContent-Type: text/plain; charset=utf-8\r\n\
+                    Content-Length: 6\r\n\r\ncréé";
+    assert_eq!(sent(StatusCode::Created, "créé"), expected);
+    assert_eq!(sent(StatusCode::Created, String::from("créé")), expected);
+    assert_eq!(
+        sent(StatusCode::Ok, ""),
+        "HTTP/1.1 200 OK\r\nContent-Type: text/plain; charset=utf-8\r\nContent-Length: 0\r\n\r\n"
+    );
+}
+
+#[test]
+fn bytes_are_an_octet_stream() {
+    let expected = "HTTP/1.1 200 OK\r\nContent-Type: application/octet-stream\r\n\
+                    Content-Length: 3\r\n\r\n\x00\x01\x02";
+    assert_eq!(sent(StatusCode::Ok, vec![0u8, 1, 2]), expected);
+    assert_eq!(sent(StatusCode::Ok, &[0u8, 1, 2][..]), expected);
+}
+
+#[cfg(feature = "serde")]
+#[test]
+fn json_is_serialized_or_an_empty_500() {
+    use simple_social::response::Json;
+    use std::collections::HashMap;
+
+    assert_eq!(
+        sent(
+            StatusCode::Created,
+            Json(serde_json::json!({"id": 7, "tags": ["a"]}))
+        ),
+        "HTTP/1.1 201 Created\r\nContent-Type: application/json\r\n\
+         Content-Length: 21\r\n\r\n{\"id\":7,\"tags\":[\"a\"]}"
+    );
+    // JSON object keys have to be strings.
+    let unserializable = HashMap::from([((1, 2), "pair")]);
+    assert_eq!(
+        sent(StatusCode::Ok, Json(unserializable)),
+        "HTTP/1.1 500 Internal Server Error\r\nContent-Length: 0\r\n\r\n"
+    );
+}
+
+#[test]
+fn no_body_statuses_send_neither_a_body_nor_a_length() {
+    for (status, line) in [
+        (StatusCode::NoContent, "HTTP/1.1 204 No Content"),
+        (StatusCode::NotModified, "HTTP/1.1 304 Not Modified"),
+    ] {
+        let expected = format!("{line}\r\n\r\n");
+        assert_eq!(wire(Response::status_only(status)), expected);
+        assert_eq!(sent(status, "ignored"), expected);
+        assert_eq!(sent(status, vec![1u8, 2, 3]), expected);
+    }
+    // A status that does allow one still says it's empty.
+    assert_eq!(
+        wire(Response::status_only(StatusCode::Ok)),
+        "HTTP/1.1 200 OK\r\nContent-Length: 0\r\n\r\n"
+    );
+}
+
+#[test]
+fn a_204_leaves_a_kept_alive_connection_in_step() {
+    let mut server = Server::new("127.0.0.1:0", 2);
+    server
+        .delete("/posts/1", |_| {
+            Ok(Response::send(StatusCode::NoContent, "gone"))
+        })
+        .get("/posts", |_| Ok(Response::send(StatusCode::Ok, "[]")));
+    let conn = Duplex::new(
+        "DELETE /posts/1 HTTP/1.1\r\nHost: x\r\n\r\n\
+         GET /posts HTTP/1.1\r\nHost: x\r\nConnection: close\r\n\r\n",
+    );
+    server.serve_connection(conn.clone()).unwrap();
+    let responses = conn.responses().unwrap();
+    assert_eq!(responses.len(), 2);
+    assert_eq!(responses[0].status, 204);
+    assert!(responses[0].body.is_empty());
+    assert!(!responses[0]
+        .headers
+        .iter()
+        .any(|(k, _)| k.eq_ignore_ascii_case("Content-Length")));
+    assert_eq!(responses[1].status, 200);
+    assert_eq!(responses[1].text(), "[]");
+}
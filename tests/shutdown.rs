@@ -0,0 +1,77 @@
+//! Graceful shutdown while a handler is still working.
+
+use simple_social::{
+    error::ServerError,
+    response::{Response, StatusCode},
+    server::{RequestHandler, Server},
+};
+use std::{
+    io::{Read, Write},
+    net::TcpStream,
+    sync::mpsc,
+    thread,
+    time::{Duration, Instant},
+};
+
+/// A server whose `/slow` handler reports on `started` and then takes
+/// `delay` to answer.
+fn slow_server(delay: Duration, started: mpsc::Sender<()>) -> Server {
+    let mut server = Server::new("127.0.0.1:0", 2);
+    server.get("/slow", move |_| {
+        started.send(()).unwrap();
+        thread::sleep(delay);
+        Ok(Response::new(StatusCode::Ok).body("done"))
+    });
+    server
+}
+
+fn request(stream: &mut TcpStream) {
+    stream
+        .set_read_timeout(Some(Duration::from_secs(10)))
+        .unwrap();
+    stream
+        .write_all(b"GET /slow HTTP/1.1\r\nHost: x\r\nConnection: close\r\n\r\n")
+        .unwrap();
+}
+
+#[test]
+fn an_in_flight_response_is_sent_before_run_returns() {
+    let (started, handler_started) = mpsc::channel();
+    let mut server = slow_server(Duration::from_millis(300), started);
+    server.bind().unwrap();
+    let addr = server.local_addr().unwrap();
+    let stop = server.shutdown_handle();
+    let running = thread::spawn(move || server.run());
+
+    let mut stream = TcpStream::connect(addr).unwrap();
+    request(&mut stream);
+    handler_started
+        .recv_timeout(Duration::from_secs(5))
+        .unwrap();
+    stop.shutdown();
+
+    let mut out = String::new();
+    stream.read_to_string(&mut out).unwrap();
+    assert!(out.starts_with("HTTP/1.1 200 OK\r\n"), "{out}");
+    assert!(out.ends_with("\r\n\r\ndone"), "{out}");
+    running.join().unwrap().unwrap();
+    assert!(TcpStream::connect(addr).is_err(), "still accepting");
+}
+
+#[test]
+fn handlers_past_the_shutdown_timeout_are_abandoned() {
+    let (started, handler_started) = mpsc::channel();
+    let mut server = slow_server(Duration::from_secs(3), started);
+    server.shutdown_timeout(Duration::from_millis(200));
+    let handle = server.spawn().unwrap();
+
+    let mut stream = TcpStream::connect(handle.local_addr().unwrap()).unwrap();
+    request(&mut stream);
+    handler_started
+        .recv_timeout(Duration::from_secs(5))
+        .unwrap();
+    let stopping = Instant::now();
+    handle.shutdown();
+    assert!(matches!(handle.join(), Err(ServerError::Shutdown)));
+    assert!(stopping.elapsed() < Duration::from_secs(2));
+}
@@ -0,0 +1,50 @@
+//! SIGTERM starting a graceful shutdown. The handler is process-wide and
+//! a second signal exits, so this file holds a single test.
+
+#![cfg(all(unix, feature = "signals"))]
+
+use simple_social::{
+    response::{Response, StatusCode},
+    server::{RequestHandler, Server},
+    signals::Signal,
+};
+use std::{
+    io::{Read, Write},
+    net::TcpStream,
+    sync::mpsc,
+    thread,
+    time::Duration,
+};
+
+#[test]
+fn sigterm_lets_the_in_flight_request_finish() {
+    let (started, handler_started) = mpsc::channel();
+    let mut server = Server::new("127.0.0.1:0", 2);
+    server
+        .graceful_on_signals(&[Signal::Term])
+        .unwrap()
+        .get("/slow", move |_| {
+            started.send(()).unwrap();
+            thread::sleep(Duration::from_millis(200));
+            Ok(Response::new(StatusCode::Ok).body("done"))
+        });
+    let handle = server.spawn().unwrap();
+
+    let mut stream = TcpStream::connect(handle.local_addr().unwrap()).unwrap();
+    stream
+        .set_read_timeout(Some(Duration::from_secs(5)))
+        .unwrap();
+    stream
+        .write_all(b"GET /slow HTTP/1.1\r\nHost: x\r\nConnection: close\r\n\r\n")
+        .unwrap();
+    handler_started
+        .recv_timeout(Duration::from_secs(5))
+        .unwrap();
+    // SAFETY: kill has no memory-safety preconditions.
+    assert_eq!(unsafe { libc::kill(libc::getpid(), libc::SIGTERM) }, 0);
+
+    let mut out = String::new();
+    stream.read_to_string(&mut out).unwrap();
+    assert!(out.ends_with("\r\n\r\ndone"), "{out}");
+    handle.join().unwrap();
+}
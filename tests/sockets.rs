@@ -0,0 +1,187 @@
+//! Socket options on listeners and accepted connections.
+
+use simple_social::{
+    error::ServerError,
+    response::{Response, StatusCode},
+    server::{RequestHandler, Server},
+};
+use std::{
+    io::{ErrorKind, Read, Write},
+    net::{SocketAddr, TcpListener, TcpStream},
+};
+
+fn server() -> Server {
+    let mut server = Server::new("127.0.0.1:0", 2);
+    server.get("/", |_| Ok(Response::new(StatusCode::Ok).body("hi")));
+    server
+}
+
+fn get(addr: SocketAddr) -> String {
+    let mut stream = TcpStream::connect(addr).unwrap();
+    stream
+        .write_all(b"GET / HTTP/1.1\r\nHost: x\r\nConnection: close\r\n\r\n")
+        .unwrap();
+    let mut out = String::new();
+    stream.read_to_string(&mut out).unwrap();
+    out
+}
+
+/// The server's end of `client`, found among this process's open sockets.
+#[cfg(target_os = "linux")]
+fn accepted(client: &TcpStream) -> TcpStream {
+    use std::{fs, os::fd::BorrowedFd};
+    let (local, peer) = (client.peer_addr().unwrap(), client.local_addr().unwrap());
+    for entry in fs::read_dir("/proc/self/fd").unwrap() {
+        let Ok(fd) = entry.unwrap().file_name().to_string_lossy().parse() else {
+            continue;
+        };
+        // SAFETY: the fd is only borrowed long enough to duplicate it, and
+        // nothing in this test closes sockets meanwhile.
+        let Ok(owned) = unsafe { BorrowedFd::borrow_raw(fd) }.try_clone_to_owned() else {
+            continue;
+        };
+        let stream = TcpStream::from(owned);
+        if stream.local_addr().ok() == Some(local) && stream.peer_addr().ok() == Some(peer) {
+            return stream;
+        }
+    }
+    panic!("no accepted socket for {peer}");
+}
+
+#[cfg(target_os = "linux")]
+#[test]
+fn tcp_nodelay_is_set_on_accepted_streams() {
+    for nodelay in [true, false] {
+        let mut server = server();
+        server.tcp_nodelay(nodelay);
+        let handle = server.spawn().unwrap();
+        let mut client = TcpStream::connect(handle.local_addr().unwrap()).unwrap();
+        client
+            .write_all(b"GET / HTTP/1.1\r\nHost: x\r\n\r\n")
+            .unwrap();
+        client.read_exact(&mut [0; 12]).unwrap();
+        assert_eq!(accepted(&client).nodelay().unwrap(), nodelay);
+        drop(client);
+        assert!(get(handle.local_addr().unwrap()).ends_with("hi"));
+        handle.shutdown();
+        handle.join().unwrap();
+    }
+}
+
+#[cfg(feature = "socket2")]
+#[test]
+fn reuse_addr_rebinds_straight_after_shutdown() {
+    let mut server = server();
+    server.reuse_addr(true);
+    let handle = server.spawn().unwrap();
+    let addr = handle.local_addr().unwrap();
+    // The server closes first, leaving its side in TIME_WAIT.
+    assert!(get(handle.local_addr().unwrap()).ends_with("hi"));
+    handle.shutdown();
+    handle.join().unwrap();
+
+    let mut again = Server::new(&addr.to_string(), 2);
+    again
+        .reuse_addr(true)
+        .get("/", |_| Ok(Response::new(StatusCode::Ok).body("again")));
+    let handle = again.spawn().unwrap();
+    assert_eq!(handle.local_addr(), Some(addr));
+    assert!(get(handle.local_addr().unwrap()).ends_with("again"));
+    handle.shutdown();
+    handle.join().unwrap();
+}
+
+#[test]
+fn both_loopback_stacks_share_one_routing_table() {
+    // Nothing to test on a host without IPv6.
+    if TcpListener::bind("[::1]:0").is_err() {
+        return;
+    }
+    let mut server = server();
+    server.bind_also("[::1]:0");
+    let handle = server.spawn().unwrap();
+    let addrs = handle.local_addrs().to_vec();
+    assert_eq!(addrs.len(), 2);
+    assert!(addrs[0].is_ipv4() && addrs[1].is_ipv6(), "{addrs:?}");
+    for addr in addrs {
+        assert!(get(addr).ends_with("\r\n\r\nhi"), "{addr}");
+    }
+    assert_eq!(handle.metrics().requests, 2);
+    handle.shutdown();
+    handle.join().unwrap();
+}
+
+#[cfg(unix)]
+#[test]
+fn unix_sockets_serve_and_are_removed_on_shutdown() {
+    use std::{fs, os::unix::fs::PermissionsExt, os::unix::net::UnixStream};
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("app.sock");
+    let mut server = Server::new_unix(&path, 2);
+    server.unix_socket_mode(0o660).get("/", |req| {
+        let peer = req.remote_addr().map_or("none".into(), |a| a.to_string());
+        Ok(Response::new(StatusCode::Ok).body(peer))
+    });
+    let handle = server.spawn().unwrap();
+    let mode = fs::metadata(&path).unwrap().permissions().mode();
+    assert_eq!(mode & 0o777, 0o660);
+
+    for _ in 0..2 {
+        let mut stream = UnixStream::connect(&path).unwrap();
+        stream
+            .write_all(b"GET / HTTP/1.1\r\nHost: x\r\nConnection: close\r\n\r\n")
+            .unwrap();
+        let mut out = String::new();
+        stream.read_to_string(&mut out).unwrap();
+        assert!(out.starts_with("HTTP/1.1 200 OK\r\n"), "{out}");
+        assert!(out.ends_with("\r\n\r\nnone"), "{out}");
+    }
+    assert_eq!(handle.metrics().requests, 2);
+
+    handle.shutdown();
+    handle.join().unwrap();
+    assert!(!path.exists(), "the socket file outlived the server");
+}
+
+#[test]
+fn handlers_see_the_peer_address() {
+    let mut server = Server::new("127.0.0.1:0", 2);
+    server.get("/", |req| {
+        let peer = req.remote_addr().map_or("none".into(), |a| a.to_string());
+        Ok(Response::new(StatusCode::Ok).body(peer))
+    });
+    let handle = server.spawn().unwrap();
+    let mut stream = TcpStream::connect(handle.local_addr().unwrap()).unwrap();
+    let client = stream.local_addr().unwrap();
+    stream
+        .write_all(b"GET / HTTP/1.1\r\nHost: x\r\nConnection: close\r\n\r\n")
+        .unwrap();
+    let mut out = String::new();
+    stream.read_to_string(&mut out).unwrap();
+    assert!(client.ip().is_loopback());
+    assert!(out.ends_with(&format!("\r\n\r\n{client}")), "{out}");
+
+    handle.shutdown();
+    handle.join().unwrap();
+}
+
+#[test]
+fn a_taken_port_is_a_bind_error() {
+    let handle = server().spawn().unwrap();
+    let taken = handle.local_addr().unwrap().to_string();
+    let mut second = Server::new(&taken, 2);
+    match second.bind() {
+        Err(ServerError::Bind { addr, source }) => {
+            assert_eq!(addr, taken);
+            assert_eq!(source.kind(), ErrorKind::AddrInUse);
+        }
+        other => panic!("expected a bind error, got {:?}", other.err()),
+    }
+    assert!(matches!(
+        Server::new(&taken, 2).spawn(),
+        Err(ServerError::Bind { .. })
+    ));
+
+    handle.shutdown();
+    handle.join().unwrap();
+}
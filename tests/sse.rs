@@ -0,0 +1,100 @@
+//! Server-Sent Events read off a real socket, checked byte for byte
+//! against the `text/event-stream` framing.
+
+use simple_social::server::Server;
+use std::{
+    io::{ErrorKind, Read, Write},
+    net::{Shutdown, SocketAddr, TcpStream},
+    sync::{mpsc, Mutex},
+    thread,
+    time::Duration,
+};
+
+const EVENTS: &str = "retry: 3000\n\n\
+                      event: post\ndata: first line\ndata: second line\n\n\
+                      id: 7\ndata: plain\n\n\
+                      data: \ndata: \n\n\
+                      : a comment\n\n";
+
+fn connect(addr: SocketAddr) -> TcpStream {
+    let mut stream = TcpStream::connect(addr).unwrap();
+    stream
+        .set_read_timeout(Some(Duration::from_secs(5)))
+        .unwrap();
+    stream
+        .write_all(b"GET /events HTTP/1.1\r\nHost: x\r\n\r\n")
+        .unwrap();
+    stream
+}
+
+/// Reads until `len` bytes have arrived.
+fn read(stream: &mut TcpStream, len: usize) -> String {
+    let mut out = vec![0; len];
+    stream.read_exact(&mut out).unwrap();
+    String::from_utf8(out).unwrap()
+}
+
+#[test]
+fn events_are_framed_on_the_wire() {
+    let (done, ended) = mpsc::channel();
+    let done = Mutex::new(done);
+    let mut server = Server::new("127.0.0.1:0", 2);
+    server.sse("/events", move |_, events| {
+        events.retry(Duration::from_secs(3))?;
+        events.send(Some("post"), "first line\nsecond line")?;
+        events.send_with_id("7", None, "plain")?;
+        events.send(None, "\r\n")?;
+        events.comment("a comment")?;
+        let refused = events.send(Some("two\nlines"), "x").unwrap_err();
+        assert_eq!(refused.kind(), ErrorKind::InvalidInput);
+        events.keep_alive(Duration::from_millis(20));
+        while events.is_open() {
+            thread::sleep(Duration::from_millis(5));
+        }
+        done.lock().unwrap().send(()).unwrap();
+        Ok(())
+    });
+    let handle = server.spawn().unwrap();
+    let mut stream = connect(handle.local_addr().unwrap());
+
+    let head = "HTTP/1.1 200 OK\r\nContent-Type: text/event-stream\r\nCache-Control: no-cache\r\n\
+                X-Accel-Buffering: no\r\nConnection: close\r\n\r\n";
+    assert_eq!(read(&mut stream, head.len()), head);
+    assert_eq!(read(&mut stream, EVENTS.len()), EVENTS);
+    // Idle, the stream gets keep-alive comments.
+    let keep_alive = ": keep-alive\n\n";
+    assert_eq!(
+        read(&mut stream, keep_alive.len() * 2),
+        keep_alive.repeat(2)
+    );
+
+    // Hanging up shows up as `is_open` turning false at the next write.
+    stream.shutdown(Shutdown::Both).unwrap();
+    drop(stream);
+    ended.recv_timeout(Duration::from_secs(5)).unwrap();
+    handle.shutdown();
+    handle.join().unwrap();
+}
+
+#[test]
+fn the_stream_ends_when_the_handler_returns() {
+    let mut server = Server::new("127.0.0.1:0", 2);
+    server.sse("/events", |_, events| {
+        for n in 1..=3 {
+            events.send(Some("tick"), &n.to_string())?;
+        }
+        Ok(())
+    });
+    let handle = server.spawn().unwrap();
+    let mut stream = connect(handle.local_addr().unwrap());
+    let mut out = String::new();
+    stream.read_to_string(&mut out).unwrap();
+    handle.shutdown();
+    handle.join().unwrap();
+
+    let (_, body) = out.split_once("\r\n\r\n").unwrap();
+    assert_eq!(
+        body,
+        "event: tick\ndata: 1\n\nevent: tick\ndata: 2\n\nevent: tick\ndata: 3\n\n"
+    );
+}
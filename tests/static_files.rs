@@ -0,0 +1,561 @@
+use simple_social::{
+    file_cache::FileCache,
+    response::{Response, StatusCode},
+    server::{Method, RequestHandler, Server},
+    static_files::{static_dir, StaticDir},
+    testing::{TestClient, TestResponse},
+};
+use std::{
+    fs,
+    io::{Read, Write},
+    net::TcpStream,
+    path::Path,
+    sync::Arc,
+    time::{Duration, SystemTime},
+};
+use tempfile::TempDir;
+
+fn tree() -> TempDir {
+    let dir = TempDir::new().unwrap();
+    for name in ["a b.txt", "<script>&.txt", "ünïcode.txt", ".hidden"] {
+        fs::write(dir.path().join(name), "x").unwrap();
+    }
+    fs::create_dir(dir.path().join("sub")).unwrap();
+    fs::write(dir.path().join("sub/inner.txt"), "inner").unwrap();
+    dir
+}
+
+fn client(root: &Path, listing: bool) -> TestClient {
+    let mut server = Server::new("127.0.0.1:0", 2);
+    server.mount_static("/files", static_dir(root).with_listing(listing));
+    TestClient::start(server).unwrap()
+}
+
+fn listing(res: &TestResponse) -> String {
+    assert_eq!(res.status, 200);
+    assert!(res.header("Content-Type").unwrap().starts_with("text/html"));
+    res.text()
+}
+
+#[test]
+fn listing_escapes_names_and_encodes_hrefs() {
+    let dir = tree();
+    let client = client(dir.path(), true);
+    let body = listing(&client.get("/files/").unwrap());
+
+    assert!(body.contains("<a href=\"/files/a%20b.txt\">a b.txt</a>"));
+    assert!(body.contains("<a href=\"/files/%3Cscript%3E%26.txt\">&lt;script&gt;&amp;.txt</a>"));
+    assert!(body.contains("<a href=\"/files/%C3%BCn%C3%AFcode.txt\">ünïcode.txt</a>"));
+    assert!(body.contains("<a href=\"/files/sub/\">sub/</a>"));
+    assert!(!body.contains("<script>"));
+}
+
+#[test]
+fn listing_leaves_out_hidden_files() {
+    let dir = tree();
+    let body = listing(&client(dir.path(), true).get("/files/").unwrap());
+    assert!(!body.contains(".hidden"));
+
+    let mut server = Server::new("127.0.0.1:0", 2);
+    let mount = static_dir(dir.path()).with_listing(true).with_hidden(true);
+    server.mount_static("/files", mount);
+    let body = listing(&TestClient::start(server).unwrap().get("/files/").unwrap());
+    assert!(body.contains(".hidden"));
+}
+
+#[test]
+fn listing_links_to_the_parent_below_the_root() {
+    let dir = tree();
+    let client = client(dir.path(), true);
+
+    let root = listing(&client.get("/files/").unwrap());
+    assert!(!root.contains("../"));
+    let sub = listing(&client.get("/files/sub/").unwrap());
+    assert!(sub.contains("<a href=\"/files/\">../</a>"));
+    assert!(sub.contains("<a href=\"/files/sub/inner.txt\">inner.txt</a>"));
+}
+
+#[test]
+fn listing_stays_inside_the_root() {
+    let outer = TempDir::new().unwrap();
+    fs::write(outer.path().join("secret.txt"), "secret").unwrap();
+    let root = outer.path().join("public");
+    fs::create_dir(&root).unwrap();
+    let client = client(&root, true);
+
+    for path in [
+        "/files/../",
+        "/files/%2e%2e/",
+        "/files/sub/..%2f",
+        "/files/%2e%2e/secret.txt",
+    ] {
+        let res = client.get(path).unwrap();
+        assert_eq!(res.status, 404, "{path}");
+        assert!(!res.text().contains("secret"), "{path}");
+    }
+}
+
+#[test]
+fn directories_404_without_listing() {
+    let dir = tree();
+    let client = client(dir.path(), false);
+    assert_eq!(client.get("/files/").unwrap().status, 404);
+    assert_eq!(client.get("/files/sub/").unwrap().status, 404);
+    assert_eq!(client.get("/files/sub/inner.txt").unwrap().text(), "inner");
+}
+
+#[test]
+fn directories_without_a_slash_redirect_to_one() {
+    let dir = tree();
+    let res = client(dir.path(), true).get("/files/sub").unwrap();
+    assert_eq!(res.status, 301);
+    assert_eq!(res.header("Location"), Some("/files/sub/"));
+}
+
+fn cached_tree() -> TempDir {
+    let dir = TempDir::new().unwrap();
+    for dir_name in ["assets", "assets2", "assets/js"] {
+        fs::create_dir_all(dir.path().join(dir_name)).unwrap();
+    }
+    for name in [
+        "index.html",
+        "site.css",
+        "assets/app.css",
+        "assets2/app.css",
+        "assets/js/app.js",
+    ] {
+        fs::write(dir.path().join(name), "x").unwrap();
+    }
+    dir
+}
+
+fn cache_control(client: &TestClient, path: &str) -> Option<String> {
+    let res = client.get(path).unwrap();
+    assert_eq!(res.status, 200, "{path}");
+    res.header("Cache-Control").map(String::from)
+}
+
+#[test]
+fn cache_control_by_extension() {
+    let dir = cached_tree();
+    let mut server = Server::new("127.0.0.1:0", 2);
+    let mount = static_dir(dir.path())
+        .cache_control("public, max-age=3600")
+        .cache_control_for_ext("html", "no-cache")
+        .cache_control_for_ext(".CSS", "public, max-age=31536000, immutable");
+    server.mount_static("/", mount);
+    let client = TestClient::start(server).unwrap();
+
+    assert_eq!(
+        cache_control(&client, "/index.html").as_deref(),
+        Some("no-cache")
+    );
+    assert_eq!(
+        cache_control(&client, "/site.css").as_deref(),
+        Some("public, max-age=31536000, immutable")
+    );
+    assert_eq!(
+        cache_control(&client, "/assets/js/app.js").as_deref(),
+        Some("public, max-age=3600")
+    );
+}
+
+#[test]
+fn cache_control_prefixes_match_whole_segments() {
+    let dir = cached_tree();
+    let mut server = Server::new("127.0.0.1:0", 2);
+    let mount = static_dir(dir.path())
+        .cache_control_for_prefix("/assets/", "immutable")
+        .cache_control_for_prefix("assets/js", "no-store");
+    server.mount_static("/", mount);
+    let client = TestClient::start(server).unwrap();
+
+    assert_eq!(
+        cache_control(&client, "/assets/app.css").as_deref(),
+        Some("immutable")
+    );
+    assert_eq!(
+        cache_control(&client, "/assets/js/app.js").as_deref(),
+        Some("no-store")
+    );
+    assert_eq!(cache_control(&client, "/assets2/app.css"), None);
+    assert_eq!(cache_control(&client, "/site.css"), None);
+}
+
+fn cached(root: &Path, cache: &Arc<FileCache>) -> TestClient {
+    let mut server = Server::new("127.0.0.1:0", 2);
+    server.mount_static("/", static_dir(root).with_cache(Arc::clone(cache)));
+    TestClient::start(server).unwrap()
+}
+
+#[test]
+fn cache_serves_repeat_requests_from_memory() {
+    let dir = TempDir::new().unwrap();
+    fs::write(dir.path().join("index.html"), "<h1>hi</h1>").unwrap();
+    let cache = Arc::new(FileCache::new(1024, 1024));
+    let client = cached(dir.path(), &cache);
+
+    assert_eq!(client.get("/index.html").unwrap().text(), "<h1>hi</h1>");
+    assert_eq!(cache.hits(), 0);
+    assert_eq!(cache.size(), 11);
+    let again = client.get("/index.html").unwrap();
+    assert_eq!(again.text(), "<h1>hi</h1>");
+    assert!(again.header("ETag").is_some());
+    assert_eq!(cache.hits(), 1);
+}
+
+#[test]
+fn cache_notices_edits() {
+    let dir = TempDir::new().unwrap();
+    let path = dir.path().join("site.css");
+    fs::write(&path, "a {}").unwrap();
+    let cache = Arc::new(FileCache::new(1024, 1024));
+    let client = cached(dir.path(), &cache);
+
+    assert_eq!(client.get("/site.css").unwrap().text(), "a {}");
+    fs::write(&path, "a { color: red }").unwrap();
+    assert_eq!(client.get("/site.css").unwrap().text(), "a { color: red }");
+    assert_eq!(cache.hits(), 0);
+    assert_eq!(cache.size(), 16);
+    assert_eq!(client.get("/site.css").unwrap().text(), "a { color: red }");
+    assert_eq!(cache.hits(), 1);
+}
+
+#[test]
+fn cache_evicts_the_least_recently_used_past_its_budget() {
+    let dir = TempDir::new().unwrap();
+    for name in ["a.txt", "b.txt"] {
+        fs::write(dir.path().join(name), "123456").unwrap();
+    }
+    let cache = Arc::new(FileCache::new(10, 10));
+    let client = cached(dir.path(), &cache);
+
+    client.get("/a.txt").unwrap();
+    client.get("/b.txt").unwrap();
+    assert_eq!(cache.size(), 6);
+    client.get("/b.txt").unwrap();
+    assert_eq!(cache.hits(), 1);
+    client.get("/a.txt").unwrap();
+    assert_eq!(cache.hits(), 1, "a.txt was evicted to make room for b.txt");
+}
+
+#[test]
+fn cache_leaves_files_over_the_cap_on_disk() {
+    let dir = TempDir::new().unwrap();
+    let big = "x".repeat(4096);
+    fs::write(dir.path().join("big.txt"), &big).unwrap();
+    let cache = Arc::new(FileCache::new(1 << 20, 1024));
+    let client = cached(dir.path(), &cache);
+
+    for _ in 0..2 {
+        let res = client.get("/big.txt").unwrap();
+        assert_eq!(res.text(), big);
+        assert_eq!(res.header("Content-Length"), Some("4096"));
+    }
+    assert_eq!((cache.size(), cache.hits()), (0, 0));
+}
+
+#[test]
+fn head_reports_the_size_of_a_large_file_without_sending_it() {
+    let dir = TempDir::new().unwrap();
+    let size = 8 << 30;
+    fs::File::create(dir.path().join("big.bin"))
+        .unwrap()
+        .set_len(size)
+        .unwrap();
+    let client = client(dir.path(), false);
+
+    let mut stream = TcpStream::connect(client.addr()).unwrap();
+    stream
+        .write_all(b"HEAD /files/big.bin HTTP/1.1\r\nHost: x\r\nConnection: close\r\n\r\n")
+        .unwrap();
+    let mut out = String::new();
+    stream.read_to_string(&mut out).unwrap();
+    assert!(out.starts_with("HTTP/1.1 200 OK\r\n"), "{out}");
+    assert!(
+        out.contains(&format!("\r\nContent-Length: {size}\r\n")),
+        "{out}"
+    );
+    assert!(out.contains("\r\nETag: W/\""), "{out}");
+    assert!(out.contains("\r\nLast-Modified: "), "{out}");
+    assert!(out.ends_with("\r\n\r\n"), "{out}");
+}
+
+fn mounted(mount: StaticDir) -> TestClient {
+    let mut server = Server::new("127.0.0.1:0", 2);
+    server.mount_static("/", mount);
+    TestClient::start(server).unwrap()
+}
+
+#[test]
+fn a_directory_without_its_index_is_a_404_without_listing() {
+    let dir = tree();
+    let client = mounted(static_dir(dir.path()));
+    assert_eq!(client.get("/").unwrap().status, 404);
+    assert_eq!(client.get("/sub/").unwrap().status, 404);
+}
+
+#[test]
+fn an_alternate_index_file_is_served() {
+    let dir = tree();
+    fs::write(dir.path().join("sub/default.htm"), "<p>default</p>").unwrap();
+    fs::write(dir.path().join("sub/index.html"), "<p>index</p>").unwrap();
+    let client = mounted(static_dir(dir.path()).index_file("default.htm"));
+    let res = client.get("/sub/").unwrap();
+    assert_eq!((res.status, res.text().as_str()), (200, "<p>default</p>"));
+    assert_eq!(client.get("/").unwrap().status, 404);
+}
+
+#[test]
+fn the_index_wins_over_a_listing() {
+    let dir = tree();
+    fs::write(dir.path().join("sub/index.html"), "<p>index</p>").unwrap();
+    let client = mounted(static_dir(dir.path()).with_listing(true));
+    assert_eq!(client.get("/sub/").unwrap().text(), "<p>index</p>");
+    assert!(listing(&client.get("/").unwrap()).contains("sub/"));
+}
+
+fn spa() -> (TempDir, TestClient) {
+    let dir = TempDir::new().unwrap();
+    fs::write(dir.path().join("index.html"), "<div id=app></div>").unwrap();
+    fs::write(dir.path().join("app.js"), "boot()").unwrap();
+    let mut server = Server::new("127.0.0.1:0", 2);
+    server
+        .post("/api/posts", |_| Ok(Response::new(StatusCode::Created)))
+        .mount_static("/", static_dir(dir.path()).spa_fallback(true));
+    (dir, TestClient::start(server).unwrap())
+}
+
+fn browse(client: &TestClient, path: &str) -> TestResponse {
+    let accept = "text/html,application/xhtml+xml,*/*;q=0.8";
+    client
+        .request(Method::Get, path, &[("Accept", accept)], b"")
+        .unwrap()
+}
+
+#[test]
+fn deep_links_fall_back_to_the_index() {
+    let (_dir, client) = spa();
+    for path in ["/posts/42", "/settings/profile/edit", "/"] {
+        let res = browse(&client, path);
+        assert_eq!(res.status, 200, "{path}");
+        assert_eq!(res.text(), "<div id=app></div>", "{path}");
+    }
+    assert_eq!(browse(&client, "/app.js").text(), "boot()");
+}
+
+#[test]
+fn missing_assets_are_still_a_404() {
+    let (_dir, client) = spa();
+    assert_eq!(browse(&client, "/bundle.js").status, 404);
+    assert_eq!(browse(&client, "/static/bundle.js").status, 404);
+    // Scripts fetching data don't get an HTML page back either.
+    let res = client
+        .request(
+            Method::Get,
+            "/posts/42",
+            &[("Accept", "application/json")],
+            b"",
+        )
+        .unwrap();
+    assert_eq!(res.status, 404);
+}
+
+#[test]
+fn the_fallback_leaves_other_methods_alone() {
+    let (_dir, client) = spa();
+    let res = client
+        .post("/api/posts", b"{}", "application/json")
+        .unwrap();
+    assert_eq!(res.status, 201);
+    let res = client.post("/posts/42", b"{}", "application/json").unwrap();
+    assert_eq!(res.status, 404);
+}
+
+#[test]
+fn touching_a_file_changes_its_etag() {
+    let dir = TempDir::new().unwrap();
+    let path = dir.path().join("app.css");
+    fs::write(&path, "a {}").unwrap();
+    let cache = Arc::new(FileCache::new(1024, 1024));
+    let touch = |secs| {
+        let when = SystemTime::UNIX_EPOCH + Duration::from_secs(secs);
+        fs::File::options()
+            .write(true)
+            .open(&path)
+            .unwrap()
+            .set_modified(when)
+            .unwrap();
+    };
+    for client in [mounted(static_dir(dir.path())), cached(dir.path(), &cache)] {
+        touch(1_700_000_000);
+        let etag = client
+            .get("/app.css")
+            .unwrap()
+            .header("ETag")
+            .unwrap()
+            .to_owned();
+        let revalidate = |etag: &str| {
+            client
+                .request(Method::Get, "/app.css", &[("If-None-Match", etag)], b"")
+                .unwrap()
+        };
+        assert_eq!(revalidate(&etag).status, 304);
+
+        touch(1_700_000_060);
+        let res = revalidate(&etag);
+        assert_eq!((res.status, res.text().as_str()), (200, "a {}"));
+        let fresh = res.header("ETag").unwrap();
+        assert_ne!(fresh, etag);
+        assert_eq!(revalidate(fresh).status, 304);
+    }
+}
+
+#[test]
+fn precompressed_siblings_by_accept_encoding() {
+    let dir = TempDir::new().unwrap();
+    for (name, body) in [
+        ("app.js", "plain js"),
+        ("app.js.gz", "gzip js"),
+        ("app.js.br", "brotli js"),
+        ("site.css", "plain css"),
+        ("site.css.gz", "gzip css"),
+    ] {
+        fs::write(dir.path().join(name), body).unwrap();
+    }
+    let client = mounted(static_dir(dir.path()).with_precompressed(true));
+    let cases = [
+        ("/app.js", None, "plain js", None),
+        ("/app.js", Some("gzip"), "gzip js", Some("gzip")),
+        ("/app.js", Some("br"), "brotli js", Some("br")),
+        (
+            "/app.js",
+            Some("gzip, deflate, br"),
+            "brotli js",
+            Some("br"),
+        ),
+        ("/app.js", Some("br;q=0, gzip"), "gzip js", Some("gzip")),
+        ("/app.js", Some("*"), "brotli js", Some("br")),
+        ("/app.js", Some("identity"), "plain js", None),
+        ("/site.css", Some("br"), "plain css", None),
+        ("/site.css", Some("gzip, br"), "gzip css", Some("gzip")),
+        ("/site.css", Some("gzip;q=0"), "plain css", None),
+    ];
+    for (path, accept, body, encoding) in cases {
+        let headers: &[(&str, &str)] = match accept {
+            Some(accept) => &[("Accept-Encoding", accept)],
+            None => &[],
+        };
+        let res = client.request(Method::Get, path, headers, b"").unwrap();
+        let case = format!("{path} with {accept:?}");
+        assert_eq!(res.text(), body, "{case}");
+        assert_eq!(res.header("Content-Encoding"), encoding, "{case}");
+        assert_eq!(res.header("Vary"), Some("Accept-Encoding"), "{case}");
+        let content_type = res.header("Content-Type").unwrap();
+        let expected = match path {
+            "/app.js" => "application/javascript",
+            _ => "text/css",
+        };
+        assert!(content_type.starts_with(expected), "{case}: {content_type}");
+    }
+}
+
+fn site() -> TempDir {
+    let dir = TempDir::new().unwrap();
+    for sub in [".git", "secrets", "public"] {
+        fs::create_dir(dir.path().join(sub)).unwrap();
+    }
+    for (name, body) in [
+        (".env", "KEY=1"),
+        (".git/config", "[core]"),
+        ("secrets/key.pem", "pem"),
+        ("public/app.map", "map"),
+        ("public/app.js", "js"),
+        ("notes.bak", "bak"),
+    ] {
+        fs::write(dir.path().join(name), body).unwrap();
+    }
+    dir
+}
+
+#[test]
+fn dotfiles_are_left_out_by_default() {
+    let dir = site();
+    let client = mounted(static_dir(dir.path()));
+    for path in [
+        "/.env",
+        "/.git/config",
+        "/.git/",
+        "/%2egit/config",
+        "/%2Eenv",
+    ] {
+        assert_eq!(client.get(path).unwrap().status, 404, "{path}");
+    }
+    assert_eq!(client.get("/public/app.js").unwrap().text(), "js");
+
+    let client = mounted(static_dir(dir.path()).with_hidden(true));
+    assert_eq!(client.get("/%2egit/config").unwrap().text(), "[core]");
+}
+
+#[test]
+fn excluded_globs_are_a_404() {
+    let dir = site();
+    let mount = static_dir(dir.path())
+        .exclude("*.bak")
+        .exclude("*.map")
+        .exclude("/secrets/*");
+    let client = mounted(mount.with_listing(true));
+    for path in [
+        "/notes.bak",
+        "/public/app.map",
+        "/secrets/key.pem",
+        "/%6Eotes.bak",
+    ] {
+        assert_eq!(client.get(path).unwrap().status, 404, "{path}");
+    }
+    assert_eq!(client.get("/public/app.js").unwrap().text(), "js");
+    let body = listing(&client.get("/public/").unwrap());
+    assert!(body.contains("app.js") && !body.contains("app.map"));
+}
+
+#[test]
+fn text_files_carry_a_charset_and_binary_ones_never_do() {
+    let dir = TempDir::new().unwrap();
+    let names = [
+        "a.html", "a.css", "a.js", "a.json", "a.txt", "a.svg", "a.xml", "a.png", "a.woff2",
+    ];
+    for name in names {
+        fs::write(dir.path().join(name), "x").unwrap();
+    }
+    let content_types = |charset: Option<&str>| {
+        let mut server = Server::new("127.0.0.1:0", 2);
+        server
+            .default_charset(charset)
+            .mount_static("/", static_dir(dir.path()));
+        let client = TestClient::start(server).unwrap();
+        names.map(|name| {
+            let res = client.get(&format!("/{name}")).unwrap();
+            res.header("Content-Type").unwrap().to_owned()
+        })
+    };
+    assert_eq!(
+        content_types(Some("utf-8")),
+        [
+            "text/html; charset=utf-8",
+            "text/css; charset=utf-8",
+            "application/javascript; charset=utf-8",
+            "application/json; charset=utf-8",
+            "text/plain; charset=utf-8",
+            "image/svg+xml; charset=utf-8",
+            "application/xml; charset=utf-8",
+            "image/png",
+            "font/woff2",
+        ]
+    );
+    let latin1 = content_types(Some("iso-8859-1"));
+    assert_eq!(latin1[0], "text/html; charset=iso-8859-1");
+    assert_eq!(latin1[7], "image/png");
+    let bare = content_types(None);
+    assert_eq!(bare[0], "text/html");
+    assert_eq!(bare[2], "application/javascript");
+}
@@ -0,0 +1,99 @@
+//! `MemStore` under writers on many threads, and its JSON snapshots.
+
+use simple_social::store::MemStore;
+use std::{collections::HashSet, thread};
+
+const THREADS: u64 = 8;
+const EACH: u64 = 500;
+
+#[test]
+fn concurrent_writers_lose_nothing() {
+    let store: MemStore<u64, (u64, u64)> = MemStore::new();
+    let counter: MemStore<&str, u64> = MemStore::new();
+    counter.insert("likes", 0);
+
+    let writers: Vec<_> = (0..THREADS)
+        .map(|thread| {
+            let (store, counter) = (store.clone(), counter.clone());
+            thread::spawn(move || {
+                for n in 0..EACH {
+                    let id = store.next_id();
+                    assert!(store.insert(id, (thread, n)).is_none());
+                    assert!(counter.update(&"likes", |likes| *likes += 1));
+                    // Every other write is taken back, while others read.
+                    if n % 2 == 1 {
+                        assert_eq!(store.remove(&id), Some((thread, n)));
+                    }
+                    assert!(store.len() as u64 <= THREADS * EACH);
+                }
+            })
+        })
+        .collect();
+    for writer in writers {
+        writer.join().unwrap();
+    }
+
+    assert_eq!(counter.get(&"likes"), Some(THREADS * EACH));
+    assert_eq!(store.len() as u64, THREADS * EACH / 2);
+    let ids: HashSet<u64> = store.entries().into_iter().map(|(id, _)| id).collect();
+    assert_eq!(ids.len() as u64, THREADS * EACH / 2);
+    assert!(ids.iter().all(|&id| (1..=THREADS * EACH).contains(&id)));
+    for thread in 0..THREADS {
+        let mut kept: Vec<u64> = store
+            .list(|&(t, _)| t == thread)
+            .into_iter()
+            .map(|(_, n)| n)
+            .collect();
+        kept.sort_unstable();
+        assert_eq!(kept, (0..EACH).step_by(2).collect::<Vec<_>>());
+    }
+    assert_eq!(store.next_id(), THREADS * EACH + 1);
+}
+
+#[cfg(feature = "serde")]
+#[test]
+fn a_snapshot_round_trips() {
+    use serde::{Deserialize, Serialize};
+    use std::{fs, io::ErrorKind};
+
+    #[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+    struct Post {
+        title: String,
+        tags: Vec<String>,
+        likes: u32,
+    }
+
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("posts.json");
+    let store: MemStore<u64, Post> = MemStore::new();
+    for title in ["Hello", "Ünïcode \"quoted\"", ""] {
+        let id = store.next_id();
+        let post = Post {
+            title: String::from(title),
+            tags: vec![String::from("rust")],
+            likes: id as u32 * 10,
+        };
+        store.insert(id, post);
+    }
+    store.remove(&2);
+    store.save(&path).unwrap();
+    assert_eq!(fs::read_dir(dir.path()).unwrap().count(), 1);
+
+    let loaded: MemStore<u64, Post> = MemStore::load(&path).unwrap();
+    let mut before = store.entries();
+    let mut after = loaded.entries();
+    before.sort_by_key(|(id, _)| *id);
+    after.sort_by_key(|(id, _)| *id);
+    assert_eq!(after, before);
+    assert_eq!(after.len(), 2);
+    // Ids carry on where they left off, so a removed one isn't reused.
+    assert_eq!(loaded.next_id(), 4);
+
+    fs::write(&path, "{\"entries\": []}").unwrap();
+    let err = MemStore::<u64, Post>::load(&path).err().unwrap();
+    assert_eq!(err.kind(), ErrorKind::InvalidData);
+    let err = MemStore::<u64, Post>::load(dir.path().join("missing.json"))
+        .err()
+        .unwrap();
+    assert_eq!(err.kind(), ErrorKind::NotFound);
+}
@@ -0,0 +1,115 @@
+//! `Template` parsing and rendering: escaping, raw blocks, missing
+//! variables, and one parsed template serving many renders.
+
+use simple_social::{
+    error::TemplateError,
+    response::Response,
+    server::{RequestHandler, Server},
+    templates::Template,
+    testing::TestClient,
+};
+use std::{collections::HashMap, fs, sync::Arc};
+
+fn vars<'a>(pairs: &[(&'a str, &str)]) -> HashMap<&'a str, String> {
+    pairs
+        .iter()
+        .map(|&(name, value)| (name, String::from(value)))
+        .collect()
+}
+
+#[test]
+fn values_are_html_escaped() {
+    let template: Template = "<p title=\"{{ title }}\">{{body}}</p>".parse().unwrap();
+    let out = template
+        .render(&vars(&[
+            ("title", "\"><script>alert('x')</script>"),
+            ("body", "<script src=//evil.example></script> & more"),
+        ]))
+        .unwrap();
+    assert_eq!(
+        out,
+        "<p title=\"&quot;&gt;&lt;script&gt;alert(&#39;x&#39;)&lt;/script&gt;\">\
+         &lt;script src=//evil.example&gt;&lt;/script&gt; &amp; more</p>"
+    );
+    assert!(!out.contains("<script"));
+}
+
+#[test]
+fn triple_braces_insert_trusted_html_as_is() {
+    let template: Template = "<main>{{{ content }}}</main><p>{{ content }}</p>"
+        .parse()
+        .unwrap();
+    let out = template
+        .render(&vars(&[("content", "<em>hi</em> &amp;")]))
+        .unwrap();
+    assert_eq!(
+        out,
+        "<main><em>hi</em> &amp;</main><p>&lt;em&gt;hi&lt;/em&gt; &amp;amp;</p>"
+    );
+}
+
+#[test]
+fn a_missing_variable_is_an_error() {
+    let template: Template = "Hello {{ name }}, you have {{ count }} new posts"
+        .parse()
+        .unwrap();
+    let err = template.render(&vars(&[("name", "Ada")])).unwrap_err();
+    assert!(matches!(&err, TemplateError::MissingVariable(name) if name == "count"));
+    assert_eq!(err.to_string(), "no value for `count`");
+    // Extra values are fine.
+    let out = template
+        .render(&vars(&[("name", "Ada"), ("count", "3"), ("unused", "x")]))
+        .unwrap();
+    assert_eq!(out, "Hello Ada, you have 3 new posts");
+}
+
+#[test]
+fn malformed_tags_name_their_line() {
+    for (source, line, message) in [
+        ("one\ntwo {{ name", 2, "`{{` is never closed with `}}`"),
+        ("{{{ raw }}", 1, "`{{{` is never closed with `}}}`"),
+        (
+            "a\nb\n{{ two words }}",
+            3,
+            "\"two words\" is not a variable name",
+        ),
+        ("{{}}", 1, "\"\" is not a variable name"),
+    ] {
+        match source.parse::<Template>() {
+            Err(TemplateError::Syntax {
+                line: l,
+                message: m,
+            }) => {
+                assert_eq!((l, m.as_str()), (line, message), "{source:?}")
+            }
+            other => panic!("{source:?}: {other:?}"),
+        }
+    }
+}
+
+#[test]
+fn one_parsed_template_serves_every_request() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("post.html");
+    fs::write(&path, "<h1>{{ title }}</h1>").unwrap();
+    let template = Arc::new(Template::from_file(&path).unwrap());
+    // The file is read once; changing it afterwards changes nothing.
+    fs::write(&path, "edited").unwrap();
+
+    let mut server = Server::new("127.0.0.1:0", 4);
+    let shared = Arc::clone(&template);
+    server.get("/posts/:title", move |req| {
+        let title = req.param("title").unwrap_or_default();
+        Ok(Response::render(&shared, &vars(&[("title", title)]))?)
+    });
+    let client = TestClient::start(server).unwrap();
+    for title in ["first", "second", "a<b"] {
+        let res = client.get(&format!("/posts/{title}")).unwrap();
+        assert_eq!(res.status, 200);
+        assert_eq!(res.header("Content-Type"), Some("text/html"));
+        let expected = format!("<h1>{}</h1>", title.replace('<', "&lt;"));
+        assert_eq!(res.text(), expected);
+    }
+    drop(client);
+    assert_eq!(Arc::strong_count(&template), 1);
+}
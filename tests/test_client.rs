@@ -0,0 +1,78 @@
+use simple_social::{
+    response::{Response, StatusCode},
+    server::{RequestHandler, Server},
+    testing::TestClient,
+};
+use std::{collections::HashSet, net::TcpStream, sync::Arc, thread};
+
+fn numbered(n: usize) -> TestClient {
+    let mut server = Server::new("127.0.0.1:8080", 2);
+    server.get("/", move |_| {
+        Ok(Response::new(StatusCode::Ok).body(n.to_string()))
+    });
+    server.post("/echo", |req| {
+        Ok(Response::new(StatusCode::Created)
+            .header(
+                "Content-Type",
+                req.header("Content-Type").unwrap_or_default(),
+            )
+            .body(req.body().to_vec()))
+    });
+    TestClient::start(server).unwrap()
+}
+
+#[test]
+fn parallel_servers_get_ports_of_their_own() {
+    let servers: Vec<Arc<TestClient>> = thread::scope(|s| {
+        let started: Vec<_> = (0..8).map(|n| s.spawn(move || numbered(n))).collect();
+        started
+            .into_iter()
+            .map(|t| Arc::new(t.join().unwrap()))
+            .collect()
+    });
+    let ports: HashSet<u16> = servers.iter().map(|c| c.addr().port()).collect();
+    assert_eq!(ports.len(), servers.len());
+    assert!(!ports.contains(&8080), "the configured address is replaced");
+
+    thread::scope(|s| {
+        for (n, client) in servers.iter().enumerate() {
+            for _ in 0..4 {
+                let client = Arc::clone(client);
+                s.spawn(move || {
+                    for _ in 0..10 {
+                        let res = client.get("/").unwrap();
+                        assert_eq!((res.status, res.text()), (200, n.to_string()));
+                    }
+                });
+            }
+        }
+    });
+}
+
+#[test]
+fn requests_carry_bodies_and_headers_both_ways() {
+    let client = numbered(0);
+    let res = client
+        .post("/echo", b"{\"a\":1}", "application/json")
+        .unwrap();
+    assert_eq!(res.status, 201);
+    assert_eq!(res.header("content-type"), Some("application/json"));
+    assert_eq!(res.body, b"{\"a\":1}");
+
+    let head = client.head("/").unwrap();
+    assert_eq!(
+        (head.status, head.header("Content-Length")),
+        (200, Some("1"))
+    );
+    assert!(head.body.is_empty());
+    assert_eq!(client.delete("/").unwrap().status, 405);
+}
+
+#[test]
+fn dropping_the_client_stops_the_server() {
+    let client = numbered(0);
+    let addr = client.addr();
+    assert_eq!(client.get("/").unwrap().status, 200);
+    drop(client);
+    assert!(TcpStream::connect(addr).is_err());
+}
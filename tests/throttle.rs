@@ -0,0 +1,139 @@
+use simple_social::{
+    response::{Response, StatusCode},
+    server::{RequestHandler, Server},
+    testing::TestClient,
+    throttle::{BackoffLimits, Decision, LoginThrottle},
+};
+use std::{net::IpAddr, time::Duration};
+
+fn limits(free_attempts: u32) -> BackoffLimits {
+    BackoffLimits {
+        free_attempts,
+        base_delay: Duration::from_secs(60),
+        max_delay: Duration::from_secs(600),
+    }
+}
+
+fn ip(s: &str) -> Option<IpAddr> {
+    Some(s.parse().unwrap())
+}
+
+fn retry_after(decision: Decision) -> Duration {
+    match decision {
+        Decision::Deny { retry_after } => retry_after,
+        Decision::Allow => panic!("allowed"),
+    }
+}
+
+#[test]
+fn many_ips_against_one_username() {
+    let throttle = LoginThrottle::new()
+        .username_limits(limits(3))
+        .ip_limits(limits(100));
+    for i in 0..4 {
+        let from = ip(&format!("198.51.100.{i}"));
+        assert!(throttle.check(from, "alice").is_allowed(), "attempt {i}");
+        throttle.record_failure(from, "alice");
+    }
+
+    let wait = retry_after(throttle.check(ip("192.0.2.99"), " Alice "));
+    assert!(wait > Duration::from_secs(59) && wait <= Duration::from_secs(60));
+    assert!(throttle.check(ip("198.51.100.0"), "bob").is_allowed());
+
+    // Each failure over doubles the wait.
+    throttle.record_failure(ip("192.0.2.99"), "alice");
+    assert!(retry_after(throttle.check(None, "alice")) > Duration::from_secs(119));
+}
+
+#[test]
+fn one_ip_against_many_usernames() {
+    let throttle = LoginThrottle::new()
+        .username_limits(limits(100))
+        .ip_limits(limits(5));
+    for i in 0..6 {
+        throttle.record_failure(ip("203.0.113.7"), &format!("user{i}"));
+    }
+    assert!(!throttle
+        .check(ip("203.0.113.7"), "someone-new")
+        .is_allowed());
+    assert!(throttle.check(ip("203.0.113.8"), "user0").is_allowed());
+
+    // An IPv6 host is counted by its /64.
+    for i in 0..6 {
+        throttle.record_failure(ip(&format!("2001:db8::{i}")), &format!("v6user{i}"));
+    }
+    assert!(!throttle.check(ip("2001:db8::ffff"), "other").is_allowed());
+    assert!(throttle.check(ip("2001:db8:0:1::1"), "other").is_allowed());
+}
+
+#[test]
+fn a_success_clears_the_username_but_not_the_ip() {
+    let throttle = LoginThrottle::new()
+        .username_limits(limits(1))
+        .ip_limits(limits(2));
+    for _ in 0..3 {
+        throttle.record_failure(ip("192.0.2.1"), "carol");
+    }
+    throttle.record_success(ip("192.0.2.1"), "carol");
+    assert!(throttle.check(ip("192.0.2.2"), "carol").is_allowed());
+    assert!(!throttle.check(ip("192.0.2.1"), "dave").is_allowed());
+}
+
+#[test]
+fn a_flood_of_new_usernames_stays_within_the_cap() {
+    let throttle = LoginThrottle::new()
+        .username_limits(limits(0))
+        .max_entries(16);
+    throttle.record_failure(None, "victim");
+    assert!(!throttle.check(None, "victim").is_allowed());
+    for i in 0..1000 {
+        throttle.record_failure(None, &format!("random{i}"));
+    }
+    // The oldest go first, so the flood pushed out the victim but not the
+    // most recent names.
+    assert!(throttle.check(None, "victim").is_allowed());
+    assert!(!throttle.check(None, "random999").is_allowed());
+}
+
+#[test]
+fn unlimited_free_attempts_never_lock() {
+    let throttle = LoginThrottle::new().username_limits(limits(u32::MAX));
+    for _ in 0..10 {
+        throttle.record_failure(None, "eve");
+    }
+    assert!(throttle.check(None, "eve").is_allowed());
+}
+
+#[test]
+fn protect_turns_repeated_failures_into_429s() {
+    let throttle = LoginThrottle::new().username_limits(limits(2));
+    let mut server = Server::new("127.0.0.1:0", 2);
+    server.post(
+        "/login",
+        throttle.protect("username", |req| {
+            let form = req.form()?;
+            Ok(match form.get("password") {
+                Some("right") => Response::new(StatusCode::SeeOther).header("Location", "/"),
+                _ => Response::new(StatusCode::Unauthorized),
+            })
+        }),
+    );
+    let client = TestClient::start(server).unwrap();
+    let login = |password: &str| {
+        let body = format!("username=frank&password={password}");
+        client
+            .post(
+                "/login",
+                body.as_bytes(),
+                "application/x-www-form-urlencoded",
+            )
+            .unwrap()
+    };
+
+    for _ in 0..3 {
+        assert_eq!(login("wrong").status, 401);
+    }
+    let locked = login("right");
+    assert_eq!(locked.status, 429);
+    assert_eq!(locked.header("Retry-After"), Some("60"));
+}
@@ -0,0 +1,87 @@
+//! The per-request phase breakdown passed to `Server::on_timing`, and the
+//! queue wait it shares with the metrics.
+
+use simple_social::{
+    metrics::PhaseTimings,
+    response::{Response, StatusCode},
+    server::{RequestHandler, Server},
+};
+use std::{
+    io::{Read, Write},
+    net::{SocketAddr, TcpStream},
+    sync::{Arc, Mutex},
+    thread,
+    time::{Duration, Instant},
+};
+
+const SLEEP: Duration = Duration::from_millis(50);
+
+/// A two-worker server whose `/sleep` handler takes `SLEEP`, collecting
+/// every request's timings.
+fn server() -> (Server, Arc<Mutex<Vec<PhaseTimings>>>) {
+    let timings = Arc::new(Mutex::new(Vec::new()));
+    let seen = Arc::clone(&timings);
+    let mut server = Server::new("127.0.0.1:0", 2);
+    server
+        .on_timing(move |_, t| seen.lock().unwrap().push(*t))
+        .get("/sleep", |_| {
+            thread::sleep(SLEEP);
+            Ok(Response::new(StatusCode::Ok).body("slept"))
+        });
+    (server, timings)
+}
+
+fn get(addr: SocketAddr) -> Duration {
+    let started = Instant::now();
+    let mut stream = TcpStream::connect(addr).unwrap();
+    stream
+        .write_all(b"GET /sleep HTTP/1.1\r\nHost: x\r\nConnection: close\r\n\r\n")
+        .unwrap();
+    let mut out = String::new();
+    stream.read_to_string(&mut out).unwrap();
+    assert!(out.ends_with("\r\n\r\nslept"), "{out}");
+    started.elapsed()
+}
+
+#[test]
+fn the_phases_add_up_to_the_time_the_client_waited() {
+    let (server, timings) = server();
+    let handle = server.spawn().unwrap();
+    let waited = get(handle.local_addr().unwrap());
+    handle.shutdown();
+    handle.join().unwrap();
+
+    let timings = timings.lock().unwrap();
+    assert_eq!(timings.len(), 1);
+    let t = timings[0];
+    assert!(t.handle >= SLEEP, "{t:?}");
+    assert!(t.total() <= waited, "{t:?} over {waited:?}");
+    // Everything but the handler's sleep is small next to it.
+    let rest = t.queue + t.parse + t.route + t.write;
+    assert!(rest < SLEEP, "{t:?}");
+    assert!(waited - t.total() < SLEEP, "{t:?} against {waited:?}");
+}
+
+#[test]
+fn queue_wait_grows_when_the_pool_is_saturated() {
+    let (server, timings) = server();
+    let handle = server.spawn().unwrap();
+    let addr = handle.local_addr().unwrap();
+    let clients: Vec<_> = (0..6).map(|_| thread::spawn(move || get(addr))).collect();
+    for client in clients {
+        client.join().unwrap();
+    }
+    let metrics = handle.metrics();
+    handle.shutdown();
+    handle.join().unwrap();
+
+    // Six requests on two workers: the last pair waits for two rounds.
+    let mut queued: Vec<Duration> = timings.lock().unwrap().iter().map(|t| t.queue).collect();
+    queued.sort_unstable();
+    assert_eq!(queued.len(), 6);
+    assert!(queued[0] < SLEEP, "{queued:?}");
+    assert!(queued[5] >= SLEEP * 3 / 2, "{queued:?}");
+
+    assert_eq!(metrics.queue_wait.iter().map(|(_, n)| n).sum::<u64>(), 6);
+    assert!(metrics.queue_wait_total >= queued.iter().sum::<Duration>() / 2);
+}
@@ -0,0 +1,98 @@
+//! HTTPS with a self-signed certificate, spoken to by a rustls client
+//! that trusts only that certificate.
+
+#![cfg(feature = "tls")]
+
+use rustls::{
+    pki_types::{pem::PemObject, CertificateDer, ServerName},
+    ClientConfig, ClientConnection, RootCertStore, StreamOwned,
+};
+use simple_social::{
+    response::{Response, StatusCode},
+    server::{RequestHandler, Server, ServerHandle},
+};
+use std::{
+    io::{Read, Write},
+    net::TcpStream,
+    sync::Arc,
+    time::Duration,
+};
+
+const CERT: &str = "tests/fixtures/tls/cert.pem";
+const KEY: &str = "tests/fixtures/tls/key.pem";
+
+fn spawn() -> ServerHandle {
+    let mut server = Server::new("127.0.0.1:0", 2);
+    server.with_tls(CERT, KEY).unwrap().get("/", |req| {
+        Ok(Response::new(StatusCode::Ok).body(req.scheme().to_string()))
+    });
+    server.spawn().unwrap()
+}
+
+fn client_config() -> Arc<ClientConfig> {
+    let mut roots = RootCertStore::empty();
+    for cert in CertificateDer::pem_file_iter(CERT).unwrap() {
+        roots.add(cert.unwrap()).unwrap();
+    }
+    let config =
+        ClientConfig::builder_with_provider(Arc::new(rustls::crypto::ring::default_provider()))
+            .with_safe_default_protocol_versions()
+            .unwrap()
+            .with_root_certificates(roots)
+            .with_no_client_auth();
+    Arc::new(config)
+}
+
+fn connect(handle: &ServerHandle) -> StreamOwned<ClientConnection, TcpStream> {
+    let sock = TcpStream::connect(handle.local_addr().unwrap()).unwrap();
+    sock.set_read_timeout(Some(Duration::from_secs(5))).unwrap();
+    let name = ServerName::try_from("localhost").unwrap();
+    let conn = ClientConnection::new(client_config(), name).unwrap();
+    StreamOwned::new(conn, sock)
+}
+
+#[test]
+fn requests_are_served_over_tls() {
+    let handle = spawn();
+    let mut stream = connect(&handle);
+    stream
+        .write_all(b"GET / HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\r\n")
+        .unwrap();
+    let mut out = Vec::new();
+    // The server may hang up without a close_notify.
+    let _ = stream.read_to_end(&mut out);
+    let out = String::from_utf8(out).unwrap();
+    assert!(out.starts_with("HTTP/1.1 200 OK\r\n"), "{out}");
+    assert!(out.ends_with("\r\n\r\nhttps"), "{out}");
+
+    handle.shutdown();
+    handle.join().unwrap();
+}
+
+#[test]
+fn plain_http_on_the_tls_port_is_not_served() {
+    let handle = spawn();
+    let mut stream = TcpStream::connect(handle.local_addr().unwrap()).unwrap();
+    stream
+        .set_read_timeout(Some(Duration::from_secs(5)))
+        .unwrap();
+    stream
+        .write_all(b"GET / HTTP/1.1\r\nHost: localhost\r\n\r\n")
+        .unwrap();
+    let mut out = Vec::new();
+    let _ = stream.read_to_end(&mut out);
+    assert!(!out.starts_with(b"HTTP/1.1"));
+    assert_eq!(handle.metrics().requests, 0);
+
+    // A failed handshake doesn't take the listener down with it.
+    let mut stream = connect(&handle);
+    stream
+        .write_all(b"GET / HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\r\n")
+        .unwrap();
+    let mut out = Vec::new();
+    let _ = stream.read_to_end(&mut out);
+    assert!(out.starts_with(b"HTTP/1.1 200 OK\r\n"));
+
+    handle.shutdown();
+    handle.join().unwrap();
+}
@@ -0,0 +1,8 @@
+use simple_social::{put, request::Request, server::HandlerResult};
+
+#[put("/posts/<id>")]
+fn replace(_req: &Request) -> HandlerResult {
+    unimplemented!()
+}
+
+fn main() {}
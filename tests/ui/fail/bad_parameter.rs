@@ -0,0 +1,8 @@
+use simple_social::{get, request::Request, server::HandlerResult};
+
+#[get("/posts/:1x")]
+fn show_post(_req: &Request) -> HandlerResult {
+    unimplemented!()
+}
+
+fn main() {}
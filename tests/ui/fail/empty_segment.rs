@@ -0,0 +1,8 @@
+use simple_social::{delete, request::Request, server::HandlerResult};
+
+#[delete("/posts//comments/")]
+fn clear(_req: &Request) -> HandlerResult {
+    unimplemented!()
+}
+
+fn main() {}
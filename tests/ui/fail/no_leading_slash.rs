@@ -0,0 +1,8 @@
+use simple_social::{get, request::Request, server::HandlerResult};
+
+#[get("posts")]
+fn list_posts(_req: &Request) -> HandlerResult {
+    unimplemented!()
+}
+
+fn main() {}
@@ -0,0 +1,9 @@
+use simple_social::{request::Request, routes, server::HandlerResult};
+
+fn plain(_req: &Request) -> HandlerResult {
+    unimplemented!()
+}
+
+fn main() {
+    let _ = routes![plain];
+}
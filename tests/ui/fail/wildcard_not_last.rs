@@ -0,0 +1,8 @@
+use simple_social::{get, request::Request, server::HandlerResult};
+
+#[get("/files/*rest/edit")]
+fn edit(_req: &Request) -> HandlerResult {
+    unimplemented!()
+}
+
+fn main() {}
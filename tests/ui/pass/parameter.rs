@@ -0,0 +1,26 @@
+use simple_social::{
+    get,
+    request::Request,
+    response::{Response, StatusCode},
+    routes,
+    server::{HandlerResult, Method},
+};
+
+#[get("/posts/:id")]
+fn show_post(req: &Request) -> HandlerResult {
+    Ok(Response::new(StatusCode::Ok).body(req.param("id").unwrap_or_default().to_owned()))
+}
+
+#[get("/files/:_dir/*rest")]
+fn file(_req: &Request) -> HandlerResult {
+    Ok(Response::new(StatusCode::Ok))
+}
+
+fn main() {
+    let router = routes![show_post, file];
+    let found = router.match_route(Method::Get, "/posts/7").unwrap();
+    assert_eq!(found.pattern(), "/posts/:id");
+    assert_eq!(found.param("id"), Some("7"));
+    let found = router.match_route(Method::Get, "/files/css/a/b.css").unwrap();
+    assert_eq!(found.params(), [("_dir", "css"), ("rest", "a/b.css")]);
+}
@@ -0,0 +1,36 @@
+use simple_social::{
+    get, post,
+    request::Request,
+    response::{Response, StatusCode},
+    routes,
+    server::{HandlerResult, Method},
+};
+
+#[get("/posts")]
+fn list_posts(_req: &Request) -> HandlerResult {
+    Ok(Response::new(StatusCode::Ok).body("[]"))
+}
+
+mod admin {
+    use super::*;
+
+    #[post("/admin/posts/new-post_v2.json")]
+    pub fn create_post(_req: &Request) -> HandlerResult {
+        Ok(Response::new(StatusCode::Created))
+    }
+}
+
+fn main() {
+    let router = routes![list_posts, admin::create_post];
+    let found = router.match_route(Method::Get, "/posts").unwrap();
+    assert_eq!(found.pattern(), "/posts");
+    let found = router.match_route(Method::Head, "/posts").unwrap();
+    assert_eq!(found.method(), Method::Get);
+    assert!(router
+        .match_route(Method::Post, "/admin/posts/new-post_v2.json")
+        .is_some());
+    assert!(router.match_route(Method::Post, "/posts").is_none());
+    // The function is left as written.
+    let req = Request::builder().build().unwrap();
+    assert_eq!(list_posts(&req).unwrap().status(), StatusCode::Ok);
+}
@@ -0,0 +1,136 @@
+//! `UploadPolicy::validate` on files whose name, declared type and magic
+//! bytes disagree, and on files over the limit.
+
+use simple_social::{
+    error::UploadError,
+    form::UploadedFile,
+    request::Request,
+    response::{Response, StatusCode},
+    server::{Method, RequestHandler, Server},
+    upload::UploadPolicy,
+};
+
+const PNG: &[u8] = b"\x89PNG\r\n\x1a\n\0\0\0\rIHDR";
+const JPEG: &[u8] = b"\xff\xd8\xff\xe0\0\x10JFIF";
+const GIF: &[u8] = b"GIF89a\x01\0\x01\0";
+const WEBP: &[u8] = b"RIFF\x24\0\0\0WEBPVP8 ";
+/// The start of a Windows executable.
+const EXE: &[u8] = b"MZ\x90\0\x03\0\0\0\x04\0\0\0";
+
+const MAX: usize = 1024;
+
+fn file(name: &str, content_type: Option<&str>, data: &[u8]) -> UploadedFile {
+    UploadedFile {
+        field_name: String::from("avatar"),
+        file_name: Some(String::from(name)),
+        content_type: content_type.map(String::from),
+        data: data.to_vec(),
+    }
+}
+
+fn validate(name: &str, content_type: Option<&str>, data: &[u8]) -> Result<(), UploadError> {
+    UploadPolicy::images(MAX).validate(&file(name, content_type, data))
+}
+
+#[test]
+fn matching_images_pass() {
+    for (name, content_type, data) in [
+        ("me.png", "image/png", PNG),
+        ("me.JPG", "image/jpeg", JPEG),
+        ("me.jpeg", "application/octet-stream", JPEG),
+        ("me.gif", "image/gif", GIF),
+        ("me.webp", "image/webp", WEBP),
+    ] {
+        assert_eq!(validate(name, Some(content_type), data), Ok(()), "{name}");
+    }
+    assert_eq!(validate("me.png", None, PNG), Ok(()));
+}
+
+#[test]
+fn an_extension_that_lies_about_the_contents_is_refused() {
+    let err = validate("me.jpg", None, PNG).unwrap_err();
+    assert_eq!(
+        err,
+        UploadError::ContentMismatch {
+            claimed: String::from(".jpg"),
+            sniffed: Some("image/png"),
+        }
+    );
+    assert_eq!(err.status(), StatusCode::UnsupportedMediaType);
+    assert_eq!(err.to_string(), "file claims to be .jpg but is image/png");
+
+    // A renamed executable, with and without a matching Content-Type.
+    let err = validate("cat.png", Some("image/png"), EXE).unwrap_err();
+    assert_eq!(
+        err,
+        UploadError::ContentMismatch {
+            claimed: String::from("image/png"),
+            sniffed: None,
+        }
+    );
+    assert_eq!(err.status(), StatusCode::UnsupportedMediaType);
+    let err = validate("cat.png", None, EXE).unwrap_err();
+    assert_eq!(err, UploadError::TypeNotAllowed(None));
+    assert_eq!(err.status(), StatusCode::UnsupportedMediaType);
+
+    // The right bytes under an extension the policy doesn't take.
+    let err = validate("cat.exe", Some("image/gif"), GIF).unwrap_err();
+    assert_eq!(
+        err,
+        UploadError::ExtensionNotAllowed(Some(String::from("exe")))
+    );
+    assert_eq!(err.status(), StatusCode::BadRequest);
+}
+
+#[test]
+fn the_stored_name_follows_the_sniffed_type() {
+    let renamed = file("C:\\Users\\ada\\..\\holiday.jpg", None, PNG);
+    assert_eq!(renamed.safe_file_name(), "holiday.png");
+    let sneaky = file("../../.ssh/\"au<th>\x07orized_keys\".gif", None, GIF);
+    assert_eq!(sneaky.safe_file_name(), "authorized_keys.gif");
+}
+
+#[test]
+fn an_oversize_file_is_a_413() {
+    let mut big = PNG.to_vec();
+    big.resize(MAX + 1, 0);
+    let err = validate("big.png", Some("image/png"), &big).unwrap_err();
+    assert_eq!(
+        err,
+        UploadError::TooLarge {
+            size: MAX + 1,
+            max: MAX
+        }
+    );
+    big.truncate(MAX);
+    assert_eq!(validate("big.png", Some("image/png"), &big), Ok(()));
+
+    // Through a handler, the error's response is what the client gets.
+    let mut server = Server::new("127.0.0.1:0", 2);
+    server.post("/avatar", |req| {
+        let form = req.form()?;
+        let avatar = form.file("avatar").ok_or("no avatar")?;
+        Ok(match UploadPolicy::images(MAX).validate(avatar) {
+            Ok(()) => Response::new(StatusCode::Created).body(avatar.safe_file_name()),
+            Err(e) => e.response(),
+        })
+    });
+    let upload = |data: &[u8]| {
+        let mut body = b"--b\r\nContent-Disposition: form-data; name=\"avatar\"; \
+                         filename=\"me.png\"\r\nContent-Type: image/png\r\n\r\n"
+            .to_vec();
+        body.extend_from_slice(data);
+        body.extend_from_slice(b"\r\n--b--\r\n");
+        let req = Request::builder()
+            .method(Method::Post)
+            .path("/avatar")
+            .header("Content-Type", "multipart/form-data; boundary=b")
+            .body(body)
+            .build()
+            .unwrap();
+        server.handle(req).status()
+    };
+    assert_eq!(upload(&big), StatusCode::Created);
+    big.resize(MAX * 4, 0);
+    assert_eq!(upload(&big), StatusCode::PayloadTooLarge);
+}
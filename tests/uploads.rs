@@ -0,0 +1,90 @@
+//! A large upload to a `stream_body` route, in a test binary of its own so
+//! the allocator only sees this server.
+
+use simple_social::{
+    response::{Response, StatusCode},
+    server::{RequestHandler, Router, Server},
+};
+use std::{
+    alloc::{GlobalAlloc, Layout, System},
+    io::{self, Read, Write},
+    net::TcpStream,
+    sync::atomic::{AtomicUsize, Ordering},
+};
+
+struct Peak;
+
+static LIVE: AtomicUsize = AtomicUsize::new(0);
+static PEAK: AtomicUsize = AtomicUsize::new(0);
+
+fn grew(by: usize) {
+    let live = LIVE.fetch_add(by, Ordering::Relaxed) + by;
+    PEAK.fetch_max(live, Ordering::Relaxed);
+}
+
+unsafe impl GlobalAlloc for Peak {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        grew(layout.size());
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        LIVE.fetch_sub(layout.size(), Ordering::Relaxed);
+        System.dealloc(ptr, layout)
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        LIVE.fetch_sub(layout.size(), Ordering::Relaxed);
+        grew(new_size);
+        System.realloc(ptr, layout, new_size)
+    }
+}
+
+#[global_allocator]
+static GLOBAL: Peak = Peak;
+
+const SIZE: usize = 50 * 1024 * 1024;
+
+#[test]
+fn uploads_stream_through_a_mounted_route() {
+    let mut api = Router::new();
+    api.post("/upload", |req| {
+        let n = io::copy(&mut req.body_reader(), &mut io::sink())?;
+        Ok(Response::new(StatusCode::Ok).body(n.to_string()))
+    });
+    let mut server = Server::new("127.0.0.1:0", 2);
+    server.max_body_size(64 * 1024).mount("/api", api);
+    server.stream_body("/api/upload");
+    let handle = server.spawn().unwrap();
+    let mut stream = TcpStream::connect(handle.local_addr().unwrap()).unwrap();
+
+    let chunk = [b'x'; 64 * 1024];
+    let head = format!("POST /api/upload HTTP/1.1\r\nHost: x\r\nContent-Length: {SIZE}\r\n\r\n");
+    let baseline = LIVE.load(Ordering::Relaxed);
+    PEAK.store(baseline, Ordering::Relaxed);
+    stream.write_all(head.as_bytes()).unwrap();
+    for _ in 0..SIZE / chunk.len() {
+        stream.write_all(&chunk).unwrap();
+    }
+    let mut res = [0u8; 1024];
+    let mut n = 0;
+    while !res[..n].ends_with(SIZE.to_string().as_bytes()) {
+        match stream.read(&mut res[n..]).unwrap() {
+            0 => break,
+            read => n += read,
+        }
+    }
+    let peak = PEAK.load(Ordering::Relaxed) - baseline;
+
+    let res = String::from_utf8_lossy(&res[..n]);
+    assert!(res.starts_with("HTTP/1.1 200"), "{res}");
+    assert!(res.ends_with(&format!("\r\n\r\n{SIZE}")), "{res}");
+    assert!(
+        peak < 512 * 1024,
+        "peaked {peak} bytes over the baseline for a {SIZE} byte upload"
+    );
+
+    drop(stream);
+    handle.shutdown();
+    handle.join().unwrap();
+}
@@ -0,0 +1,190 @@
+//! The WebSocket handshake and framing, against a client written out here
+//! from RFC 6455 rather than one built on the server's own code.
+
+use simple_social::{
+    server::Server,
+    testing::TestClient,
+    websocket::{Message, CLOSE_NORMAL},
+};
+use std::{
+    io::{self, Read, Write},
+    net::{SocketAddr, TcpStream},
+    time::Duration,
+};
+
+/// The RFC's sample key, and the accept value it gives.
+const KEY: &str = "dGhlIHNhbXBsZSBub25jZQ==";
+const ACCEPT: &str = "s3pPLMBiTxaQ9kYGzzhZRbK+xOo=";
+
+const TEXT: u8 = 0x1;
+const BINARY: u8 = 0x2;
+const CLOSE: u8 = 0x8;
+const PING: u8 = 0x9;
+const PONG: u8 = 0xA;
+
+struct Client {
+    stream: TcpStream,
+}
+
+impl Client {
+    /// Connects and upgrades, returning the handshake response head too.
+    fn connect(addr: SocketAddr) -> (Client, String) {
+        let mut stream = TcpStream::connect(addr).unwrap();
+        stream
+            .set_read_timeout(Some(Duration::from_secs(5)))
+            .unwrap();
+        write!(
+            stream,
+            "GET /ws HTTP/1.1\r\nHost: x\r\nUpgrade: websocket\r\nConnection: keep-alive, Upgrade\r\n\
+             Sec-WebSocket-Key: {KEY}\r\nSec-WebSocket-Version: 13\r\n\r\n"
+        )
+        .unwrap();
+        // A byte at a time, so no frame is read along with the head.
+        let mut head = Vec::new();
+        while !head.ends_with(b"\r\n\r\n") {
+            let mut byte = [0];
+            stream.read_exact(&mut byte).unwrap();
+            head.push(byte[0]);
+        }
+        (Client { stream }, String::from_utf8(head).unwrap())
+    }
+
+    fn send(&mut self, opcode: u8, payload: &[u8]) {
+        self.send_frame(opcode, payload, Some([0x37, 0xfa, 0x21, 0x3d]));
+    }
+
+    fn send_frame(&mut self, opcode: u8, payload: &[u8], mask: Option<[u8; 4]>) {
+        let mut frame = vec![0x80 | opcode];
+        let masked = if mask.is_some() { 0x80 } else { 0 };
+        match payload.len() {
+            n @ 0..=125 => frame.push(masked | n as u8),
+            n @ 126..=0xffff => {
+                frame.push(masked | 126);
+                frame.extend_from_slice(&(n as u16).to_be_bytes());
+            }
+            n => {
+                frame.push(masked | 127);
+                frame.extend_from_slice(&(n as u64).to_be_bytes());
+            }
+        }
+        match mask {
+            Some(mask) => {
+                frame.extend_from_slice(&mask);
+                frame.extend(payload.iter().enumerate().map(|(i, b)| b ^ mask[i % 4]));
+            }
+            None => frame.extend_from_slice(payload),
+        }
+        self.stream.write_all(&frame).unwrap();
+    }
+
+    /// The next frame's opcode and payload. Server frames are never masked.
+    fn recv(&mut self) -> io::Result<(u8, Vec<u8>)> {
+        let mut head = [0; 2];
+        self.stream.read_exact(&mut head)?;
+        assert_eq!(head[0] & 0x80, 0x80, "the server doesn't fragment");
+        assert_eq!(head[1] & 0x80, 0, "server frames are unmasked");
+        let len = match head[1] & 0x7f {
+            126 => {
+                let mut len = [0; 2];
+                self.stream.read_exact(&mut len)?;
+                u16::from_be_bytes(len) as usize
+            }
+            127 => {
+                let mut len = [0; 8];
+                self.stream.read_exact(&mut len)?;
+                u64::from_be_bytes(len) as usize
+            }
+            n => n as usize,
+        };
+        let mut payload = vec![0; len];
+        self.stream.read_exact(&mut payload)?;
+        Ok((head[0] & 0x0f, payload))
+    }
+
+    fn closed(&mut self) -> bool {
+        matches!(self.stream.read(&mut [0]), Ok(0))
+    }
+}
+
+fn close_payload(code: u16, reason: &str) -> Vec<u8> {
+    let mut payload = code.to_be_bytes().to_vec();
+    payload.extend_from_slice(reason.as_bytes());
+    payload
+}
+
+fn echo() -> TestClient {
+    let mut server = Server::new("127.0.0.1:0", 2);
+    server.websocket("/ws", |_, ws| {
+        while let Some(message) = ws.recv()? {
+            match message {
+                Message::Text(_) | Message::Binary(_) => ws.send(message)?,
+                _ => {}
+            }
+        }
+        Ok(())
+    });
+    TestClient::start(server).unwrap()
+}
+
+#[test]
+fn echoes_and_closes_cleanly() {
+    let server = echo();
+    let (mut client, head) = Client::connect(server.addr());
+    assert!(head.starts_with("HTTP/1.1 101"), "{head}");
+    assert!(
+        head.contains(&format!("Sec-WebSocket-Accept: {ACCEPT}\r\n")),
+        "{head}"
+    );
+
+    client.send(TEXT, "héllo".as_bytes());
+    assert_eq!(client.recv().unwrap(), (TEXT, "héllo".as_bytes().to_vec()));
+    let big: Vec<u8> = (0..70_000).map(|i| i as u8).collect();
+    client.send(BINARY, &big);
+    assert_eq!(client.recv().unwrap(), (BINARY, big));
+    client.send(PING, b"are you there");
+    assert_eq!(client.recv().unwrap(), (PONG, b"are you there".to_vec()));
+
+    client.send(CLOSE, &close_payload(CLOSE_NORMAL, "bye"));
+    let (opcode, payload) = client.recv().unwrap();
+    assert_eq!(opcode, CLOSE);
+    assert_eq!(payload[..2], CLOSE_NORMAL.to_be_bytes());
+    assert!(
+        client.closed(),
+        "the server hangs up after the close handshake"
+    );
+}
+
+#[test]
+fn plain_requests_are_told_to_upgrade() {
+    let res = echo().get("/ws").unwrap();
+    assert_eq!(res.status, 426);
+    assert_eq!(res.header("Upgrade"), Some("websocket"));
+}
+
+#[test]
+fn unmasked_client_frames_are_refused() {
+    let server = echo();
+    let (mut client, _) = Client::connect(server.addr());
+    client.send_frame(TEXT, b"plain", None);
+    let (opcode, payload) = client.recv().unwrap();
+    assert_eq!(opcode, CLOSE);
+    assert_eq!(payload[..2], 1002u16.to_be_bytes());
+}
+
+#[test]
+fn long_close_reasons_are_cut_between_characters() {
+    let mut server = Server::new("127.0.0.1:0", 2);
+    server.websocket("/ws", |_, ws| ws.close(4000, &"é".repeat(100)));
+    let server = TestClient::start(server).unwrap();
+    let (mut client, _) = Client::connect(server.addr());
+
+    let (opcode, payload) = client.recv().unwrap();
+    assert_eq!(opcode, CLOSE);
+    assert!(payload.len() <= 125);
+    assert_eq!(payload[..2], 4000u16.to_be_bytes());
+    let reason = String::from_utf8(payload[2..].to_vec()).expect("a whole number of characters");
+    assert_eq!(reason, "é".repeat(61));
+
+    client.send(CLOSE, &close_payload(4000, ""));
+    assert!(client.closed());
+}
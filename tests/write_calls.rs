@@ -0,0 +1,62 @@
+//! How many writes `Response::write_to` makes on a writer without
+//! vectored writes: the head and a small body go out together.
+
+use simple_social::response::{Response, StatusCode};
+use std::io::{self, Write};
+
+/// Counts `write` and `flush` calls and keeps what was written.
+#[derive(Default)]
+struct Counter {
+    out: Vec<u8>,
+    writes: usize,
+    flushes: usize,
+}
+
+impl Write for Counter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.writes += 1;
+        self.out.extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.flushes += 1;
+        Ok(())
+    }
+}
+
+fn written(res: Response) -> Counter {
+    let mut w = Counter::default();
+    res.write_to(&mut w).unwrap();
+    w
+}
+
+#[test]
+fn a_small_body_shares_the_heads_write() {
+    for size in [0, 13, 4096, 8 * 1024] {
+        let body = vec![b'x'; size];
+        let w = written(Response::new(StatusCode::Ok).body(body.clone()));
+        assert_eq!((w.writes, w.flushes), (1, 1), "{size} bytes");
+        let head_end = w.out.windows(4).position(|w| w == b"\r\n\r\n").unwrap() + 4;
+        let head = String::from_utf8_lossy(&w.out[..head_end]);
+        assert!(head.starts_with("HTTP/1.1 200 OK\r\n"), "{head}");
+        assert!(
+            head.contains(&format!("Content-Length: {size}\r\n")),
+            "{head}"
+        );
+        assert_eq!(&w.out[head_end..], &body[..], "{size} bytes");
+    }
+
+    // No body at all is still one write.
+    let w = written(Response::new(StatusCode::NoContent));
+    assert_eq!((w.writes, w.flushes), (1, 1));
+}
+
+#[test]
+fn a_large_body_gets_a_write_of_its_own() {
+    for size in [8 * 1024 + 1, 100_000] {
+        let w = written(Response::new(StatusCode::Ok).body(vec![b'x'; size]));
+        assert_eq!((w.writes, w.flushes), (2, 1), "{size} bytes");
+        assert!(w.out.ends_with(&vec![b'x'; size]), "{size} bytes");
+    }
+}